@@ -0,0 +1,115 @@
+//! Throughput of the parser, [strip](ansi_optimizer::strip), and the optimizer over an embedded
+//! corpus, and of the optimizer over SGR-dense input.
+//!
+//! The `transition` group compares writing style changes into a reused buffer, which doesn't
+//! allocate, to formatting each of them into a new `String`.
+//!
+//! This uses criterion, so the manifest needs it as a dev-dependency and a `[[bench]]` entry for
+//! `throughput` with `harness = false`. Run it with `cargo bench --bench throughput`.
+
+use ansi_optimizer::token::Tokens;
+use ansi_optimizer::{Color, Optimizer, Style};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::hint::black_box;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Representative inputs, embedded so results are comparable between machines.
+const CORPUS: &[(&str, &str)] = &[
+    ("ls", include_str!("corpus/ls.txt")),
+    ("vim", include_str!("corpus/vim.txt")),
+    ("ci", include_str!("corpus/ci.txt")),
+];
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, input) in CORPUS {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_function(*name, |b| {
+            b.iter(|| Tokens::new(black_box(input)).count());
+        });
+    }
+    group.finish();
+}
+
+fn strip(c: &mut Criterion) {
+    let mut group = c.benchmark_group("strip");
+    for (name, input) in CORPUS {
+        let mut output = String::with_capacity(input.len());
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                output.clear();
+                ansi_optimizer::strip_into(black_box(input), black_box(&mut output));
+            });
+        });
+    }
+    group.finish();
+}
+
+fn optimize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("optimize");
+    for (name, input) in CORPUS {
+        let mut optimizer = Optimizer::new();
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                optimizer.reset();
+                optimizer.update(black_box(input)).unwrap();
+            });
+        });
+    }
+    group.finish();
+}
+
+fn optimize_sgr(c: &mut Criterion) {
+    // Every character has a different style, so a style change is written before each of them.
+    let input = (0..4096)
+        .map(|i| format!("\x1B[{};{}m\x1B[1m{}\x1B[22m", 31 + i % 7, 41 + i % 5, i % 10))
+        .collect::<String>();
+
+    let mut group = c.benchmark_group("optimize");
+    let mut optimizer = Optimizer::new();
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("sgr", |b| {
+        b.iter(|| {
+            optimizer.reset();
+            optimizer.update(black_box(&input)).unwrap();
+        });
+    });
+    group.finish();
+}
+
+fn transition(c: &mut Criterion) {
+    // Every style has a different foreground and background from the one before it.
+    let styles = (0..4096u32)
+        .map(|i| Style {
+            foreground: Color::Indexed(1 + (i % 7) as u8),
+            background: Color::Rgb((i % 256) as u8, (i / 256) as u8, 0),
+            ..Style::default()
+        })
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("transition");
+    group.throughput(Throughput::Elements(styles.len() as u64));
+    group.bench_function("write", |b| {
+        let mut output = String::new();
+        b.iter(|| {
+            output.clear();
+            for pair in black_box(&styles).windows(2) {
+                pair[1].write_transition(&pair[0], &mut output).unwrap();
+            }
+        });
+    });
+    group.bench_function("to_string", |b| {
+        b.iter(|| {
+            for pair in black_box(&styles).windows(2) {
+                black_box(pair[1].transition_from(Some(&pair[0])).to_string());
+            }
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse, strip, optimize, optimize_sgr, transition);
+criterion_main!(benches);
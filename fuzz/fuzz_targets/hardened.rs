@@ -0,0 +1,34 @@
+//! Feeds arbitrary input to an optimizer with [Options::hardened], split into chunks of a size
+//! chosen by the first byte. This looks for panics, and for input that takes too long.
+//!
+//! ```sh
+//! cargo +nightly fuzz run hardened
+//! ```
+
+#![no_main]
+
+use ansi_optimizer::{Optimizer, Options};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let (chunk_len, data) = match data.split_first() {
+        Some((&first, rest)) => (usize::from(first).max(1), rest),
+        None => return,
+    };
+
+    let input = String::from_utf8_lossy(data);
+    let mut optimizer = Optimizer::with_options(Options::hardened());
+    let mut rest = &*input;
+    while !rest.is_empty() {
+        let mut end = chunk_len.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end += 1;
+        }
+
+        optimizer.feed(&rest[..end]);
+        optimizer.take_output();
+        rest = &rest[end..];
+    }
+
+    optimizer.finish();
+});
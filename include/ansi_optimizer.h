@@ -0,0 +1,68 @@
+#ifndef ANSI_OPTIMIZER_H
+#define ANSI_OPTIMIZER_H
+
+/* This file is generated by cbindgen. Do not edit it manually. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// An optimizer for a stream of bytes.
+//
+// Bytes that aren't valid UTF-8 are passed through unchanged.
+typedef struct AnsiOptimizer AnsiOptimizer;
+
+// Creates an optimizer with the default options.
+//
+// It must be freed with [ansi_optimizer_free].
+AnsiOptimizer *ansi_optimizer_new(void);
+
+// Frees an optimizer.
+//
+// ## Safety
+//
+// The optimizer must have been created by [ansi_optimizer_new], and must not be used after this.
+// It may be null, in which case nothing happens.
+void ansi_optimizer_free(AnsiOptimizer *optimizer);
+
+// Updates an optimizer with the next chunk of a stream.
+//
+// Escape sequences and characters that are split between chunks are held until the rest of them
+// arrives. See [Optimizer::feed].
+//
+// ## Safety
+//
+// The optimizer must be valid, and `data` must point to `len` readable bytes.
+// If `len` is zero, `data` may be null.
+void ansi_optimizer_feed(AnsiOptimizer *optimizer, const uint8_t *data, uintptr_t len);
+
+// Finishes the stream, making everything that was held back available as output.
+//
+// The optimizer can be used for a new stream afterwards.
+//
+// ## Safety
+//
+// The optimizer must be valid.
+void ansi_optimizer_finish(AnsiOptimizer *optimizer);
+
+// Gets the number of bytes of output that are ready to be taken.
+//
+// ## Safety
+//
+// The optimizer must be valid.
+uintptr_t ansi_optimizer_output_len(const AnsiOptimizer *optimizer);
+
+// Takes up to `capacity` bytes of output, copying them into `buffer`.
+//
+// ## Returns
+//
+// The number of bytes copied. This is zero once all of the output has been taken.
+//
+// ## Safety
+//
+// The optimizer must be valid, and `buffer` must point to `capacity` writable bytes.
+// If `capacity` is zero, `buffer` may be null.
+uintptr_t ansi_optimizer_take_output(AnsiOptimizer *optimizer, uint8_t *buffer, uintptr_t capacity);
+
+#endif /* ANSI_OPTIMIZER_H */
@@ -0,0 +1,105 @@
+//! Padding styled text to a width, for tables and other aligned output.
+//!
+//! Padding by the length of a string goes wrong as soon as it contains escape sequences, since
+//! they take up bytes but not cells. These functions pad by the [width](crate::width::width) of
+//! the text instead.
+//!
+//! ```
+//! # use ansi_optimizer::{align, pad_visible, Alignment};
+//! # use ansi_optimizer::align::Fill;
+//! assert_eq!(pad_visible("\x1B[1mok\x1B[m", 4), "\x1B[1mok\x1B[m  ");
+//! assert_eq!(align("\x1B[1m42\x1B[m", 4, Alignment::Right, Fill::default()), "  \x1B[1m42\x1B[m");
+//! ```
+
+use crate::width::width;
+use crate::{Optimizer, Style};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Where text is placed within its width.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Alignment {
+    /// Padding is added after the text.
+    Left,
+
+    /// Padding is split between both sides of the text, with the extra cell after it.
+    Center,
+
+    /// Padding is added before the text.
+    Right,
+}
+
+/// The padding added around text.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub struct Fill {
+    /// The character that is repeated. This should take up a single cell.
+    pub character: char,
+
+    /// The style of the padding, which doesn't depend on the style of the text.
+    pub style: Style,
+}
+
+impl Default for Fill {
+    #[inline]
+    fn default() -> Self {
+        Fill { character: ' ', style: Style::default() }
+    }
+}
+
+/// Pads text with unstyled spaces after it, until it takes up a number of cells.
+///
+/// See [align].
+#[inline]
+pub fn pad_visible(input: &str, width: usize) -> String {
+    align(input, width, Alignment::Left, Fill::default())
+}
+
+/// Pads text until it takes up a number of cells.
+///
+/// The text is expected to be a single line, and is left as-is if it's already wide enough.
+/// It's written after a reset style, and the terminal is left in whichever style the text
+/// leaves it in. The escape sequences are optimized along the way.
+pub fn align(input: &str, width: usize, alignment: Alignment, fill: Fill) -> String {
+    let padding = width.saturating_sub(self::width(input));
+    let (before, after) = match alignment {
+        Alignment::Left => (0, padding),
+        Alignment::Center => (padding / 2, padding - padding / 2),
+        Alignment::Right => (padding, 0),
+    };
+
+    let pad = |optimizer: &mut Optimizer, cells: usize| {
+        if cells > 0 {
+            optimizer.write_styled(&fill.character.to_string().repeat(cells), fill.style);
+        }
+    };
+
+    let mut optimizer = Optimizer::new();
+    pad(&mut optimizer, before);
+    optimizer.feed(input);
+    pad(&mut optimizer, after);
+    optimizer.finish();
+    optimizer.take_output()
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::align::*;
+    use crate::style::Attributes;
+
+    #[test]
+    fn pad_styled_text() {
+        assert_eq!(pad_visible("", 2), "  ");
+        assert_eq!(pad_visible("abc", 2), "abc");
+        assert_eq!(pad_visible("\x1B[41mab", 3), "\x1B[41mab\x1B[m \x1B[41m");
+        assert_eq!(align("a", 4, Alignment::Center, Fill::default()), " a  ");
+
+        let style = Style { attributes: Attributes::FAINT, ..Style::default() };
+        let fill = Fill { character: '.', style };
+        assert_eq!(
+            align("\x1B[41mab", 5, Alignment::Center, fill),
+            "\x1B[2m.\x1B[0;41mab\x1B[0;2m..\x1B[0;41m"
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use crate::ansi::{AnsiString, ControlSequence, Param, Params, Sequence};
+use crate::osc::OscCommand;
+use crate::profile::{ColorSupport, Profile};
+use crate::token::{Token, Tokens};
+use crate::optimizer::{is_device_query, is_kitty_keyboard, is_mouse_mode, is_sixel};
+
+// -------------------------------------------------------------------------------------------------
+
+/// The terminal features used by some input.
+///
+/// This is created with [analyze].
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct CapabilityReport {
+    /// The colors needed to display the input.
+    pub colors: Option<ColorSupport>,
+
+    /// Whether curly, dotted, dashed, or double underlines are used (`SGR 4:n`).
+    pub underline_styles: bool,
+
+    /// Whether underline colors are used (`SGR 58`).
+    pub underline_color: bool,
+
+    /// Whether hyperlinks are used (`OSC 8`).
+    pub hyperlinks: bool,
+
+    /// Whether the window title or icon name is set (`OSC 0`, `OSC 1`, or `OSC 2`).
+    pub titles: bool,
+
+    /// Whether the clipboard is accessed (`OSC 52`).
+    pub clipboard: bool,
+
+    /// Whether the alternate screen is used (`DECSET 47`, `1047`, or `1049`).
+    pub alternate_screen: bool,
+
+    /// Whether mouse reporting is enabled.
+    pub mouse: bool,
+
+    /// Whether bracketed paste is enabled (`DECSET 2004`).
+    pub bracketed_paste: bool,
+
+    /// Whether sixel images are used.
+    pub sixel: bool,
+
+    /// Whether kitty graphics are used (`APC G`).
+    pub kitty_graphics: bool,
+
+    /// Whether the kitty keyboard protocol is used (`CSI > u`, `CSI < u`, `CSI = u`, or `CSI ? u`).
+    pub kitty_keyboard: bool,
+
+    /// Whether REP sequences are used (`CSI n b`).
+    pub repeat: bool,
+
+    /// Whether the terminal is asked to reply with its attributes or status (`DA` or `DSR`).
+    pub queries: bool,
+}
+
+impl CapabilityReport {
+    /// Checks if the input can be displayed by a terminal with the given profile, without any
+    /// colors or sequences being converted.
+    ///
+    /// Only the capabilities that are described by a [Profile] are checked.
+    pub fn is_supported_by(&self, profile: &Profile) -> bool {
+        !matches!(self.colors, Some(colors) if colors > profile.colors)
+            && (!self.repeat || profile.repeat)
+    }
+
+    fn require_colors(&mut self, colors: ColorSupport) {
+        self.colors = Some(self.colors.map_or(colors, |current| current.max(colors)));
+    }
+
+    fn update_csi(&mut self, csi: &ControlSequence) {
+        if is_kitty_keyboard(csi) {
+            self.kitty_keyboard = true;
+        }
+
+        if is_device_query(csi) {
+            self.queries = true;
+        }
+
+        if !csi.intermediates().is_empty() {
+            return;
+        }
+
+        match (csi.private_marker(), csi.finalizer()) {
+            (None, "m") => self.update_sgr(csi.params()),
+            (None, "b") => self.repeat = true,
+            (Some('?'), "h") => {
+                for mode in csi.params().filter_map(|p| p.value()) {
+                    match mode {
+                        47 | 1047 | 1049 => self.alternate_screen = true,
+                        mode if is_mouse_mode(mode) => self.mouse = true,
+                        2004 => self.bracketed_paste = true,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn update_sgr(&mut self, mut params: Params) {
+        while let Some(param) = params.next() {
+            let (code, mut sub) = match param {
+                Param::Empty => continue,
+                Param::Int(code) => (code, None),
+                Param::Sub(mut sub) => (sub.next().flatten().unwrap_or(0), Some(sub)),
+            };
+
+            match code {
+                30..=37 | 40..=47 | 90..=97 | 100..=107 => {
+                    self.require_colors(ColorSupport::Ansi16)
+                }
+                4 => {
+                    if let Some(2..=5) = sub.as_mut().and_then(|s| s.next().flatten()) {
+                        self.underline_styles = true;
+                    }
+                }
+
+                38 | 48 | 58 => {
+                    if code == 58 {
+                        self.underline_color = true;
+                    }
+
+                    // The color type is the next sub-parameter, or the next parameter.
+                    let kind = match sub.as_mut() {
+                        Some(sub) => sub.next().flatten(),
+                        None => params.next().and_then(|p| p.value()),
+                    };
+
+                    match kind {
+                        Some(5) => self.require_colors(ColorSupport::Ansi256),
+                        Some(2) => self.require_colors(ColorSupport::TrueColor),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn update_osc(&mut self, string: &AnsiString) {
+        match OscCommand::from(string) {
+            OscCommand::SetTitleAndIconName(_)
+            | OscCommand::SetIconName(_)
+            | OscCommand::SetTitle(_) => self.titles = true,
+            OscCommand::Hyperlink { .. } => self.hyperlinks = true,
+            OscCommand::Clipboard { .. } => self.clipboard = true,
+            _ => {}
+        }
+    }
+
+    fn update_dcs(&mut self, string: &AnsiString) {
+        if is_sixel(string) {
+            self.sixel = true;
+        }
+    }
+}
+
+/// Reports the terminal features that are used by some input.
+///
+/// This can be used to check whether a recording or log can be displayed on a terminal.
+///
+/// ```
+/// # use ansi_optimizer::{analyze, ColorSupport};
+/// let report = analyze("\x1B[38;5;208morange\x1B[m");
+/// assert_eq!(report.colors, Some(ColorSupport::Ansi256));
+/// assert!(!report.hyperlinks);
+/// ```
+pub fn analyze(input: &str) -> CapabilityReport {
+    let mut report = CapabilityReport::default();
+    for token in Tokens::new(input) {
+        match token {
+            Ok(Token::Sequence(Sequence::CSI(csi))) => report.update_csi(&csi),
+            Ok(Token::Sequence(Sequence::OSC(_, string))) => report.update_osc(&string),
+            Ok(Token::Sequence(Sequence::DCS(_, string))) => report.update_dcs(&string),
+            Ok(Token::Sequence(Sequence::APC(_, string))) => {
+                report.kitty_graphics |= string.text().starts_with('G');
+            }
+            _ => {}
+        }
+    }
+
+    report
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::analyze::*;
+
+    #[test]
+    fn analyze_colors() {
+        assert_eq!(analyze("plain text").colors, None);
+        assert_eq!(analyze("\x1B[1;31mred").colors, Some(ColorSupport::Ansi16));
+        assert_eq!(analyze("\x1B[38;5;1mred\x1B[94m").colors, Some(ColorSupport::Ansi256));
+        assert_eq!(analyze("\x1B[48:2::1:2:3m\x1B[31m").colors, Some(ColorSupport::TrueColor));
+
+        let report = analyze("\x1B[4:3;58;5;1m");
+        assert!(report.underline_styles);
+        assert!(report.underline_color);
+        assert_eq!(report.colors, Some(ColorSupport::Ansi256));
+    }
+
+    #[test]
+    fn analyze_features() {
+        let report = analyze(concat!(
+            "\x1B[?1049h\x1B[?1000;1006h\x1B]0;title\x07",
+            "\x1B]8;;https://example.com\x1B\\link\x1B]8;;\x1B\\",
+            "\x1BP0;1q#0;2;0;0;0#0~~\x1B\\",
+            "\x1B[>1u\x1B_Ga=T;AAAA\x1B\\",
+        ));
+
+        assert!(report.alternate_screen);
+        assert!(report.mouse);
+        assert!(report.titles);
+        assert!(report.hyperlinks);
+        assert!(report.sixel);
+        assert!(report.kitty_graphics);
+        assert!(report.kitty_keyboard);
+        assert!(!report.clipboard);
+        assert!(!report.bracketed_paste);
+        assert!(!report.repeat);
+        assert!(!report.queries);
+        assert!(analyze("\x1B[>c\x1B[6n").queries);
+        assert!(!analyze("\x1B[?1004h").mouse);
+    }
+
+    #[test]
+    fn supported_by() {
+        let report = analyze("\x1B[38;5;1mred-\x1B[3b");
+        assert!(report.is_supported_by(&Profile::xterm()));
+        assert!(!report.is_supported_by(&Profile::default()));
+
+        let profile = Profile { colors: ColorSupport::Ansi16, ..Profile::xterm() };
+        assert!(!report.is_supported_by(&profile));
+    }
+}
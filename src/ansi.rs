@@ -1,14 +1,16 @@
 use crate::error::Error;
 use crate::error::Result;
 use crate::lex::Lexer;
-use std::str::FromStr;
-use std::sync::atomic::Ordering::SeqCst;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
 
 /// An ANSI escape sequence.
 #[derive(Eq, PartialEq, Debug)]
 pub enum Sequence<'a> {
     CSI(ControlSequence<'a>),
     OSC(AnsiSequence<'a>, AnsiString<'a>),
+    DCS(AnsiSequence<'a>, AnsiString<'a>),
+    APC(AnsiSequence<'a>, AnsiString<'a>),
     Regular(AnsiSequence<'a>),
 }
 
@@ -19,8 +21,8 @@ pub enum Sequence<'a> {
 /// ```
 #[derive(Eq, PartialEq, Debug)]
 pub struct AnsiSequence<'a> {
-    intermediates: &'a str,
-    finalizer: &'a str,
+    pub(crate) intermediates: &'a str,
+    pub(crate) finalizer: &'a str,
 }
 
 /// An ANSI control sequence.
@@ -36,24 +38,249 @@ pub struct AnsiSequence<'a> {
 /// ```
 #[derive(Eq, PartialEq, Debug)]
 pub struct ControlSequence<'a> {
-    parameters: &'a str,
-    intermediates: &'a str,
-    finalizer: &'a str,
+    pub(crate) parameters: &'a str,
+    pub(crate) intermediates: &'a str,
+    pub(crate) finalizer: &'a str,
 }
 
 /// A variable-length string, as defined in the ANSI standard.
 ///
 /// This is implicitly created by a preceding sequence.
-/// The string is terminated by `ESC '\'`, or `BEL`.
+/// The string is terminated by `ESC '\'`, its 8-bit form (`U+009C`), or `BEL`.
 #[derive(Eq, PartialEq, Debug)]
 pub struct AnsiString<'a> {
-    text: &'a str,
-    finalizer: &'a str,
+    pub(crate) text: &'a str,
+    pub(crate) finalizer: &'a str,
+}
+
+/// A single parameter of a [ControlSequence].
+///
+/// Parameters are separated by `;`, and may be further divided into sub-parameters with `:`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Param<'a> {
+    /// An omitted parameter.
+    /// The command's default value should be used in its place.
+    Empty,
+
+    /// A numeric parameter.
+    /// Values that do not fit in a `u16` are saturated.
+    Int(u16),
+
+    /// A parameter made of colon-separated sub-parameters (e.g. `38:2::255:0:0`).
+    Sub(SubParams<'a>),
+}
+
+/// An iterator over the parameters of a [ControlSequence].
+#[derive(Clone, Debug)]
+pub struct Params<'a> {
+    remaining: Option<&'a str>,
+}
+
+/// An iterator over the colon-separated sub-parameters of a [Param::Sub].
+///
+/// Omitted sub-parameters are yielded as `None`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SubParams<'a> {
+    remaining: Option<&'a str>,
 }
 
 // -------------------------------------------------------------------------------------------------
 
-trait Parse<'a> {
+impl<'a> AnsiSequence<'a> {
+    /// Gets the intermediate bytes.
+    #[inline]
+    pub fn intermediates(&self) -> &'a str {
+        self.intermediates
+    }
+
+    /// Gets the finalizer byte.
+    #[inline]
+    pub fn finalizer(&self) -> &'a str {
+        self.finalizer
+    }
+}
+
+impl<'a> AnsiString<'a> {
+    /// Gets the text of the string, excluding the terminator.
+    #[inline]
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+
+    /// Gets the string terminator (`ESC \` or `BEL`).
+    ///
+    /// An 8-bit terminator (`U+009C`) is returned as `ESC \`.
+    #[inline]
+    pub fn finalizer(&self) -> &'a str {
+        self.finalizer
+    }
+
+    /// Splits the text of an OSC (operating system command) string into its numeric command and
+    /// the data following it.
+    ///
+    /// Returns `None` if the text doesn't start with a number.
+    pub fn command(&self) -> Option<(u16, &'a str)> {
+        let (command, data) = match self.text.find(';') {
+            Some(i) => (&self.text[..i], &self.text[(i + 1)..]),
+            None => (self.text, ""),
+        };
+
+        Some((parse_int(command)?, data))
+    }
+}
+
+impl<'a> ControlSequence<'a> {
+    /// Gets the raw parameter bytes, including any private marker.
+    #[inline]
+    pub fn parameters(&self) -> &'a str {
+        self.parameters
+    }
+
+    /// Gets the intermediate bytes.
+    #[inline]
+    pub fn intermediates(&self) -> &'a str {
+        self.intermediates
+    }
+
+    /// Gets the finalizer byte.
+    #[inline]
+    pub fn finalizer(&self) -> &'a str {
+        self.finalizer
+    }
+
+    /// Gets the private marker (`<`, `=`, `>`, or `?`) that prefixes the parameters, if any.
+    pub fn private_marker(&self) -> Option<char> {
+        match self.parameters.chars().next() {
+            Some(c @ '\x3C'..='\x3F') => Some(c),
+            _ => None,
+        }
+    }
+
+    /// Checks if the sequence is reserved for private use, by having a private marker or a
+    /// finalizer from `p` to `~` (0x70 to 0x7E).
+    ///
+    /// The meaning of these sequences depends on the terminal, e.g. `CSI > 4 ; 2 m` sets xterm's
+    /// `modifyOtherKeys` instead of the style.
+    pub fn is_private_use(&self) -> bool {
+        self.private_marker().is_some() || matches!(self.finalizer.as_bytes(), [0x70..=0x7E])
+    }
+
+    /// Returns an iterator over the parsed parameters.
+    ///
+    /// The private marker is skipped, and an empty parameter string yields no parameters.
+    /// This does not allocate.
+    pub fn params(&self) -> Params<'a> {
+        let parameters = match self.private_marker() {
+            Some(_) => &self.parameters[1..],
+            None => self.parameters,
+        };
+
+        Params {
+            remaining: if parameters.is_empty() { None } else { Some(parameters) },
+        }
+    }
+}
+
+impl<'a> Param<'a> {
+    /// Gets the numeric value of the parameter.
+    ///
+    /// For a parameter with sub-parameters, this is the value of the first sub-parameter.
+    /// Returns `None` if the parameter was omitted.
+    pub fn value(&self) -> Option<u16> {
+        match self {
+            Param::Empty => None,
+            Param::Int(n) => Some(*n),
+            Param::Sub(sub) => sub.clone().next().flatten(),
+        }
+    }
+
+    /// Gets the numeric value of the parameter, or a default if it was omitted.
+    #[inline]
+    pub fn value_or(&self, default: u16) -> u16 {
+        self.value().unwrap_or(default)
+    }
+}
+
+impl<'a> Iterator for Params<'a> {
+    type Item = Param<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let remaining = self.remaining?;
+        let (param, rest) = match remaining.find(';') {
+            Some(i) => (&remaining[..i], Some(&remaining[(i + 1)..])),
+            None => (remaining, None),
+        };
+
+        self.remaining = rest;
+        Some(if param.contains(':') {
+            Param::Sub(SubParams {
+                remaining: Some(param),
+            })
+        } else {
+            match parse_int(param) {
+                Some(n) => Param::Int(n),
+                None => Param::Empty,
+            }
+        })
+    }
+}
+
+impl<'a> Iterator for SubParams<'a> {
+    type Item = Option<u16>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let remaining = self.remaining?;
+        let (param, rest) = match remaining.find(':') {
+            Some(i) => (&remaining[..i], Some(&remaining[(i + 1)..])),
+            None => (remaining, None),
+        };
+
+        self.remaining = rest;
+        Some(parse_int(param))
+    }
+}
+
+impl<'a> TryFrom<&'a str> for Sequence<'a> {
+    type Error = Error;
+
+    /// Parses a string containing exactly one escape sequence.
+    fn try_from(value: &'a str) -> Result<Self> {
+        let mut lexer = Lexer::new(value);
+        let sequence = Sequence::parse(&mut lexer)?;
+
+        if !lexer.is_empty() {
+            return Err(Error::InvalidSequence);
+        }
+
+        Ok(sequence)
+    }
+}
+
+impl<'a> Display for Sequence<'a> {
+    /// Writes the sequence exactly as it was parsed, except that 8-bit (C1) controls are written as
+    /// their 7-bit equivalents (e.g. `U+009B` as `ESC [`).
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Sequence::CSI(csi) => write!(
+                f,
+                "\x1B[{}{}{}",
+                csi.parameters, csi.intermediates, csi.finalizer
+            ),
+            Sequence::OSC(seq, string)
+            | Sequence::DCS(seq, string)
+            | Sequence::APC(seq, string) => write!(
+                f,
+                "\x1B{}{}{}{}",
+                seq.intermediates, seq.finalizer, string.text, string.finalizer
+            ),
+            Sequence::Regular(seq) => write!(f, "\x1B{}{}", seq.intermediates, seq.finalizer),
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+pub(crate) trait Parse<'a> {
     fn parse(lexer: &mut Lexer<'a>) -> Result<Self>
     where
         Self: Sized;
@@ -62,7 +289,13 @@ trait Parse<'a> {
 impl<'a> Parse<'a> for AnsiSequence<'a> {
     fn parse(lexer: &mut Lexer<'a>) -> Result<Self> {
         lexer.extract_one(is_sequence_opener)?;
+        AnsiSequence::parse_after_opener(lexer)
+    }
+}
 
+impl<'a> AnsiSequence<'a> {
+    /// Parses the rest of a regular sequence, after its opener (`ESC`).
+    fn parse_after_opener(lexer: &mut Lexer<'a>) -> Result<Self> {
         Ok(AnsiSequence {
             intermediates: lexer.extract(is_sequence_intermediate)?,
             finalizer: lexer.extract_one_greedy(is_sequence_finalizer)?,
@@ -73,29 +306,34 @@ impl<'a> Parse<'a> for AnsiSequence<'a> {
 impl<'a> Parse<'a> for ControlSequence<'a> {
     fn parse(lexer: &mut Lexer<'a>) -> Result<Self> {
         lexer.extract_one(is_sequence_opener)?;
+        ControlSequence::parse_after_opener(lexer)
+    }
+}
 
+impl<'a> ControlSequence<'a> {
+    /// Parses the rest of a control sequence, after its opener (`ESC`).
+    fn parse_after_opener(lexer: &mut Lexer<'a>) -> Result<Self> {
         match lexer.extract_one_greedy(is_csi_finalizer)? {
-            "[" => Ok(ControlSequence {
-                parameters: lexer.extract(is_csi_parameter)?,
-                intermediates: lexer.extract(is_csi_intermediate)?,
-                finalizer: lexer.extract_one_greedy(is_csi_finalizer)?,
-            }),
+            "[" => ControlSequence::parse_body(lexer),
             _ => Err(Error::InvalidSequence),
         }
-        
+    }
+
+    /// Parses the body of a control sequence, after its introducer (`ESC [` or `U+009B`).
+    fn parse_body(lexer: &mut Lexer<'a>) -> Result<Self> {
+        let (csi, len) = scan_csi(lexer.remaining())?;
+        lexer.extract_len(len)?;
+        Ok(csi)
     }
 }
 
 impl<'a> Parse<'a> for AnsiString<'a> {
     fn parse(lexer: &mut Lexer<'a>) -> Result<Self> {
-        let text = lexer.extract(|c| !is_st_opener(c))?;
-        let finalizer = match lexer.extract_one_greedy(is_st_opener)? {
+        let text = lexer.extract(|c| !is_st_opener(c) && c != '\u{9C}')?;
+        let finalizer = match lexer.extract_one_greedy(|c| is_st_opener(c) || c == '\u{9C}')? {
             "\x07" => "\x07",
-            "\x1B" => if lexer.extract_one_greedy(|c| c == '\\')? == "\\" {
-                "\x1B\\"
-            } else {
-                return Err(Error::InvalidSequence);
-            },
+            "\u{9C}" => "\x1B\\",
+            "\x1B" if lexer.extract_one_greedy(|c| c == '\\')? == "\\" => "\x1B\\",
             _ => return Err(Error::InvalidSequence),
         };
 
@@ -106,93 +344,335 @@ impl<'a> Parse<'a> for AnsiString<'a> {
     }
 }
 
+impl<'a> AnsiString<'a> {
+    /// Parses the string of a device control string (DCS) sequence.
+    ///
+    /// Unlike other strings, this is only terminated by `ESC \`. Doubled `ESC` characters are
+    /// part of the string, since tmux uses them to escape the sequences wrapped in its passthrough
+    /// sequence (`DCS tmux; ... ST`).
+    fn parse_dcs(lexer: &mut Lexer<'a>) -> Result<Self> {
+        let bytes = lexer.remaining().as_bytes();
+        let mut len = 0;
+        loop {
+            let found = bytes[len..].iter().position(|&b| b == 0x1B || b == 0xC2);
+            len += found.ok_or(Error::Incomplete)?;
+            match (bytes[len], bytes.get(len + 1)) {
+                (_, None) => return Err(Error::Incomplete),
+                (0x1B, Some(b'\\')) | (0xC2, Some(0x9C)) => break,
+                (0x1B, Some(0x1B)) => len += 2,
+                (0xC2, Some(_)) => len += 1,
+                (_, Some(_)) => return Err(Error::InvalidSequence),
+            }
+        }
+
+        // Both terminators (`ESC \` and `U+009C`) are two bytes long.
+        let text = lexer.extract_len(len)?;
+        lexer.extract_len(2)?;
+        Ok(AnsiString {
+            text,
+            finalizer: "\x1B\\",
+        })
+    }
+}
+
 impl<'a> Parse<'a> for Sequence<'a> {
     fn parse(lexer: &mut Lexer<'a>) -> Result<Self> {
-        let mut lookahead_lexer = lexer.clone();
-        let lookahead = AnsiSequence::parse(&mut lookahead_lexer)?;
-        
-        if lookahead.intermediates.is_empty() {
-            return Ok(match lookahead.finalizer {
-                "[" => Sequence::CSI(ControlSequence::parse(lexer)?), 
-                "]" => Sequence::OSC(AnsiSequence::parse(lexer)?, AnsiString::parse(lexer)?),
-                _ => Sequence::Regular(AnsiSequence::parse(lexer)?)
-            })
+        // The sequence is parsed in a single pass. Only the character following the opener is
+        // looked at ahead of time, since it determines how the rest is parsed.
+        let opener = lexer.extract_one(|c| is_sequence_opener(c) || is_c1_control(c))?;
+        let seq = match c1_equivalent(opener) {
+            Some("[") => return Ok(Sequence::CSI(ControlSequence::parse_body(lexer)?)),
+            Some(finalizer) => AnsiSequence { intermediates: "", finalizer },
+            None if lexer.peek_one(|_| true)? == "[" => {
+                return Ok(Sequence::CSI(ControlSequence::parse_after_opener(lexer)?));
+            }
+            None => AnsiSequence::parse_after_opener(lexer)?,
+        };
+
+        if !seq.intermediates.is_empty() {
+            return Ok(Sequence::Regular(seq));
         }
-        
-        Ok(Sequence::Regular(AnsiSequence::parse(lexer)?))
+
+        Ok(match seq.finalizer {
+            "]" => Sequence::OSC(seq, AnsiString::parse(lexer)?),
+            "P" => Sequence::DCS(seq, AnsiString::parse_dcs(lexer)?),
+            "_" => Sequence::APC(seq, AnsiString::parse(lexer)?),
+            _ => Sequence::Regular(seq),
+        })
     }
 }
 
 
 // -------------------------------------------------------------------------------------------------
 
+/// The classification of a character within an ANSI escape sequence.
+///
+/// A character may belong to more than one class (e.g. `0x30` to `0x3F` are both CSI parameters
+/// and regular sequence finalizers), so classes are represented as a set of flags.
+/// Classification is a single lookup into a 256-entry table built at compile time.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default)]
+pub struct CharClass(u8);
+
+impl CharClass {
+    /// No class.
+    pub const NONE: CharClass = CharClass(0);
+
+    /// The ESC character, which opens an escape sequence.
+    pub const OPENER: CharClass = CharClass(1 << 0);
+
+    /// A sequence intermediate byte, between 0x20 and 0x2F inclusive.
+    pub const INTERMEDIATE: CharClass = CharClass(1 << 1);
+
+    /// A sequence finalizer byte, between 0x30 and 0x7E inclusive.
+    pub const FINALIZER: CharClass = CharClass(1 << 2);
+
+    /// A control sequence parameter byte, between 0x30 and 0x3F inclusive.
+    pub const CSI_PARAMETER: CharClass = CharClass(1 << 3);
+
+    /// A control sequence finalizer byte, between 0x40 and 0x7E inclusive.
+    pub const CSI_FINALIZER: CharClass = CharClass(1 << 4);
+
+    /// An opener of the string terminator (`ESC` or `BEL`).
+    pub const ST_OPENER: CharClass = CharClass(1 << 5);
+
+    /// Classifies a character.
+    #[inline(always)]
+    pub const fn of(c: char) -> CharClass {
+        if (c as u32) < 256 {
+            CLASS_TABLE[c as usize]
+        } else {
+            CharClass::NONE
+        }
+    }
+
+    /// Classifies a byte.
+    #[inline(always)]
+    pub const fn of_byte(b: u8) -> CharClass {
+        CLASS_TABLE[b as usize]
+    }
+
+    /// Returns `true` if this contains every class in `other`.
+    #[inline(always)]
+    pub const fn contains(self, other: CharClass) -> bool {
+        self.0 & other.0 == other.0 && other.0 != 0
+    }
+
+    /// Returns the union of two sets of classes.
+    #[inline(always)]
+    pub const fn union(self, other: CharClass) -> CharClass {
+        CharClass(self.0 | other.0)
+    }
+}
+
+/// The lookup table used by [CharClass::of].
+static CLASS_TABLE: [CharClass; 256] = build_class_table();
+
+const fn build_class_table() -> [CharClass; 256] {
+    let mut table = [CharClass::NONE; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut class = CharClass::NONE;
+        if i == 0x1B {
+            class = class.union(CharClass::OPENER).union(CharClass::ST_OPENER);
+        }
+        if i == 0x07 {
+            class = class.union(CharClass::ST_OPENER);
+        }
+        if i >= 0x20 && i <= 0x2F {
+            class = class.union(CharClass::INTERMEDIATE);
+        }
+        if i >= 0x30 && i <= 0x7E {
+            class = class.union(CharClass::FINALIZER);
+        }
+        if i >= 0x30 && i <= 0x3F {
+            class = class.union(CharClass::CSI_PARAMETER);
+        }
+        if i >= 0x40 && i <= 0x7E {
+            class = class.union(CharClass::CSI_FINALIZER);
+        }
+
+        table[i] = class;
+        i += 1;
+    }
+
+    table
+}
+
 /// Checks if a character is an ANSI sequence opener.
 ///
 /// The opener is the ESC control character, and denotes the beginning of an escape sequence.
 /// It is followed by zero or more intermediate bytes, which are followed by a finalizer.
+#[inline]
 pub(crate) fn is_sequence_opener(c: char) -> bool {
     c == '\x1B'
 }
 
+/// Checks if a character is an 8-bit (C1) control character, from `U+0080` to `U+009F`.
+///
+/// Each of these is equivalent to `ESC` followed by a character from `@` to `_`, e.g. `U+009B` is
+/// the same as `ESC [` (CSI).
+#[inline]
+pub(crate) fn is_c1_control(c: char) -> bool {
+    matches!(c, '\u{80}'..='\u{9F}')
+}
+
+/// Gets the finalizer of the 7-bit equivalent of a C1 control character.
+///
+/// Returns `None` if the string doesn't start with a C1 control character.
+fn c1_equivalent(s: &str) -> Option<&'static str> {
+    const FINALIZERS: &str = "@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_";
+    let i = (s.chars().next()? as usize).checked_sub(0x80)?;
+    FINALIZERS.get(i..=i)
+}
+
 /// Checks if a character is an ANSI sequence finalizer byte.
 ///
 /// The finalizer is an ASCII character between 0x30 and 0x7E inclusive, and it
 /// denotes the end of an ANSI escape sequence.
+#[inline]
 pub(crate) fn is_sequence_finalizer(c: char) -> bool {
-    match c {
-        '\x30'..='\x7E' => true,
-        _ => false,
-    }
+    CharClass::of(c).contains(CharClass::FINALIZER)
 }
 
 /// Checks if a character is an ANSI sequence intermediate byte.
 ///
 /// Intermediate bytes are ASCII characters between 0x20 and 0x2F inclusive.
 /// Zero or more may be located between the escape character and the finalizer.
+#[inline]
 pub(crate) fn is_sequence_intermediate(c: char) -> bool {
-    match c {
-        '\x20'..='\x2F' => true,
-        _ => false,
-    }
+    CharClass::of(c).contains(CharClass::INTERMEDIATE)
 }
 
 /// Checks if a character is an ANSI control sequence finalizer byte.
 ///
 /// The control sequence finalizer is an ASCII character between 0x40 and 0x7E inclusive.
 /// It is used similarly to the regular ANSI finalizer, but specifically for CSI commands.
+#[inline]
 pub(crate) fn is_csi_finalizer(c: char) -> bool {
-    match c {
-        '\x40'..='\x7E' => true,
-        _ => false,
-    }
+    CharClass::of(c).contains(CharClass::CSI_FINALIZER)
 }
 
 /// Checks if a character is an ANSI control sequence parameter byte.
 ///
 /// The control sequence parameter is an ASCII character between 0x30 and 0x3F inclusive.
 /// It is used similarly to the regular ANSI finalizer, but specifically for CSI commands.
+#[inline]
 pub(crate) fn is_csi_parameter(c: char) -> bool {
-    match c {
-        '\x30'..='\x3F' => true,
-        _ => false,
-    }
+    CharClass::of(c).contains(CharClass::CSI_PARAMETER)
 }
 
 /// Checks if a character is an ANSI control sequence intermediate byte.
 ///
 /// Intermediate bytes are ASCII characters between 0x20 and 0x2F inclusive.
+#[inline]
 pub(crate) fn is_csi_intermediate(c: char) -> bool {
-    match c {
-        '\x20'..='\x2F' => true,
-        _ => false,
-    }
+    CharClass::of(c).contains(CharClass::INTERMEDIATE)
 }
 
 /// Checks if a character is an opener for the ST sequence.
 /// 
 /// On xterm, this is either `ESC \`, or BEL. 
+#[inline]
 pub(crate) fn is_st_opener(c: char) -> bool {
-    c == '\x07' || is_sequence_opener(c)
+    CharClass::of(c).contains(CharClass::ST_OPENER)
+}
+
+/// Validates and slices a control sequence body (the characters following `ESC [`).
+///
+/// This is a fast path for parsing control sequences. Rather than classifying one character at a
+/// time, it checks the raw bytes of the parameters in bulk. With the `simd` feature (nightly only),
+/// the bytes are checked using `std::simd`.
+///
+/// ## Returns
+///
+/// The parsed control sequence and the number of bytes it occupied in `body`.
+/// If `body` ends before the finalizer, this returns [Error::Incomplete].
+pub fn scan_csi(body: &str) -> Result<(ControlSequence<'_>, usize)> {
+    let bytes = body.as_bytes();
+    let parameters = count_parameter_bytes(bytes);
+    let intermediates = bytes[parameters..]
+        .iter()
+        .take_while(|&&b| is_csi_intermediate(char::from(b)))
+        .count();
+
+    let end = parameters + intermediates;
+    match bytes.get(end) {
+        None => Err(Error::Incomplete),
+        Some(&b) if CharClass::of_byte(b).contains(CharClass::CSI_FINALIZER) => Ok((
+            ControlSequence {
+                parameters: &body[..parameters],
+                intermediates: &body[parameters..end],
+                finalizer: &body[end..=end],
+            },
+            end + 1,
+        )),
+        Some(_) => Err(Error::InvalidSequence),
+    }
+}
+
+/// Counts the number of leading control sequence parameter bytes.
+///
+/// Parameter bytes are exactly the bytes with a high nibble of `3`, which allows them to be
+/// checked eight at a time inside a `u64`.
+#[cfg(not(feature = "simd"))]
+fn count_parameter_bytes(bytes: &[u8]) -> usize {
+    let mut count = 0;
+    let mut chunks = bytes.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(chunk);
+
+        let mismatched = (u64::from_le_bytes(word) & 0xF0F0_F0F0_F0F0_F0F0) ^ 0x3030_3030_3030_3030;
+        if mismatched != 0 {
+            return count + (mismatched.trailing_zeros() / 8) as usize;
+        }
+
+        count += 8;
+    }
+
+    count + count_parameter_bytes_scalar(chunks.remainder())
+}
+
+/// Counts the number of leading control sequence parameter bytes.
+///
+/// Parameter bytes are exactly the bytes with a high nibble of `3`, which allows them to be
+/// checked sixteen at a time using SIMD.
+#[cfg(feature = "simd")]
+fn count_parameter_bytes(bytes: &[u8]) -> usize {
+    use std::simd::prelude::*;
+
+    let mut count = 0;
+    let mut chunks = bytes.chunks_exact(16);
+    for chunk in &mut chunks {
+        let nibbles = u8x16::from_slice(chunk) & u8x16::splat(0xF0);
+        if let Some(i) = nibbles.simd_ne(u8x16::splat(0x30)).first_set() {
+            return count + i;
+        }
+
+        count += 16;
+    }
+
+    count + count_parameter_bytes_scalar(chunks.remainder())
+}
+
+#[inline]
+fn count_parameter_bytes_scalar(bytes: &[u8]) -> usize {
+    bytes.iter().take_while(|&&b| is_csi_parameter(char::from(b))).count()
+}
+
+/// Parses a decimal parameter without allocating.
+///
+/// Returns `None` if the parameter is empty or contains anything other than ASCII digits.
+/// Values that do not fit in a `u16` are saturated to `u16::MAX`.
+fn parse_int(param: &str) -> Option<u16> {
+    if param.is_empty() {
+        return None;
+    }
+
+    param.bytes().try_fold(0u16, |acc, b| match b {
+        b'0'..=b'9' => Some(acc.saturating_mul(10).saturating_add(u16::from(b - b'0'))),
+        _ => None,
+    })
 }
 
 
@@ -331,5 +811,139 @@ mod tests {
         // Ensure nothing is left to read.
         assert!(lex.is_empty());
     }
-    
+
+    #[test]
+    fn osc_command() {
+        let string = |text| AnsiString { text, finalizer: "\x07" };
+        assert_eq!(string("0;title;with;semicolons").command(), Some((0, "title;with;semicolons")));
+        assert_eq!(string("52;c;dGV4dA==").command(), Some((52, "c;dGV4dA==")));
+        assert_eq!(string("104").command(), Some((104, "")));
+        assert_eq!(string("L;title").command(), None);
+    }
+
+    #[test]
+    fn parse_device_control_string() {
+        // The payload of a sixel image overlaps with the CSI parameter and finalizer bytes.
+        let mut lex = Lexer::new("\x1BP0;1;0q\"1;1;2;2#0;2;0;0;0#0!2~-~~\x1B\\\x1B[m");
+        assert_eq!(
+            Sequence::parse(&mut lex),
+            Ok(Sequence::DCS(AnsiSequence {
+                intermediates: "",
+                finalizer: "P",
+            }, AnsiString {
+                text: "0;1;0q\"1;1;2;2#0;2;0;0;0#0!2~-~~",
+                finalizer: "\x1B\\"
+            }))
+        );
+
+        assert_eq!(lex.remaining(), "\x1B[m");
+        assert_eq!(Sequence::try_from("\x1BPq#0"), Err(Error::Incomplete));
+
+        // Doubled escapes and BEL don't end the string.
+        let tmux = "\x1BPtmux;\x1B\x1B]0;title\x07\x1B\\";
+        match Sequence::try_from(tmux) {
+            Ok(Sequence::DCS(_, string)) => assert_eq!(string.text(), "tmux;\x1B\x1B]0;title\x07"),
+            other => panic!("not a DCS sequence: {:?}", other),
+        }
+
+        assert_eq!(Sequence::try_from("\x1BPtmux;\x1B[m\x1B\\"), Err(Error::InvalidSequence));
+    }
+
+    #[test]
+    fn parse_application_program_command() {
+        let kitty = "\x1B_Gf=100,a=T;iVBORw0KGgo=\x1B\\";
+        assert_eq!(
+            Sequence::try_from(kitty),
+            Ok(Sequence::APC(AnsiSequence {
+                intermediates: "",
+                finalizer: "_",
+            }, AnsiString {
+                text: "Gf=100,a=T;iVBORw0KGgo=",
+                finalizer: "\x1B\\"
+            }))
+        );
+
+        assert_eq!(Sequence::try_from(kitty).unwrap().to_string(), kitty);
+    }
+
+    #[test]
+    fn char_class_table() {
+        // Ensure the table agrees with the ranges in ECMA-48.
+        for i in 0..=0xFFu8 {
+            let c = char::from(i);
+            let class = CharClass::of(c);
+            assert_eq!(class, CharClass::of_byte(i));
+            assert_eq!(class.contains(CharClass::OPENER), i == 0x1B);
+            assert_eq!(class.contains(CharClass::ST_OPENER), i == 0x1B || i == 0x07);
+            assert_eq!(class.contains(CharClass::INTERMEDIATE), (0x20..=0x2F).contains(&i));
+            assert_eq!(class.contains(CharClass::FINALIZER), (0x30..=0x7E).contains(&i));
+            assert_eq!(class.contains(CharClass::CSI_PARAMETER), (0x30..=0x3F).contains(&i));
+            assert_eq!(class.contains(CharClass::CSI_FINALIZER), (0x40..=0x7E).contains(&i));
+        }
+
+        // Ensure non-Latin-1 characters are unclassified.
+        assert_eq!(CharClass::of('\u{1B1B}'), CharClass::NONE);
+        assert!(!CharClass::NONE.contains(CharClass::NONE));
+    }
+
+    #[test]
+    fn scan_csi_bodies() {
+        // Short and long parameter lists.
+        let long = "38;2;255;255;255;48;2;0;0;0m";
+        assert_eq!(scan_csi("1mtext").map(|(csi, len)| (csi.parameters, len)), Ok(("1", 2)));
+        assert_eq!(
+            scan_csi(long).map(|(csi, len)| (csi.parameters, len)),
+            Ok((&long[..(long.len() - 1)], long.len()))
+        );
+
+        // Intermediates.
+        let (csi, len) = scan_csi("2 q").unwrap();
+        assert_eq!((csi.parameters, csi.intermediates, csi.finalizer, len), ("2", " ", "q", 3));
+
+        // Invalid and incomplete bodies.
+        assert_eq!(scan_csi("1;2\x07"), Err(Error::InvalidSequence));
+        assert_eq!(scan_csi("1;2;3;4;5;6;7;8"), Err(Error::Incomplete));
+        assert_eq!(scan_csi(""), Err(Error::Incomplete));
+    }
+
+    #[test]
+    fn csi_params() {
+        let csi = |s| match Sequence::try_from(s) {
+            Ok(Sequence::CSI(csi)) => csi,
+            other => panic!("not a CSI sequence: {:?}", other),
+        };
+
+        // No parameters.
+        assert_eq!(csi("\x1B[m").params().next(), None);
+
+        // Integer and empty parameters.
+        assert_eq!(
+            csi("\x1B[1;;033;m").params().collect::<Vec<_>>(),
+            vec![Param::Int(1), Param::Empty, Param::Int(33), Param::Empty]
+        );
+
+        // Saturated parameters.
+        assert_eq!(csi("\x1B[99999999A").params().next(), Some(Param::Int(u16::MAX)));
+
+        // Private marker.
+        let seq = csi("\x1B[?25h");
+        assert_eq!(seq.private_marker(), Some('?'));
+        assert_eq!(seq.params().collect::<Vec<_>>(), vec![Param::Int(25)]);
+
+        // Sub-parameters.
+        let params = csi("\x1B[38:2::255:0:10;4m").params().collect::<Vec<_>>();
+        assert_eq!(params.len(), 2);
+        assert_eq!(params[1], Param::Int(4));
+        match &params[0] {
+            Param::Sub(sub) => assert_eq!(
+                sub.clone().collect::<Vec<_>>(),
+                vec![Some(38), Some(2), None, Some(255), Some(0), Some(10)]
+            ),
+            other => panic!("not a sub-parameter: {:?}", other),
+        }
+
+        // Default values.
+        assert_eq!(Param::Empty.value_or(1), 1);
+        assert_eq!(params[0].value_or(1), 38);
+    }
 }
@@ -0,0 +1,123 @@
+//! Detecting right-to-left text and bidirectional formatting characters.
+//!
+//! Terminals lay text out in cells from left to right, but some of them reorder right-to-left
+//! text for display, and follow the explicit embeddings and isolates in it. An embedding that is
+//! still open where [wrap](crate::wrap) breaks a line or [slice_visible](crate::slice_visible)
+//! cuts one off changes the direction of whatever comes after it. The [Bidi] option says what
+//! these helpers do about that.
+//!
+//! Bidirectional formatting characters never take up cells, so they don't change where the
+//! cursor is.
+//!
+//! ```
+//! # use ansi_optimizer::bidi::contains_rtl;
+//! assert!(contains_rtl("\x1B[1mשלום\x1B[m"));
+//! assert!(!contains_rtl("hello"));
+//! ```
+
+use crate::token::{Token, Tokens};
+
+#[cfg(feature = "optimize")]
+pub use crate::options::Bidi;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Checks if a character is a bidirectional formatting character.
+///
+/// These are the marks (`ALM`, `LRM`, and `RLM`), embeddings and overrides (`LRE`, `RLE`, `PDF`,
+/// `LRO`, and `RLO`), and isolates (`LRI`, `RLI`, `FSI`, and `PDI`).
+pub fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}')
+        || matches!(c, '\u{2066}'..='\u{2069}')
+}
+
+/// Checks if a character is written from right to left (e.g. Hebrew or Arabic).
+pub fn is_rtl(c: char) -> bool {
+    matches!(c, '\u{0590}'..='\u{08FF}' | '\u{FB1D}'..='\u{FDFF}' | '\u{FE70}'..='\u{FEFF}')
+        || matches!(c, '\u{10800}'..='\u{10FFF}' | '\u{1E800}'..='\u{1EFFF}')
+}
+
+/// Checks if the text of a string contains right-to-left characters or bidirectional formatting
+/// characters. Escape sequences are ignored.
+pub fn contains_rtl(input: &str) -> bool {
+    Tokens::new(input).any(|token| match token {
+        Ok(Token::Text(text)) => text.chars().any(|c| is_rtl(c) || is_bidi_control(c)),
+        _ => false,
+    })
+}
+
+/// The embeddings, overrides, and isolates that are open at some point in a line.
+#[cfg(feature = "optimize")]
+#[derive(Clone, Default, Debug)]
+pub(crate) struct Embeddings {
+    open: Vec<char>,
+}
+
+#[cfg(feature = "optimize")]
+impl Embeddings {
+    /// Updates the open embeddings after a character.
+    pub fn update(&mut self, c: char) {
+        match c {
+            '\u{202A}' | '\u{202B}' | '\u{202D}' | '\u{202E}' | '\u{2066}'..='\u{2068}' => {
+                self.open.push(c)
+            }
+            '\u{202C}' if matches!(self.open.last(), Some(&c) if !is_isolate(c)) => {
+                self.open.pop();
+            }
+            '\u{2069}' => {
+                if let Some(i) = self.open.iter().rposition(|&c| is_isolate(c)) {
+                    self.open.truncate(i);
+                }
+            }
+            '\n' => self.open.clear(),
+            _ => {}
+        }
+    }
+
+    /// Gets the characters that close the open embeddings.
+    pub fn close(&self) -> String {
+        let closer = |&c| if is_isolate(c) { '\u{2069}' } else { '\u{202C}' };
+        self.open.iter().rev().map(closer).collect()
+    }
+
+    /// Gets the characters that open the open embeddings again.
+    pub fn reopen(&self) -> String {
+        self.open.iter().collect()
+    }
+}
+
+/// Checks if a character starts an isolate.
+#[cfg(feature = "optimize")]
+fn is_isolate(c: char) -> bool {
+    matches!(c, '\u{2066}'..='\u{2068}')
+}
+
+/// Gets the bidirectional formatting character that a grapheme cluster is made of, if any.
+pub(crate) fn bidi_control(cluster: &str) -> Option<char> {
+    let mut chars = cluster.chars();
+    chars.next().filter(|&c| is_bidi_control(c) && chars.next().is_none())
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::bidi::*;
+
+    #[cfg(feature = "optimize")]
+    #[test]
+    fn track_embeddings() {
+        let mut embeddings = Embeddings::default();
+        for c in "\u{202B}a\u{2067}b\u{202E}c".chars() {
+            embeddings.update(c);
+        }
+
+        assert_eq!(embeddings.reopen(), "\u{202B}\u{2067}\u{202E}");
+        assert_eq!(embeddings.close(), "\u{202C}\u{2069}\u{202C}");
+
+        embeddings.update('\u{2069}');
+        assert_eq!(embeddings.reopen(), "\u{202B}");
+        embeddings.update('\n');
+        assert_eq!(embeddings.close(), "");
+    }
+}
@@ -0,0 +1,658 @@
+//! Optimizes the ANSI escape sequences in a file or standard input.
+
+use ansi_optimizer::style::{Attributes, StyleMapper};
+use ansi_optimizer::token::Tokens;
+use ansi_optimizer::writer::Writer;
+use ansi_optimizer::{
+    ColorEncoding, ColorSupport, Error, Hyperlinks, Level, Options, Profile, Queries,
+};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::process::exit;
+
+// -------------------------------------------------------------------------------------------------
+
+const USAGE: &str = "\
+Usage: ansi-opt [OPTIONS] [FILE]
+       ansi-opt lint [--format=FORMAT] [FILE]
+       ansi-opt tokens [FILE]
+
+Optimizes the ANSI escape sequences in FILE, or standard input if no file is given.
+Input compressed with gzip or zstd is decompressed automatically.
+
+The lint command reports malformed escape sequences and bytes instead, and exits with
+status 1 if it finds any. The tokens command prints the text and escape sequences of the
+input as JSON, one per line.
+
+Options:
+  -O1, -O2, -O3       Set the optimization level: lossless, standard, or aggressive
+      --blink=MODE    Replace blinking text with: keep, bold, reverse, or none
+      --color=WHEN    Convert colors for a terminal that supports: never, 16, 256, truecolor,
+                      or auto to detect it from the environment ($TERM, $COLORTERM, $NO_COLOR)
+      --color-separator=SEP
+                      Write 256-color and RGB colors separated by: semicolon, colon
+      --columns=N     Set the width of the terminal
+      --compress=FORMAT
+                      Compress the output with: gzip, zstd
+      --erase-runs    Replace runs of spaces with erase sequences (requires --columns)
+      --format=FORMAT Print lint findings as: text, json
+      --hyperlinks=MODE
+                      Write hyperlinks as: keep, strip, inline (the URL after the text), or
+                      footnotes (a numbered list of URLs at the end)
+      --repeat        Replace runs of the same character with REP sequences
+      --rows=N        Set the height of the terminal
+      --sequence-cost=N
+                      Treat each escape sequence as costing N extra bytes
+      --strip-images  Remove sixel and iTerm2 inline images
+      --strip-mode=N[,N...]
+                      Remove the DEC private modes N from mode changes (e.g. 1049)
+      --strip-mouse   Remove sequences that enable mouse reporting
+      --strip-queries Remove device attribute and status report queries
+      --sanitize      Remove queries and input mode changes from untrusted output
+  -h, --help          Print this help
+";
+
+/// The parsed command-line arguments.
+#[derive(Debug, Default)]
+struct Args {
+    command: Command,
+    options: Options,
+    file: Option<String>,
+    compress: Option<Compression>,
+    format: Format,
+    help: bool,
+}
+
+/// What to do with the input.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Command {
+    Optimize,
+    Lint,
+    Tokens,
+}
+
+impl Default for Command {
+    #[inline]
+    fn default() -> Self {
+        Command::Optimize
+    }
+}
+
+/// How to print findings.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Format {
+    Text,
+    Json,
+}
+
+impl Default for Format {
+    #[inline]
+    fn default() -> Self {
+        Format::Text
+    }
+}
+
+/// A compression format for the input or output.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// The magic numbers at the start of gzip and zstd streams.
+    const GZIP_MAGIC: &'static [u8] = &[0x1F, 0x8B];
+    const ZSTD_MAGIC: &'static [u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+
+    /// Detects the compression format from the start of a stream.
+    fn detect(start: &[u8]) -> Option<Self> {
+        match start {
+            _ if start.starts_with(Self::GZIP_MAGIC) => Some(Compression::Gzip),
+            _ if start.starts_with(Self::ZSTD_MAGIC) => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Gets an error for a format that this build doesn't support.
+    #[allow(dead_code)]
+    fn unsupported(self) -> io::Error {
+        let name = match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        };
+
+        io::Error::new(io::ErrorKind::Unsupported, format!("built without {} support", name))
+    }
+}
+
+/// Parses the command-line arguments, excluding the program name.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter().peekable();
+    let mut profile = Profile::xterm();
+
+    match args.peek().map(String::as_str) {
+        Some("lint") => parsed.command = Command::Lint,
+        Some("tokens") => parsed.command = Command::Tokens,
+        _ => {}
+    }
+
+    if parsed.command != Command::Optimize {
+        args.next();
+    }
+
+    while let Some(arg) = args.next() {
+        let (name, inline_value) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => (&arg[..i], Some(arg[(i + 1)..].to_owned())),
+            _ => (arg.as_str(), None),
+        };
+
+        let mut value = || match inline_value.clone().or_else(|| args.next()) {
+            Some(value) => Ok(value),
+            None => Err(format!("missing value for {}", name)),
+        };
+
+        match name {
+            "-h" | "--help" => parsed.help = true,
+            "-O1" => parsed.options.set_level(Level::Lossless),
+            "-O2" => parsed.options.set_level(Level::Standard),
+            "-O3" => parsed.options.set_level(Level::Aggressive),
+            "--erase-runs" => parsed.options.erase_runs = true,
+            "--repeat" => parsed.options.repeat_runs = true,
+            "--strip-images" => parsed.options.strip_images = true,
+            "--strip-mouse" => parsed.options.strip_mouse = true,
+            "--strip-queries" => parsed.options.queries = Queries::Strip,
+            "--sanitize" => parsed.options.sanitize = true,
+            "--blink" => {
+                let substitute = match value()?.as_str() {
+                    "keep" => None,
+                    "bold" => Some(Attributes::BOLD),
+                    "reverse" => Some(Attributes::REVERSE),
+                    "none" => Some(Attributes::NONE),
+                    other => return Err(format!("invalid value for --blink: {}", other)),
+                };
+
+                parsed.options.style_mapper = substitute.map(StyleMapper::replace_blink);
+            }
+            "--color" => match value()?.as_str() {
+                "auto" => {
+                    let detected = Profile::detect();
+                    profile.colors = detected.colors;
+                    profile.repeat = detected.repeat;
+                    profile.hyperlinks &= detected.hyperlinks;
+                    profile.plain = detected.plain;
+                }
+                value => profile.colors = parse_color(value)?,
+            },
+            "--color-separator" => {
+                profile.color_encoding = match value()?.as_str() {
+                    "semicolon" => ColorEncoding::Semicolons,
+                    "colon" => ColorEncoding::Colons,
+                    other => return Err(format!("invalid value for --color-separator: {}", other)),
+                }
+            }
+            "--hyperlinks" => {
+                let hyperlinks = match value()?.as_str() {
+                    "keep" => None,
+                    "strip" => Some(Hyperlinks::Strip),
+                    "inline" => Some(Hyperlinks::Inline),
+                    "footnotes" => Some(Hyperlinks::Footnotes),
+                    other => return Err(format!("invalid value for --hyperlinks: {}", other)),
+                };
+
+                profile.hyperlinks = hyperlinks.is_none();
+                parsed.options.hyperlinks = hyperlinks.unwrap_or_default();
+            }
+            "--compress" => parsed.compress = Some(parse_compression(&value()?)?),
+            "--format" => {
+                parsed.format = match value()?.as_str() {
+                    "text" => Format::Text,
+                    "json" => Format::Json,
+                    other => return Err(format!("invalid value for --format: {}", other)),
+                }
+            }
+            "--columns" => match value()?.parse() {
+                Ok(columns) => parsed.options.columns = Some(columns),
+                Err(_) => return Err("invalid value for --columns".to_owned()),
+            },
+            "--rows" => match value()?.parse() {
+                Ok(rows) => parsed.options.rows = Some(rows),
+                Err(_) => return Err("invalid value for --rows".to_owned()),
+            },
+            "--strip-mode" => {
+                for mode in value()?.split(',') {
+                    match mode.parse() {
+                        Ok(mode) => parsed.options.strip_modes.push(mode),
+                        Err(_) => return Err(format!("invalid value for --strip-mode: {}", mode)),
+                    }
+                }
+            }
+            "--sequence-cost" => match value()?.parse() {
+                Ok(cost) => parsed.options.cost.sequence = cost,
+                Err(_) => return Err("invalid value for --sequence-cost".to_owned()),
+            },
+            "-" => parsed.file = None,
+            name if name.starts_with('-') => return Err(format!("unknown option {}", name)),
+            _ if parsed.file.is_some() => return Err("only one file can be given".to_owned()),
+            _ => parsed.file = Some(arg.clone()),
+        }
+    }
+
+    parsed.options.profile = profile;
+    Ok(parsed)
+}
+
+/// Parses the value of the `--color` option.
+fn parse_color(value: &str) -> Result<ColorSupport, String> {
+    match value {
+        "never" | "none" => Ok(ColorSupport::Monochrome),
+        "16" => Ok(ColorSupport::Ansi16),
+        "256" => Ok(ColorSupport::Ansi256),
+        "truecolor" | "24bit" => Ok(ColorSupport::TrueColor),
+        _ => Err(format!("invalid value for --color: {}", value)),
+    }
+}
+
+/// Parses the value of the `--compress` option.
+fn parse_compression(value: &str) -> Result<Compression, String> {
+    match value {
+        "gzip" | "gz" => Ok(Compression::Gzip),
+        "zstd" | "zst" => Ok(Compression::Zstd),
+        _ => Err(format!("invalid value for --compress: {}", value)),
+    }
+}
+
+/// Decompresses a stream if it starts with the magic number of a compression format.
+fn decompress<'a, R: Read + 'a>(input: R) -> io::Result<Box<dyn Read + 'a>> {
+    let mut input = BufReader::new(input);
+    match Compression::detect(input.fill_buf()?) {
+        None => Ok(Box::new(input)),
+        #[cfg(feature = "flate2")]
+        Some(Compression::Gzip) => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(input))),
+        #[cfg(feature = "zstd")]
+        Some(Compression::Zstd) => Ok(Box::new(zstd::stream::read::Decoder::with_buffer(input)?)),
+        #[allow(unreachable_patterns)]
+        Some(compression) => Err(compression.unsupported()),
+    }
+}
+
+/// An output stream that might be compressed.
+enum Output<W: Write> {
+    Plain(W),
+    #[cfg(feature = "flate2")]
+    Gzip(flate2::write::GzEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> Output<W> {
+    fn new(output: W, compression: Option<Compression>) -> io::Result<Self> {
+        match compression {
+            None => Ok(Output::Plain(output)),
+            #[cfg(feature = "flate2")]
+            Some(Compression::Gzip) => {
+                Ok(Output::Gzip(flate2::write::GzEncoder::new(output, Default::default())))
+            }
+            #[cfg(feature = "zstd")]
+            Some(Compression::Zstd) => {
+                Ok(Output::Zstd(zstd::stream::write::Encoder::new(output, 0)?))
+            }
+            #[allow(unreachable_patterns)]
+            Some(compression) => Err(compression.unsupported()),
+        }
+    }
+
+    /// Writes the end of the compressed stream.
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Output::Plain(output) => Ok(output),
+            #[cfg(feature = "flate2")]
+            Output::Gzip(encoder) => encoder.finish(),
+            #[cfg(feature = "zstd")]
+            Output::Zstd(encoder) => encoder.finish(),
+        }
+    }
+
+    fn inner(&mut self) -> &mut dyn Write {
+        match self {
+            Output::Plain(output) => output,
+            #[cfg(feature = "flate2")]
+            Output::Gzip(encoder) => encoder,
+            #[cfg(feature = "zstd")]
+            Output::Zstd(encoder) => encoder,
+        }
+    }
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner().flush()
+    }
+}
+
+/// Optimizes a stream of bytes.
+///
+/// Bytes that aren't valid UTF-8 are passed through unchanged.
+fn run<R: Read, W: Write>(mut input: R, output: W, options: Options) -> io::Result<()> {
+    let mut writer = Writer::with_options(BufWriter::new(output), options);
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+
+        // Only the output is flushed, since the optimizer may be holding sequences back.
+        writer.write_all(&buffer[..read])?;
+        writer.get_mut().flush()?;
+    }
+
+    writer.into_inner()?;
+    Ok(())
+}
+
+/// A malformed part of the input.
+#[derive(Debug, Eq, PartialEq)]
+struct Finding {
+    offset: usize,
+    message: &'static str,
+}
+
+/// Finds the malformed escape sequences and bytes in some input.
+fn lint(input: &[u8]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut offset = 0;
+    while offset < input.len() {
+        let rest = &input[offset..];
+        let (valid, invalid) = match std::str::from_utf8(rest) {
+            Ok(text) => (text, 0),
+            Err(error) => {
+                let valid = &rest[..error.valid_up_to()];
+                let invalid = error.error_len().unwrap_or(rest.len() - valid.len());
+                (std::str::from_utf8(valid).expect("valid utf-8"), invalid)
+            }
+        };
+
+        for (span, token) in Tokens::new(valid).spanned() {
+            let message = match token {
+                Ok(_) => continue,
+                Err(Error::InvalidSequence) => "invalid escape sequence",
+                Err(Error::Incomplete) => "incomplete escape sequence",
+                Err(Error::LimitExceeded) => "escape sequence exceeds the limits",
+            };
+
+            findings.push(Finding { offset: offset + span.start, message });
+        }
+
+        if invalid > 0 {
+            findings.push(Finding { offset: offset + valid.len(), message: "invalid UTF-8" });
+        }
+
+        offset += valid.len() + invalid;
+    }
+
+    findings
+}
+
+/// Prints the findings for an input.
+fn print_findings<W: Write>(
+    output: &mut W,
+    name: &str,
+    findings: &[Finding],
+    format: Format,
+) -> io::Result<()> {
+    for finding in findings {
+        match format {
+            Format::Text => writeln!(output, "{}:{}: {}", name, finding.offset, finding.message)?,
+            Format::Json => writeln!(
+                output,
+                "{{\"file\":{},\"offset\":{},\"message\":{}}}",
+                json_string(name),
+                finding.offset,
+                json_string(finding.message)
+            )?,
+        }
+    }
+
+    if format == Format::Text {
+        match findings.len() {
+            0 => writeln!(output, "{}: no problems found", name)?,
+            1 => writeln!(output, "{}: 1 problem found", name)?,
+            n => writeln!(output, "{}: {} problems found", name, n)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the tokens of the input as JSON.
+#[cfg(feature = "serde")]
+fn print_tokens<W: Write>(output: &mut W, input: &str) -> io::Result<()> {
+    output.write_all(ansi_optimizer::to_json_tokens(input).as_bytes())
+}
+
+/// Prints the tokens of the input as JSON.
+#[cfg(not(feature = "serde"))]
+fn print_tokens<W: Write>(_output: &mut W, _input: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "built without serde support"))
+}
+
+/// Quotes a string for JSON.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("ansi-opt: {}\n\n{}", message, USAGE);
+            exit(2);
+        }
+    };
+
+    if args.help {
+        print!("{}", USAGE);
+        return;
+    }
+
+    let stdout = io::stdout();
+    let mut found = false;
+    let result = (|| {
+        let input: Box<dyn Read> = match &args.file {
+            Some(path) => Box::new(File::open(path)?),
+            None => Box::new(io::stdin().lock()),
+        };
+
+        if args.command == Command::Lint {
+            let mut contents = Vec::new();
+            decompress(input)?.read_to_end(&mut contents)?;
+            let findings = lint(&contents);
+            let name = args.file.as_deref().unwrap_or("<stdin>");
+            found = !findings.is_empty();
+            return print_findings(&mut stdout.lock(), name, &findings, args.format);
+        }
+
+        if args.command == Command::Tokens {
+            let mut contents = Vec::new();
+            decompress(input)?.read_to_end(&mut contents)?;
+            return print_tokens(&mut stdout.lock(), &String::from_utf8_lossy(&contents));
+        }
+
+        let mut output = Output::new(stdout.lock(), args.compress)?;
+        run(decompress(input)?, &mut output, args.options)?;
+        output.finish()?.flush()
+    })();
+
+    if let Err(error) = result {
+        if error.kind() != io::ErrorKind::BrokenPipe {
+            eprintln!("ansi-opt: {}", error);
+            exit(1);
+        }
+    }
+
+    if found {
+        exit(1);
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ansi_optimizer::Style;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parse_color_flags() {
+        assert_eq!(parse(&[]).unwrap().options.profile.colors, ColorSupport::TrueColor);
+        assert_eq!(parse(&["--color=16"]).unwrap().options.profile.colors, ColorSupport::Ansi16);
+        assert_eq!(
+            parse(&["--color", "256"]).unwrap().options.profile.colors,
+            ColorSupport::Ansi256
+        );
+        assert_eq!(
+            parse(&["--color=never", "file"]).unwrap().options.profile.colors,
+            ColorSupport::Monochrome
+        );
+
+        assert!(parse(&["--color=8"]).is_err());
+        assert!(parse(&["--color"]).is_err());
+
+        let args = parse(&["--color-separator=colon"]).unwrap();
+        assert_eq!(args.options.profile.color_encoding, ColorEncoding::Colons);
+        assert!(parse(&["--color-separator=comma"]).is_err());
+    }
+
+    #[test]
+    fn parse_blink_flags() {
+        let args = parse(&["--blink=reverse"]).unwrap();
+        let blink = Style { attributes: Attributes::BLINK, ..Style::default() };
+        let mapped = args.options.style_mapper.map(|mapper| mapper.map(blink));
+        assert_eq!(mapped.map(|style| style.attributes), Some(Attributes::REVERSE));
+        assert!(parse(&["--blink", "keep"]).unwrap().options.style_mapper.is_none());
+        assert!(parse(&["--blink=fast"]).is_err());
+    }
+
+    #[test]
+    fn parse_hyperlink_flags() {
+        let args = parse(&["--hyperlinks=footnotes"]).unwrap();
+        assert!(!args.options.profile.hyperlinks);
+        assert_eq!(args.options.hyperlinks, Hyperlinks::Footnotes);
+        assert!(parse(&["--hyperlinks", "keep"]).unwrap().options.profile.hyperlinks);
+        assert!(parse(&["--hyperlinks=underline"]).is_err());
+    }
+
+    #[test]
+    fn parse_other_flags() {
+        let args = parse(&["--columns=80", "--erase-runs", "--strip-images", "file.txt"]).unwrap();
+        assert_eq!(args.options.columns, Some(80));
+        assert!(args.options.erase_runs);
+        assert!(args.options.strip_images);
+        assert_eq!(args.file.as_deref(), Some("file.txt"));
+
+        let args = parse(&["-O3", "--columns", "80", "--rows=24"]).unwrap();
+        assert!(args.options.erase_runs && args.options.strip_titles);
+        assert_eq!(args.options.rows, Some(24));
+        assert!(parse(&["--rows=tall"]).is_err());
+        assert_eq!(parse(&["--sequence-cost=4"]).unwrap().options.cost.sequence, 4);
+
+        let args = parse(&["--strip-mode=1049,2004", "--strip-mode", "1"]).unwrap();
+        assert_eq!(args.options.strip_modes, vec![1049, 2004, 1]);
+        assert!(parse(&["--strip-mode=alt"]).is_err());
+
+        assert_eq!(parse(&["--compress=gzip"]).unwrap().compress, Some(Compression::Gzip));
+        assert!(parse(&["--compress=xz"]).is_err());
+
+        assert!(parse(&["--unknown"]).is_err());
+        assert!(parse(&["a", "b"]).is_err());
+    }
+
+    #[test]
+    fn run_stream() {
+        let mut output = Vec::new();
+        let options = parse(&["--color=16"]).unwrap().options;
+        run(&b"\x1B[38;2;255;0;0mred\xFF\x1B[0m\x1B[?25"[..], &mut output, options).unwrap();
+        assert_eq!(output, b"\x1B[91mred\xFF\x1B[m\x1B[?25");
+    }
+
+    #[test]
+    fn parse_lint() {
+        let args = parse(&["lint", "--format=json", "file"]).unwrap();
+        assert_eq!((args.command, args.format), (Command::Lint, Format::Json));
+        assert_eq!(parse(&["file"]).unwrap().command, Command::Optimize);
+        assert_eq!(parse(&["tokens"]).unwrap().command, Command::Tokens);
+        assert!(parse(&["--format=xml"]).is_err());
+    }
+
+    #[test]
+    fn lint_input() {
+        let findings = lint(b"ok\x1B[1m\x1B\x07a\xFFb\x1B[3");
+        assert_eq!(
+            findings,
+            vec![
+                Finding { offset: 6, message: "invalid escape sequence" },
+                Finding { offset: 9, message: "invalid UTF-8" },
+                Finding { offset: 11, message: "incomplete escape sequence" },
+            ]
+        );
+
+        let mut output = Vec::new();
+        print_findings(&mut output, "a\"b", &findings[..1], Format::Json).unwrap();
+        print_findings(&mut output, "c", &findings[1..], Format::Text).unwrap();
+        print_findings(&mut output, "d", &[], Format::Text).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"file\":\"a\\\"b\",\"offset\":6,\"message\":\"invalid escape sequence\"}\n\
+             c:9: invalid UTF-8\nc:11: incomplete escape sequence\nc: 2 problems found\n\
+             d: no problems found\n"
+        );
+    }
+
+    #[test]
+    fn detect_compression() {
+        assert_eq!(Compression::detect(b"\x1F\x8B\x08"), Some(Compression::Gzip));
+        assert_eq!(Compression::detect(b"\x28\xB5\x2F\xFD"), Some(Compression::Zstd));
+        assert_eq!(Compression::detect(b"\x1F"), None);
+
+        let mut output = Vec::new();
+        run(decompress(&b"\x1B[1m\x1B[1ma"[..]).unwrap(), &mut output, Options::default()).unwrap();
+        assert_eq!(output, b"\x1B[1ma");
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn run_compressed() {
+        let mut compressed = Output::new(Vec::new(), Some(Compression::Gzip)).unwrap();
+        run(&b"\x1B[1m\x1B[1ma"[..], &mut compressed, Options::default()).unwrap();
+        let compressed = compressed.finish().unwrap();
+        assert_eq!(Compression::detect(&compressed), Some(Compression::Gzip));
+
+        let mut output = Vec::new();
+        run(decompress(&compressed[..]).unwrap(), &mut output, Options::default()).unwrap();
+        assert_eq!(output, b"\x1B[1ma");
+    }
+}
@@ -0,0 +1,80 @@
+use crate::Optimizer;
+
+// -------------------------------------------------------------------------------------------------
+
+/// An optimizer for a stream of bytes.
+///
+/// Bytes that aren't valid UTF-8 are passed through unchanged.
+pub(crate) struct ByteOptimizer {
+    pub optimizer: Optimizer,
+
+    /// The start of a UTF-8 character that was split between chunks.
+    incomplete: Vec<u8>,
+
+    /// The output that hasn't been taken yet.
+    pub output: Vec<u8>,
+}
+
+impl ByteOptimizer {
+    pub fn new(optimizer: Optimizer) -> Self {
+        ByteOptimizer { optimizer, incomplete: Vec::new(), output: Vec::new() }
+    }
+
+    /// Updates the optimizer with the next chunk of the stream.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        let joined;
+        let mut bytes = if self.incomplete.is_empty() {
+            chunk
+        } else {
+            self.incomplete.extend_from_slice(chunk);
+            joined = std::mem::take(&mut self.incomplete);
+            &joined
+        };
+
+        loop {
+            match std::str::from_utf8(bytes) {
+                Ok(text) => {
+                    self.optimizer.feed(text);
+                    break;
+                }
+                Err(error) => {
+                    let (valid, rest) = bytes.split_at(error.valid_up_to());
+                    self.optimizer.feed(std::str::from_utf8(valid).expect("valid utf-8"));
+                    match error.error_len() {
+                        None => {
+                            self.incomplete.extend_from_slice(rest);
+                            break;
+                        }
+                        Some(len) => {
+                            self.optimizer.interrupt();
+                            self.take_optimized();
+                            self.output.extend_from_slice(&rest[..len]);
+                            bytes = &rest[len..];
+                        }
+                    }
+                }
+            }
+        }
+
+        self.take_optimized();
+    }
+
+    /// Writes everything that the optimizer is holding back, except for incomplete sequences and
+    /// characters. See [Optimizer::flush].
+    pub fn flush(&mut self) {
+        self.optimizer.flush();
+        self.take_optimized();
+    }
+
+    /// Finishes the stream, moving everything that was held back into the output.
+    pub fn finish(&mut self) {
+        self.optimizer.finish();
+        self.take_optimized();
+        self.output.append(&mut self.incomplete);
+    }
+
+    /// Moves the optimizer's output into the output buffer.
+    fn take_optimized(&mut self) {
+        self.output.extend_from_slice(self.optimizer.take_output().as_bytes());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::ansi::{ControlSequence, Sequence};
+use crate::style::Style;
+use crate::token::{Token, Tokens};
+use crate::width::is_combining;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Reduces a captured terminal session (e.g. a `script(1)` typescript) to its final visible text.
+///
+/// Backspaces, carriage returns, tabs, and the sequences that move the cursor within a line or
+/// erase part of it are applied to a line buffer, so overwritten text (e.g. from progress bars,
+/// line editors, or typos) is removed. All other escape sequences are removed.
+///
+/// Only the current line can be edited. Sequences that move the cursor to a different line are
+/// ignored, since the lines before it have already been written.
+pub fn cleanup(input: &str) -> String {
+    Cleanup::new(false).run(input)
+}
+
+/// Reduces a captured terminal session to its final visible text, keeping its colors and other
+/// styles.
+///
+/// This behaves the same as [cleanup], but writes the minimal SGR sequences needed to style the
+/// text. The style is reset at the end of every line.
+pub fn cleanup_styled(input: &str) -> String {
+    Cleanup::new(true).run(input)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// The widest line that can be edited.
+///
+/// The width of the terminal isn't known, so this bounds how far sequences like CUF and ICH can
+/// move the cursor or grow the line. Text past it is written to the last column, like a terminal
+/// without automatic margins.
+const MAX_COLUMNS: usize = 4096;
+
+/// A character cell on the line.
+#[derive(Clone, Debug)]
+struct Cell {
+    /// The character, followed by any combining characters.
+    text: String,
+    style: Style,
+}
+
+impl Cell {
+    fn blank() -> Self {
+        Cell { text: String::from(" "), style: Style::default() }
+    }
+}
+
+/// The state of a cleanup.
+struct Cleanup {
+    styled: bool,
+    output: String,
+    line: Vec<Option<Cell>>,
+    column: usize,
+    style: Style,
+}
+
+impl Cleanup {
+    fn new(styled: bool) -> Self {
+        Cleanup {
+            styled,
+            output: String::new(),
+            line: Vec::new(),
+            column: 0,
+            style: Style::default(),
+        }
+    }
+
+    fn run(mut self, input: &str) -> String {
+        self.output.reserve(input.len());
+        for token in Tokens::new(input) {
+            match token {
+                Ok(Token::Text(text)) => text.chars().for_each(|c| self.write_char(c)),
+                Ok(Token::Sequence(Sequence::CSI(csi))) => self.apply_csi(&csi),
+                _ => {}
+            }
+        }
+
+        if !self.line.is_empty() {
+            self.finish_line();
+        }
+
+        self.output
+    }
+
+    fn write_char(&mut self, c: char) {
+        match c {
+            '\r' => self.column = 0,
+            '\x08' => self.column = self.column.saturating_sub(1),
+            '\t' => self.column = ((self.column / 8 + 1) * 8).min(MAX_COLUMNS - 1),
+            '\n' => {
+                self.finish_line();
+                self.output.push('\n');
+            }
+
+            // Combining characters are added to the character before the cursor.
+            c if is_combining(c) => {
+                if let Some(Some(cell)) =
+                    self.column.checked_sub(1).and_then(|i| self.line.get_mut(i))
+                {
+                    cell.text.push(c);
+                }
+            }
+
+            c if c.is_control() => {}
+            c => {
+                let cell = Cell { text: c.to_string(), style: self.style };
+                if self.column >= self.line.len() {
+                    self.line.resize(self.column + 1, None);
+                }
+
+                self.line[self.column] = Some(cell);
+                self.column = (self.column + 1).min(MAX_COLUMNS - 1);
+            }
+        }
+    }
+
+    fn apply_csi(&mut self, csi: &ControlSequence) {
+        if csi.private_marker().is_some() || !csi.intermediates().is_empty() {
+            return;
+        }
+
+        let mut params = csi.params();
+        let count = params.clone().next().map_or(1, |p| p.value_or(1)).max(1) as usize;
+        let count = count.min(MAX_COLUMNS);
+        let mode = params.next().map_or(0, |p| p.value_or(0));
+        let column = self.column;
+
+        match csi.finalizer() {
+            "m" if self.styled => {
+                self.style.apply_sgr(csi.params());
+            }
+
+            "C" => self.column = (column + count).min(MAX_COLUMNS - 1),
+            "D" => self.column = column.saturating_sub(count),
+            "G" | "`" => self.column = count - 1,
+
+            // Erase in line, or erase in display (which includes the current line).
+            "K" | "J" => match mode {
+                0 => self.line.truncate(column),
+                1 => self.blank(0, column + 1),
+                2 | 3 => self.line.clear(),
+                _ => {}
+            },
+
+            "X" => self.blank(column, column + count),
+            "P" if column < self.line.len() => {
+                self.line.drain(column..(column + count).min(self.line.len()));
+            }
+
+            "@" if column < self.line.len() => {
+                self.line.splice(column..column, (0..count).map(|_| None));
+                self.line.truncate(MAX_COLUMNS);
+            }
+
+            _ => {}
+        }
+    }
+
+    /// Erases the cells in a range.
+    fn blank(&mut self, start: usize, end: usize) {
+        let end = end.min(self.line.len());
+        if start < end {
+            self.line[start..end].iter_mut().for_each(|cell| *cell = None);
+        }
+    }
+
+    /// Writes the line to the output, and starts a new one.
+    fn finish_line(&mut self) {
+        let len = self.line.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+        let mut emitted = Style::default();
+        for cell in self.line.drain(..).take(len) {
+            let cell = cell.unwrap_or_else(Cell::blank);
+            if self.styled {
+                cell.style.write_transition(&emitted, &mut self.output).expect("write to string");
+                emitted = cell.style;
+            }
+
+            self.output.push_str(&cell.text);
+        }
+
+        if self.styled {
+            Style::default().write_transition(&emitted, &mut self.output).expect("write to string");
+        }
+
+        self.column = 0;
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::cleanup::*;
+
+    #[test]
+    fn cleanup_overwrites() {
+        assert_eq!(cleanup("plain\r\ntext"), "plain\ntext");
+        assert_eq!(cleanup("typo\x08\x08po\r\n"), "typo\n");
+        assert_eq!(cleanup("tpyo\x08\x08\x08yp"), "typo");
+        assert_eq!(cleanup("10%\r50%\r100%\n"), "100%\n");
+        assert_eq!(cleanup("a\tb"), "a       b");
+        assert_eq!(cleanup("e\u{0301}x\x08y"), "e\u{0301}y");
+    }
+
+    #[test]
+    fn cleanup_sequences() {
+        assert_eq!(cleanup("$ lss\x1B[K\x08\x1B[K\r\n"), "$ ls\n");
+        assert_eq!(cleanup("old text\r\x1B[2Knew"), "new");
+        assert_eq!(cleanup("abcdef\x1B[3D\x1B[P"), "abcef");
+        assert_eq!(cleanup("abc\x1B[2D\x1B[2@x"), "ax bc");
+        assert_eq!(cleanup("abcdef\x1B[2G\x1B[2X"), "a  def");
+        assert_eq!(cleanup("a\x1B[65535Cbc"), format!("a{}c", " ".repeat(MAX_COLUMNS - 2)));
+        assert_eq!(cleanup("\x1B[65535Gab"), format!("{}b", " ".repeat(MAX_COLUMNS - 1)));
+        assert_eq!(cleanup("ab\x1B[G\x1B[65535@\x1B[65535@x"), "x");
+        assert_eq!(cleanup("\x1B]0;title\x07\x1B[?2004h\x1B[1;31mred\x1B[m"), "red");
+    }
+
+    #[test]
+    fn cleanup_keeps_styles() {
+        assert_eq!(cleanup_styled("\x1B[31mred\x1B[m\r\n"), "\x1B[31mred\x1B[m\n");
+        assert_eq!(
+            cleanup_styled("\x1B[31mred\r\n\x1B[1mx\x1B[m"),
+            "\x1B[31mred\x1B[m\n\x1B[1;31mx\x1B[m"
+        );
+        assert_eq!(cleanup_styled("\x1B[1mbolder\r\x1B[22mplai"), "plai\x1B[1mer\x1B[m");
+    }
+}
@@ -0,0 +1,446 @@
+//! Functions for creating common escape sequences.
+//!
+//! Every sequence is created with its shortest encoding, so output built from them doesn't need
+//! to be optimized just to remove default parameters.
+//!
+//! ```
+//! # use ansi_optimizer::codes::{clear_line, cursor_up, fg, hyperlink, reset, ClearMode};
+//! # use ansi_optimizer::Color;
+//! let output = format!(
+//!     "{}{}{}error{}",
+//!     cursor_up(1),
+//!     clear_line(ClearMode::All),
+//!     fg(Color::Indexed(1)),
+//!     reset(),
+//! );
+//!
+//! assert_eq!(output, "\x1B[A\x1B[2K\x1B[31merror\x1B[m");
+//! assert_eq!(
+//!     hyperlink("https://example.com", "link"),
+//!     "\x1B]8;;https://example.com\x07link\x1B]8;;\x07"
+//! );
+//! ```
+//!
+//! Sequences that never change can be created at compile time instead, with the
+//! [sgr!](crate::sgr!), [csi!](crate::csi!), and [style!](crate::style!) macros. They're written
+//! the same way that the [Optimizer](crate::Optimizer) writes them.
+//!
+//! ```
+//! # use ansi_optimizer::style::Attributes;
+//! # use ansi_optimizer::{csi, sgr, style, Color};
+//! const WARNING: &str = style!(attributes: Attributes::BOLD, foreground: Color::Indexed(3));
+//! assert_eq!(WARNING, "\x1B[1;33m");
+//! assert_eq!(sgr!(0), "\x1B[m");
+//! assert_eq!(csi!("H", 1, 5), "\x1B[;5H");
+//! ```
+
+use crate::normalize::positional_default;
+use crate::profile::Profile;
+use crate::style::{write_color_params, ParamBuffer};
+use crate::{Color, Style};
+
+// -------------------------------------------------------------------------------------------------
+
+/// The part of a line or the screen to clear.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum ClearMode {
+    /// From the cursor to the end, including the cursor.
+    ToEnd,
+
+    /// From the start to the cursor, including the cursor.
+    ToStart,
+
+    /// Everything.
+    All,
+}
+
+impl ClearMode {
+    fn param(self) -> &'static str {
+        match self {
+            ClearMode::ToEnd => "",
+            ClearMode::ToStart => "1",
+            ClearMode::All => "2",
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Creates a sequence that moves the cursor, with a count that can be omitted if it's 1.
+/// A count of 0 creates nothing, since terminals treat it as 1.
+fn cursor_csi(n: u16, finalizer: char) -> String {
+    match n {
+        0 => String::new(),
+        1 => format!("\x1B[{}", finalizer),
+        n => format!("\x1B[{}{}", n, finalizer),
+    }
+}
+
+/// Moves the cursor up `n` rows (`CUU`).
+pub fn cursor_up(n: u16) -> String {
+    cursor_csi(n, 'A')
+}
+
+/// Moves the cursor down `n` rows (`CUD`).
+pub fn cursor_down(n: u16) -> String {
+    cursor_csi(n, 'B')
+}
+
+/// Moves the cursor right `n` columns (`CUF`).
+pub fn cursor_forward(n: u16) -> String {
+    cursor_csi(n, 'C')
+}
+
+/// Moves the cursor left `n` columns (`CUB`).
+pub fn cursor_back(n: u16) -> String {
+    cursor_csi(n, 'D')
+}
+
+/// Moves the cursor to a column, starting from 1 (`CHA`).
+pub fn cursor_column(column: u16) -> String {
+    cursor_csi(column.max(1), 'G')
+}
+
+/// Moves the cursor to a row and column, starting from 1 (`CUP`).
+pub fn cursor_to(row: u16, column: u16) -> String {
+    match (row.max(1), column.max(1)) {
+        (1, 1) => "\x1B[H".to_owned(),
+        (row, 1) => format!("\x1B[{}H", row),
+        (1, column) => format!("\x1B[;{}H", column),
+        (row, column) => format!("\x1B[{};{}H", row, column),
+    }
+}
+
+/// Saves the cursor position and style (`DECSC`).
+pub fn save_cursor() -> &'static str {
+    "\x1B7"
+}
+
+/// Restores the cursor position and style saved by [save_cursor] (`DECRC`).
+pub fn restore_cursor() -> &'static str {
+    "\x1B8"
+}
+
+/// Shows or hides the cursor (`DECTCEM`).
+pub fn show_cursor(visible: bool) -> &'static str {
+    match visible {
+        true => "\x1B[?25h",
+        false => "\x1B[?25l",
+    }
+}
+
+/// Clears part of the cursor's line (`EL`).
+pub fn clear_line(mode: ClearMode) -> String {
+    format!("\x1B[{}K", mode.param())
+}
+
+/// Clears part of the screen (`ED`).
+pub fn clear_screen(mode: ClearMode) -> String {
+    format!("\x1B[{}J", mode.param())
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Creates an SGR sequence that sets a color.
+fn color_sgr(base: u16, color: Color) -> String {
+    let mut params = String::new();
+    write_color_params(base, color, &mut params);
+    format!("\x1B[{}m", params)
+}
+
+/// Sets the foreground color.
+///
+/// Standard and bright colors (0 to 15) use their short codes (e.g. `31` or `91`).
+pub fn fg(color: Color) -> String {
+    color_sgr(30, color)
+}
+
+/// Sets the background color.
+///
+/// Standard and bright colors (0 to 15) use their short codes (e.g. `41` or `101`).
+pub fn bg(color: Color) -> String {
+    color_sgr(40, color)
+}
+
+/// Sets the underline color.
+pub fn underline_color(color: Color) -> String {
+    color_sgr(50, color)
+}
+
+/// Resets the style to the terminal's default.
+pub fn reset() -> &'static str {
+    "\x1B[m"
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Sets the window title (`OSC 2`).
+pub fn title(title: &str) -> String {
+    format!("\x1B]2;{}\x07", title)
+}
+
+/// Creates text that links to a URL (`OSC 8`).
+pub fn hyperlink(url: &str, text: &str) -> String {
+    format!("\x1B]8;;{}\x07{}\x1B]8;;\x07", url, text)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Creates an SGR sequence from its parameters at compile time.
+///
+/// A sole `0` parameter is omitted, since it's the default. Every other parameter is kept, since
+/// each of them is a separate command.
+///
+/// ```
+/// # use ansi_optimizer::sgr;
+/// assert_eq!(sgr!(1, 38, 5, 208), "\x1B[1;38;5;208m");
+/// assert_eq!(sgr!(0), "\x1B[m");
+/// ```
+#[macro_export]
+macro_rules! sgr {
+    ($($param:expr),* $(,)?) => {
+        $crate::__literal!($crate::codes::Literal::csi("m", &[$($param),*]))
+    };
+}
+
+/// Creates a control sequence from its final byte and parameters at compile time.
+///
+/// Parameters are omitted where the command's default value is implied, like the
+/// [Optimizer](crate::Optimizer) does. Commands with a private marker or intermediate bytes aren't
+/// supported.
+///
+/// ```
+/// # use ansi_optimizer::csi;
+/// assert_eq!(csi!("H", 1, 1), "\x1B[H");
+/// assert_eq!(csi!("H", 5, 1), "\x1B[5H");
+/// assert_eq!(csi!("K", 2), "\x1B[2K");
+/// ```
+#[macro_export]
+macro_rules! csi {
+    ($finalizer:expr $(, $param:expr)* $(,)?) => {
+        $crate::__literal!($crate::codes::Literal::csi($finalizer, &[$($param),*]))
+    };
+}
+
+/// Creates the shortest SGR sequence that changes the default style to a style at compile time.
+///
+/// The style is given as the fields of a [Style], and the rest of them are the default. This is
+/// the same sequence as [Style::write_transition] writes, so the default style creates nothing.
+///
+/// ```
+/// # use ansi_optimizer::style::{Attributes, Underline};
+/// # use ansi_optimizer::{style, Color};
+/// assert_eq!(style!(foreground: Color::Rgb(255, 0, 0)), "\x1B[38;2;255;0;0m");
+/// assert_eq!(style!(attributes: Attributes::ITALIC, underline: Underline::Curly), "\x1B[3;4:3m");
+/// assert_eq!(style!(), "");
+/// ```
+#[macro_export]
+macro_rules! style {
+    ($($field:ident: $value:expr),* $(,)?) => {
+        $crate::__literal!($crate::codes::Literal::style(&{
+            #[allow(unused_mut)]
+            let mut style = $crate::Style::DEFAULT;
+            $(style.$field = $value;)*
+            style
+        }))
+    };
+}
+
+/// Turns a [Literal] into a `&'static str` at compile time.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __literal {
+    ($literal:expr) => {{
+        const LITERAL: $crate::codes::Literal = $literal;
+        const BYTES: [u8; LITERAL.len()] = LITERAL.to_array();
+        const SEQUENCE: &str = $crate::codes::Literal::to_str(&BYTES);
+        SEQUENCE
+    }};
+}
+
+/// An escape sequence created at compile time. This is only public for the macros to use.
+#[doc(hidden)]
+pub struct Literal(ParamBuffer<256>);
+
+impl Literal {
+    /// Creates a control sequence, omitting parameters like the [Optimizer](crate::Optimizer) does.
+    pub const fn csi(finalizer: &str, params: &[u16]) -> Literal {
+        let finalizer = finalizer.as_bytes();
+        assert!(
+            finalizer.len() == 1 && finalizer[0] >= 0x40 && finalizer[0] <= 0x7E,
+            "the final byte must be from @ to ~"
+        );
+
+        let mut out = ParamBuffer::new();
+        out.push(b"\x1B[");
+        let mut i = 0;
+        match positional_default(finalizer, params.len()) {
+            // SGR parameters are each separate commands, so only the sole parameter can be omitted.
+            None if finalizer[0] == b'm' && params.len() == 1 && params[0] == 0 => {}
+
+            // Positional parameters can be omitted when they're equal to the default value.
+            Some(default) => {
+                let mut pending_separators = 0;
+                while i < params.len() {
+                    if i > 0 {
+                        pending_separators += 1;
+                    }
+
+                    if params[i] != default {
+                        while pending_separators > 0 {
+                            out.push(b";");
+                            pending_separators -= 1;
+                        }
+
+                        out.push_number(params[i]);
+                    }
+
+                    i += 1;
+                }
+            }
+
+            None => {
+                while i < params.len() {
+                    if i > 0 {
+                        out.push(b";");
+                    }
+
+                    out.push_number(params[i]);
+                    i += 1;
+                }
+            }
+        }
+
+        out.push(finalizer);
+        Literal(out)
+    }
+
+    /// Creates the SGR sequence that changes the default style to a style.
+    pub const fn style(style: &Style) -> Literal {
+        let mut params: ParamBuffer = ParamBuffer::new();
+        style.write_diff_params(&Style::DEFAULT, &Profile::DEFAULT, &mut params);
+
+        let mut out = ParamBuffer::new();
+        if !params.is_empty() {
+            out.push(b"\x1B[");
+            out.push(params.as_bytes());
+            out.push(b"m");
+        }
+
+        Literal(out)
+    }
+
+    /// Gets the length of the sequence.
+    pub const fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the sequence is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Gets the bytes of the sequence, as an array of its length.
+    pub const fn to_array<const N: usize>(&self) -> [u8; N] {
+        let bytes = self.0.as_bytes();
+        assert!(bytes.len() == N, "the array must have the length of the sequence");
+
+        let mut array = [0; N];
+        let mut i = 0;
+        while i < N {
+            array[i] = bytes[i];
+            i += 1;
+        }
+
+        array
+    }
+
+    /// Converts the bytes of a sequence to a string.
+    pub const fn to_str(bytes: &'static [u8]) -> &'static str {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => s,
+            Err(_) => panic!("escape sequences are ascii"),
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::codes::*;
+
+    #[test]
+    fn cursor_movement() {
+        assert_eq!(cursor_up(0), "");
+        assert_eq!(cursor_up(1), "\x1B[A");
+        assert_eq!(cursor_back(12), "\x1B[12D");
+        assert_eq!(cursor_column(0), "\x1B[G");
+        assert_eq!(cursor_to(1, 1), "\x1B[H");
+        assert_eq!(cursor_to(5, 1), "\x1B[5H");
+        assert_eq!(cursor_to(1, 5), "\x1B[;5H");
+        assert_eq!(cursor_to(5, 7), "\x1B[5;7H");
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(fg(Color::Default), "\x1B[39m");
+        assert_eq!(fg(Color::Indexed(9)), "\x1B[91m");
+        assert_eq!(bg(Color::Indexed(200)), "\x1B[48;5;200m");
+        assert_eq!(underline_color(Color::Indexed(1)), "\x1B[58;5;1m");
+        assert_eq!(fg(Color::Rgb(1, 2, 3)), "\x1B[38;2;1;2;3m");
+    }
+
+    #[test]
+    fn already_optimized() {
+        let output = [
+            cursor_to(3, 4),
+            clear_screen(ClearMode::ToEnd),
+            fg(Color::Indexed(2)),
+            "text".to_owned(),
+            clear_line(ClearMode::ToStart),
+        ]
+        .concat();
+
+        assert_eq!(crate::optimize(&output).unwrap(), output);
+    }
+
+    #[test]
+    fn literals() {
+        const CUP: &str = crate::csi!("H", 1, 1);
+        assert_eq!(CUP, "\x1B[H");
+        assert_eq!(crate::csi!("H", 3, 1), "\x1B[3H");
+        assert_eq!(crate::csi!("H", 1, 3), "\x1B[;3H");
+        assert_eq!(crate::csi!("J"), "\x1B[J");
+        assert_eq!(crate::csi!("J", 0), "\x1B[J");
+        assert_eq!(crate::csi!("r", 1, 24), "\x1B[1;24r");
+        assert_eq!(crate::sgr!(), "\x1B[m");
+        assert_eq!(crate::sgr!(0, 0), "\x1B[0;0m");
+        assert_eq!(crate::sgr!(65535), "\x1B[65535m");
+    }
+
+    #[test]
+    fn literals_match_runtime() {
+        use crate::style::{Attributes, Underline};
+
+        const STYLE: Style = Style {
+            attributes: Attributes::ALL,
+            underline: Underline::Double,
+            foreground: Color::Indexed(12),
+            background: Color::Indexed(100),
+            underline_color: Color::Rgb(0, 128, 255),
+        };
+
+        let literal = crate::style!(
+            attributes: Attributes::ALL,
+            underline: Underline::Double,
+            foreground: Color::Indexed(12),
+            background: Color::Indexed(100),
+            underline_color: Color::Rgb(0, 128, 255),
+        );
+
+        assert_eq!(literal, STYLE.transition_from(Some(&Style::default())).to_string());
+        assert_eq!(Style::DEFAULT, Style::default());
+        assert_eq!(Profile::DEFAULT, Profile::default());
+    }
+}
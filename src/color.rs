@@ -0,0 +1,528 @@
+use crate::profile::ColorSupport;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A terminal color.
+///
+/// Conversions that don't depend on a redefined palette are `const`, so colors can be converted
+/// at compile time.
+///
+/// ```
+/// # use ansi_optimizer::{Color, ColorSupport};
+/// const ORANGE: Color = Color::Rgb(255, 135, 0).downgrade(ColorSupport::Ansi256);
+/// assert_eq!(ORANGE, Color::Indexed(208));
+/// ```
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Color {
+    /// The terminal's default color.
+    Default,
+
+    /// A color from the terminal's 256-color palette.
+    ///
+    /// Indices 0 to 7 are the standard colors, and 8 to 15 are their bright variants.
+    Indexed(u8),
+
+    /// A 24-bit RGB color.
+    Rgb(u8, u8, u8),
+}
+
+/// The RGB values of a terminal's 256-color palette.
+///
+/// This defaults to the xterm palette. Programs can redefine palette colors with `OSC 4`, which
+/// changes how indexed colors look and which of them is nearest to an RGB color.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Palette {
+    colors: [(u8, u8, u8); 256],
+}
+
+/// A type of color blindness, for replacing colors that are commonly confused with each other.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum ColorBlindness {
+    /// Reduced sensitivity to green, which makes red and green hard to tell apart.
+    Deuteranopia,
+
+    /// Reduced sensitivity to red, which makes red and green hard to tell apart.
+    Protanopia,
+
+    /// Reduced sensitivity to blue, which makes blue and green, and yellow and magenta, hard to
+    /// tell apart.
+    Tritanopia,
+}
+
+impl Default for Color {
+    #[inline]
+    fn default() -> Self {
+        Color::Default
+    }
+}
+
+impl Color {
+    /// Returns `true` if this is the terminal's default color.
+    #[inline]
+    pub const fn is_default(&self) -> bool {
+        matches!(self, Color::Default)
+    }
+
+    /// Gets the RGB value of the color.
+    ///
+    /// Indexed colors are converted using the default xterm palette.
+    /// Returns `None` for the terminal's default color, since its value isn't known.
+    #[inline]
+    pub const fn to_rgb(self) -> Option<(u8, u8, u8)> {
+        self.to_rgb_with(&XTERM_PALETTE)
+    }
+
+    /// Gets the RGB value of the color, converting indexed colors using a palette.
+    ///
+    /// Returns `None` for the terminal's default color, since its value isn't known.
+    pub const fn to_rgb_with(self, palette: &Palette) -> Option<(u8, u8, u8)> {
+        match self {
+            Color::Default => None,
+            Color::Rgb(r, g, b) => Some((r, g, b)),
+            Color::Indexed(n) => Some(palette.get(n)),
+        }
+    }
+
+    /// Converts the color to the nearest color in the 256-color palette.
+    ///
+    /// Only RGB colors are changed. They are converted to the nearest color in the 6x6x6 color
+    /// cube or the grayscale ramp.
+    pub const fn to_ansi256(self) -> Color {
+        let (r, g, b) = match self {
+            Color::Rgb(r, g, b) => (r, g, b),
+            color => return color,
+        };
+
+        let cube = 16 + 36 * cube_index(r) + 6 * cube_index(g) + cube_index(b);
+        let average = (r as u16 + g as u16 + b as u16) / 3;
+        let gray = match average {
+            0..=3 => 232,
+            238..=255 => 255,
+            n => 232 + ((n - 3) / 10) as u8,
+        };
+
+        nearest(&XTERM_PALETTE, &[cube, gray], (r, g, b))
+    }
+
+    /// Converts the color to the nearest gray in the 256-color palette, keeping its luminance.
+    ///
+    /// The grays are black, white, and the 24 colors of the grayscale ramp.
+    /// The terminal's default color isn't changed.
+    pub fn to_grayscale(self) -> Color {
+        let rgb = match self.to_rgb() {
+            Some(rgb) => rgb,
+            None => return self,
+        };
+
+        // Encode the luminance as an sRGB channel value, which is what the grays are defined by.
+        let y = luminance(rgb);
+        let v = if y <= 0.0031308 { y * 12.92 } else { 1.055 * y.powf(1.0 / 2.4) - 0.055 };
+        let v = (v * 255.0).round() as u8;
+
+        nearest(&XTERM_PALETTE, &GRAYS, (v, v, v))
+    }
+
+    /// Converts the color to the nearest of the 16 standard colors.
+    #[inline]
+    pub const fn to_ansi16(self) -> Color {
+        self.to_ansi16_with(&XTERM_PALETTE)
+    }
+
+    /// Converts the color to the nearest of the 16 standard colors, as defined by a palette.
+    pub const fn to_ansi16_with(self, palette: &Palette) -> Color {
+        match self {
+            Color::Default => Color::Default,
+            Color::Indexed(n) if n < 16 => self,
+            Color::Indexed(n) => nearest(palette, &STANDARD, palette.get(n)),
+            Color::Rgb(r, g, b) => nearest(palette, &STANDARD, (r, g, b)),
+        }
+    }
+
+    /// Converts the color to one that is supported by a terminal.
+    ///
+    /// This is the same as [Color::downgrade_with] with the xterm palette.
+    pub const fn downgrade(self, support: ColorSupport) -> Color {
+        match support {
+            ColorSupport::TrueColor => self,
+            ColorSupport::Ansi256 => self.to_ansi256(),
+            ColorSupport::Ansi16 => self.to_ansi16(),
+            ColorSupport::Monochrome => Color::Default,
+        }
+    }
+
+    /// Converts the color to one that is supported by a terminal with a palette.
+    ///
+    /// If the palette was redefined, RGB colors are converted to the nearest color in it, rather
+    /// than the nearest color in the xterm palette.
+    pub fn downgrade_with(self, support: ColorSupport, palette: &Palette) -> Color {
+        match support {
+            ColorSupport::TrueColor => self,
+            ColorSupport::Ansi256 if *palette == XTERM_PALETTE => self.to_ansi256(),
+            ColorSupport::Ansi256 => match self {
+                Color::Rgb(r, g, b) => nearest(palette, &EXTENDED, (r, g, b)),
+                color => color,
+            },
+            ColorSupport::Ansi16 => self.to_ansi16_with(palette),
+            ColorSupport::Monochrome => Color::Default,
+        }
+    }
+}
+
+impl ColorBlindness {
+    /// Replaces a standard color that is commonly confused with another one.
+    ///
+    /// The replacements are from the Okabe-Ito palette, which stays distinguishable with each type
+    /// of color blindness. Bright variants are replaced with lighter versions of the same color.
+    /// Other colors aren't changed.
+    pub const fn substitute(self, color: Color) -> Color {
+        use ColorBlindness::*;
+        let index = match color {
+            Color::Indexed(n) if n < 16 => n,
+            color => return color,
+        };
+
+        let (r, g, b) = match (self, index % 8) {
+            (Deuteranopia | Protanopia, 1) => (213, 94, 0),
+            (Deuteranopia | Protanopia, 2) => (0, 158, 115),
+            (Tritanopia, 2) => (0, 158, 115),
+            (Tritanopia, 3) => (230, 159, 0),
+            (Tritanopia, 4) => (0, 114, 178),
+            (Tritanopia, 5) => (204, 121, 167),
+            _ => return color,
+        };
+
+        if index < 8 {
+            return Color::Rgb(r, g, b);
+        }
+
+        const fn lighten(v: u8) -> u8 {
+            v + (255 - v) / 3
+        }
+
+        Color::Rgb(lighten(r), lighten(g), lighten(b))
+    }
+}
+
+impl Palette {
+    /// Gets the default xterm palette.
+    #[inline]
+    pub const fn xterm() -> &'static Palette {
+        &XTERM_PALETTE
+    }
+
+    /// Gets the RGB value of a palette color.
+    #[inline]
+    pub const fn get(&self, index: u8) -> (u8, u8, u8) {
+        self.colors[index as usize]
+    }
+
+    /// Redefines a palette color.
+    #[inline]
+    pub const fn set(&mut self, index: u8, rgb: (u8, u8, u8)) {
+        self.colors[index as usize] = rgb;
+    }
+
+    /// Resets a palette color back to its xterm value.
+    #[inline]
+    pub const fn reset(&mut self, index: u8) {
+        self.colors[index as usize] = XTERM_PALETTE.colors[index as usize];
+    }
+}
+
+impl Default for Palette {
+    #[inline]
+    fn default() -> Self {
+        XTERM_PALETTE.clone()
+    }
+}
+
+/// The default xterm values of the 16 standard colors.
+const ANSI_PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The values of each channel in the 6x6x6 color cube of the 256-color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The default xterm palette.
+static XTERM_PALETTE: Palette = Palette { colors: build_xterm_palette() };
+
+const fn build_xterm_palette() -> [(u8, u8, u8); 256] {
+    let mut colors = [(0, 0, 0); 256];
+    let mut i = 0;
+    while i < 256 {
+        colors[i] = if i < 16 {
+            ANSI_PALETTE[i]
+        } else if i < 232 {
+            let n = i - 16;
+            (CUBE_LEVELS[n / 36], CUBE_LEVELS[(n / 6) % 6], CUBE_LEVELS[n % 6])
+        } else {
+            let gray = 8 + (i as u8 - 232) * 10;
+            (gray, gray, gray)
+        };
+
+        i += 1;
+    }
+
+    colors
+}
+
+/// The indices of the 16 standard colors.
+const STANDARD: [u8; 16] = indices(0);
+
+/// The indices of the 240 colors after the standard colors.
+const EXTENDED: [u8; 240] = indices(16);
+
+/// The indices of the grays: black, white, and the grayscale ramp.
+const GRAYS: [u8; 26] = {
+    let mut grays = [0; 26];
+    let ramp = indices::<24>(232);
+    grays[0] = 16;
+    grays[1] = 231;
+
+    let mut i = 0;
+    while i < ramp.len() {
+        grays[i + 2] = ramp[i];
+        i += 1;
+    }
+
+    grays
+};
+
+/// Creates an array of consecutive palette indices.
+const fn indices<const N: usize>(first: u8) -> [u8; N] {
+    let mut indices = [0; N];
+    let mut i = 0;
+    while i < N {
+        indices[i] = first + i as u8;
+        i += 1;
+    }
+
+    indices
+}
+
+/// Gets the index of the nearest level in the 6x6x6 color cube to a channel value.
+const fn cube_index(v: u8) -> u8 {
+    match v {
+        0..=47 => 0,
+        48..=114 => 1,
+        v => (v - 35) / 40,
+    }
+}
+
+/// Finds the palette color that is closest to an RGB value, out of the candidate indices.
+/// The first of equally close candidates is used.
+const fn nearest(palette: &Palette, candidates: &[u8], (r, g, b): (u8, u8, u8)) -> Color {
+    const fn d(a: u8, b: u8) -> i32 {
+        (a as i32 - b as i32).pow(2)
+    }
+
+    let (mut best, mut best_distance) = (candidates[0], i32::MAX);
+    let mut i = 0;
+    while i < candidates.len() {
+        let (cr, cg, cb) = palette.get(candidates[i]);
+        let distance = d(r, cr) + d(g, cg) + d(b, cb);
+        if distance < best_distance {
+            best = candidates[i];
+            best_distance = distance;
+        }
+
+        i += 1;
+    }
+
+    Color::Indexed(best)
+}
+
+/// Gets the relative luminance of an RGB color, as defined by WCAG 2.
+pub(crate) fn luminance((r, g, b): (u8, u8, u8)) -> f32 {
+    let linear = |v: u8| {
+        let v = f32::from(v) / 255.0;
+        if v <= 0.03928 {
+            v / 12.92
+        } else {
+            ((v + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
+
+/// Gets the WCAG 2 contrast ratio between two RGB colors, from 1 to 21.
+pub(crate) fn contrast_ratio(a: (u8, u8, u8), b: (u8, u8, u8)) -> f32 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Adjusts a foreground color to have at least a contrast ratio against a background color.
+///
+/// The foreground is mixed with white if it's lighter than the background, or black if it's
+/// darker. If that isn't enough, whichever of white or black has more contrast is used instead.
+///
+/// ## Returns
+///
+/// `None` if the foreground color already has enough contrast.
+pub(crate) fn adjust_contrast(
+    foreground: (u8, u8, u8),
+    background: (u8, u8, u8),
+    ratio: f32,
+) -> Option<(u8, u8, u8)> {
+    const WHITE: (u8, u8, u8) = (255, 255, 255);
+    const BLACK: (u8, u8, u8) = (0, 0, 0);
+
+    if contrast_ratio(foreground, background) >= ratio {
+        return None;
+    }
+
+    let target = if luminance(foreground) >= luminance(background) { WHITE } else { BLACK };
+    if contrast_ratio(target, background) < ratio {
+        let white = contrast_ratio(WHITE, background);
+        return Some(if white >= contrast_ratio(BLACK, background) { WHITE } else { BLACK });
+    }
+
+    // Moving towards the target only increases the contrast, so the smallest change that is
+    // enough can be found with a binary search.
+    let mix = |t: f32| {
+        let channel = |from: u8, to: u8| {
+            let (from, to) = (f32::from(from), f32::from(to));
+            (from + (to - from) * t).round() as u8
+        };
+
+        let (from, to) = (foreground, target);
+        (channel(from.0, to.0), channel(from.1, to.1), channel(from.2, to.2))
+    };
+
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..16 {
+        let middle = (low + high) / 2.0;
+        if contrast_ratio(mix(middle), background) >= ratio {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+
+    Some(mix(high))
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::color::*;
+
+    #[test]
+    fn to_rgb() {
+        assert_eq!(Color::Default.to_rgb(), None);
+        assert_eq!(Color::Indexed(9).to_rgb(), Some((255, 0, 0)));
+        assert_eq!(Color::Indexed(16).to_rgb(), Some((0, 0, 0)));
+        assert_eq!(Color::Indexed(196).to_rgb(), Some((255, 0, 0)));
+        assert_eq!(Color::Indexed(231).to_rgb(), Some((255, 255, 255)));
+        assert_eq!(Color::Indexed(232).to_rgb(), Some((8, 8, 8)));
+        assert_eq!(Color::Indexed(255).to_rgb(), Some((238, 238, 238)));
+    }
+
+    #[test]
+    fn downgrade() {
+        assert_eq!(Color::Rgb(255, 0, 0).to_ansi256(), Color::Indexed(196));
+        assert_eq!(Color::Rgb(128, 128, 128).to_ansi256(), Color::Indexed(244));
+        assert_eq!(Color::Rgb(0, 95, 135).to_ansi256(), Color::Indexed(24));
+        assert_eq!(Color::Rgb(250, 10, 10).to_ansi16(), Color::Indexed(9));
+        assert_eq!(Color::Indexed(196).to_ansi16(), Color::Indexed(9));
+        assert_eq!(Color::Indexed(3).to_ansi16(), Color::Indexed(3));
+        assert_eq!(Color::Rgb(1, 2, 3).downgrade(ColorSupport::Monochrome), Color::Default);
+        assert_eq!(Color::Rgb(1, 2, 3).downgrade(ColorSupport::TrueColor), Color::Rgb(1, 2, 3));
+    }
+
+    #[test]
+    fn downgrade_with_palette() {
+        let mut palette = Palette::default();
+        palette.set(1, (0, 0, 255));
+        palette.set(200, (10, 20, 30));
+
+        assert_eq!(Color::Indexed(1).to_rgb_with(&palette), Some((0, 0, 255)));
+        assert_eq!(Color::Rgb(0, 0, 250).to_ansi16_with(&palette), Color::Indexed(1));
+        assert_eq!(Color::Indexed(200).to_ansi16_with(&palette), Color::Indexed(0));
+        assert_eq!(
+            Color::Rgb(10, 20, 31).downgrade_with(ColorSupport::Ansi256, &palette),
+            Color::Indexed(200)
+        );
+
+        palette.reset(1);
+        assert_eq!(palette.get(1), (205, 0, 0));
+    }
+
+    #[test]
+    fn const_conversions() {
+        const COLORS: [Color; 4] = [
+            Color::Rgb(95, 135, 0).to_ansi256(),
+            Color::Rgb(95, 135, 0).to_ansi16(),
+            Color::Indexed(9).downgrade(ColorSupport::Monochrome),
+            ColorBlindness::Protanopia.substitute(Color::Indexed(2)),
+        ];
+
+        const RGB: Option<(u8, u8, u8)> = Color::Indexed(64).to_rgb();
+        assert_eq!(COLORS[0], Color::Indexed(64));
+        assert_eq!(COLORS[1], Color::Indexed(2));
+        assert_eq!(COLORS[2], Color::Default);
+        assert_eq!(COLORS[3], Color::Rgb(0, 158, 115));
+        assert_eq!(RGB, Some((95, 135, 0)));
+    }
+
+    #[test]
+    fn to_grayscale() {
+        assert_eq!(Color::Default.to_grayscale(), Color::Default);
+        assert_eq!(Color::Rgb(0, 0, 0).to_grayscale(), Color::Indexed(16));
+        assert_eq!(Color::Indexed(15).to_grayscale(), Color::Indexed(231));
+        assert_eq!(Color::Rgb(128, 128, 128).to_grayscale(), Color::Indexed(244));
+
+        // Green is much lighter than blue.
+        assert_eq!(Color::Rgb(0, 255, 0).to_grayscale(), Color::Indexed(253));
+        assert_eq!(Color::Rgb(0, 0, 255).to_grayscale(), Color::Indexed(239));
+    }
+
+    #[test]
+    fn color_blindness() {
+        let substitute = |color| ColorBlindness::Deuteranopia.substitute(color);
+        assert_eq!(substitute(Color::Indexed(1)), Color::Rgb(213, 94, 0));
+        assert_eq!(substitute(Color::Indexed(10)), Color::Rgb(85, 190, 161));
+        assert_eq!(substitute(Color::Indexed(4)), Color::Indexed(4));
+        assert_eq!(substitute(Color::Indexed(196)), Color::Indexed(196));
+        assert_eq!(substitute(Color::Rgb(255, 0, 0)), Color::Rgb(255, 0, 0));
+
+        let substitute = |color| ColorBlindness::Tritanopia.substitute(color);
+        assert_eq!(substitute(Color::Indexed(1)), Color::Indexed(1));
+        assert_eq!(substitute(Color::Indexed(4)), Color::Rgb(0, 114, 178));
+    }
+
+    #[test]
+    fn contrast() {
+        assert!((contrast_ratio((0, 0, 0), (255, 255, 255)) - 21.0).abs() < 0.01);
+        assert!((contrast_ratio((255, 0, 0), (255, 0, 0)) - 1.0).abs() < 0.01);
+
+        assert_eq!(adjust_contrast((255, 255, 255), (0, 0, 0), 4.5), None);
+        assert_eq!(adjust_contrast((0, 0, 0), (0, 0, 0), 21.0), Some((255, 255, 255)));
+
+        let dark_blue = adjust_contrast((0, 0, 128), (0, 0, 0), 4.5).unwrap();
+        assert!(contrast_ratio(dark_blue, (0, 0, 0)) >= 4.5);
+        assert!(dark_blue.2 > 128 && dark_blue.0 == dark_blue.1);
+
+        let light_gray = adjust_contrast((200, 200, 200), (255, 255, 255), 7.0).unwrap();
+        assert!(contrast_ratio(light_gray, (255, 255, 255)) >= 7.0);
+        assert!(light_gray.0 < 200);
+    }
+}
@@ -0,0 +1,71 @@
+//! The cost model used to choose between equivalent ways of writing the same output.
+//!
+//! By default, the cheapest output is the one with the fewest bytes. Terminals connected over
+//! slow serial links, or that have a fixed cost for processing each escape sequence, can give
+//! escape sequences an extra cost so that plain text is preferred:
+//!
+//! ```
+//! # use ansi_optimizer::{optimize_with, Cost, Options};
+//! let input = "\x1B[3;10Hab\x1B[3;6H";
+//! let options = Options { columns: Some(80), ..Options::default() };
+//! assert_eq!(optimize_with(input, &options).unwrap(), "\x1B[3;10Hab\x1B[6G");
+//!
+//! let options = Options { cost: Cost { byte: 1, sequence: 8 }, ..options };
+//! assert_eq!(optimize_with(input, &options).unwrap(), "\x1B[3;10Hab\x08\x08\x08\x08\x08\x08");
+//! ```
+
+// -------------------------------------------------------------------------------------------------
+
+/// Weights for the cost of output. See [Options::cost](crate::Options::cost).
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub struct Cost {
+    /// The cost of each byte.
+    pub byte: usize,
+
+    /// The extra cost of each escape sequence, on top of its bytes.
+    pub sequence: usize,
+}
+
+impl Cost {
+    /// The default cost model, where only the number of bytes matters.
+    pub const BYTES: Cost = Cost { byte: 1, sequence: 0 };
+
+    /// Gets the cost of some output.
+    ///
+    /// ```
+    /// # use ansi_optimizer::Cost;
+    /// let cost = Cost { byte: 1, sequence: 10 };
+    /// assert_eq!(cost.measure("\x1B[1mbold\x1B[m"), 11 + 20);
+    /// ```
+    pub fn measure(&self, output: &str) -> usize {
+        let sequences = output.bytes().filter(|&b| b == 0x1B).count();
+        self.of(output.len(), sequences)
+    }
+
+    /// Gets the cost of output with a number of bytes and escape sequences.
+    #[inline]
+    pub fn of(&self, bytes: usize, sequences: usize) -> usize {
+        bytes.saturating_mul(self.byte).saturating_add(sequences.saturating_mul(self.sequence))
+    }
+}
+
+impl Default for Cost {
+    #[inline]
+    fn default() -> Self {
+        Cost::BYTES
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::cost::*;
+
+    #[test]
+    fn measure_output() {
+        assert_eq!(Cost::BYTES.measure("\x1B[31mred"), 8);
+        assert_eq!(Cost { byte: 2, sequence: 5 }.measure("\x1B[31mred\x1B[m"), 22 + 10);
+        assert_eq!(Cost::BYTES.of(usize::MAX, 1), usize::MAX);
+    }
+}
@@ -0,0 +1,245 @@
+use crate::ansi::ControlSequence;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A parsed control sequence, for the commands that are handled differently from others.
+///
+/// ```
+/// # use ansi_optimizer::ansi::Sequence;
+/// # use ansi_optimizer::csi::CsiCommand;
+/// # use std::convert::TryFrom;
+/// if let Ok(Sequence::CSI(csi)) = Sequence::try_from("\x1B[>4;2m") {
+///     match CsiCommand::from(&csi) {
+///         CsiCommand::Private(private) => assert_eq!(private.parameters, "4;2"),
+///         _ => unreachable!("modifyOtherKeys isn't SGR"),
+///     }
+/// }
+/// ```
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum CsiCommand<'a> {
+    /// Changes the style (`CSI params m`).
+    Sgr(&'a str),
+
+    /// Manipulates or reports on the terminal's window (XTWINOPS, `CSI Ps ; Ps ; Ps t`).
+    Window(WindowOp),
+
+    /// A sequence reserved for private use, which the optimizer passes through unchanged.
+    /// See [ControlSequence::is_private_use].
+    Private(PrivateCsi<'a>),
+
+    /// Any other control sequence.
+    Other,
+}
+
+/// A control sequence reserved for private use.
+///
+/// These have a private marker (`<`, `=`, `>`, or `?`), or a finalizer from `p` to `~`.
+/// Except for DEC private mode changes (`CSI ? mode h` and `CSI ? mode l`), the optimizer writes
+/// them exactly as they were read.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub struct PrivateCsi<'a> {
+    /// The private marker, if any.
+    pub marker: Option<char>,
+
+    /// The parameter bytes, after the private marker.
+    pub parameters: &'a str,
+
+    /// The intermediate bytes.
+    pub intermediates: &'a str,
+
+    /// The finalizer byte.
+    pub finalizer: &'a str,
+}
+
+/// A window operation (XTWINOPS), as supported by xterm.
+///
+/// Sizes and positions are in the order they're given in the sequence. Omitted sizes are left
+/// unchanged, and sizes of 0 are the size of the display.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum WindowOp {
+    /// Restores the window after it was iconified (`1`).
+    Deiconify,
+
+    /// Iconifies (minimizes) the window (`2`).
+    Iconify,
+
+    /// Moves the window to a position on the display, in pixels (`3 ; x ; y`).
+    Move { x: u16, y: u16 },
+
+    /// Resizes the window, in pixels (`4 ; height ; width`).
+    ResizePixels { height: Option<u16>, width: Option<u16> },
+
+    /// Raises the window to the front (`5`).
+    Raise,
+
+    /// Lowers the window to the back (`6`).
+    Lower,
+
+    /// Redraws the window (`7`).
+    Refresh,
+
+    /// Resizes the text area, in cells (`8 ; rows ; columns`).
+    ResizeCells { rows: Option<u16>, columns: Option<u16> },
+
+    /// Restores (`0`) or maximizes (`1` to `3`) the window (`9 ; mode`).
+    Maximize(u16),
+
+    /// Leaves (`0`), enters (`1`), or toggles (`2`) full-screen mode (`10 ; mode`).
+    Fullscreen(u16),
+
+    /// Asks the terminal to reply with the state, position, or size of the window, or with its
+    /// title (`11`, and `13` to `21`).
+    Report(u16),
+
+    /// Saves the icon name and/or window title on a stack (`22 ; which`).
+    PushTitle(u16),
+
+    /// Restores the icon name and/or window title from the stack (`23 ; which`).
+    PopTitle(u16),
+
+    /// Resizes the window to a number of lines (DECSLPP, `24` or more).
+    ResizeLines(u16),
+
+    /// An unknown or missing operation.
+    Other,
+}
+
+impl WindowOp {
+    /// Parses the parameters of a window operation.
+    fn parse(csi: &ControlSequence) -> Self {
+        let mut params = csi.params().map(|p| p.value());
+        let mut next = || params.next().flatten();
+        match next() {
+            Some(1) => WindowOp::Deiconify,
+            Some(2) => WindowOp::Iconify,
+            Some(3) => WindowOp::Move { x: next().unwrap_or(0), y: next().unwrap_or(0) },
+            Some(4) => WindowOp::ResizePixels { height: next(), width: next() },
+            Some(5) => WindowOp::Raise,
+            Some(6) => WindowOp::Lower,
+            Some(7) => WindowOp::Refresh,
+            Some(8) => WindowOp::ResizeCells { rows: next(), columns: next() },
+            Some(9) => WindowOp::Maximize(next().unwrap_or(0)),
+            Some(10) => WindowOp::Fullscreen(next().unwrap_or(0)),
+            Some(op @ 11) | Some(op @ 13..=21) => WindowOp::Report(op),
+            Some(22) => WindowOp::PushTitle(next().unwrap_or(0)),
+            Some(23) => WindowOp::PopTitle(next().unwrap_or(0)),
+            Some(lines @ 24..=u16::MAX) => WindowOp::ResizeLines(lines),
+            _ => WindowOp::Other,
+        }
+    }
+
+    /// Checks if the operation makes the terminal send a reply.
+    #[inline]
+    pub fn is_report(&self) -> bool {
+        matches!(self, WindowOp::Report(_))
+    }
+
+    /// Checks if the operation changes the size, position, or state of the window.
+    pub fn changes_window(&self) -> bool {
+        !matches!(
+            self,
+            WindowOp::Refresh
+                | WindowOp::Report(_)
+                | WindowOp::PushTitle(_)
+                | WindowOp::PopTitle(_)
+                | WindowOp::Other
+        )
+    }
+
+    /// Checks if doing the operation twice in a row is the same as doing it once.
+    pub fn is_idempotent(&self) -> bool {
+        match self {
+            WindowOp::Fullscreen(mode) => *mode != 2,
+            op => op.changes_window() || *op == WindowOp::Refresh,
+        }
+    }
+}
+
+impl<'a> From<&ControlSequence<'a>> for CsiCommand<'a> {
+    fn from(csi: &ControlSequence<'a>) -> Self {
+        if csi.private_marker().is_none() && (csi.intermediates(), csi.finalizer()) == ("", "t") {
+            return CsiCommand::Window(WindowOp::parse(csi));
+        }
+
+        if csi.is_private_use() {
+            let marker = csi.private_marker();
+            let parameters = csi.parameters();
+            return CsiCommand::Private(PrivateCsi {
+                marker,
+                parameters: &parameters[marker.map_or(0, char::len_utf8)..],
+                intermediates: csi.intermediates(),
+                finalizer: csi.finalizer(),
+            });
+        }
+
+        match (csi.intermediates(), csi.finalizer()) {
+            ("", "m") => CsiCommand::Sgr(csi.parameters()),
+            _ => CsiCommand::Other,
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::ansi::Sequence;
+    use crate::csi::*;
+    use std::convert::TryFrom;
+
+    fn command(s: &str) -> CsiCommand<'_> {
+        match Sequence::try_from(s) {
+            Ok(Sequence::CSI(csi)) => CsiCommand::from(&csi),
+            other => panic!("not a CSI sequence: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(command("\x1B[1;31m"), CsiCommand::Sgr("1;31"));
+        assert_eq!(command("\x1B[2J"), CsiCommand::Other);
+        assert_eq!(command("\x1B[1 m"), CsiCommand::Other);
+    }
+
+    #[test]
+    fn parse_window_ops() {
+        let window = |s| match command(s) {
+            CsiCommand::Window(op) => op,
+            other => panic!("not a window operation: {:?}", other),
+        };
+
+        assert_eq!(window("\x1B[2t"), WindowOp::Iconify);
+        assert_eq!(window("\x1B[3;10;20t"), WindowOp::Move { x: 10, y: 20 });
+        assert_eq!(window("\x1B[8;;80t"), WindowOp::ResizeCells { rows: None, columns: Some(80) });
+        assert_eq!(window("\x1B[10;2t"), WindowOp::Fullscreen(2));
+        assert_eq!(window("\x1B[21t"), WindowOp::Report(21));
+        assert_eq!(window("\x1B[22;2t"), WindowOp::PushTitle(2));
+        assert_eq!(window("\x1B[48t"), WindowOp::ResizeLines(48));
+        assert_eq!(window("\x1B[t"), WindowOp::Other);
+
+        assert!(WindowOp::Report(18).is_report());
+        assert!(WindowOp::Iconify.is_idempotent());
+        assert!(!WindowOp::Fullscreen(2).is_idempotent());
+        assert!(!WindowOp::PopTitle(0).is_idempotent());
+    }
+
+    #[test]
+    fn parse_private() {
+        let private = |marker, parameters, intermediates, finalizer| {
+            CsiCommand::Private(PrivateCsi { marker, parameters, intermediates, finalizer })
+        };
+
+        assert_eq!(command("\x1B[>4;2m"), private(Some('>'), "4;2", "", "m"));
+        assert_eq!(command("\x1B[?25$p"), private(Some('?'), "25", "$", "p"));
+        assert_eq!(command("\x1B[=1u"), private(Some('='), "1", "", "u"));
+        assert_eq!(command("\x1B[2 q"), private(None, "2", " ", "q"));
+    }
+
+    #[cfg(feature = "optimize")]
+    #[test]
+    fn private_passed_through() {
+        for input in ["\x1B[>4;02m", "\x1B[?1;02p", "\x1B[>0001q", "\x1B[<1;2;3~"] {
+            assert_eq!(crate::optimize(input), Ok(input.to_owned()));
+        }
+    }
+}
@@ -0,0 +1,816 @@
+use crate::ansi::ControlSequence;
+use crate::cost::Cost;
+use crate::width::{self, WidthPolicy};
+use crate::Options;
+use std::fmt::Write;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A cursor position, where each coordinate may be unknown.
+///
+/// Rows and columns are 1-based, like the parameters of the sequences that move the cursor.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Position {
+    pub row: Option<u16>,
+    pub column: Option<u16>,
+}
+
+impl Position {
+    /// A position where neither coordinate is known.
+    pub const UNKNOWN: Position = Position { row: None, column: None };
+
+    /// Gets the row and column, if both are known.
+    #[inline]
+    pub fn known(&self) -> Option<(u16, u16)> {
+        Some((self.row?, self.column?))
+    }
+}
+
+/// A model of the terminal cursor.
+///
+/// The model is conservative: when the effect of a sequence on the cursor isn't known exactly,
+/// the affected coordinates become unknown. Unless the size of the screen is known, this includes
+/// writing text that wraps (or may wrap) and moving towards the bottom edge.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Cursor {
+    pub position: Position,
+    saved: Position,
+
+    /// Whether scrolling margins or origin mode may be in effect.
+    /// When they are, relative vertical movement may stop at a margin.
+    margins: bool,
+
+    /// The width of the screen, if known.
+    columns: Option<u16>,
+
+    /// The height of the screen, if known.
+    rows: Option<u16>,
+
+    /// Whether text wraps at the right edge of the screen (DECAWM), if known.
+    autowrap: Option<bool>,
+
+    /// Whether text wraps when the cursor is reset.
+    initial_autowrap: Option<bool>,
+
+    tabs: TabStops,
+
+    /// How text that terminals disagree on the width of is measured.
+    width_policy: WidthPolicy,
+}
+
+/// The columns that tabs move the cursor to.
+#[derive(Clone, Debug)]
+struct TabStops {
+    /// The interval between the initial tab stops, or 0 if they were cleared.
+    interval: u16,
+
+    /// The interval that the tab stops are reset to.
+    initial: u16,
+
+    /// Columns where a tab stop was set (HTS).
+    set: Vec<u16>,
+
+    /// Columns where a tab stop was cleared (TBC).
+    cleared: Vec<u16>,
+
+    /// Whether the tab stops are known.
+    /// They aren't if one was set or cleared while the cursor column was unknown.
+    known: bool,
+}
+
+impl Default for TabStops {
+    fn default() -> Self {
+        TabStops::new(8)
+    }
+}
+
+impl TabStops {
+    fn new(interval: u16) -> Self {
+        TabStops { interval, initial: interval, set: Vec::new(), cleared: Vec::new(), known: true }
+    }
+
+    /// Checks if there is a tab stop at a column.
+    fn is_stop(&self, column: u16) -> bool {
+        let initial = column > 1 && (column - 1).checked_rem(self.interval) == Some(0);
+        (initial || self.set.contains(&column)) && !self.cleared.contains(&column)
+    }
+
+    /// Sets a tab stop at a column.
+    fn set(&mut self, column: Option<u16>) {
+        match column {
+            Some(column) => {
+                self.cleared.retain(|&c| c != column);
+                self.set.push(column);
+            }
+            None => self.known = false,
+        }
+    }
+
+    /// Clears the tab stop at a column.
+    fn clear(&mut self, column: Option<u16>) {
+        match column {
+            Some(column) => {
+                self.set.retain(|&c| c != column);
+                self.cleared.push(column);
+            }
+            None => self.known = false,
+        }
+    }
+
+    /// Clears every tab stop.
+    fn clear_all(&mut self) {
+        *self = TabStops { interval: 0, known: true, ..TabStops::new(self.initial) };
+    }
+
+    /// Resets the tab stops back to the initial ones.
+    fn reset(&mut self) {
+        self.interval = self.initial;
+        self.set.clear();
+        self.cleared.clear();
+        self.known = true;
+    }
+}
+
+impl Cursor {
+    /// Creates a cursor for the screen described by the [Options].
+    pub fn new(options: &Options) -> Self {
+        Cursor {
+            columns: options.columns,
+            rows: options.rows,
+            autowrap: options.autowrap,
+            initial_autowrap: options.autowrap,
+            tabs: TabStops::new(options.tab_width.unwrap_or(8)),
+            width_policy: options.width_policy,
+            ..Cursor::default()
+        }
+    }
+
+    /// Changes the size of the screen.
+    ///
+    /// Terminals may reflow text when they are resized, so the cursor position becomes unknown.
+    pub fn resize(&mut self, columns: u16, rows: u16) {
+        self.columns = Some(columns);
+        self.rows = Some(rows);
+        self.position = Position::UNKNOWN;
+        self.saved = Position::UNKNOWN;
+    }
+
+    /// Gets the row below a row, which is the same row if it's at the bottom of the screen.
+    fn row_below(&self, row: Option<u16>, count: u16) -> Option<u16> {
+        match self.rows {
+            Some(rows) if !self.margins => Some(row?.saturating_add(count).min(rows)),
+            _ => None,
+        }
+    }
+
+    /// Checks if relative movement can be used to move between known positions.
+    #[inline]
+    pub fn allows_relative(&self) -> bool {
+        !self.margins
+    }
+
+    /// Checks if a newline can be used to move down a row.
+    ///
+    /// Unlike the sequences that move the cursor, a newline at the bottom of the screen scrolls,
+    /// so the number of rows needs to be known.
+    #[inline]
+    pub fn allows_newline(&self) -> bool {
+        !self.margins && self.rows.is_some()
+    }
+
+    /// Updates the cursor after writing text.
+    pub fn update_text(&mut self, text: &str) {
+        for cluster in width::clusters(text) {
+            if cluster.starts_with(char::is_control) {
+                // A CR LF pair is a single cluster.
+                cluster.chars().for_each(|c| self.update_control(c));
+                continue;
+            }
+
+            match width::cluster_width(cluster, &self.width_policy) {
+                Some(width) => self.update_printed(width),
+                None => self.position = Position::UNKNOWN,
+            }
+        }
+    }
+
+    /// Updates the cursor after a control character.
+    fn update_control(&mut self, c: char) {
+        let column = self.position.column;
+        match c {
+            '\r' => self.position.column = Some(1),
+            '\x08' => self.position.column = column.map(|c| c.saturating_sub(1).max(1)),
+            '\t' => self.position.column = column.and_then(|c| self.next_tab(c, 1)),
+
+            // Line feeds may scroll instead of moving down, and are translated into CR LF by
+            // most terminals, but not all of them.
+            '\n' | '\x0B' | '\x0C' => {
+                self.position.row = self.row_below(self.position.row, 1);
+                self.position.column = column.filter(|&c| c == 1);
+            }
+
+            _ => {}
+        }
+    }
+
+    /// Updates the cursor after printing a character that takes up `width` cells.
+    ///
+    /// Printing in the last column leaves the cursor there, and the next character wraps onto the
+    /// next line first. Since moving the cursor cancels that, the column is treated as unknown.
+    /// Wide characters that don't fit in the last column are wrapped differently by different
+    /// terminals, so the position is unknown after them.
+    fn update_printed(&mut self, width: u16) {
+        if width == 0 {
+            return;
+        }
+
+        let end = self.position.column.map(|column| column.saturating_add(width - 1));
+        match (end, self.columns) {
+            (Some(end), Some(columns)) if end < columns => {
+                self.position.column = Some(end + 1);
+            }
+            (Some(end), Some(columns)) if end == columns && self.autowrap == Some(false) => {
+                self.position.column = Some(columns);
+            }
+            (Some(end), Some(columns)) if end == columns => self.position.column = None,
+            _ => self.position = Position::UNKNOWN,
+        }
+    }
+
+    /// Sets whether text wraps at the right edge of the screen (DECAWM).
+    #[inline]
+    pub fn set_autowrap(&mut self, autowrap: bool) {
+        self.autowrap = Some(autowrap);
+    }
+
+    /// Gets the column that the cursor moves to after tabbing forward.
+    fn next_tab(&self, column: u16, count: u16) -> Option<u16> {
+        let columns = self.columns.filter(|_| self.tabs.known)?;
+        let next = |column: u16| ((column + 1)..=columns).find(|&c| self.tabs.is_stop(c));
+        (0..count).try_fold(column, |column, _| Some(next(column).unwrap_or(columns)))
+    }
+
+    /// Gets the column that the cursor moves to after tabbing backward.
+    fn previous_tab(&self, column: u16, count: u16) -> Option<u16> {
+        if !self.tabs.known {
+            return None;
+        }
+
+        let previous = |column: u16| (1..column).rev().find(|&c| self.tabs.is_stop(c));
+        (0..count).try_fold(column, |column, _| Some(previous(column).unwrap_or(1)))
+    }
+
+    /// Sets a tab stop at the cursor column (HTS).
+    #[inline]
+    pub fn set_tab_stop(&mut self) {
+        self.tabs.set(self.position.column);
+    }
+
+    /// Updates the cursor after a control sequence.
+    pub fn update_csi(&mut self, csi: &ControlSequence) {
+        if !csi.intermediates().is_empty() {
+            // A soft reset (DECSTR) may change auto-wrap.
+            if (csi.intermediates(), csi.finalizer()) == ("!", "p") {
+                self.autowrap = None;
+            }
+
+            self.position = Position::UNKNOWN;
+            return;
+        }
+
+        let mut params = csi.params();
+        let mut next = |default: u16| match params.next().map(|p| p.value_or(default)) {
+            None | Some(0) => default,
+            Some(n) => n,
+        };
+
+        let (row, column) = (self.position.row, self.position.column);
+        match (csi.private_marker(), csi.finalizer()) {
+            (None, "B") | (None, "e") => {
+                let count = next(1);
+                self.position.row = self.row_below(row, count);
+                return;
+            }
+
+            (None, "E") => {
+                let count = next(1);
+                self.position = Position { row: self.row_below(row, count), column: Some(1) };
+                return;
+            }
+
+            (None, "I") => {
+                let count = next(1);
+                self.position.column = column.and_then(|c| self.next_tab(c, count));
+                return;
+            }
+
+            (None, "Z") => {
+                let count = next(1);
+                self.position.column = column.and_then(|c| self.previous_tab(c, count));
+                return;
+            }
+
+            // Clearing tab stops (TBC) doesn't move the cursor.
+            (None, "g") => {
+                match params.next().map(|p| p.value_or(0)) {
+                    None | Some(0) => self.tabs.clear(column),
+                    Some(3) => self.tabs.clear_all(),
+                    Some(_) => {}
+                }
+
+                return;
+            }
+
+            _ => {}
+        }
+
+        let (position, columns, rows) = (&mut self.position, self.columns, self.rows);
+        match (csi.private_marker(), csi.finalizer()) {
+            (None, "H") | (None, "f") => {
+                position.row = Some(next(1));
+                position.column = Some(next(1));
+            }
+
+            (None, "G") | (None, "`") => position.column = Some(next(1)),
+            (None, "d") => position.row = Some(next(1)),
+            (None, "C") | (None, "a") => {
+                let n = next(1);
+                position.column =
+                    columns.and_then(|w| Some(position.column?.saturating_add(n).min(w)));
+            }
+
+            (None, "D") => {
+                position.column = position.column.map(|c| c.saturating_sub(next(1)).max(1))
+            }
+            (None, "A") if !self.margins => {
+                position.row = position.row.map(|r| r.saturating_sub(next(1)).max(1));
+            }
+
+            (None, "F") if !self.margins => {
+                position.row = position.row.map(|r| r.saturating_sub(next(1)).max(1));
+                position.column = Some(1);
+            }
+
+            (None, "A") => position.row = None,
+            (None, "F") => *position = Position { row: None, column: Some(1) },
+
+            // Setting the margins moves the cursor to the home position.
+            (None, "r") => {
+                self.margins = !csi.parameters().is_empty();
+                *position = Position::UNKNOWN;
+            }
+
+            // Sequences that don't move the cursor.
+            (None, "m") | (None, "J") | (None, "K") | (None, "X") | (None, "n") => {}
+            (Some('?'), "J") | (Some('?'), "K") => {}
+
+            // Origin mode makes positions relative to the margins.
+            (Some('?'), "h") | (Some('?'), "l") if csi.parameters() == "?6" => {
+                self.margins = true;
+                *position = Position::UNKNOWN;
+            }
+
+            (Some('?'), "h") | (Some('?'), "l") => {
+                if csi.params().any(|p| p.value() == Some(7)) {
+                    self.autowrap = Some(csi.finalizer() == "h");
+                }
+
+                *position = Position::UNKNOWN;
+            }
+
+            _ => *position = Position::UNKNOWN,
+        }
+
+        // The cursor stops at the edges of the screen.
+        if let (Some(column), Some(columns)) = (position.column, columns) {
+            position.column = Some(column.min(columns));
+        }
+
+        if let (Some(row), Some(rows)) = (position.row, rows) {
+            position.row = Some(row.min(rows));
+        }
+    }
+
+    /// Saves the cursor position (DECSC).
+    #[inline]
+    pub fn save(&mut self) {
+        self.saved = self.position;
+    }
+
+    /// Restores the saved cursor position (DECRC).
+    #[inline]
+    pub fn restore(&mut self) {
+        self.position = self.saved;
+    }
+
+    /// Marks the cursor position as unknown.
+    #[inline]
+    pub fn invalidate(&mut self) {
+        self.position = Position::UNKNOWN;
+    }
+
+    /// Resets the cursor back to a default state.
+    #[inline]
+    pub fn reset(&mut self) {
+        let mut tabs = std::mem::take(&mut self.tabs);
+        tabs.reset();
+        *self = Cursor {
+            columns: self.columns,
+            rows: self.rows,
+            autowrap: self.initial_autowrap,
+            initial_autowrap: self.initial_autowrap,
+            tabs,
+            width_policy: self.width_policy,
+            ..Cursor::default()
+        };
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// A single step of cursor movement.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Step {
+    None,
+    Absolute(u16, u16),
+    Row(u16),
+    Column(u16),
+    Up(u16),
+    Down(u16),
+    Forward(u16),
+    Back(u16),
+    Backspaces(u16),
+    CarriageReturn,
+    NewLine,
+}
+
+impl Step {
+    /// Gets the number of bytes needed to write the step.
+    fn len(&self) -> usize {
+        fn param(n: u16, default: u16) -> usize {
+            if n == default {
+                0
+            } else {
+                digits(n)
+            }
+        }
+
+        match *self {
+            Step::None => 0,
+            Step::Absolute(1, 1) => 3,
+            Step::Absolute(1, column) => 4 + digits(column),
+            Step::Absolute(row, 1) => 3 + digits(row),
+            Step::Absolute(row, column) => 4 + digits(row) + digits(column),
+            Step::Row(n) | Step::Column(n) => 3 + param(n, 1),
+            Step::Up(n) | Step::Down(n) | Step::Forward(n) | Step::Back(n) => 3 + param(n, 1),
+            Step::Backspaces(n) => n as usize,
+            Step::CarriageReturn => 1,
+            Step::NewLine => 2,
+        }
+    }
+
+    /// Gets the cost of writing the step.
+    fn cost(&self, cost: &Cost) -> usize {
+        let sequences = match self {
+            Step::None | Step::Backspaces(_) | Step::CarriageReturn | Step::NewLine => 0,
+            _ => 1,
+        };
+
+        cost.of(self.len(), sequences)
+    }
+
+    /// Writes the step.
+    fn write<W: Write>(&self, out: &mut W) -> std::fmt::Result {
+        fn csi<W: Write>(out: &mut W, n: u16, finalizer: char) -> std::fmt::Result {
+            match n {
+                1 => write!(out, "\x1B[{}", finalizer),
+                n => write!(out, "\x1B[{}{}", n, finalizer),
+            }
+        }
+
+        match *self {
+            Step::None => Ok(()),
+            Step::Absolute(1, 1) => out.write_str("\x1B[H"),
+            Step::Absolute(row, 1) => write!(out, "\x1B[{}H", row),
+            Step::Absolute(1, column) => write!(out, "\x1B[;{}H", column),
+            Step::Absolute(row, column) => write!(out, "\x1B[{};{}H", row, column),
+            Step::Row(n) => csi(out, n, 'd'),
+            Step::Column(n) => csi(out, n, 'G'),
+            Step::Up(n) => csi(out, n, 'A'),
+            Step::Down(n) => csi(out, n, 'B'),
+            Step::Forward(n) => csi(out, n, 'C'),
+            Step::Back(n) => csi(out, n, 'D'),
+            Step::Backspaces(n) => (0..n).try_for_each(|_| out.write_char('\x08')),
+            Step::CarriageReturn => out.write_char('\r'),
+            Step::NewLine => out.write_str("\r\n"),
+        }
+    }
+}
+
+/// Gets the number of decimal digits in a number.
+#[inline]
+pub(crate) fn digits(n: u16) -> usize {
+    match n {
+        0..=9 => 1,
+        10..=99 => 2,
+        100..=999 => 3,
+        1000..=9999 => 4,
+        _ => 5,
+    }
+}
+
+/// Picks the cheaper of two steps, preferring the first.
+#[inline]
+fn cheapest(a: Step, b: Step, cost: &Cost) -> Step {
+    if b.cost(cost) < a.cost(cost) {
+        b
+    } else {
+        a
+    }
+}
+
+/// Writes the cheapest sequence that moves the cursor between two positions.
+///
+/// This considers absolute movement (CUP, CHA, VPA), relative movement (CUU, CUD, CUF, CUB), and
+/// the CR, BS, and LF control characters. A newline is only ever written after a carriage return,
+/// so the output is the same whether or not the terminal translates LF into CR LF.
+///
+/// ## Arguments
+///
+/// - `from`: The current position. Unknown coordinates can only be reached with absolute movement.
+/// - `to`: The target row and column.
+/// - `relative`: Whether relative vertical movement can be used.
+/// - `newline`: Whether a newline can be used to move down a row, which needs the target row to
+///   be on the screen. See [Cursor::allows_newline].
+/// - `cost`: The cost model.
+/// - `out`: The output.
+pub(crate) fn write_move<W: Write>(
+    from: Position,
+    to: (u16, u16),
+    relative: bool,
+    newline: bool,
+    cost: &Cost,
+    out: &mut W,
+) -> std::fmt::Result {
+    let (row, column) = to;
+    let vertical = match from.row {
+        Some(r) if r == row => Step::None,
+        Some(r) if relative && r > row => cheapest(Step::Row(row), Step::Up(r - row), cost),
+        Some(r) if relative => cheapest(Step::Row(row), Step::Down(row - r), cost),
+        _ => Step::Row(row),
+    };
+
+    let horizontal = match from.column {
+        Some(c) if c == column => Step::None,
+        _ if column == 1 => Step::CarriageReturn,
+        Some(c) if c > column => {
+            let back = cheapest(Step::Back(c - column), Step::Backspaces(c - column), cost);
+            cheapest(Step::Column(column), back, cost)
+        }
+        Some(c) => cheapest(Step::Column(column), Step::Forward(column - c), cost),
+        None => Step::Column(column),
+    };
+
+    let absolute = Step::Absolute(row, column);
+    let split_cost = vertical.cost(cost) + horizontal.cost(cost);
+    if newline
+        && column == 1
+        && from.row == Some(row.wrapping_sub(1))
+        && Step::NewLine.cost(cost) < absolute.cost(cost).min(split_cost)
+    {
+        return Step::NewLine.write(out);
+    }
+
+    if split_cost < absolute.cost(cost) {
+        vertical.write(out)?;
+        horizontal.write(out)
+    } else {
+        absolute.write(out)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::ansi::Sequence;
+    use crate::cursor::*;
+    use std::convert::TryFrom;
+
+    fn at(row: u16, column: u16) -> Position {
+        Position { row: Some(row), column: Some(column) }
+    }
+
+    fn moved(from: Position, to: (u16, u16)) -> String {
+        let mut out = String::new();
+        write_move(from, to, true, true, &Cost::BYTES, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn write_move_shortest() {
+        assert_eq!(moved(Position::UNKNOWN, (1, 1)), "\x1B[H");
+        assert_eq!(moved(Position::UNKNOWN, (5, 10)), "\x1B[5;10H");
+        assert_eq!(moved(at(5, 3), (5, 3)), "");
+        assert_eq!(moved(at(5, 3), (5, 1)), "\r");
+        assert_eq!(moved(at(5, 3), (5, 2)), "\x08");
+        assert_eq!(moved(at(5, 30), (5, 20)), "\x1B[20G");
+        assert_eq!(moved(at(5, 3), (5, 4)), "\x1B[C");
+        assert_eq!(moved(at(5, 3), (4, 3)), "\x1B[A");
+        assert_eq!(moved(at(5, 3), (6, 1)), "\r\n");
+        assert_eq!(moved(at(5, 3), (7, 1)), "\x1B[7H");
+        assert_eq!(moved(at(5, 30), (6, 29)), "\x1B[B\x08");
+        assert_eq!(moved(at(50, 3), (1, 80)), "\x1B[;80H");
+        assert_eq!(moved(at(50, 3), (1, 1)), "\x1B[H");
+
+        let mut out = String::new();
+        write_move(at(5, 3), (4, 3), false, false, &Cost::BYTES, &mut out).unwrap();
+        assert_eq!(out, "\x1B[4d");
+
+        // Without the number of rows, a newline might scroll instead of moving down.
+        out.clear();
+        write_move(at(24, 2), (25, 1), true, false, &Cost::BYTES, &mut out).unwrap();
+        assert_eq!(out, "\x1B[B\r");
+    }
+
+    #[test]
+    fn step_len() {
+        let steps = [
+            Step::Absolute(1, 1),
+            Step::Absolute(1, 80),
+            Step::Absolute(24, 1),
+            Step::Absolute(24, 80),
+            Step::Row(1),
+            Step::Column(12),
+            Step::Back(3),
+            Step::Backspaces(3),
+            Step::NewLine,
+        ];
+
+        for step in &steps {
+            let mut out = String::new();
+            step.write(&mut out).unwrap();
+            assert_eq!(step.len(), out.len(), "{:?}", step);
+        }
+    }
+
+    #[test]
+    fn cursor_update() {
+        let mut cursor = Cursor::default();
+        let update = |cursor: &mut Cursor, sequence: &str| match Sequence::try_from(sequence) {
+            Ok(Sequence::CSI(csi)) => cursor.update_csi(&csi),
+            _ => unreachable!(),
+        };
+
+        update(&mut cursor, "\x1B[5;10H");
+        assert_eq!(cursor.position, at(5, 10));
+        update(&mut cursor, "\x1B[3D");
+        assert_eq!(cursor.position, at(5, 7));
+        update(&mut cursor, "\x1B[10A");
+        assert_eq!(cursor.position, at(1, 7));
+        update(&mut cursor, "\x1B[2K");
+        assert_eq!(cursor.position, at(1, 7));
+        update(&mut cursor, "\x1B[B");
+        assert_eq!(cursor.position.row, None);
+
+        cursor.update_text("abc\r");
+        assert_eq!(cursor.position.known(), None);
+        assert_eq!(cursor.position.column, Some(1));
+
+        cursor.update_text("\x08\x08\n");
+        assert_eq!(cursor.position.known(), None);
+        assert_eq!(cursor.position.column, Some(1));
+
+        update(&mut cursor, "\x1B[3;20r");
+        update(&mut cursor, "\x1B[5;10H");
+        update(&mut cursor, "\x1B[A");
+        assert_eq!(cursor.position.row, None);
+        assert!(!cursor.allows_relative());
+    }
+
+    #[test]
+    fn cursor_tabs() {
+        let mut cursor = Cursor::new(&Options { columns: Some(30), ..Options::default() });
+        let update = |cursor: &mut Cursor, sequence: &str| match Sequence::try_from(sequence) {
+            Ok(Sequence::CSI(csi)) => cursor.update_csi(&csi),
+            _ => unreachable!(),
+        };
+
+        update(&mut cursor, "\x1B[2;1H");
+        cursor.update_text("\t");
+        assert_eq!(cursor.position, at(2, 9));
+        cursor.update_text("\t\t\t\t");
+        assert_eq!(cursor.position, at(2, 30));
+        cursor.update_text("\r\t\x08");
+        assert_eq!(cursor.position, at(2, 8));
+
+        // Set a stop at column 4, and clear the one at column 9.
+        update(&mut cursor, "\x1B[4G");
+        cursor.set_tab_stop();
+        update(&mut cursor, "\x1B[9G");
+        update(&mut cursor, "\x1B[g");
+        cursor.update_text("\r\t");
+        assert_eq!(cursor.position, at(2, 4));
+        cursor.update_text("\t");
+        assert_eq!(cursor.position, at(2, 17));
+        update(&mut cursor, "\x1B[2Z");
+        assert_eq!(cursor.position, at(2, 1));
+        update(&mut cursor, "\x1B[2I");
+        assert_eq!(cursor.position, at(2, 17));
+
+        update(&mut cursor, "\x1B[3g");
+        cursor.update_text("\r\t");
+        assert_eq!(cursor.position, at(2, 30));
+
+        // Setting a stop at an unknown column makes every stop unknown.
+        cursor.update_text("a");
+        cursor.set_tab_stop();
+        cursor.update_text("\r\t");
+        assert_eq!(cursor.position.column, None);
+
+        // Without the width of the screen, tabs might stop at its edge.
+        let mut cursor = Cursor::default();
+        update(&mut cursor, "\x1B[H");
+        cursor.update_text("\t");
+        assert_eq!(cursor.position.column, None);
+    }
+
+    #[test]
+    fn cursor_wraps() {
+        let mut cursor =
+            Cursor::new(&Options { columns: Some(10), autowrap: Some(true), ..Options::default() });
+        let update = |cursor: &mut Cursor, sequence: &str| match Sequence::try_from(sequence) {
+            Ok(Sequence::CSI(csi)) => cursor.update_csi(&csi),
+            _ => unreachable!(),
+        };
+
+        update(&mut cursor, "\x1B[3;1H");
+        cursor.update_text("abcdefghi");
+        assert_eq!(cursor.position, at(3, 10));
+        update(&mut cursor, "\x1B[5C");
+        assert_eq!(cursor.position, at(3, 10));
+
+        // Printing in the last column might wrap the next character.
+        cursor.update_text("j");
+        assert_eq!(cursor.position, Position { row: Some(3), column: None });
+        cursor.update_text("\r");
+        assert_eq!(cursor.position, at(3, 1));
+        cursor.update_text("abcdefghijk");
+        assert_eq!(cursor.position, Position::UNKNOWN);
+
+        update(&mut cursor, "\x1B[?7l");
+        update(&mut cursor, "\x1B[3;8H");
+        cursor.update_text("abcdefghi");
+        assert_eq!(cursor.position, at(3, 10));
+
+        update(&mut cursor, "\x1B[3;40H");
+        assert_eq!(cursor.position, at(3, 10));
+        cursor.update_text("\u{65e5}");
+        assert_eq!(cursor.position, Position::UNKNOWN);
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn cursor_wide_characters() {
+        let mut cursor = Cursor::new(&Options { columns: Some(10), ..Options::default() });
+        match Sequence::try_from("\x1B[2;1H") {
+            Ok(Sequence::CSI(csi)) => cursor.update_csi(&csi),
+            _ => unreachable!(),
+        }
+
+        cursor.update_text("日本e\u{301}");
+        assert_eq!(cursor.position, at(2, 6));
+        cursor.update_text("\u{301}");
+        assert_eq!(cursor.position, at(2, 6));
+        cursor.update_text("👍👍");
+        assert_eq!(cursor.position, at(2, 10));
+
+        // Terminals disagree on the width of emoji sequences.
+        cursor.update_text("\r👩\u{200D}💻");
+        assert_eq!(cursor.position, Position::UNKNOWN);
+    }
+
+    #[test]
+    fn cursor_resize() {
+        let mut cursor = Cursor::new(&Options { rows: Some(24), ..Options::default() });
+        let update = |cursor: &mut Cursor, sequence: &str| match Sequence::try_from(sequence) {
+            Ok(Sequence::CSI(csi)) => cursor.update_csi(&csi),
+            _ => unreachable!(),
+        };
+
+        update(&mut cursor, "\x1B[20;1H");
+        cursor.update_text("\n\n");
+        assert_eq!(cursor.position, at(22, 1));
+        update(&mut cursor, "\x1B[5B");
+        assert_eq!(cursor.position, at(24, 1));
+        cursor.update_text("\n");
+        assert_eq!(cursor.position, at(24, 1));
+
+        cursor.resize(100, 10);
+        assert_eq!(cursor.position, Position::UNKNOWN);
+        update(&mut cursor, "\x1B[50;1H");
+        assert_eq!(cursor.position, at(10, 1));
+        update(&mut cursor, "\x1B[10E");
+        assert_eq!(cursor.position, at(10, 1));
+    }
+}
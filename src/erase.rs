@@ -0,0 +1,196 @@
+use crate::cost::Cost;
+use crate::cursor::digits;
+use crate::style::{Attributes, Style, Underline};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Attributes that are visible on a space character.
+const VISIBLE_ON_SPACE: Attributes =
+    Attributes::REVERSE.union(Attributes::STRIKETHROUGH).union(Attributes::OVERLINE);
+
+/// Checks if spaces written with a style look the same as erased cells.
+///
+/// Erased cells are filled with the current background color, but none of the other attributes.
+/// Attributes that only affect the glyph (e.g. bold or italic) aren't visible on a space, so they
+/// don't matter.
+pub(crate) fn is_erasable(style: &Style) -> bool {
+    !style.attributes.intersects(VISIBLE_ON_SPACE) && style.underline == Underline::None
+}
+
+/// Writes text, replacing runs of spaces with erase sequences when cheaper.
+///
+/// A run of spaces is replaced with ECH (erase characters) followed by CUF (cursor forward), or
+/// just ECH if the cursor is moved back to the start of the line afterwards. If the run ends at
+/// the last column before a carriage return, it's replaced with EL (erase in line).
+///
+/// Only runs that are known not to wrap are replaced. Processing stops at the first character
+/// that might not be one column wide.
+///
+/// ## Arguments
+///
+/// - `text`: The text.
+/// - `column`: The column of the cursor, starting from 1.
+/// - `columns`: The width of the terminal.
+/// - `cost`: The cost model.
+/// - `out`: The output.
+/// - `literal`: A function that writes the text between the replaced runs.
+pub(crate) fn write_compressed(
+    text: &str,
+    column: u16,
+    columns: u16,
+    cost: &Cost,
+    out: &mut String,
+    mut literal: impl FnMut(&str, &mut String),
+) {
+    let bytes = text.as_bytes();
+    let columns = columns as usize;
+    let mut column = column as usize;
+    let mut written = 0;
+    let mut i = 0;
+
+    while i < bytes.len() && column <= columns {
+        match bytes[i] {
+            b' ' => {
+                let start = i;
+                while bytes.get(i) == Some(&b' ') {
+                    i += 1;
+                }
+
+                let count = i - start;
+                let last = column + count - 1;
+                let before_cr = bytes.get(i) == Some(&b'\r');
+                let replacement = match u16::try_from(count) {
+                    _ if last == columns && before_cr => Erase::Line,
+                    Ok(n) if last < columns && before_cr => Erase::Characters(n, false),
+                    Ok(n) if last < columns => Erase::Characters(n, true),
+                    _ => Erase::None,
+                };
+
+                if replacement.cost(cost) < cost.of(count, 0) {
+                    literal(&text[written..start], out);
+                    replacement.write(out).expect("write to string");
+                    written = i;
+                }
+
+                column += count;
+            }
+
+            0x21..=0x7E => {
+                column += 1;
+                i += 1;
+            }
+
+            _ => break,
+        }
+    }
+
+    literal(&text[written..], out);
+}
+
+/// Checks if ECH (erase characters) followed by CUF (cursor forward) with the same count is
+/// more expensive than the spaces it's equivalent to.
+pub(crate) fn is_expandable(count: u16, cost: &Cost) -> bool {
+    cost.of(count as usize, 0) < Erase::Characters(count, true).cost(cost)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// A sequence that erases a run of spaces.
+enum Erase {
+    None,
+    Line,
+
+    /// ECH, optionally followed by CUF with the same count.
+    Characters(u16, bool),
+}
+
+impl Erase {
+    /// Gets the number of bytes needed to write the sequence.
+    fn len(&self) -> usize {
+        match *self {
+            Erase::None => usize::MAX,
+            Erase::Line => 3,
+            Erase::Characters(n, forward) => {
+                let len = if n == 1 { 3 } else { 3 + digits(n) };
+                if forward {
+                    len * 2
+                } else {
+                    len
+                }
+            }
+        }
+    }
+
+    /// Gets the cost of writing the sequence.
+    fn cost(&self, cost: &Cost) -> usize {
+        let sequences = match *self {
+            Erase::None | Erase::Line | Erase::Characters(_, false) => 1,
+            Erase::Characters(_, true) => 2,
+        };
+
+        cost.of(self.len(), sequences)
+    }
+
+    /// Writes the sequence.
+    fn write<W: Write>(&self, out: &mut W) -> std::fmt::Result {
+        match *self {
+            Erase::None => Ok(()),
+            Erase::Line => out.write_str("\x1B[K"),
+            Erase::Characters(1, false) => out.write_str("\x1B[X"),
+            Erase::Characters(1, true) => out.write_str("\x1B[X\x1B[C"),
+            Erase::Characters(n, false) => write!(out, "\x1B[{}X", n),
+            Erase::Characters(n, true) => write!(out, "\x1B[{}X\x1B[{}C", n, n),
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::erase::*;
+    use crate::Color;
+
+    fn compressed(text: &str, column: u16) -> String {
+        let mut out = String::new();
+        write_compressed(text, column, 80, &Cost::BYTES, &mut out, |text, out| out.push_str(text));
+        out
+    }
+
+    #[test]
+    fn compress_spaces() {
+        let spaces = " ".repeat(20);
+        assert_eq!(compressed("a  b", 1), "a  b");
+        assert_eq!(compressed(&format!("a{}b", spaces), 1), "a\x1B[20X\x1B[20Cb");
+        assert_eq!(compressed(&format!("a{}\r\n", spaces), 1), "a\x1B[20X\r\n");
+        assert_eq!(compressed(&format!("a{}\r\n", spaces), 60), "a\x1B[K\r\n");
+
+        // Runs that might wrap are kept.
+        assert_eq!(compressed(&format!("a{}b", spaces), 70), format!("a{}b", spaces));
+        assert_eq!(compressed(&format!("é{}b", spaces), 1), format!("é{}b", spaces));
+    }
+
+    #[test]
+    fn erasable_styles() {
+        let mut style = Style {
+            attributes: Attributes::BOLD,
+            background: Color::Indexed(4),
+            ..Style::default()
+        };
+
+        assert!(is_erasable(&style));
+
+        style.attributes.insert(Attributes::REVERSE);
+        assert!(!is_erasable(&style));
+    }
+
+    #[test]
+    fn expandable() {
+        assert!(is_expandable(1, &Cost::BYTES));
+        assert!(is_expandable(7, &Cost::BYTES));
+        assert!(!is_expandable(8, &Cost::BYTES));
+        assert!(is_expandable(20, &Cost { byte: 1, sequence: 8 }));
+    }
+}
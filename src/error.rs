@@ -3,4 +3,6 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(PartialEq, Debug)]
 pub enum Error {
     InvalidSequence,
+    Incomplete,
+    LimitExceeded,
 }
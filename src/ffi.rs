@@ -0,0 +1,159 @@
+//! C bindings for embedding the optimizer in programs that aren't written in Rust.
+//!
+//! This is behind the `capi` feature. The header for it is `include/ansi_optimizer.h`, which is
+//! generated by `cbindgen` using the `cbindgen.toml` at the root of the repository.
+//!
+//! ```c
+//! AnsiOptimizer *optimizer = ansi_optimizer_new();
+//! ansi_optimizer_feed(optimizer, input, input_len);
+//! ansi_optimizer_finish(optimizer);
+//!
+//! uint8_t buffer[4096];
+//! size_t len;
+//! while ((len = ansi_optimizer_take_output(optimizer, buffer, sizeof(buffer))) > 0) {
+//!     fwrite(buffer, 1, len, stdout);
+//! }
+//!
+//! ansi_optimizer_free(optimizer);
+//! ```
+
+use crate::bytes::ByteOptimizer;
+use crate::Optimizer;
+
+// -------------------------------------------------------------------------------------------------
+
+/// An optimizer for a stream of bytes.
+///
+/// Bytes that aren't valid UTF-8 are passed through unchanged.
+pub struct AnsiOptimizer(ByteOptimizer);
+
+// -------------------------------------------------------------------------------------------------
+
+/// Creates an optimizer with the default options.
+///
+/// It must be freed with [ansi_optimizer_free].
+#[no_mangle]
+pub extern "C" fn ansi_optimizer_new() -> *mut AnsiOptimizer {
+    Box::into_raw(Box::new(AnsiOptimizer(ByteOptimizer::new(Optimizer::new()))))
+}
+
+/// Frees an optimizer.
+///
+/// ## Safety
+///
+/// The optimizer must have been created by [ansi_optimizer_new], and must not be used after this.
+/// It may be null, in which case nothing happens.
+#[no_mangle]
+pub unsafe extern "C" fn ansi_optimizer_free(optimizer: *mut AnsiOptimizer) {
+    if !optimizer.is_null() {
+        drop(Box::from_raw(optimizer));
+    }
+}
+
+/// Updates an optimizer with the next chunk of a stream.
+///
+/// Escape sequences and characters that are split between chunks are held until the rest of them
+/// arrives. See [Optimizer::feed].
+///
+/// ## Safety
+///
+/// The optimizer must be valid, and `data` must point to `len` readable bytes.
+/// If `len` is zero, `data` may be null.
+#[no_mangle]
+pub unsafe extern "C" fn ansi_optimizer_feed(
+    optimizer: *mut AnsiOptimizer,
+    data: *const u8,
+    len: usize,
+) {
+    if len > 0 {
+        (*optimizer).0.feed(std::slice::from_raw_parts(data, len));
+    }
+}
+
+/// Finishes the stream, making everything that was held back available as output.
+///
+/// The optimizer can be used for a new stream afterwards.
+///
+/// ## Safety
+///
+/// The optimizer must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn ansi_optimizer_finish(optimizer: *mut AnsiOptimizer) {
+    (*optimizer).0.finish();
+}
+
+/// Gets the number of bytes of output that are ready to be taken.
+///
+/// ## Safety
+///
+/// The optimizer must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn ansi_optimizer_output_len(optimizer: *const AnsiOptimizer) -> usize {
+    (*optimizer).0.output.len()
+}
+
+/// Takes up to `capacity` bytes of output, copying them into `buffer`.
+///
+/// ## Returns
+///
+/// The number of bytes copied. This is zero once all of the output has been taken.
+///
+/// ## Safety
+///
+/// The optimizer must be valid, and `buffer` must point to `capacity` writable bytes.
+/// If `capacity` is zero, `buffer` may be null.
+#[no_mangle]
+pub unsafe extern "C" fn ansi_optimizer_take_output(
+    optimizer: *mut AnsiOptimizer,
+    buffer: *mut u8,
+    capacity: usize,
+) -> usize {
+    let output = &mut (*optimizer).0.output;
+    let len = output.len().min(capacity);
+    if len > 0 {
+        std::ptr::copy_nonoverlapping(output.as_ptr(), buffer, len);
+        output.drain(..len);
+    }
+
+    len
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::ffi::*;
+
+    fn optimize(chunks: &[&[u8]]) -> Vec<u8> {
+        unsafe {
+            let optimizer = ansi_optimizer_new();
+            for chunk in chunks {
+                ansi_optimizer_feed(optimizer, chunk.as_ptr(), chunk.len());
+            }
+
+            ansi_optimizer_finish(optimizer);
+
+            let mut output = Vec::new();
+            let mut buffer = [0u8; 4];
+            loop {
+                let len = ansi_optimizer_take_output(optimizer, buffer.as_mut_ptr(), buffer.len());
+                if len == 0 {
+                    break;
+                }
+
+                output.extend_from_slice(&buffer[..len]);
+            }
+
+            assert_eq!(ansi_optimizer_output_len(optimizer), 0);
+            ansi_optimizer_free(optimizer);
+            output
+        }
+    }
+
+    #[test]
+    fn feed_bytes() {
+        assert_eq!(optimize(&[b"\x1B[1m\x1B", b"[1ma"]), b"\x1B[1ma");
+        assert_eq!(optimize(&[b"\xC3", b"\xA9\xFF\x1B[1mb"]), b"\xC3\xA9\xFF\x1B[1mb");
+        assert_eq!(optimize(&[b"a\xC3"]), b"a\xC3");
+    }
+}
@@ -0,0 +1,74 @@
+//! Optimizing files.
+//!
+//! ```no_run
+//! # use ansi_optimizer::{optimize_file, Options};
+//! optimize_file("build.log", "build.optimized.log", &Options::default())?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::writer::Writer;
+use crate::Options;
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// -------------------------------------------------------------------------------------------------
+
+/// How much of the input is given to the optimizer at once, in bytes.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Optimizes a file, and writes the output to another file.
+///
+/// The input file is memory-mapped and optimized a chunk at a time, so it doesn't need to fit in
+/// memory. Bytes that aren't valid UTF-8 are passed through unchanged, like with a [Writer].
+/// The output file is created, or truncated if it already exists.
+///
+/// The input file must not be changed by another process while it's being optimized, since the
+/// memory map would see the changes partway through.
+pub fn optimize_file(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    options: &Options,
+) -> io::Result<()> {
+    let input = File::open(input)?;
+
+    // The caller is responsible for the file not being changed while it is mapped.
+    let map = unsafe { Mmap::map(&input)? };
+
+    let output = BufWriter::new(File::create(output)?);
+    let mut writer = Writer::with_options(output, options.clone());
+    for chunk in map.chunks(CHUNK_SIZE) {
+        writer.write_all(chunk)?;
+    }
+
+    writer.into_inner()?.into_inner().map_err(|error| error.into_error())?.sync_all()
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::file::*;
+
+    #[test]
+    fn optimize_files() {
+        let dir = std::env::temp_dir().join(format!("ansi-optimizer-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("input"), dir.join("output"));
+
+        let mut contents = b"\x1B[31m\x1B[1m\xFFred\x1B[m\n".repeat(CHUNK_SIZE / 4);
+        contents.extend_from_slice("\u{e9}\x1B[?25l".as_bytes());
+        std::fs::write(&input, &contents).unwrap();
+        optimize_file(&input, &output, &Options::default()).unwrap();
+
+        let mut expected = b"\x1B[1;31m\xFFred\x1B[m\n".repeat(CHUNK_SIZE / 4);
+        expected.extend_from_slice("\u{e9}\x1B[?25l".as_bytes());
+        assert_eq!(std::fs::read(&output).unwrap(), expected);
+
+        std::fs::write(&input, b"").unwrap();
+        optimize_file(&input, &output, &Options::default()).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), b"");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
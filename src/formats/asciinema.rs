@@ -0,0 +1,149 @@
+//! asciinema v2 recordings (`.cast` files).
+//!
+//! A recording is a header line, followed by one line per event. Each event is a JSON array of
+//! the time (in seconds), the event type, and the event data. Output events have the `o` type.
+//!
+//! See <https://docs.asciinema.org/manual/asciicast/v2/>.
+
+use crate::formats::Error;
+use crate::{Optimizer, Options};
+use serde_json::Value;
+use std::io::{BufRead, Write};
+
+// -------------------------------------------------------------------------------------------------
+
+/// The maximum size of an escape sequence, if the options don't set one. This stops an unfinished
+/// sequence from being held back (and growing) for the rest of the recording.
+pub const MAX_SEQUENCE_BYTES: usize = 1024 * 1024;
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::InvalidFormat(error.to_string())
+    }
+}
+
+/// Optimizes the output of an asciinema v2 recording.
+///
+/// The output events are given to a single [Optimizer], so state is kept between them.
+/// Since the optimizer holds back sequences until they have a visible effect, some may be moved
+/// into a later event. Events that end up empty are removed, and other events are kept unchanged.
+/// Before any other event, the output that was held back is written, so it stays in order with
+/// the output.
+///
+/// If [Options::max_sequence_bytes] isn't set, [MAX_SEQUENCE_BYTES] is used.
+///
+/// ## Arguments
+///
+/// - `input`: The recording.
+/// - `output`: Where the optimized recording is written.
+/// - `options`: The optimizer options.
+pub fn optimize<R: BufRead, W: Write>(
+    input: R,
+    mut output: W,
+    options: Options,
+) -> Result<(), Error> {
+    let mut lines = input.lines();
+    let header = match lines.next() {
+        Some(line) => line?,
+        None => return Err(Error::InvalidFormat("missing header".to_owned())),
+    };
+
+    let version = serde_json::from_str::<Value>(&header)?.get("version").and_then(Value::as_u64);
+    if version != Some(2) {
+        return Err(Error::InvalidFormat("unsupported version".to_owned()));
+    }
+
+    writeln!(output, "{}", header)?;
+
+    let mut optimizer = Optimizer::with_options(Options {
+        max_sequence_bytes: options.max_sequence_bytes.or(Some(MAX_SEQUENCE_BYTES)),
+        ..options
+    });
+
+    let mut time = 0.0;
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (event_time, event_type, data): (f64, String, String) = serde_json::from_str(&line)?;
+        if event_type != "o" {
+            optimizer.flush();
+            write_event(&mut output, time, &optimizer.take_output())?;
+            writeln!(output, "{}", line)?;
+            continue;
+        }
+
+        time = event_time;
+        optimizer.feed(&data);
+        write_event(&mut output, time, &optimizer.take_output())?;
+    }
+
+    optimizer.finish();
+    write_event(&mut output, time, &optimizer.take_output())?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Writes an output event, unless it's empty.
+fn write_event<W: Write>(output: &mut W, time: f64, data: &str) -> Result<(), Error> {
+    if !data.is_empty() {
+        serde_json::to_writer(&mut *output, &(time, "o", data))?;
+        writeln!(output)?;
+    }
+
+    Ok(())
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::formats::asciinema::*;
+
+    fn optimized(input: &str) -> Result<String, Error> {
+        let mut output = Vec::new();
+        optimize(input.as_bytes(), &mut output, Options::default())?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn optimize_cast() {
+        let input = concat!(
+            r#"{"version": 2, "width": 80, "height": 24}"#,
+            "\n",
+            r#"[0.5, "o", "\u001b[1m\u001b[3"]"#,
+            "\n",
+            r#"[1.0, "i", "x"]"#,
+            "\n",
+            r#"[1.5, "o", "1mhi\u001b[0m"]"#,
+            "\n",
+            r#"[2.0, "o", "\u001b[?25l"]"#,
+            "\n",
+        );
+
+        assert_eq!(
+            optimized(input).unwrap(),
+            concat!(
+                r#"{"version": 2, "width": 80, "height": 24}"#,
+                "\n",
+                r#"[0.5,"o","\u001b[1m"]"#,
+                "\n",
+                r#"[1.0, "i", "x"]"#,
+                "\n",
+                r#"[1.5,"o","\u001b[31mhi"]"#,
+                "\n",
+                r#"[2.0,"o","\u001b[?25l\u001b[m"]"#,
+                "\n",
+            )
+        );
+    }
+
+    #[test]
+    fn optimize_invalid() {
+        assert!(optimized("").is_err());
+        assert!(optimized(r#"{"version": 1}"#).is_err());
+        assert!(optimized("{\"version\": 2}\n[0.5, \"o\"]\n").is_err());
+    }
+}
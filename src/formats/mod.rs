@@ -0,0 +1,38 @@
+//! Support for reading and writing terminal recordings.
+//!
+//! Each format is behind a feature flag of the same name.
+
+#[cfg(feature = "asciinema")]
+pub mod asciinema;
+
+#[cfg(feature = "ttyrec")]
+pub mod ttyrec;
+
+// -------------------------------------------------------------------------------------------------
+
+/// An error from reading or writing a recording.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing failed.
+    Io(std::io::Error),
+
+    /// The input is not a valid recording.
+    InvalidFormat(String),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::InvalidFormat(reason) => write!(f, "invalid recording: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,211 @@
+//! ttyrec recordings, as used by ttyrec, termrec, and ipbt.
+//!
+//! A recording is a series of records. Each record has a 12-byte header of three little-endian
+//! 32-bit integers (the seconds and microseconds of the timestamp, and the length of the data),
+//! followed by the data written to the terminal.
+
+use crate::bytes::ByteOptimizer;
+use crate::formats::Error;
+use crate::{Optimizer, Options};
+use std::convert::TryFrom;
+use std::io::{ErrorKind, Read, Write};
+
+// -------------------------------------------------------------------------------------------------
+
+/// A single record of a ttyrec recording.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Record {
+    /// The seconds part of the timestamp.
+    pub sec: u32,
+
+    /// The microseconds part of the timestamp.
+    pub usec: u32,
+
+    /// The data written to the terminal.
+    pub data: Vec<u8>,
+}
+
+/// Reads the records of a ttyrec recording.
+pub struct Reader<R: Read> {
+    input: R,
+}
+
+impl<R: Read> Reader<R> {
+    /// Creates a reader for a recording.
+    pub fn new(input: R) -> Self {
+        Reader { input }
+    }
+
+    /// Reads the next record.
+    ///
+    /// ## Returns
+    ///
+    /// `None` at the end of the recording.
+    /// If the recording ends partway through a record, this returns [Error::InvalidFormat].
+    pub fn read_record(&mut self) -> Result<Option<Record>, Error> {
+        let mut header = [0u8; 12];
+        match self.input.read(&mut header[..1]) {
+            Ok(0) => return Ok(None),
+            Ok(_) => {}
+            Err(error) if error.kind() == ErrorKind::Interrupted => return self.read_record(),
+            Err(error) => return Err(error.into()),
+        }
+
+        self.read_exact(&mut header[1..])?;
+        let field =
+            |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        let mut record = Record { sec: field(0), usec: field(4), data: Vec::new() };
+
+        // The length comes from the recording, so the data is read in as it arrives instead of
+        // allocating it all up front.
+        let len = field(8) as usize;
+        (&mut self.input).take(len as u64).read_to_end(&mut record.data)?;
+        if record.data.len() < len {
+            return Err(Error::InvalidFormat("truncated record".to_owned()));
+        }
+
+        Ok(Some(record))
+    }
+
+    fn read_exact(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        self.input.read_exact(buffer).map_err(|error| match error.kind() {
+            ErrorKind::UnexpectedEof => Error::InvalidFormat("truncated record".to_owned()),
+            _ => error.into(),
+        })
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Writes the records of a ttyrec recording.
+pub struct Writer<W: Write> {
+    output: W,
+}
+
+impl<W: Write> Writer<W> {
+    /// Creates a writer for a recording.
+    pub fn new(output: W) -> Self {
+        Writer { output }
+    }
+
+    /// Writes a record.
+    ///
+    /// If the record has more data than fits in a single record, this returns
+    /// [Error::InvalidFormat].
+    pub fn write_record(&mut self, record: &Record) -> Result<(), Error> {
+        let len = match u32::try_from(record.data.len()) {
+            Ok(len) => len,
+            Err(_) => return Err(Error::InvalidFormat("record too large".to_owned())),
+        };
+
+        self.output.write_all(&record.sec.to_le_bytes())?;
+        self.output.write_all(&record.usec.to_le_bytes())?;
+        self.output.write_all(&len.to_le_bytes())?;
+        self.output.write_all(&record.data)?;
+        Ok(())
+    }
+
+    /// Gets the underlying output.
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+/// Optimizes the data of a ttyrec recording.
+///
+/// The records are given to a single [Optimizer], so state is kept between them, and timestamps
+/// are kept unchanged. Since the optimizer holds back sequences until they have a visible effect,
+/// some may be moved into a later record. Records that end up empty are removed.
+///
+/// Data that isn't valid UTF-8 is passed through unchanged.
+///
+/// ## Arguments
+///
+/// - `input`: The recording.
+/// - `output`: Where the optimized recording is written.
+/// - `options`: The optimizer options.
+pub fn optimize<R: Read, W: Write>(input: R, output: W, options: Options) -> Result<(), Error> {
+    let mut writer = Writer::new(output);
+    let mut stream = ByteOptimizer::new(Optimizer::with_options(options));
+    let mut last = Record::default();
+
+    for record in Reader::new(input) {
+        let record = record?;
+        stream.feed(&record.data);
+
+        last = Record { data: std::mem::take(&mut stream.output), ..record };
+        if !last.data.is_empty() {
+            writer.write_record(&last)?;
+        }
+    }
+
+    // Anything left over is written in a final record, with the same time as the last one.
+    stream.finish();
+    last.data = std::mem::take(&mut stream.output);
+    if !last.data.is_empty() {
+        writer.write_record(&last)?;
+    }
+
+    writer.into_inner().flush()?;
+    Ok(())
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::formats::ttyrec::*;
+
+    fn record(sec: u32, data: &[u8]) -> Record {
+        Record { sec, usec: 500, data: data.to_vec() }
+    }
+
+    fn recording(records: &[Record]) -> Vec<u8> {
+        let mut writer = Writer::new(Vec::new());
+        records.iter().try_for_each(|r| writer.write_record(r)).unwrap();
+        writer.into_inner()
+    }
+
+    fn read(bytes: &[u8]) -> Result<Vec<Record>, Error> {
+        Reader::new(bytes).collect()
+    }
+
+    #[test]
+    fn read_write() {
+        let records = vec![record(1, b"hello"), record(2, b""), record(3, b"\x1B[m")];
+        let bytes = recording(&records);
+        assert_eq!(bytes.len(), 12 * 3 + 8);
+        assert_eq!(read(&bytes).unwrap(), records);
+        assert!(read(&bytes[..bytes.len() - 1]).is_err());
+        assert!(read(&bytes[..5]).is_err());
+
+        let huge = [0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, b'x'];
+        assert!(read(&huge).is_err());
+    }
+
+    #[test]
+    fn optimize_recording() {
+        let input = recording(&[
+            record(1, b"\x1B[1m\x1B[3"),
+            record(2, b"1mhi \xE2\x94"),
+            record(3, b"\x80\x1B[0m\xFF\x1B[?25l"),
+        ]);
+
+        let mut output = Vec::new();
+        optimize(&input[..], &mut output, Options::default()).unwrap();
+        assert_eq!(
+            read(&output).unwrap(),
+            vec![
+                record(2, b"\x1B[1;31mhi "),
+                record(3, b"\xE2\x94\x80\x1B[m\xFF"),
+                record(3, b"\x1B[?25l"),
+            ]
+        );
+    }
+}
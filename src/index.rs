@@ -0,0 +1,187 @@
+//! Indexing the style of optimized output, for viewers that seek to arbitrary offsets.
+//!
+//! A viewer that jumps partway through its output needs to know which style is active there,
+//! which would normally mean reading everything before it. Optimizing with [optimize_indexed]
+//! also records every offset of the output where the style changes.
+//!
+//! ```
+//! # use ansi_optimizer::{optimize_indexed, Color, Options, Style};
+//! let index = optimize_indexed("a\x1B[31mb\x1B[1mc\x1B[0md", &Options::default()).unwrap();
+//! assert_eq!(index.output, "a\x1B[31mb\x1B[1mc\x1B[md");
+//!
+//! let red = Style { foreground: Color::Indexed(1), ..Style::default() };
+//! assert_eq!(index.style_at(0), Style::default());
+//! assert_eq!(index.style_at(6), red);
+//! ```
+//!
+//! The index also makes it possible to change part of the output with [StyleIndex::replace_range],
+//! which only optimizes the new input instead of everything.
+
+use crate::{Error, Optimizer, Options, Style};
+use std::ops::Range;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Optimized output, and the styles that are active in it.
+///
+/// This is created with [optimize_indexed].
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct StyleIndex {
+    /// The optimized output.
+    pub output: String,
+
+    /// The byte offsets of the output where the style changes, and the style after them.
+    /// The offsets are in increasing order, and the style before the first one is the default.
+    pub changes: Vec<(usize, Style)>,
+}
+
+impl StyleIndex {
+    /// Gets the style that is active at a byte offset of the output.
+    pub fn style_at(&self, offset: usize) -> Style {
+        let i = self.changes.partition_point(|&(start, _)| start <= offset);
+        match i {
+            0 => Style::default(),
+            i => self.changes[i - 1].1,
+        }
+    }
+
+    /// Replaces part of the output with the optimized form of new input.
+    ///
+    /// The new input continues from the style active at the start of the range, and the style
+    /// that was active at the end of the range is changed back to after it. Only the new input is
+    /// optimized, so the rest of the output and its index are unchanged apart from being moved.
+    /// The range should start and end between escape sequences, like at the start of a line.
+    ///
+    /// ```
+    /// # use ansi_optimizer::{optimize_indexed, Options};
+    /// let mut index = optimize_indexed("\x1B[31mred\nline\n", &Options::default()).unwrap();
+    /// index.replace_range(9..14, "\x1B[1mbold\n", &Options::default()).unwrap();
+    /// assert_eq!(index.output, "\x1B[31mred\n\x1B[1mbold\n\x1B[22m");
+    /// ```
+    ///
+    /// ## Panics
+    ///
+    /// If the range is out of bounds, or doesn't start and end on character boundaries.
+    ///
+    /// ## Returns
+    ///
+    /// If the input contains an invalid escape sequence, this returns [Error::InvalidSequence].
+    /// If the input ends partway through an escape sequence, this returns [Error::Incomplete].
+    /// In both cases, nothing is changed.
+    pub fn replace_range(
+        &mut self,
+        range: Range<usize>,
+        input: &str,
+        options: &Options,
+    ) -> Result<(), Error> {
+        let (before, after) = (self.style_at(range.start), self.style_at(range.end));
+
+        let mut optimizer = Optimizer::with_options(options.clone());
+        optimizer.input_style = before;
+        optimizer.style = before;
+        optimizer.emitted = before;
+        optimizer.style_changes = Some(vec![(0, before)]);
+        optimizer.update(input)?;
+
+        // The rest of the output expects the exact style, even if another looks the same.
+        optimizer.style = after;
+        if optimizer.emitted != after {
+            optimizer.write_style();
+        }
+
+        optimizer.flush();
+        let output = optimizer.take_output();
+        let changes = optimizer.style_changes.take().unwrap_or_default();
+        self.output.replace_range(range.clone(), &output);
+
+        let first = self.changes.partition_point(|&(offset, _)| offset <= range.start);
+        let last = self.changes.partition_point(|&(offset, _)| offset <= range.end);
+        let moved = self.changes.drain(first..).skip(last - first);
+        let moved = moved
+            .map(|(offset, style)| (offset - range.end + range.start + output.len(), style))
+            .collect::<Vec<_>>();
+
+        let added = changes.into_iter().skip(1);
+        self.changes.extend(added.map(|(offset, style)| (range.start + offset, style)));
+        self.changes.extend(moved);
+        Ok(())
+    }
+}
+
+/// Optimizes a complete string, and records where the style changes in the output.
+///
+/// See [optimize_with](crate::optimize_with).
+///
+/// ## Returns
+///
+/// If the input contains an invalid escape sequence, this returns [Error::InvalidSequence].
+/// If the input ends partway through an escape sequence, this returns [Error::Incomplete].
+pub fn optimize_indexed(input: &str, options: &Options) -> Result<StyleIndex, Error> {
+    let mut optimizer = Optimizer::with_options(options.clone());
+    optimizer.style_changes = Some(Vec::new());
+    optimizer.update(input)?;
+    optimizer.flush();
+
+    Ok(StyleIndex {
+        changes: optimizer.style_changes.take().unwrap_or_default(),
+        output: optimizer.take_output(),
+    })
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::index::*;
+    use crate::style::{Attributes, Underline};
+    use crate::Color;
+
+    #[test]
+    fn index_style_changes() {
+        let input = "\x1B[1ma\x1B[31m\x1B[?25lb\x1B[22m\x1B[39mc\x1B7\x1B[4md\x1B8e\x1B[4m";
+        let index = optimize_indexed(input, &Options::default()).unwrap();
+        assert_eq!(index.output, "\x1B[1ma\x1B[?25l\x1B[31mb\x1B[mc\x1B7\x1B[4md\x1B8e\x1B[4m");
+
+        let bold = Style { attributes: Attributes::BOLD, ..Style::default() };
+        let red = Style { foreground: Color::Indexed(1), ..bold };
+        let underline = Style { underline: Underline::Single, ..Style::default() };
+        assert_eq!(
+            index.changes,
+            vec![
+                (4, bold),
+                (16, red),
+                (20, Style::default()),
+                (27, underline),
+                (30, Style::default()),
+                (35, underline)
+            ]
+        );
+
+        assert_eq!(index.style_at(0), Style::default());
+        assert_eq!(index.style_at(4), bold);
+        assert_eq!(index.style_at(19), red);
+        assert_eq!(index.style_at(usize::MAX), underline);
+        assert_eq!(optimize_indexed("", &Options::default()).unwrap(), StyleIndex::default());
+    }
+
+    #[test]
+    fn index_replace_range() {
+        let options = Options::default();
+        let mut index = optimize_indexed("a\x1B[31mb\nc\n\x1B[1md\n", &options).unwrap();
+        assert_eq!(index.replace_range(8..10, "\x1B[3", &options), Err(Error::Incomplete));
+        assert_eq!(index, optimize_indexed("a\x1B[31mb\nc\n\x1B[1md\n", &options).unwrap());
+
+        index.replace_range(8..10, "\x1B[32mx\n", &options).unwrap();
+        assert_eq!(index.output, "a\x1B[31mb\n\x1B[32mx\n\x1B[31m\x1B[1md\n");
+
+        let red = Style { foreground: Color::Indexed(1), ..Style::default() };
+        let green = Style { foreground: Color::Indexed(2), ..Style::default() };
+        let bold = Style { attributes: Attributes::BOLD, ..red };
+        assert_eq!(index.changes, vec![(6, red), (13, green), (20, red), (24, bold)]);
+
+        index.replace_range(20..20, "y", &options).unwrap();
+        index.replace_range(0..0, "", &options).unwrap();
+        assert_eq!(index.output, "a\x1B[31mb\n\x1B[32mx\n\x1B[31my\x1B[1md\n");
+        assert_eq!(index.changes, vec![(6, red), (13, green), (20, red), (25, bold)]);
+    }
+}
@@ -0,0 +1,90 @@
+//! Joining styled fragments together.
+//!
+//! Concatenating fragments that were styled separately lets the style of one leak into the
+//! next, unless every fragment resets the style at the end. Resetting wastes bytes when the next
+//! fragment uses the same style anyway. Joining them with an optimizer does neither.
+//!
+//! ```
+//! # use ansi_optimizer::join_optimized;
+//! let parts = ["\x1B[31merror\x1B[m", "\x1B[31m: disk full", " (\x1B[1m/dev/sda\x1B[m)"];
+//! assert_eq!(join_optimized(parts), "\x1B[31merror: disk full\x1B[m (\x1B[1m/dev/sda\x1B[m)");
+//! ```
+
+use crate::{Optimizer, Options};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Whether a fragment starts with the style left by the one before it.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Isolation {
+    /// Each fragment continues with the style of the one before it, like plain concatenation.
+    Inherit,
+
+    /// Each fragment starts with the default style.
+    Reset,
+}
+
+impl Default for Isolation {
+    #[inline]
+    fn default() -> Self {
+        Isolation::Reset
+    }
+}
+
+/// Joins styled fragments, where each one starts with the default style.
+///
+/// See [join_optimized_with].
+pub fn join_optimized<'a>(parts: impl IntoIterator<Item = &'a str>) -> String {
+    join_optimized_with(parts, Isolation::default(), &Options::default())
+}
+
+/// Joins styled fragments, and optimizes the escape sequences across them.
+///
+/// A fragment that ends partway through an escape sequence keeps it unchanged, instead of it
+/// being completed by the next fragment. The output is left in whichever style the last
+/// fragment leaves it in.
+pub fn join_optimized_with<'a>(
+    parts: impl IntoIterator<Item = &'a str>,
+    isolation: Isolation,
+    options: &Options,
+) -> String {
+    let mut optimizer = Optimizer::with_options(options.clone());
+    for (i, part) in parts.into_iter().enumerate() {
+        if i > 0 && isolation == Isolation::Reset {
+            optimizer.feed("\x1B[m");
+        }
+
+        optimizer.feed(part);
+        if !optimizer.partial.is_empty() {
+            let partial = std::mem::take(&mut optimizer.partial);
+            optimizer.update_text(&partial);
+        }
+    }
+
+    optimizer.flush();
+    optimizer.take_output()
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::join::*;
+
+    #[test]
+    fn join_isolated() {
+        let parts = ["\x1B[31ma", "b", "\x1B[31mc\x1B[", "d"];
+        assert_eq!(join_optimized(parts.iter().copied()), "\x1B[31ma\x1B[mb\x1B[31mc\x1B[\x1B[md");
+        assert_eq!(join_optimized(Vec::new()), "");
+    }
+
+    #[test]
+    fn join_inherited() {
+        let parts = ["\x1B[31ma", "\x1B[1mb", "\x1B[31mc"];
+        let options = Options::default();
+        assert_eq!(
+            join_optimized_with(parts.iter().copied(), Isolation::Inherit, &options),
+            "\x1B[31ma\x1B[1mbc"
+        );
+    }
+}
@@ -1,6 +1,4 @@
-use std::fmt::{Debug, Formatter};
-use std::iter::Peekable;
-use std::str::Chars;
+use std::fmt::Debug;
 
 // -------------------------------------------------------------------------------------------------
 
@@ -13,51 +11,67 @@ pub enum Error {
 }
 
 impl From<Error> for crate::error::Error {
-    fn from(_: Error) -> Self {
-        crate::error::Error::InvalidSequence
+    fn from(error: Error) -> Self {
+        match error {
+            Error::EOF => crate::error::Error::Incomplete,
+            Error::Unexpected => crate::error::Error::InvalidSequence,
+        }
     }
 }
 
 // -------------------------------------------------------------------------------------------------
 
-/// A simple allocation-free string lexer.
-#[derive(Debug, Clone)]
-pub struct Lexer<'a> {
-    cursor: &'a str,
-    cursor_saved: &'a str,
+/// Input that can be read by a [Lexer].
+///
+/// This is implemented for `str` (where each unit is a `char`) and `[u8]` (where each unit is a
+/// byte). All lengths and offsets are measured in bytes.
+pub trait Input: Debug {
+    /// The unit that patterns are matched against.
+    type Unit: Copy;
+
+    /// Gets the length of the input in bytes.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if the input is empty.
+    #[inline(always)]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Splits the input at a byte offset.
+    fn split_at(&self, mid: usize) -> (&Self, &Self);
+
+    /// Gets the first unit of the input, along with its length in bytes.
+    fn first(&self) -> Option<(Self::Unit, usize)>;
+
+    /// Gets the length in bytes of the longest prefix where every unit matches a pattern.
+    fn prefix_len(&self, pattern: impl Fn(Self::Unit) -> bool) -> usize;
 }
 
-impl<'a> Lexer<'a> {
-    pub fn new(string: &'a str) -> Self {
-        Lexer {
-            cursor: string,
-            cursor_saved: string,
-        }
+impl Input for str {
+    type Unit = char;
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        str::len(self)
     }
 
-    /// Extracts all characters that match a pattern.
-    ///
-    /// ## Arguments
-    ///
-    /// - `pattern`: The pattern predicate.
-    ///
-    /// ## Returns
-    ///
-    /// A `&str` slice containing matching characters, or `None` if there's nothing left.
-    /// 
-    /// ## State
-    /// 
-    /// The lexer cursor will advance by however many characters were extracted.
-    pub fn extract(&mut self, pattern: impl Fn(char) -> bool) -> Result<&'a str> {
+    #[inline(always)]
+    fn split_at(&self, mid: usize) -> (&Self, &Self) {
+        str::split_at(self, mid)
+    }
+
+    #[inline]
+    fn first(&self) -> Option<(char, usize)> {
+        self.chars().next().map(|c| (c, c.len_utf8()))
+    }
+
+    fn prefix_len(&self, pattern: impl Fn(char) -> bool) -> usize {
         //
         // PERFORMANCE: Although this implementation looks weirdly inefficient, it's safer and
         //              faster than using `char_indices()` and get_unchecked().
         //
-        if self.cursor.is_empty() {
-            return Err(Error::EOF);
-        }
-
-        let mut iter = self.cursor.chars();
+        let mut iter = self.chars();
         let mut last_iter = iter.clone();
 
         // Advance the iterator until we reach either the end, or a character past the pattern.
@@ -69,16 +83,96 @@ impl<'a> Lexer<'a> {
             last_iter.clone_from(&iter);
         }
 
-        // Using the position of the last acceptable character, we can create a &str that contains
-        // all of the characters that weren't extracted by the predicate.
-        let remaining = last_iter.as_str();
+        // Using the position of the last acceptable character and the length of the original
+        // string, we can find the length of the matching characters.
+        self.len() - last_iter.as_str().len()
+    }
+}
+
+impl Input for [u8] {
+    type Unit = u8;
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
+    }
+
+    #[inline(always)]
+    fn split_at(&self, mid: usize) -> (&Self, &Self) {
+        <[u8]>::split_at(self, mid)
+    }
+
+    #[inline]
+    fn first(&self) -> Option<(u8, usize)> {
+        <[u8]>::first(self).map(|&b| (b, 1))
+    }
 
-        // Using the length of the original cursor size and the remaining characters, we can then
-        // create a &str that contains all of the extracted characters.
-        let extracted = &self.cursor[0..(self.cursor.len() - remaining.len())];
+    #[inline]
+    fn prefix_len(&self, pattern: impl Fn(u8) -> bool) -> usize {
+        self.iter().position(|&b| !pattern(b)).unwrap_or(self.len())
+    }
+}
 
-        // And finally, we update the cursor and return the extracted characters.
-        self.cursor = remaining;
+// -------------------------------------------------------------------------------------------------
+
+/// The maximum number of marks that can be pushed onto a [Lexer]'s mark stack.
+pub const MAX_MARKS: usize = 8;
+
+/// A simple allocation-free lexer.
+///
+/// This works on either string input (matching `char`s), or byte input (matching `u8`s).
+/// Positions are stored as byte offsets into the original input.
+#[derive(Debug)]
+pub struct Lexer<'a, T: ?Sized + Input = str> {
+    source: &'a T,
+    cursor: usize,
+    cursor_saved: usize,
+    marks: [usize; MAX_MARKS],
+    marks_len: usize,
+}
+
+/// A lexer over bytes.
+pub type ByteLexer<'a> = Lexer<'a, [u8]>;
+
+impl<'a, T: ?Sized + Input> Clone for Lexer<'a, T> {
+    fn clone(&self) -> Self {
+        Lexer {
+            source: self.source,
+            cursor: self.cursor,
+            cursor_saved: self.cursor_saved,
+            marks: self.marks,
+            marks_len: self.marks_len,
+        }
+    }
+}
+
+impl<'a, T: ?Sized + Input> Lexer<'a, T> {
+    pub fn new(input: &'a T) -> Self {
+        Lexer {
+            source: input,
+            cursor: 0,
+            cursor_saved: 0,
+            marks: [0; MAX_MARKS],
+            marks_len: 0,
+        }
+    }
+
+    /// Extracts all characters that match a pattern.
+    ///
+    /// ## Arguments
+    ///
+    /// - `pattern`: The pattern predicate.
+    ///
+    /// ## Returns
+    ///
+    /// A slice containing matching characters, or [Error::EOF] if there's nothing left.
+    /// 
+    /// ## State
+    /// 
+    /// The lexer cursor will advance by however many characters were extracted.
+    pub fn extract(&mut self, pattern: impl Fn(T::Unit) -> bool) -> Result<&'a T> {
+        let extracted = self.peek(pattern)?;
+        self.cursor += extracted.len();
         Ok(extracted)
     }
 
@@ -90,31 +184,16 @@ impl<'a> Lexer<'a> {
     ///
     /// ## Returns
     ///
-    /// A `&str` slice containing the matching character.
+    /// A slice containing the matching character.
     /// If the character does not match, it returns [Error::Unexpected] instead.
     /// 
     /// ## State
     /// 
     /// The lexer cursor will advance if a character was extracted.
-    pub fn extract_one(&mut self, pattern: impl Fn(char) -> bool) -> Result<&'a str> {
-        let mut iter = self.cursor.char_indices();
-
-        if let Some((_, c)) = iter.next() {
-            return if pattern(c) {
-                let remaining = iter.as_str();
-                let extracted = match iter.next() {
-                    None => self.cursor,
-                    Some((i, _)) => &self.cursor[0..i],
-                };
-
-                self.cursor = remaining;
-                Ok(extracted)
-            } else {
-                Err(Error::Unexpected)
-            };
-        }
-
-        Err(Error::EOF)
+    pub fn extract_one(&mut self, pattern: impl Fn(T::Unit) -> bool) -> Result<&'a T> {
+        let extracted = self.peek_one(pattern)?;
+        self.cursor += extracted.len();
+        Ok(extracted)
     }
 
     /// Extracts one character that matches a pattern.
@@ -126,14 +205,14 @@ impl<'a> Lexer<'a> {
     ///
     /// ## Returns
     ///
-    /// A `&str` slice containing the matching character.
+    /// A slice containing the matching character.
     /// If the character does not match, it returns [Error::Unexpected] instead.
     /// 
     /// ## State
     /// 
     /// The lexer cursor will advance by one character.
     #[inline]
-    pub fn extract_one_greedy(&mut self, pattern: impl Fn(char) -> bool) -> Result<&'a str> {
+    pub fn extract_one_greedy(&mut self, pattern: impl Fn(T::Unit) -> bool) -> Result<&'a T> {
         match self.extract_one(pattern) {
             Err(Error::Unexpected) => {
                 self.skip(1)?;
@@ -143,6 +222,74 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Peeks at all characters that match a pattern, without extracting them.
+    ///
+    /// ## Arguments
+    ///
+    /// - `pattern`: The pattern predicate.
+    ///
+    /// ## Returns
+    ///
+    /// The same slice that [Lexer::extract] would return.
+    ///
+    /// ## State
+    ///
+    /// The lexer cursor will not advance.
+    pub fn peek(&self, pattern: impl Fn(T::Unit) -> bool) -> Result<&'a T> {
+        let remaining = self.remaining();
+        if remaining.is_empty() {
+            return Err(Error::EOF);
+        }
+
+        Ok(remaining.split_at(remaining.prefix_len(pattern)).0)
+    }
+
+    /// Peeks at one character that matches a pattern, without extracting it.
+    ///
+    /// ## Arguments
+    ///
+    /// - `pattern`: The pattern predicate.
+    ///
+    /// ## Returns
+    ///
+    /// The same slice that [Lexer::extract_one] would return.
+    ///
+    /// ## State
+    ///
+    /// The lexer cursor will not advance.
+    pub fn peek_one(&self, pattern: impl Fn(T::Unit) -> bool) -> Result<&'a T> {
+        let remaining = self.remaining();
+        match remaining.first() {
+            None => Err(Error::EOF),
+            Some((c, len)) if pattern(c) => Ok(remaining.split_at(len).0),
+            Some(_) => Err(Error::Unexpected),
+        }
+    }
+
+    /// Extracts a number of bytes.
+    ///
+    /// ## Arguments
+    ///
+    /// - `len`: The number of bytes to extract. This must land on a character boundary.
+    ///
+    /// ## Returns
+    ///
+    /// A slice containing the extracted bytes.
+    /// If there are fewer than `len` bytes remaining, this returns [Error::EOF] instead.
+    ///
+    /// ## State
+    ///
+    /// The lexer cursor will advance by `len` bytes if they were extracted.
+    pub fn extract_len(&mut self, len: usize) -> Result<&'a T> {
+        let remaining = self.remaining();
+        if len > remaining.len() {
+            return Err(Error::EOF);
+        }
+
+        self.cursor += len;
+        Ok(remaining.split_at(len).0)
+    }
+
     /// Marks the current cursor position.
     #[inline(always)]
     pub fn mark(&mut self) {
@@ -155,26 +302,78 @@ impl<'a> Lexer<'a> {
         self.cursor = self.cursor_saved;
     }
 
-    /// Gets a string of the characters consumed since the marked position.
-    pub fn consumed(&self) -> &'a str {
-        // SAFETY: 1. `cursor_saved` is a substring of the same source string as `cursor`.
-        //         2. `cursor` is always either at the same position or ahead of `cursor_saved`. 
-        //         3. We're creating the slice from a str, so it is safe to turn it back into a str.
-        let start = self.cursor_saved.as_ptr();
-        let length = unsafe { self.cursor.as_ptr().sub(start as usize) as usize };
-        unsafe { std::str::from_utf8_unchecked(std::slice::from_raw_parts(start, length)) }
+    /// Gets a slice of the characters consumed since the marked position.
+    pub fn consumed(&self) -> &'a T {
+        self.slice(self.cursor_saved)
+    }
+
+    /// Pushes the current cursor position onto the mark stack.
+    ///
+    /// Unlike [Lexer::mark], this does not replace any previous marks. This allows nested
+    /// speculative parsing to rewind to its own starting position without affecting its caller.
+    ///
+    /// ## Panics
+    ///
+    /// This panics if more than [MAX_MARKS] marks are pushed.
+    #[inline]
+    pub fn push_mark(&mut self) {
+        assert!(self.marks_len < MAX_MARKS, "lexer mark stack overflow");
+        self.marks[self.marks_len] = self.cursor;
+        self.marks_len += 1;
+    }
+
+    /// Pops the most recent mark off the mark stack.
+    ///
+    /// ## Returns
+    ///
+    /// A slice of the characters consumed since the popped mark, or `None` if there were no marks.
+    /// If the cursor was rewound to before the popped mark, this also returns `None`.
+    ///
+    /// ## State
+    ///
+    /// The lexer cursor will not change.
+    #[inline]
+    pub fn pop_mark(&mut self) -> Option<&'a T> {
+        if self.marks_len == 0 {
+            return None;
+        }
+
+        self.marks_len -= 1;
+        match self.marks[self.marks_len] {
+            mark if mark > self.cursor => None,
+            mark => Some(self.slice(mark)),
+        }
+    }
+
+    /// Rewinds the cursor back to the most recent mark on the mark stack.
+    /// The mark remains on the stack.
+    ///
+    /// ## State
+    ///
+    /// If there are no marks on the stack, the lexer cursor will not change.
+    #[inline]
+    pub fn rewind_to_mark(&mut self) {
+        if self.marks_len > 0 {
+            self.cursor = self.marks[self.marks_len - 1];
+        }
     }
 
     /// Gets the remaining characters that haven't been extracted.
     #[inline(always)]
-    pub fn remaining(&self) -> &'a str {
+    pub fn remaining(&self) -> &'a T {
+        self.source.split_at(self.cursor).1
+    }
+
+    /// Gets the byte offset of the cursor from the start of the original input.
+    #[inline(always)]
+    pub fn offset(&self) -> usize {
         self.cursor
     }
 
     /// Returns `true` if there are no more characters left to be extracted.
     #[inline(always)]
     pub fn is_empty(&self) -> bool {
-        self.cursor.is_empty()
+        self.cursor == self.source.len()
     }
     
     /// Skips a number of characters.
@@ -185,25 +384,31 @@ impl<'a> Lexer<'a> {
     ///
     /// ## Returns
     ///
-    /// If this would skip past the end of the input, this returns [Error::Unexpected].
+    /// If this would skip past the end of the input, this returns [Error::EOF].
     /// 
     /// ## State
     /// 
     /// If there are `n` characters available to skip, the lexer cursor will advance by `n` characters.
     /// Otherwise, no state changes will occur.
-    fn skip(&mut self, mut n: usize) -> Result<()> {
-        let mut iter = self.cursor.chars();
-        
-        while n > 0 {
-            n -= 1;
-            if iter.next() == None {
-                return Err(Error::EOF);
+    fn skip(&mut self, n: usize) -> Result<()> {
+        let mut cursor = self.cursor;
+
+        for _ in 0..n {
+            match self.source.split_at(cursor).1.first() {
+                None => return Err(Error::EOF),
+                Some((_, len)) => cursor += len,
             }
         }
-        
-        self.cursor = iter.as_str();
+
+        self.cursor = cursor;
         Ok(())
     }
+
+    /// Gets the slice of the original input between an earlier offset and the cursor.
+    #[inline(always)]
+    fn slice(&self, start: usize) -> &'a T {
+        self.source.split_at(self.cursor).0.split_at(start).1
+    }
 }
 
 // -------------------------------------------------------------------------------------------------
@@ -213,6 +418,7 @@ mod tests {
     use crate::lex::*;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn extract() {
         let mut lex = Lexer::new("hello123 world");
         assert_eq!(lex.is_empty(), false);
@@ -254,6 +460,16 @@ mod tests {
         assert_eq!(lex.is_empty(), true);
     }
 
+    #[test]
+    fn offset() {
+        let mut lex = Lexer::new("hello123 world");
+        assert_eq!(lex.offset(), 0);
+
+        // Extract "hello", and ensure the offset is after it.
+        assert_eq!(lex.extract(char::is_alphabetic), Ok("hello"));
+        assert_eq!(lex.offset(), 5);
+    }
+
     #[test]
     fn mark() {
         let mut lex = Lexer::new("hello123 world");
@@ -277,6 +493,62 @@ mod tests {
         assert_eq!(lex.remaining(), "123 world");
     }
 
+    #[test]
+    fn mark_stack() {
+        let mut lex = Lexer::new("abc123def");
+
+        // Push an outer mark, then extract "abc".
+        lex.push_mark();
+        assert_eq!(lex.extract(char::is_alphabetic), Ok("abc"));
+
+        // Push an inner mark, extract "123", and rewind to it.
+        lex.push_mark();
+        assert_eq!(lex.extract(char::is_numeric), Ok("123"));
+        lex.rewind_to_mark();
+        assert_eq!(lex.remaining(), "123def");
+
+        // Extract "123" again and pop the inner mark.
+        assert_eq!(lex.extract(char::is_numeric), Ok("123"));
+        assert_eq!(lex.pop_mark(), Some("123"));
+
+        // Ensure the outer mark was not clobbered.
+        lex.rewind_to_mark();
+        assert_eq!(lex.remaining(), "abc123def");
+        assert_eq!(lex.pop_mark(), Some(""));
+        assert_eq!(lex.pop_mark(), None);
+
+        // Ensure the single mark is independent from the stack.
+        assert_eq!(lex.extract(char::is_alphabetic), Ok("abc"));
+        assert_eq!(lex.consumed(), "abc");
+    }
+
+    #[test]
+    fn pop_mark_after_rewind() {
+        let mut lex = Lexer::new("abc123");
+
+        // Push a mark after "abc", then rewind to before it.
+        assert_eq!(lex.extract(char::is_alphabetic), Ok("abc"));
+        lex.push_mark();
+        lex.rewind();
+        assert_eq!(lex.pop_mark(), None);
+        assert_eq!(lex.remaining(), "abc123");
+
+        // The mark was still popped.
+        lex.push_mark();
+        assert_eq!(lex.extract(char::is_alphabetic), Ok("abc"));
+        assert_eq!(lex.pop_mark(), Some("abc"));
+        assert_eq!(lex.pop_mark(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn mark_stack_overflow() {
+        let mut lex = Lexer::new("");
+        for _ in 0..=MAX_MARKS {
+            lex.push_mark();
+        }
+    }
+
     #[test]
     fn skip() {
         let mut lex = Lexer::new("12345");
@@ -304,5 +576,56 @@ mod tests {
         assert_eq!(lex.remaining(), "");
     }
 
+    #[test]
+    fn peek() {
+        let mut lex = Lexer::new("héllo123");
+
+        // Peek at "héllo" and "h", and ensure nothing was extracted.
+        assert_eq!(lex.peek(char::is_alphabetic), Ok("héllo"));
+        assert_eq!(lex.peek_one(char::is_alphabetic), Ok("h"));
+        assert_eq!(lex.peek_one(char::is_numeric), Err(Error::Unexpected));
+        assert_eq!(lex.remaining(), "héllo123");
+
+        // Peek at a multi-byte character.
+        assert_eq!(lex.extract_one(|c| c == 'h'), Ok("h"));
+        assert_eq!(lex.peek_one(char::is_alphabetic), Ok("é"));
+
+        // Peek at the end.
+        assert_eq!(lex.extract(|_| true), Ok("éllo123"));
+        assert_eq!(lex.peek(|_| true), Err(Error::EOF));
+        assert_eq!(lex.peek_one(|_| true), Err(Error::EOF));
+    }
+
+    #[test]
+    fn extract_len() {
+        let mut lex = Lexer::new("12345");
+
+        // Extract 0 and 2 bytes.
+        assert_eq!(lex.extract_len(0), Ok(""));
+        assert_eq!(lex.extract_len(2), Ok("12"));
+        assert_eq!(lex.remaining(), "345");
+
+        // Extract past the end.
+        assert_eq!(lex.extract_len(4), Err(Error::EOF));
+        assert_eq!(lex.remaining(), "345");
+    }
+
+    #[test]
+    fn extract_bytes() {
+        let mut lex = ByteLexer::new(b"\x1B[1;2m\xFF");
+
+        // Extract bytes matching patterns.
+        assert_eq!(lex.extract_one(|b| b == 0x1B), Ok(&b"\x1B"[..]));
+        assert_eq!(lex.extract_one_greedy(|b| b == b']'), Err(Error::Unexpected));
+        assert_eq!(lex.extract(|b| b.is_ascii_digit() || b == b';'), Ok(&b"1;2"[..]));
+        assert_eq!(lex.peek_one(|b| b == b'm'), Ok(&b"m"[..]));
+        assert_eq!(lex.offset(), 5);
+
+        // Extract the rest, and ensure the consumed bytes cover the whole input.
+        assert_eq!(lex.extract(|_| true), Ok(&b"m\xFF"[..]));
+        assert_eq!(lex.consumed(), &b"\x1B[1;2m\xFF"[..]);
+        assert_eq!(lex.extract(|_| true), Err(Error::EOF));
+    }
+
     // TODO: extract() test, with unicode.
 }
@@ -1,74 +1,153 @@
 #![allow(clippy::match_like_matches_macro)]
+#![allow(clippy::upper_case_acronyms)]
+#![cfg_attr(feature = "simd", feature(portable_simd))]
 #![cfg_attr(test, allow(unused_variables, unused_imports, dead_code))]
 
 // Modules.
-mod ansi;
+//
+// The tokenizer is behind the `parse` feature, and everything that depends on the optimizer is
+// behind the `optimize` feature (which enables `parse`). Modules behind a feature of their own rely
+// on that feature enabling `optimize`. The `cli` feature is only used by the `ansi-opt` binary.
+#[cfg(feature = "optimize")]
+pub mod align;
+#[cfg(feature = "optimize")]
+pub mod analyze;
+#[cfg(feature = "parse")]
+pub mod ansi;
+#[cfg(feature = "parse")]
+pub mod bidi;
+#[cfg(feature = "optimize")]
+mod bytes;
+#[cfg(feature = "optimize")]
+pub mod cleanup;
+#[cfg(feature = "optimize")]
+pub mod codes;
+#[cfg(feature = "optimize")]
+pub mod color;
+#[cfg(feature = "optimize")]
+pub mod cost;
+#[cfg(feature = "parse")]
+pub mod csi;
+#[cfg(feature = "optimize")]
+mod cursor;
+#[cfg(feature = "optimize")]
+mod erase;
+#[cfg(feature = "parse")]
 pub mod error;
+#[cfg(feature = "memmap2")]
+pub mod file;
+#[cfg(feature = "capi")]
+pub mod ffi;
+#[cfg(feature = "optimize")]
+pub mod formats;
+#[cfg(feature = "optimize")]
+pub mod index;
+#[cfg(feature = "optimize")]
+pub mod join;
+#[cfg(feature = "optimize")]
+mod limits;
+#[cfg(feature = "parse")]
+#[allow(dead_code)]
 pub(crate) mod lex;
+#[cfg(feature = "optimize")]
+pub mod markup;
+#[cfg(feature = "optimize")]
+pub mod mux;
+#[cfg(feature = "nu-ansi-term")]
+pub mod nu;
+#[cfg(feature = "optimize")]
+mod normalize;
+#[cfg(feature = "optimize")]
+mod optimizer;
+#[cfg(feature = "optimize")]
+pub mod options;
+#[cfg(feature = "parse")]
+pub mod osc;
+#[cfg(feature = "optimize")]
+mod palette;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+#[cfg(feature = "optimize")]
+mod pending;
+#[cfg(feature = "optimize")]
+pub mod prefix;
+#[cfg(all(feature = "pty", unix))]
+pub mod probe;
+#[cfg(feature = "optimize")]
+pub mod profile;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(all(feature = "pty", unix))]
+pub mod pty;
+#[cfg(feature = "optimize")]
+mod repeat;
+#[cfg(feature = "optimize")]
+pub mod report;
+#[cfg(feature = "optimize")]
+mod sanitize;
+#[cfg(feature = "optimize")]
+pub mod slice;
+#[cfg(feature = "parse")]
+pub mod strip;
+#[cfg(feature = "optimize")]
+pub mod style;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "terminfo")]
+pub mod terminfo;
+#[cfg(feature = "optimize")]
+pub mod tmux;
+#[cfg(feature = "parse")]
+pub mod token;
+#[cfg(feature = "optimize")]
+mod trace;
+#[cfg(feature = "ratatui")]
+pub mod tui;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "parse")]
+pub mod width;
+#[cfg(feature = "optimize")]
+pub mod wrap;
+#[cfg(feature = "optimize")]
+pub mod writer;
 
 // Exports.
+#[cfg(feature = "optimize")]
+pub use align::{align, pad_visible, Alignment};
+#[cfg(feature = "optimize")]
+pub use analyze::{analyze, CapabilityReport};
+#[cfg(feature = "optimize")]
+pub use cleanup::{cleanup, cleanup_styled};
+#[cfg(feature = "optimize")]
+pub use color::{Color, ColorBlindness};
+#[cfg(feature = "optimize")]
+pub use cost::Cost;
+#[cfg(feature = "parse")]
 pub use error::Error;
-
-// Imports.
-use std::fmt::{Display, Formatter};
-
-// -------------------------------------------------------------------------------------------------
-
-/// An ANSI escape sequence optimizer.
-///
-/// This will consume a series of ANSI/VT100 escape sequences and generate equivalent sequences.
-/// The sequences will be comprised of a smaller or equal number of characters than the input.
-///
-/// To create the optimized sequence, the [ToString] trait or [Display] trait should be used:
-///
-/// ```text
-/// # use ansi_optimizer::Optimizer;
-/// let mut optimizer = Optimizer::new();
-/// optimizer.update("\x1B[33;41m");
-/// optimizer.update("\x1B[39m");
-///
-/// assert_eq!(optimizer.to_string(), "\x1B[41m");
-/// ```
-#[derive(Clone, Debug, Default)]
-pub struct Optimizer {
-    // TODO: Internal representation.
-}
-
-impl Optimizer {
-    /// Creates a new optimizer with a default state.
-    pub fn new() -> Self {
-        Optimizer {}
-    }
-
-    /// Resets the optimizer back to a default state.
-    /// This is equivalent to creating a new optimizer, but avoids unnecessary allocations.
-    #[inline]
-    pub fn reset(&mut self) {
-        unimplemented!()
-    }
-
-    /// Updates
-    #[inline]
-    pub fn update(&mut self, sequence: impl AsRef<str>) -> Result<(), Error> {
-        unimplemented!()
-    }
-}
-
-impl Display for Optimizer {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        unimplemented!()
-    }
-}
-
-// extern crate peekmore;
-//
-// mod ansi;
-// mod state;
-//
-// #[cfg(test)]
-// mod tests {
-//     #[test]
-//     fn it_works() {
-//         assert_eq!(2 + 2, 4);
-//     }
-// }
+#[cfg(feature = "memmap2")]
+pub use file::optimize_file;
+#[cfg(feature = "optimize")]
+pub use index::{optimize_indexed, StyleIndex};
+#[cfg(feature = "optimize")]
+pub use join::{join_optimized, join_optimized_with, Isolation};
+#[cfg(feature = "optimize")]
+pub use optimizer::{optimize, optimize_with, Optimizer};
+#[cfg(feature = "optimize")]
+pub use options::{Bidi, ControlChars, Hyperlinks, Level, Options, OverLimit, Queries};
+#[cfg(feature = "rayon")]
+pub use parallel::optimize_parallel;
+#[cfg(feature = "optimize")]
+pub use profile::{ColorEncoding, ColorSupport, Profile, Sgr21};
+#[cfg(feature = "optimize")]
+pub use report::{report, Report};
+#[cfg(feature = "optimize")]
+pub use slice::{slice_visible, slice_visible_with};
+#[cfg(feature = "parse")]
+pub use strip::{strip, strip_into};
+#[cfg(feature = "optimize")]
+pub use style::Style;
+#[cfg(feature = "serde")]
+pub use token::to_json_tokens;
+#[cfg(feature = "optimize")]
+pub use wrap::wrap;
@@ -0,0 +1,150 @@
+use crate::ansi::Sequence;
+use crate::optimizer::is_sgr;
+use crate::options::Options;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Checks if a sequence exceeds the limits set by the [Options].
+pub(crate) fn exceeds(sequence: &Sequence, options: &Options) -> bool {
+    let over = |len: usize, max: Option<usize>| matches!(max, Some(max) if len > max);
+    let over_string = match sequence {
+        Sequence::CSI(csi) => over(csi.params().count(), options.max_params),
+        Sequence::OSC(_, string) | Sequence::DCS(_, string) | Sequence::APC(_, string) => {
+            over(string.text.len(), options.max_string_bytes)
+        }
+        Sequence::Regular(_) => false,
+    };
+
+    over_string || over(len(sequence), options.max_sequence_bytes)
+}
+
+/// Shortens a sequence to fit the limits set by the [Options].
+///
+/// Control sequences lose their last parameters, and strings lose the end of their text. Other
+/// sequences are never long enough to exceed the limits, and are kept as they are.
+///
+/// SGR sequences only lose whole commands, so a color's parameters aren't split up. If no command
+/// fits, this returns an empty string, since an SGR without parameters would reset the style.
+pub(crate) fn truncate(sequence: &Sequence, options: &Options) -> String {
+    let max_len = options.max_sequence_bytes.unwrap_or(usize::MAX);
+    match sequence {
+        Sequence::CSI(csi) => {
+            let marker_len = csi.private_marker().map_or(0, char::len_utf8);
+            let (marker, parameters) = csi.parameters.split_at(marker_len);
+            let fixed = 2 + marker.len() + csi.intermediates.len() + csi.finalizer.len();
+
+            let max_params = options.max_params.unwrap_or(usize::MAX);
+            let mut params = parameters.split(';').take(max_params).collect::<Vec<_>>();
+            let params_len = |params: &[&str]| {
+                params.iter().map(|param| param.len()).sum::<usize>() + params.len().max(1) - 1
+            };
+
+            while fixed + params_len(&params) > max_len && !params.is_empty() {
+                params.pop();
+            }
+
+            if is_sgr(csi) {
+                params.truncate(sgr_commands_len(parameters.split(';'), params.len()));
+                if params.is_empty() {
+                    return String::new();
+                }
+            }
+
+            format!("\x1B[{}{}{}{}", marker, params.join(";"), csi.intermediates, csi.finalizer)
+        }
+        Sequence::OSC(seq, string) | Sequence::DCS(seq, string) | Sequence::APC(seq, string) => {
+            let fixed = 1 + seq.intermediates.len() + seq.finalizer.len() + string.finalizer.len();
+            let max_text = options.max_string_bytes.unwrap_or(usize::MAX);
+            let mut end = string.text.len().min(max_text).min(max_len.saturating_sub(fixed));
+            while !string.text.is_char_boundary(end) {
+                end -= 1;
+            }
+
+            // A DCS string escapes `ESC` by doubling it, so it can't end partway through a pair.
+            let text = string.text[..end].trim_end_matches('\x1B');
+            format!("\x1B{}{}{}{}", seq.intermediates, seq.finalizer, text, string.finalizer)
+        }
+        Sequence::Regular(_) => sequence.to_string(),
+    }
+}
+
+/// Gets how many of the first `max` SGR parameters make up whole commands.
+///
+/// Extended colors (`38`, `48`, and `58`) take two more parameters for an indexed color, and four
+/// more for an RGB color.
+fn sgr_commands_len<'a>(params: impl Iterator<Item = &'a str>, max: usize) -> usize {
+    let mut params = params.peekable();
+    let mut len = 0;
+    while let Some(param) = params.next() {
+        let extra = match (param, params.peek()) {
+            ("38" | "48" | "58", Some(&"5")) => 2,
+            ("38" | "48" | "58", Some(&"2")) => 4,
+            _ => 0,
+        };
+
+        if len + 1 + extra > max {
+            break;
+        }
+
+        len += 1 + extra;
+        for _ in 0..extra {
+            params.next();
+        }
+    }
+
+    len
+}
+
+/// Gets the length of a sequence, in bytes.
+fn len(sequence: &Sequence) -> usize {
+    match sequence {
+        Sequence::CSI(csi) => {
+            2 + csi.parameters.len() + csi.intermediates.len() + csi.finalizer.len()
+        }
+        Sequence::OSC(seq, string) | Sequence::DCS(seq, string) | Sequence::APC(seq, string) => {
+            let len = 1 + seq.intermediates.len() + seq.finalizer.len();
+            len + string.text.len() + string.finalizer.len()
+        }
+        Sequence::Regular(seq) => 1 + seq.intermediates.len() + seq.finalizer.len(),
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::limits::*;
+    use std::convert::TryFrom;
+
+    fn truncated(sequence: &str, options: &Options) -> (bool, String) {
+        let sequence = Sequence::try_from(sequence).unwrap();
+        (exceeds(&sequence, options), truncate(&sequence, options))
+    }
+
+    #[test]
+    fn truncate_sequences() {
+        let options = Options {
+            max_params: Some(3),
+            max_string_bytes: Some(8),
+            max_sequence_bytes: Some(12),
+            ..Options::default()
+        };
+
+        assert_eq!(truncated("\x1B[1;2;3m", &options), (false, "\x1B[1;2;3m".to_owned()));
+        assert_eq!(truncated("\x1B[?1;2;3;4h", &options), (true, "\x1B[?1;2;3h".to_owned()));
+        assert_eq!(truncated("\x1B[38;5;208m", &options), (false, "\x1B[38;5;208m".to_owned()));
+        assert_eq!(truncated("\x1B[38;2;1;2;3m", &options), (true, String::new()));
+        assert_eq!(truncated("\x1B[1;38;5;208m", &options), (true, "\x1B[1m".to_owned()));
+        assert_eq!(truncated("\x1B[48;5;1;2;3m", &options), (true, "\x1B[48;5;1m".to_owned()));
+        assert_eq!(truncated("\x1B[1;22222222m", &options), (true, "\x1B[1m".to_owned()));
+        assert_eq!(truncated("\x1B]2;title\x07", &options), (false, "\x1B]2;title\x07".to_owned()));
+        assert_eq!(
+            truncated("\x1B]2;long title\x07", &options),
+            (true, "\x1B]2;long t\x07".to_owned())
+        );
+        assert_eq!(
+            truncated("\x1BPabcdefg\x1B\x1Bhi\x1B\\", &options),
+            (true, "\x1BPabcdefg\x1B\\".to_owned())
+        );
+    }
+}
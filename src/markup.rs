@@ -0,0 +1,216 @@
+//! Writing styled text with markup tags, instead of escape sequences.
+//!
+//! A tag like `{bold red}` starts a scope with those styles added to the current one, and `{/}`
+//! ends the innermost scope. Braces are escaped by doubling them (`{{` and `}}`). The markup is
+//! rendered through an [Optimizer], so the output only has the sequences that are needed.
+//!
+//! ```
+//! # use ansi_optimizer::markup::render;
+//! let output = render("{red}error: {bold}disk full{/}{/} (sda1)").unwrap();
+//! assert_eq!(output, "\x1B[31merror: \x1B[1mdisk full\x1B[m (sda1)");
+//! ```
+//!
+//! Tags can contain these words, separated by spaces:
+//!
+//! - Attributes: `bold`, `dim`, `italic`, `underline`, `blink`, `reverse`, `hidden`, `strike`,
+//!   and `overline`.
+//! - Colors: `black`, `red`, `green`, `yellow`, `blue`, `magenta`, `cyan`, `white`, the same
+//!   names prefixed with `bright_`, `default`, a palette index (e.g. `208`), or a hex color
+//!   (e.g. `#ff8700`).
+//! - Background colors: any color prefixed with `on_` (e.g. `on_blue`).
+
+use crate::style::{Attributes, Underline};
+use crate::{Color, Optimizer, Options, Style};
+use std::fmt::{Display, Formatter};
+
+// -------------------------------------------------------------------------------------------------
+
+/// An error in some markup, at a byte offset.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Error {
+    /// A tag contains a word that isn't a style.
+    UnknownStyle(usize, String),
+
+    /// A tag isn't closed with `}`.
+    UnclosedTag(usize),
+
+    /// There is a `{/}` without a scope to end.
+    UnmatchedEnd(usize),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::UnknownStyle(offset, word) => {
+                write!(f, "unknown style {:?} at {}", word, offset)
+            }
+            Error::UnclosedTag(offset) => write!(f, "unclosed tag at {}", offset),
+            Error::UnmatchedEnd(offset) => write!(f, "unmatched end tag at {}", offset),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Renders markup into text with escape sequences.
+///
+/// See [render_with].
+pub fn render(markup: &str) -> Result<String, Error> {
+    render_with(markup, &Options::default())
+}
+
+/// Renders markup into text with escape sequences, using [Options].
+///
+/// Scopes that are still open at the end are ended, so the output always ends with the default
+/// style.
+pub fn render_with(markup: &str, options: &Options) -> Result<String, Error> {
+    let mut optimizer = Optimizer::with_options(options.clone());
+    let mut depth = 0;
+    let mut rest = markup;
+
+    while let Some(i) = rest.find(['{', '}']) {
+        let offset = markup.len() - rest.len() + i;
+        optimizer.feed(&rest[..i]);
+        rest = &rest[i..];
+
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            optimizer.feed(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+
+        let end = match rest.find('}') {
+            Some(end) if rest.starts_with('{') => end,
+            _ => return Err(Error::UnclosedTag(offset)),
+        };
+
+        let tag = &rest[1..end];
+        rest = &rest[(end + 1)..];
+        if tag == "/" {
+            if !optimizer.pop_style() {
+                return Err(Error::UnmatchedEnd(offset));
+            }
+
+            depth -= 1;
+            continue;
+        }
+
+        let mut style = optimizer.input_style;
+        for word in tag.split_whitespace() {
+            if !apply_word(&mut style, word) {
+                return Err(Error::UnknownStyle(offset, word.to_owned()));
+            }
+        }
+
+        optimizer.push_style(style);
+        depth += 1;
+    }
+
+    optimizer.feed(rest);
+    for _ in 0..depth {
+        optimizer.pop_style();
+    }
+
+    optimizer.finish();
+    Ok(optimizer.take_output())
+}
+
+/// Adds a style word from a tag to a style.
+///
+/// ## Returns
+///
+/// `false` if the word isn't a style.
+fn apply_word(style: &mut Style, word: &str) -> bool {
+    let attribute = match word {
+        "bold" => Attributes::BOLD,
+        "dim" => Attributes::FAINT,
+        "italic" => Attributes::ITALIC,
+        "blink" => Attributes::BLINK,
+        "reverse" => Attributes::REVERSE,
+        "hidden" => Attributes::HIDDEN,
+        "strike" => Attributes::STRIKETHROUGH,
+        "overline" => Attributes::OVERLINE,
+        "underline" => {
+            style.underline = Underline::Single;
+            return true;
+        }
+        _ => {
+            let (background, name) = match word.strip_prefix("on_") {
+                Some(name) => (true, name),
+                None => (false, word),
+            };
+
+            let color = match parse_color(name) {
+                Some(color) => color,
+                None => return false,
+            };
+
+            match background {
+                true => style.background = color,
+                false => style.foreground = color,
+            }
+
+            return true;
+        }
+    };
+
+    style.attributes.insert(attribute);
+    true
+}
+
+/// Parses a color from a tag.
+fn parse_color(name: &str) -> Option<Color> {
+    const NAMES: [&str; 8] =
+        ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+    if name == "default" {
+        return Some(Color::Default);
+    }
+
+    if let Some(hex) = name.strip_prefix('#') {
+        let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6)?;
+        return Some(Color::Rgb((value >> 16) as u8, (value >> 8) as u8, value as u8));
+    }
+
+    if let Ok(index) = name.parse::<u8>() {
+        return Some(Color::Indexed(index));
+    }
+
+    let (offset, name) = match name.strip_prefix("bright_") {
+        Some(name) => (8, name),
+        None => (0, name),
+    };
+
+    let index = NAMES.iter().position(|&n| n == name)?;
+    Some(Color::Indexed(offset + index as u8))
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::markup::*;
+
+    #[test]
+    fn render_markup() {
+        assert_eq!(render("plain {{text}}"), Ok("plain {text}".to_owned()));
+        assert_eq!(render("{bold}{/}{italic}{/}a"), Ok("a".to_owned()));
+        assert_eq!(
+            render("{bold on_blue}a{dim}b{/}c"),
+            Ok("\x1B[1;44ma\x1B[2mb\x1B[22;1mc\x1B[m".to_owned())
+        );
+        assert_eq!(
+            render("{#ff8700 on_208}a{bright_red underline}b"),
+            Ok("\x1B[38;2;255;135;0;48;5;208ma\x1B[4;91mb\x1B[m".to_owned())
+        );
+    }
+
+    #[test]
+    fn render_errors() {
+        assert_eq!(render("a{bold"), Err(Error::UnclosedTag(1)));
+        assert_eq!(render("a}b"), Err(Error::UnclosedTag(1)));
+        assert_eq!(render("{bold}{/}{/}"), Err(Error::UnmatchedEnd(9)));
+        assert_eq!(render("{bold purple}"), Err(Error::UnknownStyle(0, "purple".to_owned())));
+        assert_eq!(render("{#ff87}"), Err(Error::UnknownStyle(0, "#ff87".to_owned())));
+    }
+}
@@ -0,0 +1,252 @@
+//! Optimizing several interleaved streams that are written to the same terminal.
+//!
+//! Programs like `docker-compose` write the output of many processes to one terminal, switching
+//! between them line by line. Each stream needs its own optimizer, since the sequences of one
+//! stream have nothing to do with the text of another. A [Multiplexer] keeps an optimizer for
+//! each stream, and restores the style of a stream when switching back to it.
+//!
+//! ```
+//! # use ansi_optimizer::mux::Multiplexer;
+//! # use ansi_optimizer::Options;
+//! let mut mux = Multiplexer::new(Options::default());
+//! let mut output = String::new();
+//! output += mux.feed("web", "\x1B[32mweb: \x1B[32mstarted\n");
+//! output += mux.feed("db", "db: ready\n");
+//! output += mux.feed("web", "web: listening\x1B[m\n");
+//! output += mux.finish_all();
+//!
+//! assert_eq!(output, "\x1B[32mweb: started\n\x1B[mdb: ready\n\x1B[32mweb: listening\x1B[m\n");
+//! ```
+
+use crate::{Optimizer, Options, Style};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Optimizes several streams, identified by keys, that are interleaved in the same output.
+///
+/// The output of each call assumes that the output of the previous calls was written before it.
+/// When switching to a different stream, the sequences held back by the previous stream are
+/// written, and the new stream changes the style from whatever the previous stream left it as.
+/// The cursor position, modes, and titles are forgotten, since the other streams may have changed
+/// them.
+#[derive(Clone, Debug)]
+pub struct Multiplexer<K> {
+    options: Options,
+    streams: HashMap<K, Optimizer>,
+
+    /// The stream that the output was last written for.
+    current: Option<K>,
+
+    /// The style that the output leaves the terminal in.
+    terminal: Style,
+
+    /// The output of the last call, which is reused between calls.
+    output: String,
+}
+
+impl<K: Eq + Hash + Clone> Multiplexer<K> {
+    /// Creates a multiplexer that optimizes each stream with the provided options.
+    pub fn new(options: Options) -> Self {
+        Multiplexer {
+            options,
+            streams: HashMap::new(),
+            current: None,
+            terminal: Style::default(),
+            output: String::new(),
+        }
+    }
+
+    /// Updates a stream with its next chunk. See [Optimizer::feed].
+    ///
+    /// ## Returns
+    ///
+    /// The output that is ready to be written.
+    pub fn feed(&mut self, key: K, chunk: &str) -> &str {
+        self.output.clear();
+        let optimizer = self.switch_to(key);
+        optimizer.feed(chunk);
+        self.take_output()
+    }
+
+    /// Emits everything that the current stream is holding back. See [Optimizer::flush].
+    ///
+    /// ## Returns
+    ///
+    /// The output that is ready to be written.
+    pub fn flush(&mut self) -> &str {
+        self.output.clear();
+        let streams = &mut self.streams;
+        if let Some(optimizer) = self.current.as_ref().and_then(|key| streams.get_mut(key)) {
+            optimizer.flush();
+        }
+
+        self.take_output()
+    }
+
+    /// Finishes a stream, forgetting its state. See [Optimizer::finish].
+    ///
+    /// The style that the stream was changing to isn't written, since there's no more text to use
+    /// it. If the stream wrote the last output, the style it left the terminal in is reset.
+    ///
+    /// ## Returns
+    ///
+    /// The output that is ready to be written.
+    pub fn finish(&mut self, key: K) -> &str {
+        self.output.clear();
+        if self.streams.contains_key(&key) {
+            let current = self.current.as_ref() == Some(&key);
+            let optimizer = self.switch_to(key.clone());
+            optimizer.style = optimizer.emitted;
+            optimizer.finish();
+            if current {
+                optimizer.style = Style::default();
+                optimizer.flush();
+            }
+
+            self.take_output();
+            self.streams.remove(&key);
+            self.current = None;
+        }
+
+        &self.output
+    }
+
+    /// Finishes every stream, with the current stream first.
+    ///
+    /// ## Returns
+    ///
+    /// The output that is ready to be written.
+    pub fn finish_all(&mut self) -> &str {
+        let mut output = std::mem::take(&mut self.output);
+        output.clear();
+
+        let current = self.current.clone().into_iter();
+        let keys =
+            current.chain(self.streams.keys().cloned().collect::<Vec<_>>()).collect::<Vec<_>>();
+        for key in keys {
+            output.push_str(self.finish(key));
+        }
+
+        // Streams that didn't write the last output leave the style alone when they're finished,
+        // so it may still need to be reset.
+        let profile = &self.options.profile;
+        let reset = Style::default().write_transition_for(&self.terminal, profile, &mut output);
+        reset.expect("write to string");
+        self.terminal = Style::default();
+        self.output = output;
+        &self.output
+    }
+
+    /// Gets the number of streams that haven't been finished.
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Returns `true` if there are no unfinished streams.
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    /// Switches the output to a stream, creating it if needed.
+    fn switch_to(&mut self, key: K) -> &mut Optimizer {
+        if self.current.as_ref() != Some(&key) {
+            // The previous stream's held back sequences came before the new stream's output.
+            // Its style is only needed before its next text, so it can stay held back.
+            let streams = &mut self.streams;
+            if let Some(previous) = self.current.take().and_then(|k| streams.get_mut(&k)) {
+                previous.commit();
+                self.output.push_str(&previous.output);
+                previous.output.clear();
+                self.terminal = previous.emitted;
+            }
+
+            let options = &self.options;
+            let optimizer = self
+                .streams
+                .entry(key.clone())
+                .or_insert_with(|| Optimizer::with_options(options.clone()));
+
+            optimizer.emitted = self.terminal;
+            optimizer.cursor.invalidate();
+            optimizer.forget_modes_and_titles();
+            self.current = Some(key.clone());
+        }
+
+        self.streams.get_mut(&key).expect("stream exists")
+    }
+
+    /// Moves the output of the current stream into the output buffer.
+    fn take_output(&mut self) -> &str {
+        let streams = &mut self.streams;
+        if let Some(optimizer) = self.current.as_ref().and_then(|key| streams.get_mut(key)) {
+            self.output.push_str(&optimizer.output);
+            optimizer.output.clear();
+            self.terminal = optimizer.emitted;
+        }
+
+        &self.output
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::mux::*;
+
+    #[test]
+    fn switch_streams() {
+        let mut mux = Multiplexer::new(Options::default());
+        assert_eq!(mux.feed(1, "\x1B[1ma\x1B[31m"), "\x1B[1ma");
+        assert_eq!(mux.feed(2, "\x1B[4mb"), "\x1B[0;4mb");
+        assert_eq!(mux.feed(2, "\x1B[mc"), "\x1B[mc");
+        assert_eq!(mux.feed(1, "d"), "\x1B[1;31md");
+        assert_eq!(mux.len(), 2);
+
+        // The pending style of a stream isn't written when it's finished.
+        assert_eq!(mux.finish(2), "");
+        assert_eq!(mux.finish(2), "");
+        assert_eq!(mux.feed(1, "\x1B[4m"), "");
+        assert_eq!(mux.finish_all(), "\x1B[m");
+        assert!(mux.is_empty());
+
+        // The style is only reset if a stream left it changed.
+        assert_eq!(mux.feed(1, "a\x1B[1m"), "a");
+        assert_eq!(mux.finish(1), "");
+        assert_eq!(mux.feed(1, "\x1B[1mb"), "\x1B[1mb");
+        assert_eq!(mux.feed(2, "c"), "\x1B[mc");
+        assert_eq!(mux.feed(1, "d"), "\x1B[1md");
+        assert_eq!(mux.finish(2), "");
+        assert_eq!(mux.finish(2), "");
+        assert_eq!(mux.finish_all(), "\x1B[m");
+    }
+
+    #[test]
+    fn switch_forgets_cursor() {
+        let mut mux = Multiplexer::new(Options { columns: Some(80), ..Options::default() });
+        assert_eq!(mux.feed("a", "\x1B[2;1Hab\x1B[2;1H"), "\x1B[2Hab");
+        assert_eq!(mux.feed("a", "c"), "\rc");
+        assert_eq!(mux.feed("b", "\x1B[5;1Hx"), "\x1B[5Hx");
+        assert_eq!(mux.feed("a", "\x1B[2;3Hd"), "\x1B[2;3Hd");
+    }
+
+    #[test]
+    fn switch_forgets_modes() {
+        let mut mux = Multiplexer::new(Options::default());
+        assert_eq!(mux.feed(1, "\x1B[?25la"), "\x1B[?25la");
+        assert_eq!(mux.feed(2, "\x1B[?25hb"), "\x1B[?25hb");
+        assert_eq!(mux.feed(1, "\x1B[?25lc"), "\x1B[?25lc");
+        assert_eq!(mux.feed(1, "\x1B[?25ld"), "d");
+    }
+
+    #[test]
+    fn switch_forgets_titles() {
+        let mut mux = Multiplexer::new(Options::default());
+        assert_eq!(mux.feed(1, "\x1B]2;one\x07a"), "\x1B]2;one\x07a");
+        assert_eq!(mux.feed(2, "\x1B]2;two\x07b"), "\x1B]2;two\x07b");
+        assert_eq!(mux.feed(1, "\x1B]2;one\x07c"), "\x1B]2;one\x07c");
+        assert_eq!(mux.feed(1, "\x1B]2;one\x07d"), "d");
+    }
+}
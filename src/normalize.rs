@@ -0,0 +1,196 @@
+use crate::ansi::ControlSequence;
+use crate::optimizer::is_mode_change;
+use std::fmt::Write;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Writes a control sequence in its normalized form.
+///
+/// Normalization does not depend on any other sequences, and produces a sequence that is
+/// equivalent to the original:
+///
+/// - Leading zeros are removed from parameters (`CSI 01 m` becomes `CSI 1 m`).
+/// - Parameters equal to the command's default value are omitted (`CSI 1;1 H` becomes `CSI H`).
+/// - Trailing empty parameters are removed for commands with positional parameters.
+///
+/// Sequences with intermediate bytes are written unchanged, and so are sequences with a private
+/// marker other than DEC private mode changes, since their parameters might not be numbers.
+pub(crate) fn write_csi<W: Write>(csi: &ControlSequence, out: &mut W) -> std::fmt::Result {
+    let private = csi.private_marker().is_some() && !is_mode_change(csi);
+    if private || !csi.intermediates().is_empty() {
+        return write!(
+            out,
+            "\x1B[{}{}{}",
+            csi.parameters(),
+            csi.intermediates(),
+            csi.finalizer()
+        );
+    }
+
+    out.write_str("\x1B[")?;
+    let mut parameters = csi.parameters();
+    if let Some(marker) = csi.private_marker() {
+        out.write_char(marker)?;
+        parameters = &parameters[1..];
+    }
+
+    match default_value(csi) {
+        // SGR parameters are each separate commands, so only the sole parameter can be omitted.
+        None if csi.private_marker().is_none() && csi.finalizer() == "m" => {
+            if strip_zeros(parameters) != "0" {
+                write_params(parameters, out)?;
+            }
+        }
+
+        // Positional parameters can be omitted when they're equal to the default value.
+        Some(default) => {
+            let mut pending_separators = 0;
+            for (i, param) in parameters.split(';').enumerate() {
+                let param = strip_zeros(param);
+                if i > 0 {
+                    pending_separators += 1;
+                }
+
+                if !param.is_empty() && param != default {
+                    for _ in 0..pending_separators {
+                        out.write_char(';')?;
+                    }
+
+                    write_params(param, out)?;
+                    pending_separators = 0;
+                }
+            }
+        }
+
+        None => write_params(parameters, out)?,
+    }
+
+    out.write_str(csi.finalizer())
+}
+
+/// Writes a parameter string with leading zeros removed from each parameter and sub-parameter.
+fn write_params<W: Write>(parameters: &str, out: &mut W) -> std::fmt::Result {
+    let mut start = 0;
+    for (i, c) in parameters.char_indices() {
+        if c == ';' || c == ':' {
+            out.write_str(strip_zeros(&parameters[start..i]))?;
+            out.write_char(c)?;
+            start = i + 1;
+        }
+    }
+
+    out.write_str(strip_zeros(&parameters[start..]))
+}
+
+/// Removes leading zeros from a parameter, leaving at least one digit.
+fn strip_zeros(param: &str) -> &str {
+    let stripped = param.trim_start_matches('0');
+    if stripped.is_empty() && !param.is_empty() {
+        "0"
+    } else {
+        stripped
+    }
+}
+
+/// Gets the default value of a command's positional parameters.
+///
+/// Returns `None` if the command's parameters are not positional, or if it's unknown.
+fn default_value(csi: &ControlSequence) -> Option<&'static str> {
+    if csi.private_marker().is_some() {
+        return None;
+    }
+
+    match positional_default(csi.finalizer().as_bytes(), csi.params().count())? {
+        0 => Some("0"),
+        _ => Some("1"),
+    }
+}
+
+/// Gets the default value of a command's positional parameters from its final byte and number of
+/// parameters, for commands without a private marker.
+///
+/// Returns `None` if the command's parameters are not positional, or if it's unknown.
+pub(crate) const fn positional_default(finalizer: &[u8], params: usize) -> Option<u16> {
+    match finalizer {
+        // With more than one parameter, this is xterm's mouse highlight tracking instead of SD.
+        b"T" if params > 1 => None,
+
+        // Erase commands (ED, EL, TBC).
+        b"J" | b"K" | b"g" => Some(0),
+
+        // Cursor movement commands (CUU, CUD, CUF, CUB, CNL, CPL, CHA, CUP, HVP, VPA, VPR, HPA,
+        // HPR, CHT, CBT).
+        b"A" | b"B" | b"C" | b"D" | b"E" | b"F" | b"G" | b"H" | b"f" | b"d" | b"e" | b"`" | b"a"
+        | b"I" | b"Z" => Some(1),
+
+        // Editing commands (ICH, IL, DL, DCH, ECH, SU, SD, REP).
+        b"@" | b"L" | b"M" | b"P" | b"X" | b"S" | b"T" | b"b" => Some(1),
+
+        _ => None,
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::ansi::Sequence;
+    use crate::normalize::*;
+    use std::convert::TryFrom;
+
+    fn normalize(s: &str) -> String {
+        let mut out = String::new();
+        match Sequence::try_from(s) {
+            Ok(Sequence::CSI(csi)) => write_csi(&csi, &mut out).unwrap(),
+            other => panic!("not a CSI sequence: {:?}", other),
+        }
+
+        out
+    }
+
+    #[test]
+    fn normalize_leading_zeros() {
+        assert_eq!(normalize("\x1B[01m"), "\x1B[1m");
+        assert_eq!(normalize("\x1B[001;031m"), "\x1B[1;31m");
+        assert_eq!(normalize("\x1B[38:2:0:00:010m"), "\x1B[38:2:0:0:10m");
+        assert_eq!(normalize("\x1B[?0025h"), "\x1B[?25h");
+    }
+
+    #[test]
+    fn normalize_default_values() {
+        assert_eq!(normalize("\x1B[0K"), "\x1B[K");
+        assert_eq!(normalize("\x1B[00J"), "\x1B[J");
+        assert_eq!(normalize("\x1B[2J"), "\x1B[2J");
+        assert_eq!(normalize("\x1B[1;1H"), "\x1B[H");
+        assert_eq!(normalize("\x1B[5;1H"), "\x1B[5H");
+        assert_eq!(normalize("\x1B[1;5H"), "\x1B[;5H");
+        assert_eq!(normalize("\x1B[01A"), "\x1B[A");
+        assert_eq!(normalize("\x1B[0A"), "\x1B[0A");
+        assert_eq!(normalize("\x1B[0m"), "\x1B[m");
+    }
+
+    #[test]
+    fn normalize_trailing_empty() {
+        assert_eq!(normalize("\x1B[5;H"), "\x1B[5H");
+        assert_eq!(normalize("\x1B[;;H"), "\x1B[H");
+
+        // Trailing empty SGR parameters reset the style, so they must be preserved.
+        assert_eq!(normalize("\x1B[1;m"), "\x1B[1;m");
+    }
+
+    #[test]
+    fn normalize_unchanged() {
+        assert_eq!(normalize("\x1B[2 q"), "\x1B[2 q");
+        assert_eq!(normalize("\x1B[?1;1H"), "\x1B[?1;1H");
+        assert_eq!(normalize("\x1B[0;0r"), "\x1B[0;0r");
+        assert_eq!(normalize("\x1B[>4;02m"), "\x1B[>4;02m");
+        assert_eq!(normalize("\x1B[=01;1u"), "\x1B[=01;1u");
+    }
+
+    #[test]
+    fn normalize_mouse_highlight_tracking() {
+        assert_eq!(normalize("\x1B[1T"), "\x1B[T");
+        assert_eq!(normalize("\x1B[1;2;1;1;1T"), "\x1B[1;2;1;1;1T");
+        assert_eq!(normalize("\x1B[01;2;1;1;1T"), "\x1B[1;2;1;1;1T");
+    }
+}
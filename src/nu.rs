@@ -0,0 +1,193 @@
+//! Conversions to and from the styled strings of the `nu-ansi-term` crate.
+//!
+//! Applications that already style their output with `nu-ansi-term` (or `ansi_term`, which it's
+//! a fork of) can keep doing so, and only render the final `AnsiStrings` through the optimizer.
+//!
+//! ```
+//! # use ansi_optimizer::nu::from_ansi_strings;
+//! use nu_ansi_term::{AnsiStrings, Color};
+//!
+//! let strings = [Color::Red.bold().paint("error"), Color::Red.paint(": disk full")];
+//! assert_eq!(AnsiStrings(&strings).to_string(), "\x1B[1;31merror\x1B[0m\x1B[31m: disk full\x1B[0m");
+//! assert_eq!(from_ansi_strings(&strings), "\x1B[1;31merror\x1B[22m: disk full\x1B[m");
+//! ```
+//!
+//! Styles are converted with [From]. Anything that `nu-ansi-term` doesn't support (overlines,
+//! rapid blinking, underline styles, and underline colors) is lost when converting to it.
+
+use crate::ansi::Sequence;
+use crate::style::{Attributes, Underline};
+use crate::token::{Token, Tokens};
+use crate::{is_sgr, Color, Optimizer, Style};
+use nu_ansi_term::{AnsiString, Color as NuColor, Style as NuStyle};
+
+// -------------------------------------------------------------------------------------------------
+
+/// The `nu-ansi-term` colors for the first 16 palette colors.
+const NAMED_COLORS: [NuColor; 16] = [
+    NuColor::Black,
+    NuColor::Red,
+    NuColor::Green,
+    NuColor::Yellow,
+    NuColor::Blue,
+    NuColor::Magenta,
+    NuColor::Cyan,
+    NuColor::White,
+    NuColor::DarkGray,
+    NuColor::LightRed,
+    NuColor::LightGreen,
+    NuColor::LightYellow,
+    NuColor::LightBlue,
+    NuColor::LightMagenta,
+    NuColor::LightCyan,
+    NuColor::LightGray,
+];
+
+/// A field of a `nu-ansi-term` style, for an attribute.
+type Field = fn(&mut NuStyle) -> &mut bool;
+
+/// The attributes that both crates support, and the `nu-ansi-term` fields for them.
+const ATTRIBUTES: [(Attributes, Field); 7] = [
+    (Attributes::BOLD, |s| &mut s.is_bold),
+    (Attributes::FAINT, |s| &mut s.is_dimmed),
+    (Attributes::ITALIC, |s| &mut s.is_italic),
+    (Attributes::BLINK, |s| &mut s.is_blink),
+    (Attributes::REVERSE, |s| &mut s.is_reverse),
+    (Attributes::HIDDEN, |s| &mut s.is_hidden),
+    (Attributes::STRIKETHROUGH, |s| &mut s.is_strikethrough),
+];
+
+impl From<NuColor> for Color {
+    fn from(color: NuColor) -> Self {
+        match color {
+            NuColor::Default => Color::Default,
+            NuColor::Fixed(index) => Color::Indexed(index),
+            NuColor::Rgb(r, g, b) => Color::Rgb(r, g, b),
+            NuColor::Purple => Color::Indexed(5),
+            NuColor::LightPurple => Color::Indexed(13),
+            named => {
+                let index = NAMED_COLORS.iter().position(|&c| c == named).unwrap_or(0);
+                Color::Indexed(index as u8)
+            }
+        }
+    }
+}
+
+impl From<NuStyle> for Style {
+    fn from(mut nu: NuStyle) -> Self {
+        let mut style = Style {
+            foreground: nu.foreground.map_or(Color::Default, Color::from),
+            background: nu.background.map_or(Color::Default, Color::from),
+            ..Style::default()
+        };
+
+        if nu.is_underline {
+            style.underline = Underline::Single;
+        }
+
+        for (attribute, field) in ATTRIBUTES.iter() {
+            if *field(&mut nu) {
+                style.attributes.insert(*attribute);
+            }
+        }
+
+        style
+    }
+}
+
+impl From<Style> for NuStyle {
+    fn from(style: Style) -> Self {
+        let mut nu = NuStyle {
+            foreground: to_nu_color(style.foreground),
+            background: to_nu_color(style.background),
+            is_underline: style.underline != Underline::None,
+            ..NuStyle::default()
+        };
+
+        for (attribute, field) in ATTRIBUTES.iter() {
+            *field(&mut nu) = style.attributes.contains(*attribute);
+        }
+
+        nu
+    }
+}
+
+/// Converts a color to a `nu-ansi-term` color, where `None` is the default color.
+fn to_nu_color(color: Color) -> Option<NuColor> {
+    match color {
+        Color::Default => None,
+        Color::Indexed(index) if index < 16 => Some(NAMED_COLORS[index as usize]),
+        Color::Indexed(index) => Some(NuColor::Fixed(index)),
+        Color::Rgb(r, g, b) => Some(NuColor::Rgb(r, g, b)),
+    }
+}
+
+/// Renders styled strings, with the fewest escape sequences needed.
+///
+/// The output ends with the default style. Hyperlinks and window titles are not kept.
+pub fn from_ansi_strings(strings: &[AnsiString<'_>]) -> String {
+    let mut optimizer = Optimizer::new();
+    for string in strings {
+        optimizer.write_styled(string.as_str(), Style::from(*string.style_ref()));
+    }
+
+    optimizer.feed("\x1B[m");
+    optimizer.finish();
+    optimizer.take_output()
+}
+
+/// Splits text with escape sequences into styled strings.
+///
+/// Adjacent text with the same style is merged into one string. Escape sequences other than
+/// SGR sequences can't be represented, and are removed.
+pub fn to_ansi_strings(input: &str) -> Vec<AnsiString<'static>> {
+    let mut strings: Vec<(Style, String)> = Vec::new();
+    let mut style = Style::default();
+
+    for token in Tokens::new(input) {
+        match token {
+            Ok(Token::Text(text)) => match strings.last_mut() {
+                Some((last, string)) if *last == style => string.push_str(text),
+                _ => strings.push((style, text.to_owned())),
+            },
+            Ok(Token::Sequence(Sequence::CSI(csi))) if is_sgr(&csi) => {
+                style.apply_sgr(csi.params());
+            }
+            _ => {}
+        }
+    }
+
+    strings.into_iter().map(|(style, text)| NuStyle::from(style).paint(text)).collect()
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::nu::*;
+
+    #[test]
+    fn convert_styles() {
+        let nu = NuColor::Purple.on(NuColor::Fixed(208)).bold().underline();
+        let style = Style::from(nu);
+        assert_eq!(style.foreground, Color::Indexed(5));
+        assert_eq!(style.background, Color::Indexed(208));
+        assert_eq!(style.underline, Underline::Single);
+        assert_eq!(style.attributes, Attributes::BOLD);
+        assert_eq!(
+            NuStyle::from(style),
+            NuColor::Magenta.on(NuColor::Fixed(208)).bold().underline()
+        );
+        assert_eq!(NuStyle::from(Style::default()), NuStyle::default());
+    }
+
+    #[test]
+    fn split_into_strings() {
+        let strings = to_ansi_strings("a\x1B[1mb\x1B[2Jc\x1B[22;38;2;1;2;3md\x1B[m");
+        assert_eq!(strings.len(), 3);
+        assert_eq!(strings[0], NuStyle::default().paint("a"));
+        assert_eq!(strings[1], NuStyle::new().bold().paint("bc"));
+        assert_eq!(strings[2], NuColor::Rgb(1, 2, 3).paint("d"));
+        assert_eq!(from_ansi_strings(&strings), "a\x1B[1mbc\x1B[0;38;2;1;2;3md\x1B[m");
+    }
+}
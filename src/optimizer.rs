@@ -0,0 +1,2633 @@
+use crate::ansi::{AnsiString, ControlSequence, Param, Sequence};
+use crate::csi::{CsiCommand, WindowOp};
+use crate::cursor::{Cursor, Position};
+use crate::osc::{DynamicColor, OscCommand, PaletteEntries};
+use crate::palette::PaletteState;
+use crate::pending::{Kind, Pending};
+use crate::report::Tracker;
+use crate::token::{Token, Tokens};
+use crate::trace::decision;
+use crate::{color, cursor, erase, limits, normalize, repeat, sanitize, token};
+use crate::{Color, ColorSupport, Error, Hyperlinks, Options, OverLimit, Queries, Sgr21, Style};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter, Write};
+use std::time::Instant;
+
+// -------------------------------------------------------------------------------------------------
+
+/// An ANSI escape sequence optimizer.
+///
+/// This will consume a series of ANSI/VT100 escape sequences and generate equivalent sequences.
+/// The sequences will be comprised of a smaller or equal number of characters than the input.
+///
+/// To create the optimized sequence, the [ToString] trait or [Display] trait should be used:
+///
+/// ```
+/// # use ansi_optimizer::Optimizer;
+/// let mut optimizer = Optimizer::new();
+/// optimizer.update("\x1B[33;41m");
+/// optimizer.update("\x1B[39m");
+///
+/// assert_eq!(optimizer.to_string(), "\x1B[41m");
+/// ```
+///
+/// ## Ordering
+///
+/// Sequences are written in the order they're given, except for style changes, which are held
+/// back until they're needed and can move past cursor movement. Other sequences can be removed if
+/// a later one makes them redundant, but are never moved past each other. The only exception is a
+/// progress report held back by [Options::collapse_progress]. See [Optimizer::barrier].
+#[derive(Clone, Debug, Default)]
+pub struct Optimizer {
+    options: Options,
+    pub(crate) output: String,
+
+    /// The length of the output that was returned by [Optimizer::feed_borrowed], and is removed
+    /// before more is returned.
+    borrowed: usize,
+
+    /// The start of an escape sequence that was split between chunks given to [Optimizer::feed].
+    pub(crate) partial: String,
+
+    /// The rest of an unfinished sequence that was too long to hold back, and is being dropped.
+    discard: Option<Discard>,
+
+    /// Sequences emitted since the last text.
+    pub(crate) pending: Pending,
+
+    /// When the oldest pending sequence or style change was held back.
+    pending_since: Option<Instant>,
+
+    /// The style that should be active before the next text.
+    pub(crate) style: Style,
+
+    /// The style set by the input, before it was changed to follow the [Options].
+    pub(crate) input_style: Style,
+
+    /// The style that the emitted output leaves the terminal in.
+    pub(crate) emitted: Style,
+
+    /// The emitted and input styles saved by the last DECSC sequence.
+    saved_style: Option<(Style, Style)>,
+
+    /// The input styles to restore with [Optimizer::pop_style].
+    style_stack: Vec<Style>,
+
+    /// Whether an SGR 21 was emitted that the terminal might not treat as a double underline,
+    /// so the emitted style isn't known until it's reset.
+    ambiguous_style: bool,
+
+    /// The last character written, if it can be repeated with REP.
+    last_char: Option<char>,
+
+    /// The cursor, as positioned by the emitted output.
+    pub(crate) cursor: Cursor,
+
+    /// The cursor position before the most recent run of pending cursor movement.
+    move_origin: Position,
+
+    /// The committed state of each mode in [CANCELLABLE_MODES].
+    modes: [Option<bool>; CANCELLABLE_MODES.len()],
+
+    /// The titles set by the emitted output.
+    titles: Titles,
+
+    /// The titles set by the committed output.
+    committed_titles: Titles,
+
+    /// The palette and dynamic colors set by the emitted output.
+    palette: PaletteState,
+
+    /// The parameters and URI of the hyperlink that the emitted output leaves open.
+    hyperlink: Option<(String, String)>,
+
+    /// The URI of the hyperlink that the input left open, if the profile doesn't support them.
+    link: Option<String>,
+
+    /// The URIs of the hyperlinks written as footnotes, in order of their numbers.
+    footnotes: Vec<String>,
+
+    /// The last progress report held back by [Options::collapse_progress], and its source.
+    progress: Option<(String, Option<usize>)>,
+
+    /// When the progress report was first held back.
+    progress_since: Option<Instant>,
+
+    /// The output written for each input sequence, when creating a [Report].
+    pub(crate) tracker: Option<Box<Tracker>>,
+
+    /// The output offsets at which the style changed, when creating a [StyleIndex].
+    pub(crate) style_changes: Option<Vec<(usize, Style)>>,
+}
+
+/// DEC private modes that only affect future output, and can be toggled without side effects.
+/// These are: DECAWM (auto-wrap), cursor blinking, and DECTCEM (cursor visibility).
+const CANCELLABLE_MODES: [u16; 3] = [7, 12, 25];
+
+impl Optimizer {
+    /// Creates a new optimizer with a default state.
+    pub fn new() -> Self {
+        Self::with_options(Options::default())
+    }
+
+    /// Creates a new optimizer with the provided options.
+    pub fn with_options(options: Options) -> Self {
+        let cursor = Cursor::new(&options);
+        let mut optimizer = Optimizer {
+            options,
+            output: String::new(),
+            borrowed: 0,
+            partial: String::new(),
+            discard: None,
+            pending: Pending::default(),
+            pending_since: None,
+            style: Style::default(),
+            input_style: Style::default(),
+            emitted: Style::default(),
+            saved_style: None,
+            style_stack: Vec::new(),
+            ambiguous_style: false,
+            last_char: None,
+            cursor,
+            move_origin: Position::UNKNOWN,
+            modes: [None; CANCELLABLE_MODES.len()],
+            titles: Titles::default(),
+            committed_titles: Titles::default(),
+            palette: PaletteState::default(),
+            hyperlink: None,
+            link: None,
+            footnotes: Vec::new(),
+            progress: None,
+            progress_since: None,
+            tracker: None,
+            style_changes: None,
+        };
+
+        optimizer.style = optimizer.resolve_style(Style::default());
+        optimizer
+    }
+
+    /// Forgets the modes and titles set by the emitted output, after something else may have
+    /// changed them.
+    pub(crate) fn forget_modes_and_titles(&mut self) {
+        self.modes = [None; CANCELLABLE_MODES.len()];
+        self.titles = Titles::default();
+        self.committed_titles = Titles::default();
+    }
+
+    /// Resets the optimizer back to a default state.
+    /// This is equivalent to creating a new optimizer, but avoids unnecessary allocations.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.output.clear();
+        self.borrowed = 0;
+        self.partial.clear();
+        self.discard = None;
+        self.pending.clear();
+        self.pending_since = None;
+        self.input_style = Style::default();
+        self.emitted = Style::default();
+        self.saved_style = None;
+        self.style_stack.clear();
+        self.ambiguous_style = false;
+        self.last_char = None;
+        self.cursor.reset();
+        self.move_origin = Position::UNKNOWN;
+        self.modes = [None; CANCELLABLE_MODES.len()];
+        self.titles = Titles::default();
+        self.committed_titles = Titles::default();
+        self.palette.clear();
+        self.hyperlink = None;
+        self.link = None;
+        self.footnotes.clear();
+        self.progress = None;
+        self.progress_since = None;
+        self.style = self.resolve_style(Style::default());
+    }
+
+    /// Updates the optimizer with more input.
+    ///
+    /// The input may contain any mix of text and escape sequences.
+    /// SGR sequences are combined until they are needed (i.e. before text or a sequence that
+    /// depends on the current style), and then emitted as a single minimal sequence.
+    ///
+    /// Other sequences are held until the next text, and sequences that have no effect by the
+    /// time it arrives are removed. For example, hiding and then showing the cursor, or saving and
+    /// then immediately restoring the cursor.
+    ///
+    /// ## Returns
+    ///
+    /// If the input contains an invalid escape sequence, this returns [Error::InvalidSequence].
+    /// If the input ends partway through an escape sequence, this returns [Error::Incomplete].
+    /// If a sequence exceeds the limits set by the [Options], and [OverLimit::Error] is used, this
+    /// returns [Error::LimitExceeded].
+    /// In every case, everything before the offending sequence will have been consumed.
+    pub fn update(&mut self, input: impl AsRef<str>) -> Result<(), Error> {
+        for token in Tokens::new(input.as_ref()) {
+            match token? {
+                Token::Text(text) => self.update_text(text),
+                Token::Sequence(sequence) => self.update_sequence(&sequence)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Updates the optimizer with the next chunk of a stream.
+    ///
+    /// Unlike [Optimizer::update], an escape sequence that is split between chunks is held until
+    /// the rest of it arrives, and invalid escape sequences are passed through unchanged.
+    /// This makes it suitable for output read from a program, where chunk boundaries are arbitrary
+    /// and nothing should be lost. The exception is an unfinished sequence that grows longer than
+    /// [Options::max_sequence_bytes], which is dropped along with the rest of it.
+    ///
+    /// Once the stream ends, [Optimizer::finish] should be called.
+    pub fn feed(&mut self, chunk: &str) {
+        let chunk = self.skip_discarded(chunk);
+        if self.discard.is_some() {
+            return;
+        }
+
+        // An unfinished string (e.g. OSC) can only end at a `BEL`, `ESC`, or 8-bit `ST`, and an
+        // unfinished control sequence can only end at a byte that isn't a parameter or an
+        // intermediate, so neither is parsed again until one arrives. Otherwise, a long sequence
+        // split into many chunks would be parsed once for each of them.
+        let string = is_unfinished_string(&self.partial);
+        let csi = self.partial.starts_with("\x1B[") || self.partial.starts_with('\u{9B}');
+        if string && !chunk.bytes().any(|b| matches!(b, 0x07 | 0x1B | 0x9C))
+            || csi && chunk.bytes().all(|b| matches!(b, 0x20..=0x3F))
+        {
+            self.partial.push_str(chunk);
+        } else {
+            self.update_chunk(chunk);
+        }
+
+        // Where the sequence ends isn't known yet, so it can't be truncated or passed through.
+        // The chunk may have finished the last sequence and started another, so what's dropped is
+        // whatever is unfinished now.
+        if matches!(self.options.max_sequence_bytes, Some(max) if self.partial.len() > max) {
+            self.discard = Some(match is_unfinished_string(&self.partial) {
+                true if self.partial.ends_with('\x1B') => Discard::StringEscape,
+                true => Discard::String,
+                false => Discard::Control,
+            });
+
+            decision!("dropped", self.partial, "unfinished sequence exceeds the limits");
+            self.partial.clear();
+        }
+    }
+
+    /// Removes the start of a chunk that belongs to a sequence being dropped.
+    ///
+    /// ## Returns
+    ///
+    /// The rest of the chunk, after the end of the sequence.
+    fn skip_discarded<'a>(&mut self, chunk: &'a str) -> &'a str {
+        let bytes = chunk.as_bytes();
+        let (end, discard) = match self.discard {
+            None => return chunk,
+
+            // A control sequence ends at its final byte, or is cancelled by any other byte.
+            Some(Discard::Control) => match bytes.iter().position(|b| !(0x20..=0x3F).contains(b)) {
+                Some(i) if (0x40..=0x7E).contains(&bytes[i]) => (i + 1, None),
+                Some(i) => (i, None),
+                None => (bytes.len(), Some(Discard::Control)),
+            },
+
+            // A string ends at `BEL` or `ST`, or is cancelled by an `ESC` that starts another
+            // sequence.
+            Some(Discard::String) => {
+                let end = (0..bytes.len()).find(|&i| match bytes[i] {
+                    0x07 | 0x1B => true,
+                    0x9C => i > 0 && bytes[i - 1] == 0xC2,
+                    _ => false,
+                });
+
+                match end.map(|i| (i, bytes[i], bytes.get(i + 1))) {
+                    None => (bytes.len(), Some(Discard::String)),
+                    Some((i, 0x1B, None)) => (i + 1, Some(Discard::StringEscape)),
+                    Some((i, 0x1B, Some(b'\\'))) => (i + 2, None),
+                    Some((i, 0x1B, Some(_))) => (i, None),
+                    Some((i, _, _)) => (i + 1, None),
+                }
+            }
+
+            Some(Discard::StringEscape) => match bytes.first() {
+                None => (0, Some(Discard::StringEscape)),
+                Some(b'\\') => (1, None),
+                Some(_) => {
+                    self.partial.push('\x1B');
+                    (0, None)
+                }
+            },
+        };
+
+        self.discard = discard;
+        &chunk[end..]
+    }
+
+    /// Updates the optimizer with the next chunk of a stream, and returns the output that is ready.
+    ///
+    /// This is the same as [Optimizer::feed] followed by [Optimizer::take_output], except that the
+    /// output stays in a buffer that is reused by the next call. Streams that are fed many small
+    /// chunks can use this to avoid allocating a string for each of them.
+    ///
+    /// ```
+    /// # use ansi_optimizer::Optimizer;
+    /// let mut optimizer = Optimizer::new();
+    /// assert_eq!(optimizer.feed_borrowed("\x1B[1mbold\x1B["), "\x1B[1mbold");
+    /// assert_eq!(optimizer.feed_borrowed("22mplain"), "\x1B[mplain");
+    /// ```
+    pub fn feed_borrowed(&mut self, chunk: &str) -> &str {
+        self.release_borrowed();
+        self.feed(chunk);
+        self.borrowed = self.output.len();
+        &self.output
+    }
+
+    /// Updates the optimizer with the next chunk of a stream, and returns the output that is ready.
+    ///
+    /// This is the same as [Optimizer::feed] followed by [Optimizer::take_output], except that a
+    /// chunk which would be written unchanged is returned without being copied. That is the case
+    /// for plain text (the bulk of most logs) when nothing is being held back before it.
+    ///
+    /// ```
+    /// # use ansi_optimizer::Optimizer;
+    /// # use std::borrow::Cow;
+    /// let mut optimizer = Optimizer::new();
+    /// assert!(matches!(optimizer.feed_cow("plain text\n"), Cow::Borrowed(_)));
+    /// assert!(matches!(optimizer.feed_cow("\x1B[1mbold"), Cow::Owned(_)));
+    /// ```
+    pub fn feed_cow<'a>(&mut self, chunk: &'a str) -> Cow<'a, str> {
+        self.release_borrowed();
+        if self.pass_through(chunk) {
+            return Cow::Borrowed(chunk);
+        }
+
+        self.feed(chunk);
+        Cow::Owned(self.take_output())
+    }
+
+    /// Updates the optimizer with text that doesn't need to be copied to the output, because
+    /// nothing is written before it and it would be written unchanged.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the text was consumed, and should be written by the caller.
+    fn pass_through(&mut self, text: &str) -> bool {
+        let options = &self.options;
+        let erasing = match (options.columns, self.cursor.position.column) {
+            (Some(_), Some(_)) => options.erase_runs && erase::is_erasable(&self.emitted),
+            _ => false,
+        };
+
+        let unchanged = self.output.is_empty()
+            && self.partial.is_empty()
+            && self.discard.is_none()
+            && self.pending.is_empty()
+            && self.progress.is_none()
+            && self.tracker.is_none()
+            && self.style_changes.is_none()
+            && self.style.visually_eq(&self.emitted, &options.profile)
+            && !(options.repeat_runs && options.profile.repeat)
+            && !erasing
+            && token::find_escape(text).is_none()
+            && matches!(sanitize::replace_controls(text, options.control_chars), Cow::Borrowed(_));
+
+        if !unchanged || text.is_empty() {
+            return unchanged;
+        }
+
+        self.commit();
+        self.cursor.update_text(text);
+        self.last_char = text.chars().next_back().filter(|&c| repeat::is_repeatable(c));
+        true
+    }
+
+    /// Removes the output that was returned by [Optimizer::feed_borrowed].
+    fn release_borrowed(&mut self) {
+        if self.borrowed == self.output.len() {
+            self.output.clear();
+        } else {
+            self.output.drain(..self.borrowed);
+        }
+
+        self.borrowed = 0;
+    }
+
+    /// Updates the optimizer with the next chunk of a stream, after what's left of the last one.
+    fn update_chunk(&mut self, chunk: &str) {
+        if self.partial.is_empty() {
+            let consumed = self.update_stream(chunk);
+            self.partial.push_str(&chunk[consumed..]);
+            return;
+        }
+
+        // The joined input is parsed from a buffer of its own, which then holds what's left of it.
+        let mut joined = std::mem::take(&mut self.partial);
+        joined.push_str(chunk);
+        let consumed = self.update_stream(&joined);
+        joined.drain(..consumed);
+        self.partial = joined;
+    }
+
+    /// Updates the optimizer with the complete tokens at the start of a stream's input.
+    ///
+    /// ## Returns
+    ///
+    /// The length of the input that was consumed.
+    fn update_stream(&mut self, input: &str) -> usize {
+        let mut tokens = Tokens::new(input).spanned();
+        for (span, token) in &mut tokens {
+            match token {
+                Ok(Token::Text(text)) => self.update_text(text),
+                Ok(Token::Sequence(sequence)) => {
+                    let _ = self.update_sequence(&sequence);
+                }
+                Err(Error::InvalidSequence) if self.options.sanitize => {
+                    decision!("dropped", &input[span.start..span.end], "invalid sequence");
+                }
+                Err(Error::InvalidSequence) => self.update_text(&input[span.start..span.end]),
+                Err(_) => {}
+            }
+        }
+
+        input.len() - tokens.remaining().len()
+    }
+
+    /// Finishes a stream that was given to the optimizer with [Optimizer::feed].
+    ///
+    /// This flushes everything that is being held back. If the stream ended partway through an
+    /// escape sequence, the incomplete sequence is written unchanged. A hyperlink that is still
+    /// open is closed, and if hyperlinks are written as [footnotes](Hyperlinks::Footnotes), the
+    /// list of them is written last.
+    pub fn finish(&mut self) {
+        self.discard = None;
+        self.interrupt();
+        if self.hyperlink.take().is_some() {
+            self.output.push_str("\x1B]8;;\x1B\\");
+        }
+
+        self.close_hyperlink();
+        if !self.footnotes.is_empty() {
+            let mut list = String::from("\n");
+            for (i, uri) in self.footnotes.drain(..).enumerate() {
+                let _ = writeln!(list, "[{}] {}", i + 1, uri);
+            }
+
+            self.update_text(&list);
+        }
+    }
+
+    /// Flushes everything that is being held back, and writes an incomplete sequence unchanged.
+    ///
+    /// This is for input that has something that can't be given to the optimizer (e.g. bytes that
+    /// aren't valid UTF-8) in the middle of it. Unlike [Optimizer::finish], the stream continues
+    /// afterwards, so open hyperlinks are left open and footnotes aren't written.
+    pub(crate) fn interrupt(&mut self) {
+        self.flush();
+        if !self.partial.is_empty() {
+            let partial = std::mem::take(&mut self.partial);
+            self.update_text(&partial);
+        }
+    }
+
+    pub(crate) fn update_text(&mut self, text: &str) {
+        let text = &*sanitize::replace_controls(text, self.options.control_chars);
+        if text.is_empty() {
+            return;
+        }
+
+        self.flush_style();
+        self.commit();
+        self.write_text(text);
+        self.cursor.update_text(text);
+    }
+
+    /// Writes text in a style of its own, without changing the style set by the input.
+    ///
+    /// This is meant for text that isn't part of the input, like a prefix added to each line.
+    /// The style follows the [Options] like the input's styles do, and the input's style is
+    /// changed back to with whichever sequence is shortest before its next text.
+    ///
+    /// ```
+    /// # use ansi_optimizer::{Color, Optimizer, Style};
+    /// let mut optimizer = Optimizer::new();
+    /// optimizer.update("\x1B[1;31m").unwrap();
+    /// optimizer.write_styled("[web] ", Style { foreground: Color::Indexed(4), ..Style::default() });
+    /// optimizer.update("started").unwrap();
+    ///
+    /// assert_eq!(optimizer.to_string(), "\x1B[34m[web] \x1B[1;31mstarted");
+    /// ```
+    pub fn write_styled(&mut self, text: &str, style: Style) {
+        let style = self.resolve_style(style);
+        let input = std::mem::replace(&mut self.style, style);
+        self.update_text(text);
+        self.style = input;
+    }
+
+    /// Changes the style until [Optimizer::pop_style] is called, like a nested scope in markup.
+    ///
+    /// The style replaces the current one, so nested styles should be based on it. Nothing is
+    /// written until the next text, so pushing and popping a style around no text does nothing.
+    ///
+    /// ```
+    /// # use ansi_optimizer::style::Attributes;
+    /// # use ansi_optimizer::{Color, Optimizer, Style};
+    /// let red = Style { foreground: Color::Indexed(1), ..Style::default() };
+    /// let mut optimizer = Optimizer::new();
+    /// optimizer.push_style(red);
+    /// optimizer.update("error: ").unwrap();
+    /// optimizer.push_style(Style { attributes: Attributes::BOLD, ..red });
+    /// optimizer.update("disk full").unwrap();
+    /// optimizer.pop_style();
+    /// optimizer.pop_style();
+    /// optimizer.update("\n").unwrap();
+    ///
+    /// assert_eq!(optimizer.to_string(), "\x1B[31merror: \x1B[1mdisk full\x1B[m\n");
+    /// ```
+    pub fn push_style(&mut self, style: Style) {
+        self.style_stack.push(self.input_style);
+        self.input_style = style;
+        self.style = self.resolve_style(style);
+    }
+
+    /// Changes the style back to what it was before the last [Optimizer::push_style], even if the
+    /// input changed it since then.
+    ///
+    /// ## Returns
+    ///
+    /// `false` if there is no style to change back to.
+    pub fn pop_style(&mut self) -> bool {
+        match self.style_stack.pop() {
+            Some(style) => {
+                self.input_style = style;
+                self.style = self.resolve_style(style);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Emits everything that is being held back while looking for merge opportunities.
+    ///
+    /// This is done automatically when text is written or the bounds set by the [Options] are
+    /// reached, but can be done manually to guarantee the output is up to date.
+    pub fn flush(&mut self) {
+        self.flush_style();
+        self.commit();
+
+        if let Some((progress, source)) = self.progress.take() {
+            if let (Some(tracker), Some(source)) = (&mut self.tracker, source) {
+                tracker.write(source, &progress);
+            }
+
+            self.output.push_str(&progress);
+            self.progress_since = None;
+        }
+    }
+
+    /// Emits everything that is being held back, and stops anything given afterwards from being
+    /// merged with it.
+    ///
+    /// Nothing given before a barrier is written after anything given after it, including style
+    /// changes and progress reports. Sequences before it aren't removed for being made redundant
+    /// by ones after it, but sequences after it are still removed if the terminal is known to
+    /// already be in the state that they set. Text after it isn't written as a repeat of the text
+    /// before it.
+    ///
+    /// ```
+    /// # use ansi_optimizer::Optimizer;
+    /// let mut optimizer = Optimizer::new();
+    /// optimizer.feed("\x1B]2;building\x07");
+    /// optimizer.barrier();
+    /// optimizer.feed("\x1B]2;done\x07text");
+    /// assert_eq!(optimizer.take_output(), "\x1B]2;building\x07\x1B]2;done\x07text");
+    /// ```
+    pub fn barrier(&mut self) {
+        self.flush();
+        self.last_char = None;
+    }
+
+    /// Changes the size of the terminal that the output is written to.
+    ///
+    /// Programs that sit between a PTY and a terminal should call this when the terminal is
+    /// resized (e.g. on `SIGWINCH`), after resizing the PTY. Everything being held back is flushed
+    /// first, since it was written for the old size. Since some terminals reflow text when they
+    /// are resized, the cursor position is unknown until the output moves it somewhere absolute.
+    ///
+    /// The contents of the screen aren't tracked, so they can't be redrawn. The program that's
+    /// writing the output is expected to do that after it's resized.
+    pub fn resize(&mut self, columns: u16, rows: u16) {
+        self.flush();
+        self.options.columns = Some(columns);
+        self.options.rows = Some(rows);
+        self.cursor.resize(columns, rows);
+    }
+
+    /// Takes the output that is ready to be written.
+    ///
+    /// This does not include anything that is being held back. See [Optimizer::flush].
+    pub fn take_output(&mut self) -> String {
+        self.release_borrowed();
+        std::mem::take(&mut self.output)
+    }
+
+    /// Gets a single SGR sequence that changes the default style to the style set by the input.
+    ///
+    /// This can be given to [Optimizer::import_state] later, to continue optimizing from the same
+    /// point of the input. Nothing is returned if the input's style is the default style.
+    ///
+    /// ```
+    /// # use ansi_optimizer::Optimizer;
+    /// let mut optimizer = Optimizer::new();
+    /// optimizer.update("\x1B[1m\x1B[38;2;255;0;0mred\x1B[22m\x1B[4m").unwrap();
+    /// assert_eq!(optimizer.export_state(), "\x1B[4;38;2;255;0;0m");
+    /// ```
+    pub fn export_state(&self) -> String {
+        self.input_style.transition_from(Some(&Style::default())).to_string()
+    }
+
+    /// Changes the style set by the input to the one set by SGR sequences created by
+    /// [Optimizer::export_state].
+    ///
+    /// The terminal is still expected to have whichever style the output left it in, so the
+    /// style is changed before the next text. Pagers can use this after jumping partway through
+    /// their input, with the state exported when the input was first read.
+    ///
+    /// ## Returns
+    ///
+    /// If the state contains anything other than SGR sequences, this returns
+    /// [Error::InvalidSequence] and the style isn't changed.
+    pub fn import_state(&mut self, state: &str) -> Result<(), Error> {
+        let mut style = Style::default();
+        for token in Tokens::new(state) {
+            match token? {
+                Token::Sequence(Sequence::CSI(csi)) if is_sgr(&csi) => {
+                    style.apply_sgr_for(csi.params(), &self.options.profile);
+                }
+                _ => return Err(Error::InvalidSequence),
+            }
+        }
+
+        self.input_style = style;
+        self.colors_changed();
+        Ok(())
+    }
+
+    /// Gets the time at which the held back sequences exceed [Options::max_buffered_time].
+    ///
+    /// ## Returns
+    ///
+    /// `None` if there is no time limit, or if nothing is being held back.
+    pub fn deadline(&self) -> Option<Instant> {
+        let since = self.pending_since.into_iter().chain(self.progress_since).min()?;
+        Some(since + self.options.max_buffered_time?)
+    }
+
+    /// Flushes the held back sequences if they exceed the limits set by the [Options].
+    fn check_bounds(&mut self) {
+        let holding = !self.pending.is_empty() || self.style != self.emitted;
+        if !holding {
+            self.pending_since = None;
+            if self.progress.is_none() {
+                return;
+            }
+        }
+
+        let over_bytes = match self.options.max_buffered_bytes {
+            Some(max) => self.pending.len() > max,
+            None => false,
+        };
+
+        // The clock is only read if there is a time limit, since it isn't available on every
+        // target (e.g. `wasm32-unknown-unknown`).
+        let over_time = match self.options.max_buffered_time {
+            Some(max) => {
+                let now = Instant::now();
+                if holding {
+                    self.pending_since.get_or_insert(now);
+                }
+
+                if self.progress.is_some() {
+                    self.progress_since.get_or_insert(now);
+                }
+
+                let since = self.pending_since.into_iter().chain(self.progress_since).min();
+                matches!(since, Some(since) if now.duration_since(since) >= max)
+            }
+            None => false,
+        };
+
+        if over_bytes || over_time {
+            self.flush();
+        }
+    }
+
+    pub(crate) fn update_sequence(&mut self, sequence: &Sequence) -> Result<(), Error> {
+        if !matches!(sequence, Sequence::CSI(csi) if is_sgr(csi) || is_repeat(csi)) {
+            self.last_char = None;
+        }
+
+        decision!("parsed", sequence);
+        let result = if limits::exceeds(sequence, &self.options) {
+            self.update_over_limit(sequence)
+        } else {
+            self.update_sequence_kind(sequence);
+            Ok(())
+        };
+
+        self.check_bounds();
+        result
+    }
+
+    /// Handles a sequence that exceeds the limits, as the [Options::over_limit] says.
+    fn update_over_limit(&mut self, sequence: &Sequence) -> Result<(), Error> {
+        match self.options.over_limit {
+            OverLimit::Pass => {
+                decision!("kept", sequence, "exceeds the limits");
+                self.flush_style();
+                self.cursor.invalidate();
+                self.ambiguous_style |= matches!(sequence, Sequence::CSI(csi) if is_sgr(csi));
+                self.pending.push(Kind::Other, sequence);
+            }
+            OverLimit::Truncate => {
+                let truncated = limits::truncate(sequence, &self.options);
+                decision!("rewritten", sequence, "as {:?}, to fit the limits", truncated);
+                if let Ok(truncated) = Sequence::try_from(truncated.as_str()) {
+                    self.update_sequence_kind(&truncated);
+                }
+            }
+            OverLimit::Error => {
+                decision!("dropped", sequence, "exceeds the limits");
+                return Err(Error::LimitExceeded);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn update_sequence_kind(&mut self, sequence: &Sequence) {
+        if self.options.sanitize && sanitize::is_unsafe(sequence) {
+            return decision!("dropped", sequence, "unsafe to display");
+        }
+
+        match sequence {
+            Sequence::CSI(csi) => self.update_csi(sequence, csi),
+            _ if is_image(sequence) => self.update_image(sequence),
+            Sequence::OSC(_, string) => match OscCommand::from(string) {
+                OscCommand::SetTitleAndIconName(title) => self.update_title(sequence, 0, title),
+                OscCommand::SetIconName(title) => self.update_title(sequence, 1, title),
+                OscCommand::SetTitle(title) => self.update_title(sequence, 2, title),
+                OscCommand::Clipboard { data, .. } => self.update_clipboard(sequence, data),
+                OscCommand::Hyperlink { params, uri } => {
+                    self.update_hyperlink(sequence, params, uri, string.finalizer())
+                }
+
+                OscCommand::Other(9, data)
+                    if data.starts_with("4;") && self.options.collapse_progress =>
+                {
+                    self.update_progress(sequence)
+                }
+
+                OscCommand::SetPalette(entries) => {
+                    self.update_palette(sequence, entries, string.finalizer())
+                }
+
+                OscCommand::ResetPalette(indices) => {
+                    let changed = match indices {
+                        "" => self.palette.reset_all(),
+                        indices => indices.split(';').fold(false, |changed, index| {
+                            let reset = match index.parse() {
+                                Ok(index) => self.palette.reset(index),
+                                Err(_) => true,
+                            };
+
+                            reset || changed
+                        }),
+                    };
+
+                    if changed {
+                        self.pending.push(Kind::Other, sequence);
+                        self.colors_changed();
+                    } else {
+                        decision!("dropped", sequence, "palette is already reset");
+                    }
+                }
+
+                // Only commands that set a single color are checked.
+                OscCommand::SetDynamicColor(color, spec) if !spec.contains(';') => {
+                    if self.palette.set_dynamic(color.offset(), spec) {
+                        self.pending.push(Kind::Other, sequence);
+                        self.colors_changed();
+                    } else {
+                        decision!("dropped", sequence, "color is already set");
+                    }
+                }
+
+                OscCommand::ResetDynamicColor(color) => {
+                    if self.palette.reset_dynamic(color.offset()) {
+                        self.pending.push(Kind::Other, sequence);
+                        self.colors_changed();
+                    } else {
+                        decision!("dropped", sequence, "color is already reset");
+                    }
+                }
+
+                _ => self.pending.push(Kind::Other, sequence),
+            },
+            Sequence::DCS(..) | Sequence::APC(..) => {
+                self.flush_style();
+                self.cursor.invalidate();
+                self.pending.push(Kind::Other, sequence);
+            }
+            Sequence::Regular(seq) => match (seq.intermediates(), seq.finalizer()) {
+                ("", "7") => self.save_cursor(sequence),
+                ("", "8") => self.restore_cursor(sequence),
+                ("", "H") => {
+                    self.flush_style();
+                    self.cursor.set_tab_stop();
+                    self.pending.push(Kind::Other, sequence);
+                }
+                ("", "c") => {
+                    self.cursor.reset();
+                    self.hyperlink = None;
+                    self.palette.clear();
+                    self.update_reset(sequence);
+                }
+                _ => {
+                    self.flush_style();
+                    self.cursor.invalidate();
+                    self.pending.push(Kind::Other, sequence);
+                }
+            },
+        }
+    }
+
+    fn update_csi(&mut self, sequence: &Sequence, csi: &ControlSequence) {
+        if is_repeat(csi) {
+            return self.update_repeat(sequence, csi);
+        }
+
+        if is_sgr(csi) {
+            // When the style is changed to follow the options, unknown parameters are removed.
+            // They can't be written as-is, since they might be followed by parameters that need
+            // to be changed.
+            let mut input = self.input_style;
+            let options = &self.options;
+            let rewrite = options.profile.plain
+                || options.profile.colors != ColorSupport::TrueColor
+                || options.style_mapper.is_some()
+                || !options.style_filter.is_empty();
+
+            let ambiguous = !options.profile.plain
+                && options.profile.sgr21 == Sgr21::Ambiguous
+                && csi.params().any(|param| matches!(param, Param::Int(21)));
+
+            let understood = input.apply_sgr_for(csi.params(), &options.profile);
+            if self.ambiguous_style || ambiguous {
+                // SGR 21 either starts a double underline or turns bold off, so what the terminal
+                // does with the following changes isn't known until the style is reset.
+                self.write_style();
+                self.ambiguous_style = ambiguous || !matches!(csi.parameters(), "" | "0");
+                self.input_style = input;
+                self.style = input;
+                self.emitted = input;
+                self.pending.push_with(Kind::Style, |out| normalize::write_csi(csi, out));
+                return decision!("kept", sequence, "the style after SGR 21 isn't known");
+            }
+
+            if understood || rewrite {
+                self.input_style = input;
+                self.style = self.resolve_style(input);
+                if let (Some(tracker), Some(source)) = (&mut self.tracker, self.pending.source()) {
+                    tracker.merge_style(source);
+                }
+
+                return decision!("merged", sequence, "into the pending style");
+            }
+
+            // The sequence has parameters that can't be represented, so it needs to be emitted
+            // as-is. Any pending changes need to be emitted before it to preserve their order, and
+            // since it changes the terminal's actual style, they can't be skipped.
+            self.write_style();
+            self.input_style = input;
+            self.style = input;
+            self.emitted = input;
+            self.pending.push_with(Kind::Style, |out| normalize::write_csi(csi, out));
+            return decision!("kept", sequence, "has parameters that can't be represented");
+        }
+
+        if self.options.strip_mouse && is_mouse_report(csi) {
+            return decision!("dropped", sequence, "mouse report");
+        }
+
+        if is_device_query(csi) {
+            return self.update_query(sequence);
+        }
+
+        let options = &self.options;
+        let stripped = |p: Param| matches!(p.value(), Some(m) if is_stripped_mode(options, m));
+        if is_mode_change(csi) && csi.params().any(stripped) {
+            return self.update_without_stripped_modes(sequence, csi);
+        }
+
+        if let Some((mode, set)) = cancellable_mode(csi) {
+            // Only the last change before the next text matters, and only if it changes the mode.
+            // If the mode's state is unknown, a change that reverts a pending one is assumed to
+            // be a no-op, since that is what a pair like `?25l` and `?25h` is used for.
+            let previous = self.pending.kinds_rev().find_map(|kind| match kind {
+                Kind::Mode(m, value) if m == mode => Some(value),
+                _ => None,
+            });
+
+            if mode == 7 {
+                self.cursor.set_autowrap(set);
+            }
+
+            let pending_mode = |kind| matches!(kind, Kind::Mode(m, _) if m == mode);
+            if self.pending.remove_where(pending_mode) > 0 {
+                decision!("merged", sequence, "replaces the pending change to mode {}", mode);
+            }
+
+            let index = CANCELLABLE_MODES.iter().position(|&m| m == mode);
+            let committed = index.and_then(|i| self.modes[i]).or(previous.map(|value| !value));
+            if committed != Some(set) {
+                self.pending.push_with(Kind::Mode(mode, set), |out| normalize::write_csi(csi, out));
+            } else {
+                decision!("dropped", sequence, "mode is already set");
+            }
+
+            return;
+        }
+
+        if is_kitty_keyboard(csi) && self.options.strip_keyboard_protocol {
+            return decision!("dropped", sequence, "changes the keyboard protocol");
+        }
+
+        if csi.parameters().is_empty() && csi.intermediates().is_empty() {
+            match csi.finalizer() {
+                "s" => return self.save_cursor(sequence),
+                "u" => return self.restore_cursor(sequence),
+                _ => {}
+            }
+        }
+
+        if (csi.private_marker(), csi.intermediates(), csi.finalizer()) == (None, "!", "p") {
+            self.cursor.update_csi(csi);
+            return self.update_reset(sequence);
+        }
+
+        if is_style_independent(csi) {
+            return self.update_cursor(sequence, csi);
+        }
+
+        if let CsiCommand::Window(op) = CsiCommand::from(csi) {
+            return self.update_window(sequence, csi, op);
+        }
+
+        self.flush_style();
+        self.cursor.update_csi(csi);
+        let kind = match erased_count(csi) {
+            Some(count) => Kind::Erase(count),
+            None => Kind::Other,
+        };
+
+        self.pending.push_with(kind, |out| normalize::write_csi(csi, out));
+    }
+
+    /// Writes a full reset (RIS) or soft reset (DECSTR), and forgets the style and modes that it
+    /// resets on the terminal.
+    ///
+    /// The pending style doesn't need to be written, since the reset replaces it. The pending
+    /// sequences are committed, so nothing after the reset is merged with anything before it.
+    fn update_reset(&mut self, sequence: &Sequence) {
+        if let Some(tracker) = &mut self.tracker {
+            tracker.clear_style();
+        }
+
+        self.pending.push(Kind::Other, sequence);
+        self.commit();
+
+        self.modes = [None; CANCELLABLE_MODES.len()];
+        self.input_style = Style::default();
+        self.emitted = Style::default();
+        self.saved_style = None;
+        self.ambiguous_style = false;
+        self.style = self.resolve_style(Style::default());
+    }
+
+    /// Writes a window operation, unless it repeats the one before it.
+    fn update_window(&mut self, sequence: &Sequence, csi: &ControlSequence, op: WindowOp) {
+        let mut normalized = String::new();
+        normalize::write_csi(csi, &mut normalized).expect("write to string");
+        if op.is_idempotent() && self.pending.last() == Some(normalized.as_str()) {
+            return decision!("dropped", sequence, "repeats the last window operation");
+        }
+
+        self.flush_style();
+        self.cursor.update_csi(csi);
+        self.pending.push(Kind::Other, normalized);
+    }
+
+    /// Writes a query that the terminal replies to, without holding it back.
+    fn update_query(&mut self, sequence: &Sequence) {
+        if self.options.queries == Queries::Strip {
+            return decision!("dropped", sequence, "asks the terminal for a reply");
+        }
+
+        // Whatever sent the query is waiting for the reply, so it can't wait for more output.
+        self.flush_style();
+        self.pending.push(Kind::Other, sequence);
+        self.commit();
+    }
+
+    /// Writes a mode change without any of the modes that should be removed.
+    fn update_without_stripped_modes(&mut self, sequence: &Sequence, csi: &ControlSequence) {
+        let modes = || csi.params().filter_map(|p| p.value());
+        if modes().all(|m| is_stripped_mode(&self.options, m)) {
+            return decision!("dropped", sequence, "every mode is stripped");
+        }
+
+        self.flush_style();
+        let options = &self.options;
+        let modes = modes().filter(|&m| !is_stripped_mode(options, m));
+        self.pending.push_with(Kind::Other, |out| {
+            out.write_str("\x1B[?")?;
+            for (i, mode) in modes.enumerate() {
+                if i > 0 {
+                    out.write_char(';')?;
+                }
+
+                write!(out, "{}", mode)?;
+            }
+
+            out.write_str(csi.finalizer())
+        });
+
+        let rewritten = self.pending.last().unwrap_or_default();
+        decision!("rewritten", sequence, "as {:?}, without stripped modes", rewritten);
+    }
+
+    fn write_text(&mut self, text: &str) {
+        let repeat = self.options.repeat_runs && self.options.profile.repeat;
+        let cost = self.options.cost;
+        let literal = |text: &str, out: &mut String| match repeat {
+            true => repeat::write_compressed(text, &cost, out),
+            false => out.push_str(text),
+        };
+
+        match (self.options.columns, self.cursor.position.column) {
+            (Some(columns), Some(column))
+                if self.options.erase_runs && erase::is_erasable(&self.emitted) =>
+            {
+                erase::write_compressed(text, column, columns, &cost, &mut self.output, literal)
+            }
+            _ => literal(text, &mut self.output),
+        }
+
+        self.last_char = text.chars().next_back().filter(|&c| repeat::is_repeatable(c));
+    }
+
+    fn update_repeat(&mut self, sequence: &Sequence, csi: &ControlSequence) {
+        self.flush_style();
+        self.commit();
+        self.cursor.invalidate();
+
+        let count = csi.params().next().map_or(1, |p| p.value_or(1)).max(1);
+        let start = self.output.len();
+        match self.last_char {
+            Some(c) if self.options.repeat_runs && !self.options.profile.repeat => {
+                (0..count).for_each(|_| self.output.push(c));
+                decision!("rewritten", sequence, "as {} of {:?}", count, c);
+            }
+            _ => normalize::write_csi(csi, &mut self.output).expect("write to string"),
+        }
+
+        if let (Some(tracker), Some(source)) = (&mut self.tracker, self.pending.source()) {
+            tracker.write(source, &self.output[start..]);
+        }
+    }
+
+    /// Replaces erasing characters and moving past them with spaces, if shorter.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the sequences were replaced.
+    fn expand_erase(&mut self, sequence: &Sequence, csi: &ControlSequence) -> bool {
+        let count = match self.pending.last_kind() {
+            Some(Kind::Erase(count)) if self.options.erase_runs => count,
+            _ => return false,
+        };
+
+        let is_cuf = csi.finalizer() == "C" && csi.private_marker().is_none();
+        let forward = csi.params().next().map_or(1, |p| p.value_or(1)).max(1);
+        let fits = match (self.options.columns, self.cursor.position.column) {
+            (Some(columns), Some(column)) => column as usize + count as usize <= columns as usize,
+            _ => false,
+        };
+
+        if !is_cuf || forward != count || !fits || !erase::is_erasable(&self.emitted) {
+            return false;
+        }
+
+        if !erase::is_expandable(count, &self.options.cost) {
+            return false;
+        }
+
+        self.track_merge(Kind::Erase(count));
+        self.pending.pop_while(Kind::Erase(count));
+        self.pending.push_with(Kind::Other, |out| (0..count).try_for_each(|_| out.write_char(' ')));
+        self.cursor.position.column = self.cursor.position.column.map(|c| c + count);
+        decision!("rewritten", sequence, "with the erase before it as {} spaces", count);
+        true
+    }
+
+    fn update_cursor(&mut self, sequence: &Sequence, csi: &ControlSequence) {
+        if self.expand_erase(sequence, csi) {
+            return;
+        }
+
+        if self.pending.last_kind() != Some(Kind::CursorMove) {
+            self.move_origin = self.cursor.position;
+        }
+
+        self.cursor.update_csi(csi);
+        match self.cursor.position.known() {
+            // The destination is known, so the whole run of movement can be replaced with the
+            // shortest way of getting there.
+            Some(to) => {
+                let (from, relative) = (self.move_origin, self.cursor.allows_relative());
+                let (newline, cost) = (self.cursor.allows_newline(), self.options.cost);
+                self.track_merge(Kind::CursorMove);
+                self.pending.pop_while(Kind::CursorMove);
+                self.pending.push_with(Kind::CursorMove, |out| {
+                    cursor::write_move(from, to, relative, newline, &cost, out)
+                });
+                let movement = self.pending.last().unwrap_or_default();
+                decision!("merged", sequence, "into cursor movement {:?}", movement);
+            }
+
+            None => {
+                self.pending.push_with(Kind::CursorMove, |out| normalize::write_csi(csi, out));
+            }
+        }
+    }
+
+    fn update_title(&mut self, sequence: &Sequence, command: u8, title: &str) {
+        if self.options.strip_titles {
+            return decision!("dropped", sequence, "title");
+        }
+
+        // Setting the same title again does nothing.
+        if self.titles.matches(command, title) {
+            return decision!("dropped", sequence, "title is already set");
+        }
+
+        // Only the last title before the next text is ever visible.
+        // OSC 0 sets both the title and icon name, replacing OSC 1 and OSC 2.
+        let replaced = self.pending.remove_where(|kind| match kind {
+            Kind::Title(c) => c == command || command == 0,
+            _ => false,
+        });
+
+        if replaced > 0 {
+            decision!("merged", sequence, "replaces {} pending title(s)", replaced);
+        }
+
+        // If that reverts the title back to the committed one, the change can be dropped entirely.
+        let overridden = command != 0 && self.pending.kinds_rev().any(|k| k == Kind::Title(0));
+        if overridden || !self.committed_titles.matches(command, title) {
+            self.pending.push(Kind::Title(command), sequence);
+        } else {
+            decision!("dropped", sequence, "reverts to the current title");
+        }
+
+        self.titles.set(command, title);
+    }
+
+    /// Holds a progress report back until the next flush, replacing the one being held back.
+    fn update_progress(&mut self, sequence: &Sequence) {
+        let source = self.pending.source();
+        if let Some((_, replaced)) = self.progress.take() {
+            let tracker = self.tracker.as_mut();
+            if let (Some(tracker), Some(replaced), Some(source)) = (tracker, replaced, source) {
+                tracker.merge(std::iter::once(replaced), source);
+            }
+
+            decision!("merged", sequence, "replaces the held progress report");
+        }
+
+        self.progress = Some((sequence.to_string(), source));
+    }
+
+    fn update_image(&mut self, sequence: &Sequence) {
+        if self.options.strip_images {
+            return decision!("dropped", sequence, "image");
+        }
+
+        // Images are drawn at the cursor using the current colors, and move the cursor past them.
+        // Their payload is passed through untouched.
+        self.flush_style();
+        self.cursor.invalidate();
+        self.pending.push(Kind::Other, sequence);
+    }
+
+    /// Removes palette colors that are being set to the value they already have.
+    fn update_palette(&mut self, sequence: &Sequence, entries: PaletteEntries, finalizer: &str) {
+        let mut changed = Vec::new();
+        let mut unchanged = 0;
+        for (index, spec) in entries {
+            match index.map(u8::try_from) {
+                Some(Ok(index)) if !self.palette.set(index, spec) => unchanged += 1,
+                Some(Ok(index)) => changed.push((index, spec)),
+                _ => {}
+            }
+        }
+
+        if unchanged == 0 {
+            self.pending.push(Kind::Other, sequence);
+        } else if !changed.is_empty() {
+            self.pending.push_with(Kind::Other, |out| {
+                out.write_str("\x1B]4")?;
+                changed.iter().try_for_each(|(index, spec)| write!(out, ";{};{}", index, spec))?;
+                out.write_str(finalizer)
+            });
+
+            let rewritten = self.pending.last().unwrap_or_default();
+            decision!("rewritten", sequence, "as {:?}, without unchanged colors", rewritten);
+        } else {
+            decision!("dropped", sequence, "colors are already set");
+        }
+
+        if !changed.is_empty() {
+            self.colors_changed();
+        }
+    }
+
+    /// Writes hyperlinks as well-formed pairs of opening and closing sequences.
+    ///
+    /// Opening a link closes the one before it, so links can't be nested. Generators that nest
+    /// them anyway (or never close them) leave terminals and other tools to guess, so the link
+    /// before is closed explicitly. The parameters (e.g. `id`) are kept.
+    fn update_hyperlink(&mut self, sequence: &Sequence, params: &str, uri: &str, finalizer: &str) {
+        if !self.options.profile.hyperlinks {
+            return self.replace_hyperlink(sequence, uri);
+        }
+
+        let opening = !uri.is_empty();
+        match self.hyperlink.take() {
+            None if !opening => return decision!("dropped", sequence, "no link is open"),
+            Some(open) if opening && open.0 == params && open.1 == uri => {
+                self.hyperlink = Some(open);
+                return decision!("dropped", sequence, "link is already open");
+            }
+            Some(_) if opening => {
+                decision!("rewritten", sequence, "closes the link before it");
+                self.pending.push(Kind::Other, format_args!("\x1B]8;;{}", finalizer));
+            }
+            _ => {}
+        }
+
+        if opening {
+            self.hyperlink = Some((params.to_owned(), uri.to_owned()));
+        }
+
+        self.pending.push(Kind::Other, sequence);
+    }
+
+    /// Replaces a hyperlink that the profile doesn't support, as the [Options::hyperlinks] say.
+    fn replace_hyperlink(&mut self, sequence: &Sequence, uri: &str) {
+        decision!("dropped", sequence, "hyperlinks are unsupported");
+
+        // Opening a link closes the one before it, so links are never nested.
+        self.close_hyperlink();
+        if !uri.is_empty() {
+            self.link = Some(uri.to_owned());
+        }
+    }
+
+    /// Writes whatever replaces the end of the open hyperlink, if there is one.
+    fn close_hyperlink(&mut self) {
+        let uri = match self.link.take() {
+            Some(uri) => uri,
+            None => return,
+        };
+
+        match self.options.hyperlinks {
+            Hyperlinks::Strip => {}
+            Hyperlinks::Inline => self.update_text(&format!(" ({})", uri)),
+            Hyperlinks::Footnotes => {
+                let number = match self.footnotes.iter().position(|footnote| *footnote == uri) {
+                    Some(i) => i + 1,
+                    None => {
+                        self.footnotes.push(uri);
+                        self.footnotes.len()
+                    }
+                };
+
+                self.update_text(&format!("[{}]", number));
+            }
+        }
+    }
+
+    fn update_clipboard(&mut self, sequence: &Sequence, data: &str) {
+        let too_large = matches!(self.options.max_clipboard_bytes, Some(max) if data.len() > max);
+        if !self.options.sanitize && !too_large {
+            self.pending.push(Kind::Other, sequence);
+        } else {
+            decision!("dropped", sequence, "clipboard write");
+        }
+    }
+
+    fn save_cursor(&mut self, sequence: &Sequence) {
+        self.flush_style();
+        self.cursor.save();
+        self.saved_style = Some((self.emitted, self.input_style));
+        self.pending.push(Kind::SaveCursor, sequence);
+    }
+
+    fn restore_cursor(&mut self, sequence: &Sequence) {
+        // Restoring right after saving does nothing, and undoes any cursor movement in between.
+        if !self.pending.truncate_after(Kind::SaveCursor, |kind| kind == Kind::CursorMove) {
+            self.pending.push(Kind::Other, sequence);
+        } else {
+            decision!("dropped", sequence, "cancels the pending save");
+        }
+
+        self.cursor.restore();
+
+        // Restoring the cursor also restores the style, discarding any pending changes.
+        if let Some(tracker) = &mut self.tracker {
+            tracker.clear_style();
+        }
+
+        let (emitted, input) = self.saved_style.unwrap_or_default();
+        self.emitted = emitted;
+        self.input_style = input;
+        self.style = self.resolve_style(input);
+    }
+
+    /// Changes a style set by the input to follow the [Options].
+    fn resolve_style(&self, input: Style) -> Style {
+        if self.options.profile.plain {
+            return Style::default();
+        }
+
+        let mut style = match &self.options.style_mapper {
+            Some(mapper) => mapper.map(input),
+            None => input,
+        };
+
+        if let Some(ratio) = self.options.min_contrast {
+            let foreground = self.palette.to_rgb(style.foreground, DynamicColor::Foreground);
+            let background = self.palette.to_rgb(style.background, DynamicColor::Background);
+            if let (Some(foreground), Some(background)) = (foreground, background) {
+                if let Some((r, g, b)) = color::adjust_contrast(foreground, background, ratio) {
+                    style.foreground = Color::Rgb(r, g, b);
+                }
+            }
+        }
+
+        style.downgrade_with(self.options.profile.colors, &self.palette.colors);
+        self.options.style_filter.apply(&mut style);
+        style
+    }
+
+    /// Resolves the style again after the palette or dynamic colors were changed.
+    fn colors_changed(&mut self) {
+        self.style = self.resolve_style(self.input_style);
+    }
+
+    /// Emits the pending style changes, unless text would look the same without them.
+    fn flush_style(&mut self) {
+        if !self.style.visually_eq(&self.emitted, &self.options.profile) {
+            self.write_style();
+        } else if self.style != self.emitted {
+            decision!("dropped", self.style.transition_from(Some(&self.emitted)), "looks the same");
+        }
+
+        if let Some(tracker) = &mut self.tracker {
+            tracker.clear_style();
+        }
+    }
+
+    /// Emits the pending style changes.
+    pub(crate) fn write_style(&mut self) {
+        let (style, emitted) = (self.style, self.emitted);
+        let profile = &self.options.profile;
+        let source = self.pending.source();
+        if let Some(tracker) = &mut self.tracker {
+            self.pending.set_source(tracker.take_style());
+        }
+
+        self.pending
+            .push_with(Kind::Style, |out| style.write_transition_for(&emitted, profile, out));
+        self.pending.set_source(source);
+        self.emitted = self.style;
+        decision!("rewritten", "style changes", "as {:?}", self.pending.last().unwrap_or_default());
+    }
+
+    /// Records that the most recent pending sequences of a kind were replaced by the current one.
+    fn track_merge(&mut self, kind: Kind) {
+        if let (Some(tracker), Some(source)) = (&mut self.tracker, self.pending.source()) {
+            tracker.merge(self.pending.trailing_sources(kind), source);
+        }
+    }
+
+    /// Commits the pending sequences to the output.
+    pub(crate) fn commit(&mut self) {
+        let (modes, tracker) = (&mut self.modes, &mut self.tracker);
+        self.pending.commit(&mut self.output, |kind, source, text| {
+            if let Kind::Mode(mode, set) = kind {
+                if let Some(i) = CANCELLABLE_MODES.iter().position(|&m| m == mode) {
+                    modes[i] = Some(set);
+                }
+            }
+
+            if let (Some(tracker), Some(source)) = (tracker.as_mut(), source) {
+                tracker.write(source, text);
+            }
+        });
+
+        self.committed_titles.clone_from(&self.titles);
+        self.pending_since = None;
+
+        if let Some(changes) = &mut self.style_changes {
+            let last = changes.last().map_or(Style::default(), |&(_, style)| style);
+            if last != self.emitted {
+                changes.push((self.output.len(), self.emitted));
+            }
+        }
+    }
+}
+
+impl Display for Optimizer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.output[self.borrowed..])?;
+        self.pending.write_to(f)?;
+        self.style.write_transition_for(&self.emitted, &self.options.profile, f)?;
+        match &self.progress {
+            Some((progress, _)) => f.write_str(progress),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Optimizes a complete string.
+///
+/// This is the same as updating an [Optimizer] with the whole string and writing its output.
+///
+/// ```
+/// let output = ansi_optimizer::optimize("\x1B[31m\x1B[1mred\x1B[0m\x1B[0m").unwrap();
+/// assert_eq!(output, "\x1B[1;31mred\x1B[m");
+/// ```
+///
+/// ## Returns
+///
+/// If the input contains an invalid escape sequence, this returns [Error::InvalidSequence].
+/// If the input ends partway through an escape sequence, this returns [Error::Incomplete].
+pub fn optimize(input: &str) -> Result<String, Error> {
+    optimize_with(input, &Options::default())
+}
+
+/// Optimizes a complete string, using [Options].
+///
+/// See [optimize].
+pub fn optimize_with(input: &str, options: &Options) -> Result<String, Error> {
+    let mut optimizer = Optimizer::with_options(options.clone());
+    optimizer.update(input)?;
+    optimizer.finish();
+    Ok(optimizer.to_string())
+}
+
+/// Gets the DEC private mode and its new state from a set (`h`) or reset (`l`) sequence, if the
+/// mode is one of [CANCELLABLE_MODES].
+fn cancellable_mode(csi: &ControlSequence) -> Option<(u16, bool)> {
+    if csi.private_marker() != Some('?') || !csi.intermediates().is_empty() {
+        return None;
+    }
+
+    let set = match csi.finalizer() {
+        "h" => true,
+        "l" => false,
+        _ => return None,
+    };
+
+    let mut params = csi.params();
+    match (params.next(), params.next()) {
+        (Some(Param::Int(mode)), None) if CANCELLABLE_MODES.contains(&mode) => Some((mode, set)),
+        _ => None,
+    }
+}
+
+/// Checks if a control sequence sets (`h`) or resets (`l`) DEC private modes.
+pub(crate) fn is_mode_change(csi: &ControlSequence) -> bool {
+    csi.private_marker() == Some('?')
+        && csi.intermediates().is_empty()
+        && matches!(csi.finalizer(), "h" | "l")
+}
+
+/// Checks if a DEC private mode should be removed from mode changes.
+fn is_stripped_mode(options: &Options, mode: u16) -> bool {
+    ((options.strip_mouse || options.sanitize) && is_mouse_mode(mode))
+        || (options.sanitize && sanitize::is_input_mode(mode))
+        || options.strip_modes.contains(&mode)
+}
+
+/// Checks if a DEC private mode enables mouse reporting or changes how it's encoded.
+///
+/// Focus events (`1004`) are in the same range, but aren't part of mouse reporting.
+pub(crate) fn is_mouse_mode(mode: u16) -> bool {
+    matches!(mode, 9 | 1000..=1003 | 1005 | 1006 | 1015 | 1016)
+}
+
+/// Checks if a control sequence is an SGR mouse report (`CSI < b ; x ; y M` or `m`).
+///
+/// These are sent by the terminal, but end up in recordings when the program echoes its input.
+pub(crate) fn is_mouse_report(csi: &ControlSequence) -> bool {
+    csi.private_marker() == Some('<')
+        && csi.intermediates().is_empty()
+        && matches!(csi.finalizer(), "M" | "m")
+}
+
+/// The rest of an unfinished sequence being dropped by [Optimizer::feed], because it grew longer
+/// than [Options::max_sequence_bytes].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Discard {
+    /// A control sequence.
+    Control,
+
+    /// An OSC, DCS, or APC string.
+    String,
+
+    /// A string that ended with an `ESC`, which is either the start of `ST` or of another
+    /// sequence.
+    StringEscape,
+}
+
+/// The window title and icon name, as set by OSC 0, 1, and 2.
+#[derive(Clone, Debug, Default)]
+struct Titles {
+    title: Option<String>,
+    icon_name: Option<String>,
+}
+
+impl Titles {
+    /// Checks if setting a title with an OSC command would leave the titles unchanged.
+    fn matches(&self, command: u8, text: &str) -> bool {
+        (command == 1 || self.title.as_deref() == Some(text))
+            && (command == 2 || self.icon_name.as_deref() == Some(text))
+    }
+
+    /// Sets a title with an OSC command.
+    fn set(&mut self, command: u8, text: &str) {
+        if command != 1 {
+            self.title = Some(text.to_owned());
+        }
+
+        if command != 2 {
+            self.icon_name = Some(text.to_owned());
+        }
+    }
+}
+
+/// Checks if a control sequence is a REP (repeat) sequence.
+#[inline]
+fn is_repeat(csi: &ControlSequence) -> bool {
+    csi.finalizer() == "b" && csi.private_marker().is_none() && csi.intermediates().is_empty()
+}
+
+/// Gets the number of characters erased by an ECH sequence.
+fn erased_count(csi: &ControlSequence) -> Option<u16> {
+    if csi.finalizer() != "X" || csi.private_marker().is_some() || !csi.intermediates().is_empty() {
+        return None;
+    }
+
+    let mut params = csi.params();
+    match (params.next(), params.next()) {
+        (None, None) => Some(1),
+        (Some(param), None) => Some(param.value_or(1).max(1)),
+        _ => None,
+    }
+}
+
+/// Checks if the start of an unfinished sequence is the start of a string (OSC, DCS, or APC).
+fn is_unfinished_string(partial: &str) -> bool {
+    matches!(partial.get(..2), Some("\x1B]" | "\x1BP" | "\x1B_"))
+        || matches!(partial.chars().next(), Some('\u{9D}' | '\u{90}' | '\u{9F}'))
+}
+
+/// Checks if a sequence is an inline image.
+///
+/// This is either a sixel image (`DCS P1;P2;P3 q ... ST`), an iTerm2 inline image
+/// (`OSC 1337 ; File=... ST`), or a kitty graphics command (`APC G ... ST`).
+fn is_image(sequence: &Sequence) -> bool {
+    match sequence {
+        Sequence::DCS(_, string) => is_sixel(string),
+        Sequence::APC(_, string) => string.text().starts_with('G'),
+        Sequence::OSC(_, string) => match OscCommand::from(string) {
+            OscCommand::Other(1337, data) => data.starts_with("File="),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Checks if a device control string is a sixel image.
+///
+/// Sixel images start with optional parameters followed by `q`.
+pub(crate) fn is_sixel(string: &AnsiString) -> bool {
+    string.text().trim_start_matches(|c: char| c.is_ascii_digit() || c == ';').starts_with('q')
+}
+
+/// Checks if a control sequence changes the kitty keyboard protocol flags.
+///
+/// These are `CSI > flags u` (push), `CSI < n u` (pop), `CSI = flags ; mode u` (set), and
+/// `CSI ? u` (query).
+pub(crate) fn is_kitty_keyboard(csi: &ControlSequence) -> bool {
+    csi.finalizer() == "u"
+        && csi.intermediates().is_empty()
+        && matches!(csi.private_marker(), Some('>') | Some('<') | Some('=') | Some('?'))
+}
+
+/// Checks if a control sequence asks the terminal to reply with its attributes or status.
+///
+/// These are device attributes (`CSI c`, `CSI > c`, and `CSI = c`) and device status reports
+/// (`CSI n` and `CSI ? n`).
+pub(crate) fn is_device_query(csi: &ControlSequence) -> bool {
+    csi.intermediates().is_empty()
+        && matches!(
+            (csi.private_marker(), csi.finalizer()),
+            (None, "c") | (Some('>'), "c") | (Some('='), "c") | (None, "n") | (Some('?'), "n")
+        )
+}
+
+/// Checks if a control sequence is an SGR (Select Graphic Rendition) sequence.
+pub(crate) fn is_sgr(csi: &ControlSequence) -> bool {
+    csi.finalizer() == "m" && csi.intermediates().is_empty() && csi.private_marker().is_none()
+}
+
+/// Checks if a control sequence is unaffected by the current style.
+///
+/// Only cursor movement is unaffected. Erasing and inserting fill cells with the current
+/// background color, and saving the cursor also saves the style.
+fn is_style_independent(csi: &ControlSequence) -> bool {
+    if !csi.intermediates().is_empty() || csi.private_marker().is_some() {
+        return false;
+    }
+
+    match csi.finalizer() {
+        "A" | "B" | "C" | "D" | "E" | "F" | "G" | "H" | "f" | "d" | "e" | "`" | "a" => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::optimizer::*;
+    use crate::style::{Attributes, StyleFilter, StyleMapper};
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn update_merges_sgr() {
+        let mut optimizer = Optimizer::new();
+        optimizer.update("\x1B[1m\x1B[31mred\x1B[0m\x1B[1m\x1B[32mgreen\x1B[m").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[1;31mred\x1B[32mgreen\x1B[m");
+
+        // Cursor movement doesn't need the style, but erasing does.
+        optimizer.reset();
+        optimizer.update("\x1B[41m\x1B[H\x1B[44m\x1B[K\x1B[m").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[H\x1B[44m\x1B[K\x1B[m");
+
+        // Unknown parameters are kept in order.
+        optimizer.reset();
+        optimizer.update("\x1B[1m\x1B[73;31mx\x1B[22m").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[1m\x1B[73;31mx\x1B[22m");
+    }
+
+    #[test]
+    fn update_cancels_no_ops() {
+        let mut optimizer = Optimizer::new();
+
+        // Hiding and showing the cursor with no text in between.
+        optimizer.update("a\x1B[?25l\x1B[H\x1B[?25hb").unwrap();
+        assert_eq!(optimizer.to_string(), "a\x1B[Hb");
+
+        // Hiding the cursor when it's already hidden.
+        optimizer.reset();
+        optimizer.update("\x1B[?25la\x1B[?25h\x1B[?25lb").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[?25lab");
+
+        // Saving and immediately restoring the cursor.
+        optimizer.reset();
+        optimizer.update("\x1B[1ma\x1B7\x1B[5;5H\x1B[31m\x1B8b").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[1ma\x1B7b");
+
+        // Restoring the cursor after text restores the saved style.
+        optimizer.reset();
+        optimizer.update("\x1B[1m\x1B7\x1B[31ma\x1B8\x1B[1mb").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[1m\x1B7\x1B[31ma\x1B8b");
+
+        // Setting the title twice.
+        optimizer.reset();
+        optimizer.update("\x1B]2;one\x07\x1B]2;two\x07text").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B]2;two\x07text");
+
+        optimizer.reset();
+        optimizer.update("\x1B]2;one\x07\x1B]1;icon\x07\x1B]0;two\x07text").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B]0;two\x07text");
+
+        // Removed sequences don't build up while nothing is written.
+        optimizer.reset();
+        optimizer.update("\x1B[1;1H").unwrap();
+        for i in 0..1000 {
+            optimizer.update(format!("\x1B]2;t{}\x07\x1B[?25l\x1B[?25h", i)).unwrap();
+            assert!(optimizer.pending.len() < 256);
+        }
+
+        optimizer.update("text").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[H\x1B]2;t999\x07text");
+    }
+
+    #[test]
+    fn update_deduplicates_titles() {
+        let mut optimizer = Optimizer::new();
+
+        // Setting the same title before every prompt.
+        optimizer.update("\x1B]0;~\x07$ ls\n\x1B]0;~\x07$ ").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B]0;~\x07$ ls\n$ ");
+
+        // Changing only the title keeps the icon name.
+        optimizer.reset();
+        optimizer.update("\x1B]0;a\x07x\x1B]2;b\x07y\x1B]1;a\x07\x1B]2;b\x1B\\z").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B]0;a\x07x\x1B]2;b\x07yz");
+
+        // Changing the title and back again.
+        optimizer.reset();
+        optimizer.update("\x1B]2;a\x07x\x1B]2;b\x07\x1B]2;a\x07y").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B]2;a\x07xy");
+    }
+
+    #[test]
+    fn update_shortens_cursor_movement() {
+        let mut optimizer = Optimizer::new();
+        optimizer.update("\x1B[5;10H\x1B[2;3H\x1B[10Gx").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[2;10Hx");
+
+        optimizer.reset();
+        optimizer.update("\x1B[5;10H\x1B[K\x1B[6;1H\x1B[K\x1B[6;5H\x1B[3Dx").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[5;10H\x1B[K\x1B[6H\x1B[K\x1B[Cx");
+
+        // A newline scrolls at the bottom of the screen, so it's only used if the rows are known.
+        let mut optimizer = Optimizer::with_options(Options {
+            columns: Some(80),
+            ..Options::default()
+        });
+
+        optimizer.update("\x1B[24;1Hx\x1B[25;1Hy").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[24Hx\x1B[B\ry");
+
+        let mut optimizer = Optimizer::with_options(Options {
+            columns: Some(80),
+            rows: Some(24),
+            ..Options::default()
+        });
+
+        optimizer.update("\x1B[5;10H\x1B[K\x1B[6;1H\x1B[Kx\x1B[23;1Hy\x1B[24;1Hz").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[5;10H\x1B[K\r\n\x1B[Kx\x1B[23Hy\r\nz");
+
+        let mut optimizer = Optimizer::new();
+
+        // Text may wrap, so the position is unknown after it.
+        optimizer.reset();
+        optimizer.update("\x1B[5;10Hx\x1B[5;12Hy").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[5;10Hx\x1B[5;12Hy");
+    }
+
+    #[test]
+    fn update_erases_runs() {
+        let mut optimizer = Optimizer::with_options(Options {
+            columns: Some(80),
+            erase_runs: true,
+            ..Options::default()
+        });
+
+        let spaces = " ".repeat(30);
+        optimizer.update(format!("\x1B[44m\x1B[5;1Hx{}y", spaces)).unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[5H\x1B[44mx\x1B[30X\x1B[30Cy");
+
+        optimizer.reset();
+        optimizer.update("\x1B[5;1H\x1B[2X\x1B[2Cy").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[5H  y");
+
+        // Spaces look different from erased cells when reversed.
+        optimizer.reset();
+        optimizer.update(format!("\x1B[7m\x1B[5;1Hx{}y", spaces)).unwrap();
+        assert_eq!(optimizer.to_string(), format!("\x1B[5H\x1B[7mx{}y", spaces));
+    }
+
+    #[test]
+    fn update_tracks_tabs() {
+        let mut optimizer = Optimizer::with_options(Options {
+            columns: Some(80),
+            ..Options::default()
+        });
+
+        optimizer.update("\x1B[5;1H\t\x1B[5;17Hb").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[5H\t\x1B[8Cb");
+
+        optimizer.reset();
+        optimizer.update("\x1B[5;1Hhello\x1B[5;1Hworld\x1B[5;80Hx\x1B[5;1Hy").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[5Hhello\rworld\x1B[80Gx\ry");
+
+        optimizer.reset();
+        optimizer.update("\x1B[5;1H\x1B[3g\x1B[20G\x1BH\r\t\x1B[5;21Hb").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[5H\x1B[3g\x1B[20G\x1BH\r\t\x1B[Cb");
+    }
+
+    #[test]
+    fn export_state() {
+        let mut optimizer = Optimizer::new();
+        assert_eq!(optimizer.export_state(), "");
+        optimizer.update("\x1B[1;31ma\x1B[44m").unwrap();
+        let state = optimizer.export_state();
+        assert_eq!(state, "\x1B[1;31;44m");
+
+        let mut optimizer = Optimizer::new();
+        optimizer.update("\x1B[31mb").unwrap();
+        optimizer.import_state(&state).unwrap();
+        optimizer.update("c\x1B[1m").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[31mb\x1B[1;44mc");
+        assert_eq!(optimizer.import_state("\x1B[1mx"), Err(Error::InvalidSequence));
+        assert_eq!(optimizer.import_state("\x1B[2J"), Err(Error::InvalidSequence));
+        assert_eq!(optimizer.import_state("\x1B[1"), Err(Error::Incomplete));
+        assert_eq!(optimizer.export_state(), "\x1B[1;31;44m");
+    }
+
+    #[test]
+    fn style_stack() {
+        let bold = Style { attributes: Attributes::BOLD, ..Style::default() };
+        let mut optimizer = Optimizer::new();
+        assert!(!optimizer.pop_style());
+
+        optimizer.push_style(bold);
+        optimizer.update("a\x1B[31mb").unwrap();
+        optimizer.push_style(Style::default());
+        optimizer.pop_style();
+        optimizer.update("c").unwrap();
+        assert!(optimizer.pop_style());
+        optimizer.update("d\x1B[4m").unwrap();
+        optimizer.push_style(bold);
+        optimizer.update("e").unwrap();
+        optimizer.pop_style();
+        assert!(!optimizer.pop_style());
+        assert_eq!(optimizer.to_string(), "\x1B[1ma\x1B[31mbc\x1B[md\x1B[1me\x1B[0;4m");
+    }
+
+    #[test]
+    fn resize() {
+        let mut optimizer = Optimizer::new();
+        optimizer.update("\x1B[?25l\x1B[5;1H").unwrap();
+        optimizer.resize(40, 10);
+        optimizer.update("\x1B[20;1Ha\x1B[10;40Hb\x1B[10;1Hc").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[?25l\x1B[5H\x1B[10Ha\x1B[40Gb\rc");
+    }
+
+    #[test]
+    fn optimization_levels() {
+        let input = "\x1B]0;a\x07\x1B]9;4;1;10\x07x\x1B]9;4;1;50\x07\x1B]9;4;1;90\x07y";
+        let options = Options::with_level(Level::Lossless);
+        assert_eq!(options, Options::default());
+        assert_eq!(optimize_with(input, &options).unwrap(), input);
+
+        // Progress reports are held back until the optimizer is flushed.
+        let mut optimizer = Optimizer::with_options(Options::with_level(Level::Aggressive));
+        optimizer.update(input).unwrap();
+        assert_eq!(optimizer.take_output(), "xy");
+        assert_eq!(optimizer.to_string(), "\x1B]9;4;1;90\x07");
+        optimizer.flush();
+        assert_eq!(optimizer.take_output(), "\x1B]9;4;1;90\x07");
+        assert_eq!(optimizer.to_string(), "");
+    }
+
+    #[test]
+    fn update_repeats() {
+        let mut optimizer = Optimizer::with_options(Options {
+            repeat_runs: true,
+            profile: Profile::xterm(),
+            ..Options::default()
+        });
+
+        optimizer.update(format!("+{}+", "-".repeat(20))).unwrap();
+        assert_eq!(optimizer.to_string(), "+-\x1B[19b+");
+
+        // REP is expanded if it's not supported.
+        let mut optimizer = Optimizer::with_options(Options {
+            repeat_runs: true,
+            ..Options::default()
+        });
+
+        optimizer.update("+-\x1B[31m\x1B[4b").unwrap();
+        assert_eq!(optimizer.to_string(), "+-\x1B[31m----");
+
+        // The repeated character is unknown after other sequences.
+        optimizer.reset();
+        optimizer.update("+-\x1B[H\x1B[4b").unwrap();
+        assert_eq!(optimizer.to_string(), "+-\x1B[H\x1B[4b");
+    }
+
+    #[test]
+    fn update_passes_images() {
+        let sixel = "\x1BPq#0;2;0;0;0#0~~~~~~~~~~\x1B\\";
+        let iterm = "\x1B]1337;File=inline=1:AAAA\x07";
+
+        let mut optimizer = Optimizer::with_options(Options {
+            columns: Some(80),
+            erase_runs: true,
+            repeat_runs: true,
+            profile: Profile::xterm(),
+            ..Options::default()
+        });
+
+        optimizer.update(format!("\x1B[31m{}\x1B[m{}x", sixel, iterm)).unwrap();
+        assert_eq!(optimizer.to_string(), format!("\x1B[31m{}\x1B[m{}x", sixel, iterm));
+
+        let mut optimizer = Optimizer::with_options(Options {
+            strip_images: true,
+            ..Options::default()
+        });
+
+        optimizer.update(format!("a{}b{}c\x1BP$qm\x1B\\", sixel, iterm)).unwrap();
+        assert_eq!(optimizer.to_string(), "abc\x1BP$qm\x1B\\");
+
+        optimizer.reset();
+        optimizer.update("a\x1B_Ga=T,f=100;AAAA\x1B\\b\x1B_Ga=d\x1B\\").unwrap();
+        assert_eq!(optimizer.to_string(), "ab");
+
+        // Sequences wrapped in tmux's passthrough sequence are kept.
+        let wrapped = tmux::wrap("\x1B[1m\x1B[1m");
+        optimizer.update(format!("{}c", wrapped)).unwrap();
+        assert_eq!(optimizer.to_string(), format!("ab{}c", wrapped));
+    }
+
+    #[test]
+    fn update_strips_keyboard_protocol() {
+        let input = "\x1B[>1u\x1B[=5;1u\x1B[?ua\x1B[<u\x1B[u";
+
+        let mut optimizer = Optimizer::new();
+        optimizer.update(input).unwrap();
+        assert_eq!(optimizer.to_string(), input);
+
+        let mut optimizer = Optimizer::with_options(Options {
+            strip_keyboard_protocol: true,
+            ..Options::default()
+        });
+
+        optimizer.update(input).unwrap();
+        assert_eq!(optimizer.to_string(), "a\x1B[u");
+    }
+
+    #[test]
+    fn update_strips_mouse() {
+        let mut optimizer = Optimizer::with_options(Options {
+            strip_mouse: true,
+            ..Options::default()
+        });
+
+        optimizer.update("\x1B[?1000;1006ha\x1B[<0;10;5Mb\x1B[<0;10;5m\x1B[?1000l").unwrap();
+        assert_eq!(optimizer.to_string(), "ab");
+
+        // Other modes in the same sequence are kept.
+        optimizer.reset();
+        optimizer.update("\x1B[?1049;1002;2004hx\x1B[?1002;1049l").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[?1049;2004hx\x1B[?1049l");
+
+        // Focus events aren't mouse reporting.
+        optimizer.reset();
+        optimizer.update("\x1B[?1004;1005hx").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[?1004hx");
+    }
+
+    #[test]
+    fn update_resets() {
+        // The terminal's style is reset, so it's written again afterwards.
+        let mut optimizer = Optimizer::new();
+        optimizer.update("\x1B[1ma\x1Bcb\x1B[1mc").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[1ma\x1Bcb\x1B[1mc");
+
+        optimizer.reset();
+        optimizer.update("\x1B[1;31ma\x1B[!pb\x1B[1;31mc").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[1;31ma\x1B[!pb\x1B[1;31mc");
+
+        // A pending style isn't written, and no reset is needed after it.
+        optimizer.reset();
+        optimizer.update("\x1B[1ma\x1B[4m\x1Bcb\x1B[0mc").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[1ma\x1Bcbc");
+
+        // Modes are reset too, so changing them afterwards isn't a no-op.
+        optimizer.reset();
+        optimizer.update("\x1B[?25la\x1B[!p\x1B[?25lb\x1Bc\x1B[?25l\x1B[?25hc").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[?25la\x1B[!p\x1B[?25lb\x1Bcc");
+
+        // The saved cursor's style is forgotten.
+        optimizer.reset();
+        optimizer.update("\x1B[1m\x1B7a\x1Bc\x1B8b").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[1m\x1B7a\x1Bc\x1B8b");
+    }
+
+    #[test]
+    fn update_strips_modes() {
+        let mut optimizer = Optimizer::with_options(Options {
+            strip_modes: vec![1049, 2004],
+            ..Options::default()
+        });
+
+        optimizer.update("\x1B[?1049h\x1B[?2004;25lx\x1B[?1049l\x1B[4h").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[?25lx\x1B[4h");
+    }
+
+    #[test]
+    fn update_sanitizes() {
+        let mut optimizer = Optimizer::with_options(Options {
+            sanitize: true,
+            ..Options::default()
+        });
+
+        let input = "\x1B[?2004;25l\x1B[c\x1B[31mx\x1B[6n\x1BZ\x1B]11;?\x07\x1B[?1004h";
+        optimizer.update(input).unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[?25l\x1B[31mx");
+
+        optimizer.reset();
+        optimizer.update("\u{9B}6n\u{9B}c\u{9D}50;?\u{9C}\x1BP|17/6C\x1B\\y").unwrap();
+        assert_eq!(optimizer.to_string(), "y");
+
+        optimizer.reset();
+        optimizer.update("\x1B]11;?\x01\x07\x1BP$\x0Bqm\x1B\\z").unwrap();
+        assert_eq!(optimizer.to_string(), "z");
+
+        let mut optimizer = Optimizer::with_options(Options::hardened());
+        optimizer.feed("\x1B]5\x012;c;aGk=\x07a\x1B]52\x01;c;aGk=\x07b");
+        optimizer.finish();
+        assert_eq!(optimizer.take_output(), "ab");
+    }
+
+    #[test]
+    fn feed_sanitizes_invalid_sequences() {
+        // Terminals run a C0 control inside a control sequence, and then carry on with it.
+        let inputs = [
+            ("\x1B[6\x0Bn", "[6\x0Bn"),
+            ("\x1B[\x086n", "[\x086n"),
+            ("\x1B[>\x0Bc", "[>\x0Bc"),
+        ];
+
+        for (input, expected) in inputs {
+            let mut optimizer = Optimizer::with_options(Options::hardened());
+            optimizer.feed(input);
+            optimizer.finish();
+            assert_eq!(optimizer.take_output(), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn barrier_stops_merging() {
+        let mut optimizer = Optimizer::with_options(Options {
+            repeat_runs: true,
+            profile: Profile::xterm(),
+            ..Options::default()
+        });
+
+        optimizer.feed("\x1B[?25l\x1B[1ma");
+        optimizer.barrier();
+        optimizer.feed("\x1B[?25h\x1B[1maaaaaaaa");
+        assert_eq!(optimizer.take_output(), "\x1B[?25l\x1B[1ma\x1B[?25ha\x1B[7b");
+    }
+
+    #[test]
+    fn update_drops_repeated_window_ops() {
+        let mut optimizer = Optimizer::new();
+        optimizer.update("\x1B[8;24;80t\x1B[8;024;80t\x1B[22t\x1B[22ta").unwrap();
+        optimizer.update("\x1B[2t\x1B[1t\x1B[2t").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[8;24;80t\x1B[22t\x1B[22ta\x1B[2t\x1B[1t\x1B[2t");
+
+        let mut optimizer = Optimizer::with_options(Options {
+            sanitize: true,
+            ..Options::default()
+        });
+
+        optimizer.update("\x1B[2t\x1B[8;1;1t\x1B[21t\x1B[22;0ta\x1B[23;0t").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[22;0ta\x1B[23;0t");
+    }
+
+    #[test]
+    fn update_writes_queries() {
+        let mut optimizer = Optimizer::new();
+        // The query is written right away, after everything before it.
+        optimizer.feed("\x1B[31m\x1B[5;1H\x1B[6n\x1B[1m");
+        assert_eq!(optimizer.take_output(), "\x1B[5H\x1B[31m\x1B[6n");
+
+        let mut optimizer = Optimizer::with_options(Options {
+            queries: Queries::Strip,
+            ..Options::default()
+        });
+
+        optimizer.update("a\x1B[c\x1B[>0c\x1B[?6nb").unwrap();
+        assert_eq!(optimizer.to_string(), "ab");
+    }
+
+    #[test]
+    fn update_replaces_control_chars() {
+        let mut optimizer = Optimizer::with_options(Options {
+            control_chars: ControlChars::Escape,
+            ..Options::default()
+        });
+
+        optimizer.update("\x1B[1ma\0\x1B[m\x7F\x1B[1m\x08").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[1ma^@\x1B[m^?\x1B[1m^H");
+    }
+
+    #[test]
+    fn update_replaces_hyperlinks() {
+        let input = "\x1B]8;;https://a.com\x1B\\a\x1B]8;;\x1B\\ \x1B]8;id=2;https://b.com\x07b\
+                     \x1B]8;;https://a.com\x07c\x1B]8;;\x07";
+
+        let replace = |hyperlinks, supported| {
+            let profile = Profile { hyperlinks: supported, ..Profile::default() };
+            let mut optimizer = Optimizer::with_options(Options {
+                profile,
+                hyperlinks,
+                ..Options::default()
+            });
+
+            optimizer.update(input).unwrap();
+            optimizer.finish();
+            optimizer.to_string()
+        };
+
+        assert_eq!(
+            replace(Hyperlinks::Strip, true),
+            input.replace("b\x1B]8;;https://a", "b\x1B]8;;\x07\x1B]8;;https://a")
+        );
+        assert_eq!(replace(Hyperlinks::Strip, false), "a bc");
+        assert_eq!(
+            replace(Hyperlinks::Inline, false),
+            "a (https://a.com) b (https://b.com)c (https://a.com)"
+        );
+        assert_eq!(
+            replace(Hyperlinks::Footnotes, false),
+            "a[1] b[2]c[1]\n[1] https://a.com\n[2] https://b.com\n"
+        );
+    }
+
+    #[test]
+    fn update_normalizes_hyperlinks() {
+        let mut optimizer = Optimizer::new();
+        optimizer.update("\x1B]8;;\x1B\\\x1B]8;id=1;https://a.com\x1B\\a").unwrap();
+        optimizer.update("\x1B]8;id=1;https://a.com\x1B\\b\x1B]8;;https://b.com\x07c").unwrap();
+        optimizer.finish();
+        assert_eq!(
+            optimizer.to_string(),
+            "\x1B]8;id=1;https://a.com\x1B\\ab\x1B]8;;\x07\x1B]8;;https://b.com\x07c\x1B]8;;\x1B\\"
+        );
+    }
+
+    #[test]
+    fn update_limits_sequences() {
+        let input = "\x1B[1;2;3;4;5;6mA\x1B]2;long title\x07B";
+        let limited = |over_limit| {
+            let mut optimizer = Optimizer::with_options(Options {
+                max_params: Some(4),
+                max_string_bytes: Some(6),
+                over_limit,
+                ..Options::default()
+            });
+
+            let result = optimizer.update(input);
+            (result, optimizer.to_string())
+        };
+
+        assert_eq!(limited(OverLimit::Pass), (Ok(()), input.to_owned()));
+        assert_eq!(
+            limited(OverLimit::Truncate),
+            (Ok(()), "\x1B[1;2;3;4mA\x1B]2;long\x07B".to_owned())
+        );
+        assert_eq!(limited(OverLimit::Error), (Err(Error::LimitExceeded), String::new()));
+
+        let mut optimizer = Optimizer::with_options(Options {
+            max_sequence_bytes: Some(8),
+            ..Options::default()
+        });
+
+        optimizer.feed("a\x1B]2;ti");
+        assert_eq!(optimizer.take_output(), "a");
+        optimizer.feed("tle");
+        optimizer.feed(" text\x07b");
+        assert_eq!(optimizer.take_output(), "b");
+    }
+
+    #[test]
+    fn feed_bounds_unfinished_sequences() {
+        let input = format!("\x1B]2;{}", "a".repeat(10_000));
+        let mut optimizer = Optimizer::with_options(Options::hardened());
+        for c in input.chars() {
+            optimizer.feed(c.encode_utf8(&mut [0; 4]));
+            assert!(optimizer.partial.len() <= 4096);
+        }
+
+        optimizer.feed("\x07x");
+        optimizer.finish();
+        assert_eq!(optimizer.take_output(), "x");
+
+        // A clipboard write is dropped, rather than written without being sanitized.
+        let input = format!("\x1B]52;c;{}\x07a", "QUFB".repeat(2000));
+        for chunk in input.as_bytes().chunks(100) {
+            optimizer.feed(std::str::from_utf8(chunk).unwrap());
+        }
+
+        optimizer.finish();
+        assert_eq!(optimizer.take_output(), "a");
+
+        // The end of the string can be split between chunks.
+        optimizer.feed(&format!("\x1B]2;{}\x1B", "a".repeat(5000)));
+        optimizer.feed("\\b\x1B]2;");
+        optimizer.feed(&"a".repeat(5000));
+        optimizer.feed("\x1B");
+        optimizer.feed("[1mc\x1BP");
+        optimizer.feed(&"a".repeat(5000));
+        optimizer.feed("\u{9C}d");
+        assert_eq!(optimizer.take_output(), "b\x1B[1mcd");
+
+        // So can the end of a control sequence, which is any byte that isn't a parameter.
+        optimizer.feed(&format!("\x1B[{}", "1;".repeat(3000)));
+        optimizer.feed("1;1me\x1B[");
+        optimizer.feed(&"2;".repeat(3000));
+        optimizer.feed("\nf");
+        optimizer.finish();
+        assert_eq!(optimizer.take_output(), "e\nf");
+    }
+
+    #[test]
+    fn feed_bounds_sequences_started_by_chunk() {
+        let options = Options { max_sequence_bytes: Some(64), ..Options::default() };
+
+        // The string goes over the limit in the chunk that starts it.
+        let mut optimizer = Optimizer::with_options(options.clone());
+        optimizer.feed(&format!("\x1B]2;{}", "a".repeat(100)));
+        optimizer.feed("bcd\x07text");
+        optimizer.finish();
+        assert_eq!(optimizer.take_output(), "text");
+
+        // The control sequence goes over the limit in the chunk that ends the string before it.
+        let mut optimizer = Optimizer::with_options(options);
+        optimizer.feed("\x1B]2;x");
+        optimizer.feed(&format!("\x07\x1B[{}", "1;".repeat(50)));
+        optimizer.feed("1mvisible text");
+        optimizer.finish();
+        assert_eq!(optimizer.take_output(), "\x1B]2;x\x07visible text");
+    }
+
+    #[test]
+    fn update_limits_clipboard() {
+        let small = "\x1B]52;c;dGV4dA==\x07";
+        let large = format!("\x1B]52;c;{}\x07", "A".repeat(100));
+
+        let mut optimizer = Optimizer::with_options(Options {
+            max_clipboard_bytes: Some(64),
+            ..Options::default()
+        });
+
+        optimizer.update(format!("{}a{}b", small, large)).unwrap();
+        assert_eq!(optimizer.to_string(), format!("{}ab", small));
+
+        let mut optimizer = Optimizer::with_options(Options {
+            sanitize: true,
+            ..Options::default()
+        });
+
+        optimizer.update(format!("{}a", small)).unwrap();
+        assert_eq!(optimizer.to_string(), "a");
+    }
+
+    #[test]
+    fn update_tracks_palette() {
+        let mut optimizer = Optimizer::new();
+        optimizer.update("\x1B]4;1;#ff0000;2;#00ff00\x07a").unwrap();
+        optimizer.update("\x1B]4;1;rgb:ff/00/00;2;#0000ff\x07b").unwrap();
+        optimizer.update("\x1B]4;1;#ff0000\x07\x1B]11;#000\x07\x1B]11;#000000\x07c").unwrap();
+        assert_eq!(
+            optimizer.to_string(),
+            "\x1B]4;1;#ff0000;2;#00ff00\x07a\x1B]4;2;#0000ff\x07b\x1B]11;#000\x07c"
+        );
+
+        optimizer.reset();
+        optimizer.update("\x1B]104\x07a\x1B]104;1\x07\x1B]104\x07").unwrap();
+        optimizer.update("\x1B]110\x07\x1B]110\x07b").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B]104\x07a\x1B]110\x07b");
+
+        // A full reset (RIS) resets the palette.
+        optimizer.reset();
+        optimizer.update("\x1B]4;1;#ff0000\x07\x1B]11;#000\x07a\x1Bc").unwrap();
+        optimizer.update("\x1B]4;1;#ff0000\x07\x1B]11;#000\x07b").unwrap();
+        assert_eq!(
+            optimizer.to_string(),
+            "\x1B]4;1;#ff0000\x07\x1B]11;#000\x07a\x1Bc\x1B]4;1;#ff0000\x07\x1B]11;#000\x07b"
+        );
+
+        // Colors are converted using the redefined palette.
+        let mut optimizer = Optimizer::with_options(Options {
+            profile: Profile { colors: ColorSupport::Ansi16, ..Profile::default() },
+            ..Options::default()
+        });
+
+        optimizer.update("\x1B]4;4;#ff8000\x07\x1B[38;2;250;130;0mx").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B]4;4;#ff8000\x07\x1B[34mx");
+
+        optimizer.reset();
+        optimizer.update("\x1B]4;4;#ff8000\x07\x1B]104;4\x07\x1B[38;2;250;130;0mx").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B]4;4;#ff8000\x07\x1B]104;4\x07\x1B[33mx");
+    }
+
+    #[test]
+    fn feed() {
+        let mut optimizer = Optimizer::new();
+        optimizer.feed("a\x1B[3");
+        assert_eq!(optimizer.take_output(), "a");
+        optimizer.feed("1mb\x1B");
+        assert_eq!(optimizer.take_output(), "\x1B[31mb");
+        optimizer.feed("[39");
+        optimizer.finish();
+        assert_eq!(optimizer.take_output(), "\x1B[39");
+
+        // Parameters can arrive in chunks of their own.
+        optimizer.reset();
+        ["\x1B[1", ";", "4", "", "mc"].iter().for_each(|chunk| optimizer.feed(chunk));
+        assert_eq!(optimizer.take_output(), "\x1B[1;4mc");
+
+        // Invalid sequences are passed through.
+        optimizer.reset();
+        optimizer.feed("a\x1B\x07b\u{9B}\x07c");
+        optimizer.finish();
+        assert_eq!(optimizer.take_output(), "a\x1B\x07b\u{9B}\x07c");
+
+        // 8-bit sequences are written as 7-bit ones.
+        optimizer.reset();
+        optimizer.feed("\u{9B}1ma\u{9D}2;ti");
+        optimizer.feed("tle\u{9C}b");
+        assert_eq!(optimizer.take_output(), "\x1B[1ma\x1B]2;title\x1B\\b");
+    }
+
+    #[test]
+    fn feed_cow() {
+        let mut optimizer = Optimizer::with_options(Options {
+            columns: Some(80),
+            erase_runs: false,
+            ..Options::default()
+        });
+        assert!(matches!(optimizer.feed_cow("a\tb"), Cow::Borrowed("a\tb")));
+        assert!(matches!(optimizer.feed_cow("\x1B[31mc\x1B[3"), Cow::Owned(s) if s == "\x1B[31mc"));
+        assert!(matches!(optimizer.feed_cow("9m"), Cow::Owned(s) if s.is_empty()));
+
+        // The style change is held back until the next text.
+        assert!(matches!(optimizer.feed_cow("d"), Cow::Owned(s) if s == "\x1B[md"));
+        assert!(matches!(optimizer.feed_cow("e\rfg"), Cow::Borrowed("e\rfg")));
+        assert_eq!(optimizer.cursor.position.column, Some(3));
+
+        // Text that would be changed is copied.
+        let mut optimizer = Optimizer::with_options(Options {
+            control_chars: ControlChars::Escape,
+            ..Options::default()
+        });
+
+        assert!(matches!(optimizer.feed_cow("a\x08"), Cow::Owned(s) if s == "a^H"));
+    }
+
+    #[test]
+    fn feed_borrowed() {
+        let mut optimizer = Optimizer::new();
+        optimizer.update("a").unwrap();
+        assert_eq!(optimizer.feed_borrowed("b\x1B[3"), "ab");
+        assert_eq!(optimizer.feed_borrowed("1mc"), "\x1B[31mc");
+
+        // Output that wasn't borrowed yet is still taken.
+        optimizer.update("d\x1B[m").unwrap();
+        assert_eq!(optimizer.to_string(), "d\x1B[m");
+        optimizer.flush();
+        assert_eq!(optimizer.take_output(), "d\x1B[m");
+
+        // Resetting keeps the buffers.
+        optimizer.feed_borrowed("e\x1B[1");
+        let capacity = (optimizer.output.capacity(), optimizer.partial.capacity());
+        optimizer.reset();
+        assert_eq!((optimizer.output.capacity(), optimizer.partial.capacity()), capacity);
+        assert_eq!(optimizer.feed_borrowed("f"), "f");
+    }
+
+    #[test]
+    fn update_downgrades_colors() {
+        let mut optimizer = Optimizer::with_options(Options {
+            profile: Profile { colors: ColorSupport::Ansi16, ..Profile::default() },
+            ..Options::default()
+        });
+
+        optimizer.update("\x1B[38;2;255;0;0;48;5;21mred\x1B[m").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[91;44mred\x1B[m");
+
+        let mut optimizer = Optimizer::with_options(Options {
+            profile: Profile { colors: ColorSupport::Monochrome, ..Profile::default() },
+            ..Options::default()
+        });
+
+        optimizer.update("\x1B[1;31mbold\x1B[73;39;22m").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[1mbold\x1B[m");
+    }
+
+    #[test]
+    fn update_filters_style() {
+        let mut optimizer = Optimizer::with_options(Options {
+            style_filter: StyleFilter { foreground: false, ..StyleFilter::allow(Attributes::BOLD) },
+            ..Options::default()
+        });
+
+        optimizer.update("\x1B[1;5;31;44ma\x1B[7;73mb\x1B[22mc").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[1;31mab\x1B[22mc");
+    }
+
+    #[test]
+    fn update_skips_invisible_style() {
+        // Underline colors don't change how text looks unless it's underlined.
+        let mut optimizer = Optimizer::new();
+        optimizer.update("\x1B[58;5;1ma\x1B[59mb\x1B[58;5;2mc\x1B[4md").unwrap();
+        assert_eq!(optimizer.to_string(), "abc\x1B[4;58;5;2md");
+    }
+
+    #[test]
+    fn optimize_string() {
+        assert_eq!(optimize("\x1B[1m\x1B[1ma").unwrap(), "\x1B[1ma");
+        assert_eq!(optimize("a\x1B["), Err(Error::Incomplete));
+
+        let options =
+            Options { repeat_runs: true, profile: Profile::xterm(), ..Options::default() };
+        assert_eq!(optimize_with("aaaaaaaaaa", &options).unwrap(), "a\x1B[9b");
+    }
+
+    #[test]
+    fn update_bold_is_bright() {
+        let input = "\x1B[1;31ma\x1B[91mb\x1B[22mc";
+
+        let mut optimizer = Optimizer::new();
+        optimizer.update(input).unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[1;31ma\x1B[91mb\x1B[22mc");
+
+        let mut optimizer = Optimizer::with_options(Options {
+            profile: Profile { bold_is_bright: true, ..Profile::xterm() },
+            ..Options::default()
+        });
+
+        optimizer.update(input).unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[1;31mab\x1B[0;91mc");
+    }
+
+    #[test]
+    fn update_color_encoding() {
+        let input = "\x1B[38;5;208ma\x1B[48:2::1:2:3mb\x1B[1;38;2;4;5;6mc";
+        let mut optimizer = Optimizer::new();
+        optimizer.update(input).unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[38;5;208ma\x1B[48;2;1;2;3mb\x1B[1;38;2;4;5;6mc");
+
+        let profile = Profile { color_encoding: ColorEncoding::Colons, ..Profile::xterm() };
+        let mut optimizer = Optimizer::with_options(Options { profile, ..Options::default() });
+        optimizer.update(input).unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[38:5:208ma\x1B[48:2::1:2:3mb\x1B[1;38:2::4:5:6mc");
+    }
+
+    #[test]
+    fn update_plain_profile() {
+        let options = Options { profile: Profile::plain(), ..Options::default() };
+        let mut optimizer = Optimizer::with_options(options);
+        optimizer.update("\x1B[1;31ma\x1B[73;4mb\x1B[21mc\x1B[m\x1B[Hd").unwrap();
+        assert_eq!(optimizer.to_string(), "abc\x1B[Hd");
+    }
+
+    #[test]
+    fn update_sgr_21() {
+        let optimize = |sgr21, input| {
+            let profile = Profile { sgr21, ..Profile::xterm() };
+            let mut optimizer = Optimizer::with_options(Options { profile, ..Options::default() });
+            optimizer.update(input).unwrap();
+            optimizer.to_string()
+        };
+
+        let input = "\x1B[1ma\x1B[21mb\x1B[24mc";
+        assert_eq!(optimize(Sgr21::DoubleUnderline, input), "\x1B[1ma\x1B[21mb\x1B[24mc");
+        assert_eq!(optimize(Sgr21::NormalIntensity, input), "\x1B[1ma\x1B[mbc");
+        assert_eq!(optimize(Sgr21::Ambiguous, input), "\x1B[1ma\x1B[21mb\x1B[24mc");
+
+        // Nothing is merged until the style is reset.
+        let input = "\x1B[1ma\x1B[21mb\x1B[22m\x1B[1mc\x1B[0m\x1B[1m\x1B[31md";
+        let ambiguous = optimize(Sgr21::Ambiguous, input);
+        assert_eq!(ambiguous, "\x1B[1ma\x1B[21mb\x1B[22m\x1B[1mc\x1B[m\x1B[1;31md");
+    }
+
+    #[test]
+    fn update_maps_style() {
+        // Swap red and green, and make everything bold.
+        let mapper = StyleMapper::new(|mut style: Style| {
+            style.foreground = match style.foreground {
+                Color::Indexed(1) => Color::Indexed(2),
+                Color::Indexed(2) => Color::Indexed(1),
+                color => color,
+            };
+
+            style.attributes.insert(Attributes::BOLD);
+            style
+        });
+
+        let mut optimizer = Optimizer::with_options(Options {
+            style_mapper: Some(mapper),
+            ..Options::default()
+        });
+
+        optimizer.update("a\x1B[31mb\x1B[1mc\x1B[32md\x1B[me").unwrap();
+        assert_eq!(optimizer.to_string(), "\x1B[1ma\x1B[32mbc\x1B[31md\x1B[39me");
+    }
+
+    #[test]
+    fn update_enforces_contrast() {
+        let mut optimizer = Optimizer::with_options(Options {
+            min_contrast: Some(4.5),
+            ..Options::default()
+        });
+
+        // The default background isn't known until it's set.
+        optimizer.update("\x1B[37ma\x1B]11;#ffffff\x07b\x1B[30mc").unwrap();
+        assert_eq!(
+            optimizer.to_string(),
+            "\x1B[37ma\x1B]11;#ffffff\x07\x1B[38;2;118;118;118mb\x1B[30mc"
+        );
+    }
+
+    #[test]
+    fn update_bounded() {
+        let mut optimizer = Optimizer::with_options(Options {
+            max_buffered_bytes: Some(8),
+            ..Options::default()
+        });
+
+        optimizer.update("\x1B[H\x1B[31m").unwrap();
+        assert_eq!(optimizer.take_output(), "");
+        optimizer.update("\x1B[2J").unwrap();
+        assert_eq!(optimizer.take_output(), "\x1B[H\x1B[31m\x1B[2J");
+        optimizer.update("\x1B[32mx").unwrap();
+        assert_eq!(optimizer.take_output(), "\x1B[32mx");
+
+        let mut optimizer = Optimizer::with_options(Options {
+            max_buffered_time: Some(Duration::from_secs(0)),
+            ..Options::default()
+        });
+
+        optimizer.update("\x1B[?25l").unwrap();
+        assert_eq!(optimizer.take_output(), "\x1B[?25l");
+        assert_eq!(optimizer.deadline(), None);
+    }
+
+    #[test]
+    fn flush() {
+        let mut optimizer = Optimizer::new();
+        optimizer.update("a\x1B[31m\x1B[?25l").unwrap();
+        assert_eq!(optimizer.take_output(), "a");
+        assert_eq!(optimizer.deadline(), None);
+
+        optimizer.flush();
+        assert_eq!(optimizer.take_output(), "\x1B[?25l\x1B[31m");
+        assert_eq!(optimizer.to_string(), "");
+    }
+
+    #[test]
+    fn update_normalizes() {
+        let mut optimizer = Optimizer::new();
+        assert_eq!(optimizer.update("text\x1B[01;31mred\x1B[1;1H"), Ok(()));
+        assert_eq!(optimizer.to_string(), "text\x1B[1;31mred\x1B[H");
+
+        // Invalid sequences stop the update.
+        assert_eq!(optimizer.update("\x1B\x01"), Err(Error::InvalidSequence));
+
+        // Resetting clears the output.
+        optimizer.reset();
+        assert_eq!(optimizer.to_string(), "");
+    }
+}
+
+// extern crate peekmore;
+//
+// mod ansi;
+// mod state;
+//
+// #[cfg(test)]
+// mod tests {
+//     #[test]
+//     fn it_works() {
+//         assert_eq!(2 + 2, 4);
+//     }
+// }
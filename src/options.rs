@@ -0,0 +1,382 @@
+use crate::cost::Cost;
+use crate::style::{StyleFilter, StyleMapper};
+use crate::width::WidthPolicy;
+use crate::Profile;
+use std::time::Duration;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Options that control how an [Optimizer](crate::Optimizer) behaves.
+///
+/// ```
+/// # use ansi_optimizer::{Optimizer, Options};
+/// # use std::time::Duration;
+/// let optimizer = Optimizer::with_options(Options {
+///     max_buffered_bytes: Some(4096),
+///     max_buffered_time: Some(Duration::from_millis(5)),
+///     ..Options::default()
+/// });
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    /// The maximum number of bytes of escape sequences that can be held back while looking for
+    /// merge opportunities. When exceeded, the held sequences are flushed to the output.
+    ///
+    /// If `None`, there is no limit.
+    pub max_buffered_bytes: Option<usize>,
+
+    /// The maximum amount of time escape sequences can be held back while looking for merge
+    /// opportunities. When exceeded, the held sequences are flushed to the output.
+    ///
+    /// This is only checked when the optimizer is updated. Interactive programs should wait until
+    /// [Optimizer::deadline](crate::Optimizer::deadline) and call
+    /// [Optimizer::flush](crate::Optimizer::flush) if no more input arrives by then.
+    ///
+    /// If `None`, there is no limit.
+    pub max_buffered_time: Option<Duration>,
+
+    /// The width of the terminal, in columns.
+    ///
+    /// This is needed to know when text will wrap onto the next line. If `None`, optimizations
+    /// that depend on it are disabled.
+    pub columns: Option<u16>,
+
+    /// The height of the terminal, in rows.
+    ///
+    /// This is needed to know when the cursor is at the bottom of the screen. If `None`, the row
+    /// is unknown after moving down.
+    pub rows: Option<u16>,
+
+    /// Whether the terminal wraps text at its right edge (DECAWM) before the output changes it.
+    ///
+    /// This is enabled by default on almost every terminal. If `None`, it's assumed to be unknown
+    /// until the output sets it.
+    pub autowrap: Option<bool>,
+
+    /// The distance between the terminal's initial tab stops, which is used to know where tabs move
+    /// the cursor. Tab stops set and cleared by the output (`HTS` and `TBC`) are also tracked.
+    ///
+    /// If `None`, the usual distance of 8 columns is used.
+    pub tab_width: Option<u16>,
+
+    /// How the terminal shows text that terminals disagree on the width of (e.g. emoji joined with
+    /// `ZWJ`), which is needed to know where the cursor is after writing it.
+    pub width_policy: WidthPolicy,
+
+    /// Whether to replace long runs of spaces with sequences that erase them, and short erase
+    /// sequences with spaces.
+    ///
+    /// This assumes the terminal fills erased cells with the current background color, which
+    /// is true for almost every modern terminal. It has no effect unless [Options::columns] is set.
+    pub erase_runs: bool,
+
+    /// Whether to replace runs of the same character with REP (repeat) sequences if the
+    /// [Options::profile] supports them, and to expand REP sequences into characters if not.
+    pub repeat_runs: bool,
+
+    /// Whether to remove sequences that set the window title or icon name (`OSC 0`, `1`, and `2`).
+    pub strip_titles: bool,
+
+    /// Whether to hold progress reports (`OSC 9 ; 4`) back across text, so only the last one is
+    /// written each time the optimizer is flushed.
+    ///
+    /// Progress reports only change the terminal's tab or taskbar. Together with
+    /// [Options::max_buffered_time], this limits how often they're written by a program that
+    /// reports every step of its progress.
+    pub collapse_progress: bool,
+
+    /// Whether to remove inline images (sixel, iTerm2, and kitty graphics) instead of passing them
+    /// through.
+    pub strip_images: bool,
+
+    /// Whether to remove sequences that change the kitty keyboard protocol flags.
+    ///
+    /// Programs enable the protocol while running and disable it when they exit, so a recording
+    /// that was cut short can leave the viewer's terminal sending unexpected key codes.
+    pub strip_keyboard_protocol: bool,
+
+    /// Whether to remove sequences that enable or disable mouse reporting, and any SGR mouse
+    /// reports (`CSI < b ; x ; y M`) echoed into the output.
+    ///
+    /// A recording that enables mouse reporting will otherwise enable it on the terminal it's
+    /// replayed on. Legacy X10 mouse reports (`CSI M` followed by three bytes) can't be told apart
+    /// from DL (delete line), and are kept.
+    pub strip_mouse: bool,
+
+    /// DEC private modes to remove from the sequences that set or reset them (`CSI ? Pm h` and
+    /// `CSI ? Pm l`).
+    ///
+    /// This is for modes the terminal the output is meant for doesn't support, or shouldn't have
+    /// changed (e.g. `1049` to keep a recording out of the alternate screen).
+    pub strip_modes: Vec<u16>,
+
+    /// Whether to remove sequences that are unsafe to display from untrusted content.
+    ///
+    /// This removes queries that make the terminal reply as if the reply was typed (e.g. device
+    /// attributes or cursor position reports), and sequences that change how input is sent (e.g.
+    /// bracketed paste, focus events, mouse reporting, or the kitty keyboard protocol). Clipboard
+    /// writes (OSC 52) and window operations that move, resize, or iconify the window are also
+    /// removed. Sequences that only change what's displayed are kept, and the escapes that start
+    /// invalid sequences are dropped, since terminals may still run them.
+    pub sanitize: bool,
+
+    /// What to do with queries that the terminal replies to (device attributes and status reports).
+    ///
+    /// Queries that are kept are never held back or reordered, since whatever sent them is waiting
+    /// for the reply, and the reply can depend on everything written before them.
+    pub queries: Queries,
+
+    /// What to do with control characters in text that don't move the cursor in a predictable way
+    /// (e.g. `NUL`, `BS`, `VT`, `FF`, `DEL`, or 8-bit C1 controls).
+    ///
+    /// This doesn't affect tabs, line feeds, carriage returns, or escape sequences.
+    pub control_chars: ControlChars,
+
+    /// What to do with hyperlinks (OSC 8) if the [Options::profile] doesn't support them.
+    pub hyperlinks: Hyperlinks,
+
+    /// What the [wrap](crate::wrap) and [slice_visible](crate::slice_visible_with) helpers
+    /// do with bidirectional formatting characters (e.g. `RLE` or `FSI`).
+    pub bidi: Bidi,
+
+    /// The maximum size of the base64-encoded text in a clipboard write (OSC 52). Larger writes
+    /// are removed, and `Some(0)` removes all of them.
+    ///
+    /// If `None`, there is no limit.
+    pub max_clipboard_bytes: Option<usize>,
+
+    /// The maximum number of parameters in a control sequence (CSI).
+    /// Sequences with more are handled as [Options::over_limit] says.
+    ///
+    /// If `None`, there is no limit.
+    pub max_params: Option<usize>,
+
+    /// The maximum size of the text of an OSC, DCS, or APC string (e.g. a title, a hyperlink, or
+    /// an inline image). Sequences with more are handled as [Options::over_limit] says.
+    ///
+    /// If `None`, there is no limit.
+    pub max_string_bytes: Option<usize>,
+
+    /// The maximum size of an escape sequence. Larger sequences are handled as
+    /// [Options::over_limit] says.
+    ///
+    /// This also limits how much of an unfinished sequence is held back by
+    /// [Optimizer::feed](crate::Optimizer::feed) while waiting for the rest of it. Since where it
+    /// ends isn't known yet, a longer one is dropped, along with the rest of it as it arrives.
+    ///
+    /// If `None`, there is no limit.
+    pub max_sequence_bytes: Option<usize>,
+
+    /// What to do with escape sequences that exceed [Options::max_params],
+    /// [Options::max_string_bytes], or [Options::max_sequence_bytes].
+    pub over_limit: OverLimit,
+
+    /// A function that changes each style before it's written (e.g. to translate a theme).
+    ///
+    /// It's applied before colors are converted for the [Options::profile] and before the
+    /// [Options::style_filter], and is given the style as set by the input.
+    pub style_mapper: Option<StyleMapper>,
+
+    /// The minimum WCAG contrast ratio (from 1 to 21) between the foreground and background colors.
+    /// Foreground colors with less contrast are made lighter or darker until they have enough.
+    ///
+    /// The terminal's default colors are only known if they were set by the output (`OSC 10` and
+    /// `OSC 11`). Converting colors for the [Options::profile] can reduce the contrast again.
+    ///
+    /// If `None`, colors aren't changed.
+    pub min_contrast: Option<f32>,
+
+    /// The parts of the style (e.g. attributes or background colors) to remove from the output.
+    pub style_filter: StyleFilter,
+
+    /// The capabilities of the terminal that the output is intended for.
+    pub profile: Profile,
+
+    /// The weights used to choose the cheapest of equivalent ways of writing the output.
+    ///
+    /// By default, the output with the fewest bytes is the cheapest.
+    pub cost: Cost,
+}
+
+impl Options {
+    /// Creates options with every optimization of a [Level] enabled.
+    ///
+    /// ```
+    /// # use ansi_optimizer::{Level, Options};
+    /// let options = Options { columns: Some(80), ..Options::with_level(Level::Aggressive) };
+    /// assert!(options.strip_titles);
+    /// ```
+    pub fn with_level(level: Level) -> Self {
+        let mut options = Options::default();
+        options.set_level(level);
+        options
+    }
+
+    /// Creates options for optimizing untrusted input (e.g. logs collected from other programs)
+    /// in a service, where the input mustn't be able to use up its time or memory.
+    ///
+    /// With these options, the memory used is bounded no matter how the input is split into
+    /// chunks:
+    ///
+    /// - Escape sequences are limited to 4096 bytes and 32 parameters, and are
+    ///   [truncated](OverLimit::Truncate) to fit. An unfinished sequence held back by
+    ///   [Optimizer::feed](crate::Optimizer::feed) is never longer than that, and is dropped if
+    ///   it grows longer.
+    /// - At most 4096 bytes of escape sequences are held back while looking for merge
+    ///   opportunities.
+    /// - Sequences that are unsafe to display from untrusted content are removed. See
+    ///   [Options::sanitize].
+    ///
+    /// The output isn't bounded, and should be taken after every chunk. It's never much larger
+    /// than the input, unless [Options::repeat_runs] expands REP sequences, or hyperlinks are
+    /// written as [footnotes](Hyperlinks::Footnotes).
+    ///
+    /// ```
+    /// # use ansi_optimizer::{Optimizer, Options};
+    /// let mut optimizer = Optimizer::with_options(Options::hardened());
+    /// optimizer.feed(&format!("\x1B[{}m", "1;".repeat(100_000)));
+    /// optimizer.feed("text");
+    /// assert_eq!(optimizer.take_output(), "\x1B[1mtext");
+    /// ```
+    pub fn hardened() -> Self {
+        Options {
+            max_buffered_bytes: Some(4096),
+            max_params: Some(32),
+            max_string_bytes: Some(4096),
+            max_sequence_bytes: Some(4096),
+            over_limit: OverLimit::Truncate,
+            sanitize: true,
+            ..Options::default()
+        }
+    }
+
+    /// Enables every optimization of a [Level], and disables the optimizations of higher levels.
+    pub fn set_level(&mut self, level: Level) {
+        self.erase_runs = level >= Level::Standard;
+        self.repeat_runs = level >= Level::Standard;
+        self.strip_titles = level >= Level::Aggressive;
+        self.collapse_progress = level >= Level::Aggressive;
+    }
+}
+
+/// How aggressively to optimize. See [Options::with_level].
+///
+/// Each level includes the optimizations of the levels below it.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Level {
+    /// Only normalizes sequences and merges the ones that are equivalent (`-O1`).
+    /// This is what the default options do.
+    Lossless,
+
+    /// Also makes assumptions that hold for almost every modern terminal (`-O2`).
+    /// This enables [Options::erase_runs] and [Options::repeat_runs].
+    Standard,
+
+    /// Also removes or delays sequences that don't change what's displayed (`-O3`).
+    /// This enables [Options::strip_titles] and [Options::collapse_progress].
+    Aggressive,
+}
+
+/// What to do with stray control characters in text. See [Options::control_chars].
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum ControlChars {
+    /// Write them unchanged.
+    Keep,
+
+    /// Remove them.
+    Strip,
+
+    /// Replace them with caret notation (e.g. `^@` for `NUL`, or `^?` for `DEL`).
+    /// 8-bit C1 controls are written with an `M-` prefix (e.g. `M-^[` for `U+009B`).
+    Escape,
+}
+
+impl Default for ControlChars {
+    #[inline]
+    fn default() -> Self {
+        ControlChars::Keep
+    }
+}
+
+/// What to do with hyperlinks that the terminal doesn't support. See [Options::hyperlinks].
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Hyperlinks {
+    /// Remove them, leaving only their text.
+    Strip,
+
+    /// Write the URI in parentheses after the text of each link (e.g. `docs (https://docs.rs)`).
+    Inline,
+
+    /// Write a number in brackets after the text of each link (e.g. `docs[1]`), and a list of the
+    /// URIs when the optimizer is [finished](crate::Optimizer::finish). Links to the same URI
+    /// share a number.
+    Footnotes,
+}
+
+impl Default for Hyperlinks {
+    #[inline]
+    fn default() -> Self {
+        Hyperlinks::Strip
+    }
+}
+
+/// What to do with escape sequences that exceed the limits. See [Options::over_limit].
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum OverLimit {
+    /// Write them unchanged, without trying to understand them. Whatever they change is assumed
+    /// to be unknown afterwards (e.g. the cursor position).
+    Pass,
+
+    /// Remove parameters or text from the end of them until they fit.
+    Truncate,
+
+    /// Remove them, and return [Error::LimitExceeded](crate::Error::LimitExceeded) from
+    /// [Optimizer::update](crate::Optimizer::update).
+    Error,
+}
+
+impl Default for OverLimit {
+    #[inline]
+    fn default() -> Self {
+        OverLimit::Pass
+    }
+}
+
+/// What to do with bidirectional formatting characters when breaking or cutting off lines.
+/// See [Options::bidi].
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Bidi {
+    /// Write them unchanged, even if embeddings are left open across a line break.
+    Pass,
+
+    /// Close the embeddings, overrides, and isolates that are open at the end of each line, and
+    /// open them again at the start of the next one, so each line can be displayed on its own.
+    Isolate,
+
+    /// Remove them.
+    Strip,
+}
+
+impl Default for Bidi {
+    #[inline]
+    fn default() -> Self {
+        Bidi::Pass
+    }
+}
+
+/// What to do with device attribute and status report queries. See [Options::queries].
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Queries {
+    /// Write them unchanged, as soon as they're read.
+    Keep,
+
+    /// Remove them.
+    Strip,
+}
+
+impl Default for Queries {
+    #[inline]
+    fn default() -> Self {
+        Queries::Keep
+    }
+}
@@ -0,0 +1,188 @@
+use crate::ansi::AnsiString;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A parsed OSC (operating system command) string.
+///
+/// ```
+/// # use ansi_optimizer::ansi::Sequence;
+/// # use ansi_optimizer::osc::OscCommand;
+/// # use std::convert::TryFrom;
+/// if let Ok(Sequence::OSC(_, string)) = Sequence::try_from("\x1B]2;Title\x07") {
+///     assert_eq!(OscCommand::from(&string), OscCommand::SetTitle("Title"));
+/// }
+/// ```
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum OscCommand<'a> {
+    /// Sets both the window title and icon name (`OSC 0`).
+    SetTitleAndIconName(&'a str),
+
+    /// Sets the icon name (`OSC 1`).
+    SetIconName(&'a str),
+
+    /// Sets the window title (`OSC 2`).
+    SetTitle(&'a str),
+
+    /// Sets colors in the palette (`OSC 4 ; index ; spec ...`).
+    SetPalette(PaletteEntries<'a>),
+
+    /// Starts or ends a hyperlink (`OSC 8 ; params ; uri`).
+    /// The hyperlink ends when the URI is empty.
+    Hyperlink { params: &'a str, uri: &'a str },
+
+    /// Sets one of the dynamic colors (`OSC 10` to `OSC 19`), e.g. the default foreground color.
+    ///
+    /// Following colors in the same command set the next dynamic colors.
+    SetDynamicColor(DynamicColor, &'a str),
+
+    /// Writes to or queries the clipboard (`OSC 52 ; selection ; data`).
+    /// The data is base64-encoded, or `?` for a query.
+    Clipboard { selection: &'a str, data: &'a str },
+
+    /// Resets colors in the palette (`OSC 104 ; index ...`).
+    /// If there are no indices, the whole palette is reset.
+    ResetPalette(&'a str),
+
+    /// Resets one of the dynamic colors (`OSC 110` to `OSC 119`).
+    ResetDynamicColor(DynamicColor),
+
+    /// Any other numbered command, and its data.
+    Other(u16, &'a str),
+
+    /// A string that doesn't start with a command number.
+    Unknown(&'a str),
+}
+
+/// A dynamic color, set by `OSC 10` to `OSC 19`.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum DynamicColor {
+    Foreground,
+    Background,
+    Cursor,
+
+    /// One of the other dynamic colors, by its offset from `OSC 10`.
+    Other(u8),
+}
+
+/// An iterator over the palette entries of an `OSC 4` command.
+///
+/// Each entry is a palette index and a color specification (e.g. `rgb:ff/00/00`, or `?` to query
+/// it). Indices that aren't numbers are yielded as `None`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PaletteEntries<'a> {
+    remaining: &'a str,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl<'a> From<&AnsiString<'a>> for OscCommand<'a> {
+    fn from(string: &AnsiString<'a>) -> Self {
+        let (command, data) = match string.command() {
+            Some(parsed) => parsed,
+            None => return OscCommand::Unknown(string.text()),
+        };
+
+        match command {
+            0 => OscCommand::SetTitleAndIconName(data),
+            1 => OscCommand::SetIconName(data),
+            2 => OscCommand::SetTitle(data),
+            4 => OscCommand::SetPalette(PaletteEntries { remaining: data }),
+            8 => match data.split_once(';') {
+                Some((params, uri)) => OscCommand::Hyperlink { params, uri },
+                None => OscCommand::Other(command, data),
+            },
+            10..=19 => OscCommand::SetDynamicColor(DynamicColor::from_offset(command - 10), data),
+            52 => match data.split_once(';') {
+                Some((selection, data)) => OscCommand::Clipboard { selection, data },
+                None => OscCommand::Other(command, data),
+            },
+            104 => OscCommand::ResetPalette(data),
+            110..=119 => OscCommand::ResetDynamicColor(DynamicColor::from_offset(command - 110)),
+            _ => OscCommand::Other(command, data),
+        }
+    }
+}
+
+impl DynamicColor {
+    /// Gets the offset of the color's command from `OSC 10`.
+    pub fn offset(self) -> u8 {
+        match self {
+            DynamicColor::Foreground => 0,
+            DynamicColor::Background => 1,
+            DynamicColor::Cursor => 2,
+            DynamicColor::Other(n) => n,
+        }
+    }
+
+    fn from_offset(offset: u16) -> Self {
+        match offset {
+            0 => DynamicColor::Foreground,
+            1 => DynamicColor::Background,
+            2 => DynamicColor::Cursor,
+            n => DynamicColor::Other(n as u8),
+        }
+    }
+}
+
+impl<'a> Iterator for PaletteEntries<'a> {
+    type Item = (Option<u16>, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining.is_empty() {
+            return None;
+        }
+
+        let mut parts = self.remaining.splitn(3, ';');
+        let index = parts.next().unwrap_or("");
+        let spec = parts.next().unwrap_or("");
+        self.remaining = parts.next().unwrap_or("");
+        Some((index.parse().ok(), spec))
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::ansi::Sequence;
+    use crate::osc::*;
+    use std::convert::TryFrom;
+
+    fn parse(sequence: &str) -> OscCommand<'_> {
+        match Sequence::try_from(sequence) {
+            Ok(Sequence::OSC(_, string)) => OscCommand::from(&string),
+            other => panic!("not an OSC sequence: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(parse("\x1B]0;a;b\x07"), OscCommand::SetTitleAndIconName("a;b"));
+        assert_eq!(
+            parse("\x1B]8;id=1;https://example.com\x1B\\"),
+            OscCommand::Hyperlink { params: "id=1", uri: "https://example.com" }
+        );
+        assert_eq!(
+            parse("\x1B]11;#000000\x07"),
+            OscCommand::SetDynamicColor(DynamicColor::Background, "#000000")
+        );
+        assert_eq!(
+            parse("\x1B]52;c;dGV4dA==\x07"),
+            OscCommand::Clipboard { selection: "c", data: "dGV4dA==" }
+        );
+        assert_eq!(parse("\x1B]104\x07"), OscCommand::ResetPalette(""));
+        assert_eq!(parse("\x1B]112\x07"), OscCommand::ResetDynamicColor(DynamicColor::Cursor));
+        assert_eq!(parse("\x1B]7;file:///tmp\x07"), OscCommand::Other(7, "file:///tmp"));
+        assert_eq!(parse("\x1B]L;label\x07"), OscCommand::Unknown("L;label"));
+    }
+
+    #[test]
+    fn palette_entries() {
+        let entries = match parse("\x1B]4;1;rgb:ff/00/00;x;?;2\x07") {
+            OscCommand::SetPalette(entries) => entries.collect::<Vec<_>>(),
+            other => panic!("not a palette command: {:?}", other),
+        };
+
+        assert_eq!(entries, vec![(Some(1), "rgb:ff/00/00"), (None, "?"), (Some(2), "")]);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::color::{Color, Palette};
+use crate::osc::DynamicColor;
+
+// -------------------------------------------------------------------------------------------------
+
+/// The value of a palette color or dynamic color, as set by the emitted output.
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum Value {
+    /// The color hasn't been set or reset, so its value depends on what happened before.
+    Unknown,
+
+    /// The color was reset to the terminal's default.
+    Default,
+
+    /// The color was set to a color specification.
+    /// Specifications that could be parsed are normalized to `#rrggbb`.
+    Set(String),
+}
+
+/// The palette and dynamic colors (e.g. the default foreground), as set by `OSC 4`, `OSC 10` to
+/// `OSC 19`, and the commands that reset them.
+#[derive(Clone, Debug)]
+pub(crate) struct PaletteState {
+    indexed: Vec<Value>,
+    dynamic: Vec<Value>,
+
+    /// The RGB values of the palette, for converting colors.
+    pub colors: Palette,
+}
+
+impl Default for PaletteState {
+    fn default() -> Self {
+        PaletteState {
+            indexed: vec![Value::Unknown; 256],
+            dynamic: vec![Value::Unknown; 10],
+            colors: Palette::default(),
+        }
+    }
+}
+
+impl PaletteState {
+    /// Sets a palette color.
+    ///
+    /// ## Returns
+    ///
+    /// `false` if the color was already set to the same value, and the command can be dropped.
+    pub fn set(&mut self, index: u8, spec: &str) -> bool {
+        if spec == "?" {
+            return true;
+        }
+
+        let rgb = parse_spec(spec);
+        if !update(&mut self.indexed[index as usize], Value::Set(normalize_spec(spec, rgb))) {
+            return false;
+        }
+
+        match rgb {
+            Some(rgb) => self.colors.set(index, rgb),
+            None => self.colors.reset(index),
+        }
+
+        true
+    }
+
+    /// Resets a palette color to the terminal's default.
+    ///
+    /// ## Returns
+    ///
+    /// `false` if the color was already reset, and the command can be dropped.
+    pub fn reset(&mut self, index: u8) -> bool {
+        self.colors.reset(index);
+        update(&mut self.indexed[index as usize], Value::Default)
+    }
+
+    /// Resets every palette color to the terminal's default.
+    ///
+    /// ## Returns
+    ///
+    /// `false` if every color was already reset, and the command can be dropped.
+    pub fn reset_all(&mut self) -> bool {
+        let changed = self.indexed.iter().any(|value| *value != Value::Default);
+        self.indexed.iter_mut().for_each(|value| *value = Value::Default);
+        self.colors = Palette::default();
+        changed
+    }
+
+    /// Sets a dynamic color.
+    ///
+    /// ## Returns
+    ///
+    /// `false` if the color was already set to the same value, and the command can be dropped.
+    pub fn set_dynamic(&mut self, offset: u8, spec: &str) -> bool {
+        let value = Value::Set(normalize_spec(spec, parse_spec(spec)));
+        spec == "?" || update(&mut self.dynamic[offset as usize], value)
+    }
+
+    /// Resets a dynamic color to the terminal's default.
+    ///
+    /// ## Returns
+    ///
+    /// `false` if the color was already reset, and the command can be dropped.
+    pub fn reset_dynamic(&mut self, offset: u8) -> bool {
+        update(&mut self.dynamic[offset as usize], Value::Default)
+    }
+
+    /// Gets the RGB value of a color, using a dynamic color for the terminal's default color.
+    ///
+    /// Returns `None` for the default color if the dynamic color wasn't set to an RGB value.
+    pub fn to_rgb(&self, color: Color, default: DynamicColor) -> Option<(u8, u8, u8)> {
+        match (color, &self.dynamic[default.offset() as usize]) {
+            (Color::Default, Value::Set(spec)) => parse_spec(spec),
+            (Color::Default, _) => None,
+            (color, _) => color.to_rgb_with(&self.colors),
+        }
+    }
+
+    /// Forgets every color.
+    pub fn clear(&mut self) {
+        self.indexed.iter_mut().for_each(|value| *value = Value::Unknown);
+        self.dynamic.iter_mut().for_each(|value| *value = Value::Unknown);
+        self.colors = Palette::default();
+    }
+}
+
+/// Replaces a value.
+///
+/// ## Returns
+///
+/// `true` if the value changed.
+fn update(value: &mut Value, new: Value) -> bool {
+    let changed = *value != new;
+    *value = new;
+    changed
+}
+
+/// Normalizes a color specification, so equivalent specifications compare equal.
+fn normalize_spec(spec: &str, rgb: Option<(u8, u8, u8)>) -> String {
+    match rgb {
+        Some((r, g, b)) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        None => spec.to_ascii_lowercase(),
+    }
+}
+
+/// Parses an X11 color specification in the `rgb:r/g/b` or `#rgb` forms.
+///
+/// Color names (e.g. `red`) aren't parsed, since their values depend on the terminal.
+fn parse_spec(spec: &str) -> Option<(u8, u8, u8)> {
+    let (channels, scaled) = if let Some(rgb) = spec.strip_prefix("rgb:") {
+        let mut parts = rgb.split('/');
+        let channels = [parts.next()?, parts.next()?, parts.next()?];
+        if parts.next().is_some() {
+            return None;
+        }
+
+        (channels, true)
+    } else if let Some(hex) = spec.strip_prefix('#') {
+        let n = hex.len() / 3;
+        if hex.len() % 3 != 0 || !hex.is_ascii() {
+            return None;
+        }
+
+        ([&hex[..n], &hex[n..(2 * n)], &hex[(2 * n)..]], false)
+    } else {
+        return None;
+    };
+
+    let mut rgb = [0u8; 3];
+    for (value, hex) in rgb.iter_mut().zip(channels.iter()) {
+        *value = parse_channel(hex, scaled)?;
+    }
+
+    Some((rgb[0], rgb[1], rgb[2]))
+}
+
+/// Parses a color channel of 1 to 4 hex digits.
+///
+/// In the `rgb:` form, channels are scaled (so `f` and `ffff` are both the maximum intensity). In
+/// the `#` form, they are the most significant bits (so `#f00` is the same as `#f00000`).
+fn parse_channel(hex: &str, scaled: bool) -> Option<u8> {
+    let digits = hex.len() as u32;
+    if !(1..=4).contains(&digits) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    let max = (1 << (4 * digits)) - 1;
+    Some(match (scaled, digits) {
+        (true, _) => (value * 255 + max / 2) / max,
+        (false, 1) => value << 4,
+        (false, n) => value >> (4 * (n - 2)),
+    } as u8)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::palette::*;
+
+    #[test]
+    fn parse_specs() {
+        assert_eq!(parse_spec("rgb:ff/00/80"), Some((255, 0, 128)));
+        assert_eq!(parse_spec("rgb:f/0/ffff"), Some((255, 0, 255)));
+        assert_eq!(parse_spec("#ff0080"), Some((255, 0, 128)));
+        assert_eq!(parse_spec("#f08"), Some((240, 0, 128)));
+        assert_eq!(parse_spec("#ffff00008000"), Some((255, 0, 128)));
+        assert_eq!(parse_spec("red"), None);
+        assert_eq!(parse_spec("rgb:ff/00"), None);
+        assert_eq!(parse_spec("#ff00"), None);
+    }
+
+    #[test]
+    fn redundant_changes() {
+        let mut state = PaletteState::default();
+        assert!(state.set(1, "rgb:ff/00/00"));
+        assert!(!state.set(1, "#ff0000"));
+        assert_eq!(state.colors.get(1), (255, 0, 0));
+        assert!(state.set(1, "?"));
+
+        assert!(state.reset_all());
+        assert!(!state.reset(1));
+        assert!(!state.reset_all());
+
+        assert!(state.set_dynamic(1, "#000"));
+        assert!(!state.set_dynamic(1, "rgb:0/0/0"));
+        assert!(state.reset_dynamic(1));
+    }
+
+    #[test]
+    fn to_rgb() {
+        let mut state = PaletteState::default();
+        assert_eq!(state.to_rgb(Color::Default, DynamicColor::Background), None);
+        assert_eq!(state.to_rgb(Color::Indexed(1), DynamicColor::Foreground), Some((205, 0, 0)));
+
+        state.set_dynamic(1, "rgb:ff/ff/ff");
+        assert_eq!(state.to_rgb(Color::Default, DynamicColor::Background), Some((255, 255, 255)));
+        assert_eq!(state.to_rgb(Color::Default, DynamicColor::Foreground), None);
+    }
+}
@@ -0,0 +1,239 @@
+//! Optimizing large inputs on multiple threads.
+//!
+//! The input is split into chunks where the state of the terminal is known: after a line that
+//! ends by resetting the style, or before a full reset (`RIS`). Only sequences found by the
+//! tokenizer are used, so the input is never split inside another sequence (e.g. a tmux
+//! passthrough). It also isn't split while something a chunk can't know about is still in
+//! effect: an open hyperlink, a saved cursor, a changed mode, or a title. Each chunk is optimized
+//! on its own, and the outputs are joined together.
+//!
+//! The output is equivalent to [optimize_with], but can be slightly larger, since nothing is
+//! merged across chunks and each chunk starts without knowing where the cursor is. Hyperlinks
+//! written as [footnotes](Hyperlinks::Footnotes) are numbered across the whole input, so that
+//! input is optimized on a single thread.
+//!
+//! ```
+//! # use ansi_optimizer::{optimize_parallel, Options};
+//! let input = "\x1B[31m\x1B[1mfailed\x1B[m\n".repeat(10000);
+//! let output = optimize_parallel(&input, &Options::default()).unwrap();
+//! assert_eq!(output, "\x1B[1;31mfailed\x1B[m\n".repeat(10000));
+//! ```
+
+use crate::ansi::{Param, Sequence};
+use crate::osc::OscCommand;
+use crate::token::{Token, Tokens};
+use crate::{optimize_with, Error, Hyperlinks, Options};
+use rayon::prelude::*;
+
+// -------------------------------------------------------------------------------------------------
+
+/// The smallest chunk worth giving to another thread, in bytes.
+const MIN_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Optimizes a complete string on multiple threads, using [Options].
+///
+/// This is only worth it for large inputs (e.g. CI logs), and inputs without places to split
+/// them are optimized on a single thread. See [optimize_with].
+///
+/// ## Returns
+///
+/// If the input contains an invalid escape sequence, this returns [Error::InvalidSequence].
+/// If the input ends partway through an escape sequence, this returns [Error::Incomplete].
+pub fn optimize_parallel(input: &str, options: &Options) -> Result<String, Error> {
+    if options.hyperlinks == Hyperlinks::Footnotes && !options.profile.hyperlinks {
+        return optimize_with(input, options);
+    }
+
+    let chunk_size = (input.len() / rayon::current_num_threads()).max(MIN_CHUNK_SIZE);
+    let outputs = split(input, chunk_size)
+        .par_iter()
+        .map(|chunk| optimize_with(chunk, options))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(outputs.concat())
+}
+
+/// Splits input into chunks that are at least `size` bytes, where the state is known.
+fn split(input: &str, size: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut state = State::default();
+    for (span, token) in Tokens::new(input).spanned() {
+        let sequence = match token {
+            Ok(Token::Sequence(sequence)) => sequence,
+            _ => continue,
+        };
+
+        state.update(&sequence);
+        if state.in_effect() || span.start < start + size.max(1) {
+            continue;
+        }
+
+        // Before a full reset, or after a line that ends by resetting the style.
+        let rest = &input[span.end..];
+        let end = match &input[span.start..span.end] {
+            "\x1Bc" => span.start,
+            "\x1B[m" | "\x1B[0m" if rest.starts_with('\n') => span.end + 1,
+            "\x1B[m" | "\x1B[0m" if rest.starts_with("\r\n") => span.end + 2,
+            _ => continue,
+        };
+
+        if end < input.len() {
+            chunks.push(&input[start..end]);
+            start = end;
+        }
+    }
+
+    chunks.push(&input[start..]);
+    chunks
+}
+
+/// What the input has changed that a chunk starting after it wouldn't know about.
+#[derive(Default)]
+struct State {
+    link_open: bool,
+    cursor_saved: bool,
+    title_set: bool,
+
+    /// The modes that were changed, with whether they were set before and after.
+    modes: Vec<(Option<char>, u16, bool, bool)>,
+}
+
+impl State {
+    fn update(&mut self, sequence: &Sequence) {
+        match sequence {
+            Sequence::Regular(seq) if seq.intermediates().is_empty() => match seq.finalizer() {
+                "c" => *self = State::default(),
+                "7" => self.cursor_saved = true,
+                _ => {}
+            },
+
+            Sequence::CSI(csi) if csi.intermediates().is_empty() => {
+                let set = match (csi.private_marker(), csi.finalizer()) {
+                    (None, "s") => {
+                        self.cursor_saved = true;
+                        return;
+                    }
+
+                    (None | Some('?'), "h") => true,
+                    (None | Some('?'), "l") => false,
+                    _ => return,
+                };
+
+                for param in csi.params() {
+                    if let Param::Int(mode) = param {
+                        self.update_mode(csi.private_marker(), mode, set);
+                    }
+                }
+            }
+
+            Sequence::OSC(_, string) => match OscCommand::from(string) {
+                OscCommand::Hyperlink { uri, .. } => self.link_open = !uri.is_empty(),
+                OscCommand::SetTitleAndIconName(_)
+                | OscCommand::SetIconName(_)
+                | OscCommand::SetTitle(_) => self.title_set = true,
+                _ => {}
+            },
+
+            _ => {}
+        }
+    }
+
+    fn update_mode(&mut self, marker: Option<char>, mode: u16, set: bool) {
+        match self.modes.iter_mut().find(|m| m.0 == marker && m.1 == mode) {
+            Some(changed) => changed.3 = set,
+            None => self.modes.push((marker, mode, !set, set)),
+        }
+    }
+
+    /// Checks if anything that was changed is still in effect.
+    fn in_effect(&self) -> bool {
+        self.link_open
+            || self.cursor_saved
+            || self.title_set
+            || self.modes.iter().any(|(_, _, before, after)| before != after)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::parallel::*;
+    use crate::Profile;
+
+    #[test]
+    fn split_at_resets() {
+        let input = "\x1B[31mab\x1B[m\nc\x1B[m\r\nd\x1Bce\x1B[0m\n";
+        assert_eq!(
+            split(input, 1),
+            vec!["\x1B[31mab\x1B[m\n", "c\x1B[m\r\n", "d", "\x1Bce\x1B[0m\n"]
+        );
+        assert_eq!(split(input, 12), vec!["\x1B[31mab\x1B[m\nc\x1B[m\r\n", "d\x1Bce\x1B[0m\n"]);
+        assert_eq!(split(input, 100), vec![input]);
+        assert_eq!(split("\x1B[31m\x1B[m\n", 0), vec!["\x1B[31m\x1B[m\n"]);
+
+        // Resets inside other sequences aren't boundaries.
+        let input = "a\x1BPtmux;\x1B\x1Bc\x1B\x1B[m\n\x1B\\b\x1B[m\nc";
+        assert_eq!(split(input, 1), vec!["a\x1BPtmux;\x1B\x1Bc\x1B\x1B[m\n\x1B\\b\x1B[m\n", "c"]);
+
+        // Nor are resets inside a hyperlink.
+        let input = "\x1B]8;;https://a\x07a\x1B[m\nb\x1B]8;;\x07\x1B[m\nc";
+        assert_eq!(
+            split(input, 1),
+            vec!["\x1B]8;;https://a\x07a\x1B[m\nb\x1B]8;;\x07\x1B[m\n", "c"]
+        );
+    }
+
+    #[test]
+    fn split_with_state() {
+        // Nothing is split after a saved cursor, until a full reset.
+        let input = "\x1B7a\x1B[m\nb\x1B8\x1B[m\nc\x1Bcd\x1B[m\ne";
+        assert_eq!(split(input, 1), vec!["\x1B7a\x1B[m\nb\x1B8\x1B[m\nc", "\x1Bcd\x1B[m\n", "e"]);
+
+        // Or while a mode is changed.
+        let input = "\x1B[?25;1004la\x1B[m\nb\x1B[?25h\x1B[m\nc\x1B[?1004h\x1B[m\nd";
+        assert_eq!(
+            split(input, 1),
+            vec!["\x1B[?25;1004la\x1B[m\nb\x1B[?25h\x1B[m\nc\x1B[?1004h\x1B[m\n", "d"]
+        );
+
+        // Or after a title.
+        let input = "a\x1B[m\n\x1B]2;title\x07b\x1B[m\nc";
+        assert_eq!(split(input, 1), vec!["a\x1B[m\n", "\x1B]2;title\x07b\x1B[m\nc"]);
+    }
+
+    #[test]
+    fn optimize_chunks() {
+        let line = "\x1B[1m\x1B[32mok\x1B[0m \x1B[?25l\x1B[?25h\x1B[0m\n";
+        let input = format!("{}\x1B[31m", line.repeat(MIN_CHUNK_SIZE / 10));
+        let output = optimize_parallel(&input, &Options::default()).unwrap();
+        assert_eq!(output, optimize_with(&input, &Options::default()).unwrap());
+        assert_eq!(optimize_parallel("\x1B[", &Options::default()), Err(Error::Incomplete));
+    }
+
+    #[test]
+    fn optimize_saved_cursor() {
+        let padding = "x\x1B[m\n".repeat(MIN_CHUNK_SIZE);
+        let input = format!("\x1B[31m\x1B7\x1B[m\n{}\x1B8red\x1B[mplain\n", padding);
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let output = pool.install(|| optimize_parallel(&input, &Options::default())).unwrap();
+        assert_eq!(output, optimize_with(&input, &Options::default()).unwrap());
+        assert!(output.ends_with("\x1B8red\x1B[mplain\n"));
+    }
+
+    #[test]
+    fn optimize_footnotes() {
+        let options = Options {
+            hyperlinks: Hyperlinks::Footnotes,
+            profile: Profile { hyperlinks: false, ..Profile::default() },
+            ..Options::default()
+        };
+
+        let line = "\x1B]8;;https://example.com\x07link\x1B]8;;\x07\x1B[m\n";
+        let input = line.repeat(MIN_CHUNK_SIZE / 10);
+        let output = optimize_parallel(&input, &options).unwrap();
+        assert_eq!(output, optimize_with(&input, &options).unwrap());
+        assert_eq!(output.matches("https://example.com").count(), 1);
+    }
+}
@@ -0,0 +1,270 @@
+use std::fmt::Write;
+
+// -------------------------------------------------------------------------------------------------
+
+/// The number of removed sequences (and bytes in them) that are kept before being compacted away,
+/// even if they outnumber the sequences that remain.
+const COMPACT_MIN: usize = 64;
+
+/// The kind of a deferred sequence.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(crate) enum Kind {
+    /// A sequence that is never removed.
+    Other,
+
+    /// An SGR sequence.
+    Style,
+
+    /// A sequence that only moves the cursor.
+    CursorMove,
+
+    /// An ECH sequence that erases a number of characters.
+    Erase(u16),
+
+    /// A sequence that saves the cursor (DECSC or SCOSC).
+    SaveCursor,
+
+    /// A sequence that sets (`true`) or resets (`false`) a DEC private mode.
+    Mode(u16, bool),
+
+    /// An OSC sequence that sets the window title and/or icon name (OSC 0, 1, or 2).
+    Title(u8),
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    kind: Kind,
+    start: usize,
+    end: usize,
+    live: bool,
+
+    /// The input sequence that the sequence was created for, if it's being tracked.
+    source: Option<usize>,
+}
+
+/// Sequences that have been emitted since the last text, but not yet committed to the output.
+///
+/// Until text is written, the sequences have no visible effect. This allows sequences to be
+/// removed after the fact, if a later sequence makes them redundant.
+///
+/// Removed sequences are only marked as removed, and are compacted away once they make up most of
+/// the buffer. This keeps every method proportional to the number of sequences that remain.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Pending {
+    buffer: String,
+    entries: Vec<Entry>,
+
+    /// The number of entries that haven't been removed.
+    live: usize,
+
+    /// The number of bytes in the entries that haven't been removed.
+    live_bytes: usize,
+
+    /// The source of the sequences being appended.
+    source: Option<usize>,
+}
+
+impl Pending {
+    /// Appends a sequence.
+    pub fn push(&mut self, kind: Kind, sequence: impl std::fmt::Display) {
+        let start = self.buffer.len();
+        write!(self.buffer, "{}", sequence).expect("write to string");
+        self.push_written(kind, start);
+    }
+
+    /// Appends a sequence using a writer function.
+    pub fn push_with(&mut self, kind: Kind, write: impl FnOnce(&mut String) -> std::fmt::Result) {
+        let start = self.buffer.len();
+        write(&mut self.buffer).expect("write to string");
+        self.push_written(kind, start);
+    }
+
+    fn push_written(&mut self, kind: Kind, start: usize) {
+        let end = self.buffer.len();
+        if end > start {
+            self.live += 1;
+            self.live_bytes += end - start;
+            self.entries.push(Entry {
+                kind,
+                start,
+                end,
+                live: true,
+                source: self.source,
+            });
+        }
+    }
+
+    /// Gets the source of the sequences being appended.
+    #[inline]
+    pub fn source(&self) -> Option<usize> {
+        self.source
+    }
+
+    /// Sets the source of the sequences being appended.
+    #[inline]
+    pub fn set_source(&mut self, source: Option<usize>) {
+        self.source = source;
+    }
+
+    /// Removes every sequence matching a predicate.
+    ///
+    /// ## Returns
+    ///
+    /// The number of sequences removed.
+    pub fn remove_where(&mut self, predicate: impl Fn(Kind) -> bool) -> usize {
+        let live = self.live;
+        for entry in self.entries.iter_mut().filter(|e| e.live && predicate(e.kind)) {
+            entry.live = false;
+            self.live -= 1;
+            self.live_bytes -= entry.end - entry.start;
+        }
+
+        let removed = live - self.live;
+        self.compact();
+        removed
+    }
+
+    /// Gets the kinds of the sequences, most recent first.
+    pub fn kinds_rev(&self) -> impl Iterator<Item = Kind> + '_ {
+        self.entries.iter().rev().filter(|e| e.live).map(|e| e.kind)
+    }
+
+    /// Gets the kind of the most recent sequence.
+    #[inline]
+    pub fn last_kind(&self) -> Option<Kind> {
+        self.kinds_rev().next()
+    }
+
+    /// Gets the most recent sequence.
+    pub fn last(&self) -> Option<&str> {
+        let entry = self.entries.iter().rev().find(|e| e.live)?;
+        Some(&self.buffer[entry.start..entry.end])
+    }
+
+    /// Gets the sources of the most recent sequences, for as long as they are of a kind.
+    pub fn trailing_sources(&self, kind: Kind) -> impl Iterator<Item = usize> + '_ {
+        let entries = self.entries.iter().rev().filter(|e| e.live);
+        entries.take_while(move |e| e.kind == kind).filter_map(|e| e.source)
+    }
+
+    /// Removes the most recent sequences, for as long as they are of a kind.
+    pub fn pop_while(&mut self, kind: Kind) {
+        for entry in self.entries.iter_mut().rev().filter(|e| e.live) {
+            if entry.kind != kind {
+                break;
+            }
+
+            entry.live = false;
+            self.live -= 1;
+            self.live_bytes -= entry.end - entry.start;
+        }
+
+        self.compact();
+    }
+
+    /// Removes every sequence after the most recent sequence of a kind.
+    ///
+    /// ## Returns
+    ///
+    /// `false` if there was no sequence of that kind, or if any sequence after it doesn't match the
+    /// predicate. In that case, nothing is removed.
+    pub fn truncate_after(&mut self, kind: Kind, predicate: impl Fn(Kind) -> bool) -> bool {
+        let index = match self.entries.iter().rposition(|e| e.live && e.kind == kind) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        let after = &mut self.entries[(index + 1)..];
+        if !after.iter().filter(|e| e.live).all(|e| predicate(e.kind)) {
+            return false;
+        }
+
+        for entry in after.iter_mut().filter(|e| e.live) {
+            entry.live = false;
+            self.live -= 1;
+            self.live_bytes -= entry.end - entry.start;
+        }
+
+        self.compact();
+        true
+    }
+
+    /// Gets the number of bytes in the buffer, including removed sequences that haven't been
+    /// compacted away yet.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns `true` if there are no sequences.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    /// Writes the sequences.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> std::fmt::Result {
+        for entry in self.entries.iter().filter(|e| e.live) {
+            out.write_str(&self.buffer[entry.start..entry.end])?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the sequences to the output and clears them.
+    ///
+    /// ## Arguments
+    ///
+    /// - `out`: The output.
+    /// - `committed`: A function called with the kind, source, and text of each sequence that was
+    ///   written.
+    pub fn commit(
+        &mut self,
+        out: &mut String,
+        mut committed: impl FnMut(Kind, Option<usize>, &str),
+    ) {
+        for entry in self.entries.iter().filter(|e| e.live) {
+            let text = &self.buffer[entry.start..entry.end];
+            out.push_str(text);
+            committed(entry.kind, entry.source, text);
+        }
+
+        self.clear();
+    }
+
+    /// Removes all sequences.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.entries.clear();
+        self.live = 0;
+        self.live_bytes = 0;
+    }
+
+    /// Drops the removed sequences from the buffer, once there are more of them (or more bytes in
+    /// them) than there are in the sequences that remain.
+    fn compact(&mut self) {
+        if self.live == 0 {
+            return self.clear();
+        }
+
+        let removed = self.entries.len() - self.live;
+        let removed_bytes = self.buffer.len() - self.live_bytes;
+        if removed <= self.live.max(COMPACT_MIN)
+            && removed_bytes <= self.live_bytes.max(COMPACT_MIN)
+        {
+            return;
+        }
+
+        let mut buffer = String::with_capacity(self.live_bytes);
+        self.entries.retain(|e| e.live);
+        for entry in &mut self.entries {
+            let start = buffer.len();
+            buffer.push_str(&self.buffer[entry.start..entry.end]);
+            entry.start = start;
+            entry.end = buffer.len();
+        }
+
+        self.buffer = buffer;
+    }
+}
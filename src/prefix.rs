@@ -0,0 +1,175 @@
+//! Adding a prefix to each line of a stream, like a timestamp or the name of a service.
+//!
+//! Prefixing lines by writing text before them is easy to get wrong: the prefix is written in
+//! whatever style the previous line left the terminal in, and the line loses its style if the
+//! prefix changes it. A [LinePrefixer] writes the prefix in a style of its own, and changes the
+//! style back to the line's with the shortest sequence.
+//!
+//! ```
+//! # use ansi_optimizer::prefix::LinePrefixer;
+//! # use ansi_optimizer::style::Attributes;
+//! # use ansi_optimizer::{Options, Style};
+//! let style = Style { attributes: Attributes::FAINT, ..Style::default() };
+//! let mut prefixer = LinePrefixer::new(Options::default(), style, |out| out.push_str("web | "));
+//!
+//! let mut output = String::new();
+//! output += prefixer.feed("\x1B[31mfailed\n");
+//! output += prefixer.feed("retrying\x1B[m\n");
+//! output += prefixer.finish();
+//!
+//! assert_eq!(output, "\x1B[2mweb | \x1B[0;31mfailed\n\x1B[0;2mweb | \x1B[0;31mretrying\x1B[m\n");
+//! ```
+
+use crate::token::{Token, Tokens};
+use crate::{Error, Optimizer, Options, Style};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Optimizes a stream, and adds a prefix to the start of each line.
+///
+/// The prefix is written by a function when the first text of a line arrives, so a timestamp
+/// shows when the line started. Escape sequences at the end of the stream don't start a line of
+/// their own. The prefix should only write text, since it isn't parsed for escape sequences.
+pub struct LinePrefixer<F> {
+    optimizer: Optimizer,
+    style: Style,
+    prefix: F,
+
+    /// The prefix written by the function, which is reused between lines.
+    buffer: String,
+
+    /// Whether the next input starts a new line.
+    line_start: bool,
+}
+
+impl<F: FnMut(&mut String)> LinePrefixer<F> {
+    /// Creates a prefixer that writes prefixes in the provided style.
+    pub fn new(options: Options, style: Style, prefix: F) -> Self {
+        LinePrefixer {
+            optimizer: Optimizer::with_options(options),
+            style,
+            prefix,
+            buffer: String::new(),
+            line_start: true,
+        }
+    }
+
+    /// Updates the prefixer with the next chunk of a stream. See [Optimizer::feed].
+    ///
+    /// ## Returns
+    ///
+    /// The output that is ready to be written.
+    pub fn feed(&mut self, chunk: &str) -> &str {
+        self.optimizer.output.clear();
+        for line in chunk.split_inclusive('\n') {
+            let mut rest = line;
+            if self.line_start {
+                let start = match self.text_start(line) {
+                    Some(start) => start,
+                    None => {
+                        self.optimizer.feed(line);
+                        continue;
+                    }
+                };
+
+                self.optimizer.feed(&line[..start]);
+                self.buffer.clear();
+                (self.prefix)(&mut self.buffer);
+                self.optimizer.write_styled(&self.buffer, self.style);
+                rest = &line[start..];
+            }
+
+            self.optimizer.feed(rest);
+            self.line_start = line.ends_with('\n');
+        }
+
+        &self.optimizer.output
+    }
+
+    /// Emits everything that is being held back. See [Optimizer::flush].
+    ///
+    /// ## Returns
+    ///
+    /// The output that is ready to be written.
+    pub fn flush(&mut self) -> &str {
+        self.optimizer.output.clear();
+        self.optimizer.flush();
+        &self.optimizer.output
+    }
+
+    /// Finishes the stream. See [Optimizer::finish].
+    ///
+    /// The prefixer can be used for a new stream afterwards.
+    ///
+    /// ## Returns
+    ///
+    /// The output that is ready to be written.
+    pub fn finish(&mut self) -> &str {
+        self.optimizer.output.clear();
+        self.optimizer.finish();
+        self.line_start = true;
+        &self.optimizer.output
+    }
+
+    /// Finds where the text of a line starts, after any escape sequences before it.
+    ///
+    /// The line continues the sequence that the optimizer is holding back, if there is one.
+    /// Invalid sequences are written as text, so they start the line too.
+    fn text_start(&self, line: &str) -> Option<usize> {
+        let partial = self.optimizer.partial.as_str();
+        let joined;
+        let input = match partial {
+            "" => line,
+            partial => {
+                joined = [partial, line].concat();
+                &joined
+            }
+        };
+
+        let (span, _) = Tokens::new(input)
+            .spanned()
+            .find(|(_, token)| !matches!(token, Ok(Token::Sequence(_)) | Err(Error::Incomplete)))?;
+
+        Some(span.start.saturating_sub(partial.len()))
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::prefix::*;
+    use crate::Color;
+
+    #[test]
+    fn prefix_lines() {
+        let mut line = 0;
+        let mut prefixer = LinePrefixer::new(Options::default(), Style::default(), |out| {
+            line += 1;
+            out.push_str(&format!("{}: ", line));
+        });
+
+        assert_eq!(prefixer.feed(""), "");
+        assert_eq!(prefixer.feed("a\nb"), "1: a\n2: b");
+        assert_eq!(prefixer.feed("c\n"), "c\n");
+        assert_eq!(prefixer.feed("\n"), "3: \n");
+        assert_eq!(prefixer.finish(), "");
+
+        // Sequences don't start a line until its text arrives.
+        assert_eq!(prefixer.feed("line\n\x1B[m"), "4: line\n");
+        assert_eq!(prefixer.finish(), "");
+        assert_eq!(prefixer.feed("\x1B]0;title\x07\x1B[3"), "");
+        assert_eq!(prefixer.feed("1m"), "");
+        assert_eq!(prefixer.feed("a\n"), "\x1B]0;title\x075: \x1B[31ma\n");
+    }
+
+    #[test]
+    fn prefix_keeps_line_style() {
+        let style = Style { foreground: Color::Indexed(4), ..Style::default() };
+        let mut prefixer = LinePrefixer::new(Options::default(), style, |out| out.push('>'));
+        assert_eq!(prefixer.feed("\x1B[1;31ma\n"), "\x1B[34m>\x1B[1;31ma\n");
+        assert_eq!(prefixer.feed("b\x1B[m\n\x1B[4m"), "\x1B[0;34m>\x1B[1;31mb\x1B[m\n");
+        assert_eq!(prefixer.feed("c"), "\x1B[34m>\x1B[0;4mc");
+        assert_eq!(prefixer.finish(), "");
+    }
+}
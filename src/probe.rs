@@ -0,0 +1,237 @@
+//! Asking a live terminal what it supports.
+//!
+//! This is behind the `pty` feature, and is only available on Unix. Tools that talk to the
+//! terminal directly (e.g. a proxy like [pty::run](crate::pty::run)) can use it to create a
+//! [Profile] that matches the terminal exactly, instead of guessing from the environment.
+//!
+//! The terminal is asked for its terminfo capabilities (XTGETTCAP), the state of some modes
+//! (DECRQM), and its primary device attributes (DA1). Every terminal replies to DA1, and replies
+//! come in the order the queries were sent, so once its reply arrives there are no more to wait
+//! for. Terminals that ignore the other queries are still probed quickly.
+//!
+//! ```no_run
+//! # use ansi_optimizer::probe::probe;
+//! # use std::time::Duration;
+//! let probe = probe(Duration::from_millis(200))?;
+//! println!("{:?}", probe.profile());
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::ansi::Sequence;
+use crate::profile::{ColorSupport, Profile};
+use crate::pty::{poll, poll_fd, read_fd, RawMode};
+use crate::token::{Token, Tokens};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+// -------------------------------------------------------------------------------------------------
+
+/// The terminfo capabilities that are asked for.
+const CAPABILITIES: [&str; 4] = ["RGB", "Tc", "colors", "rep"];
+
+/// The DEC private modes that are asked about: bracketed paste and synchronized output.
+const MODES: [u16; 2] = [2004, 2026];
+
+/// What a terminal replied to the queries.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Probe {
+    /// The primary device attributes (DA1), starting with the conformance level (e.g. `64` for a
+    /// VT420). Empty if the terminal didn't reply.
+    pub attributes: Vec<u16>,
+
+    /// The terminfo capabilities that the terminal has, and their values.
+    /// Boolean capabilities have empty values.
+    pub capabilities: Vec<(String, String)>,
+
+    /// The DEC private modes that the terminal recognizes, and whether they're set.
+    pub modes: Vec<(u16, bool)>,
+}
+
+impl Probe {
+    /// Gets the queries that are written to the terminal, ending with DA1.
+    pub fn queries() -> String {
+        let mut queries = String::new();
+        for name in CAPABILITIES.iter() {
+            queries.push_str("\x1BP+q");
+            queries.extend(name.bytes().map(|b| format!("{:02X}", b)));
+            queries.push_str("\x1B\\");
+        }
+
+        for mode in MODES.iter() {
+            queries.push_str(&format!("\x1B[?{}$p", mode));
+        }
+
+        queries.push_str("\x1B[c");
+        queries
+    }
+
+    /// Parses the replies to the [queries](Probe::queries). Anything else is ignored.
+    pub fn parse(replies: &str) -> Self {
+        let mut probe = Probe::default();
+        for token in Tokens::new(replies) {
+            match token {
+                Ok(Token::Sequence(Sequence::DCS(_, string))) => {
+                    if let Some(capability) = string.text().strip_prefix("1+r") {
+                        probe.capabilities.extend(parse_capability(capability));
+                    }
+                }
+                Ok(Token::Sequence(Sequence::CSI(csi))) if csi.private_marker() == Some('?') => {
+                    let mut params = csi.params().map(|param| param.value_or(0));
+                    match (csi.intermediates(), csi.finalizer()) {
+                        ("", "c") => probe.attributes = params.collect(),
+                        ("$", "y") => match (params.next(), params.next()) {
+                            (Some(mode), Some(1 | 3)) => probe.modes.push((mode, true)),
+                            (Some(mode), Some(2 | 4)) => probe.modes.push((mode, false)),
+                            _ => {}
+                        },
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        probe
+    }
+
+    /// Gets the value of a capability, if the terminal has it.
+    pub fn capability(&self, name: &str) -> Option<&str> {
+        self.capabilities.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Creates a profile for the terminal.
+    ///
+    /// If the terminal didn't say how many colors it supports, colors aren't converted.
+    pub fn profile(&self) -> Profile {
+        let colors = self.capability("colors").and_then(|colors| colors.parse::<u32>().ok());
+        let colors = match colors {
+            _ if self.capability("RGB").is_some() || self.capability("Tc").is_some() => {
+                ColorSupport::TrueColor
+            }
+            Some(256..=u32::MAX) => ColorSupport::Ansi256,
+            Some(8..=255) => ColorSupport::Ansi16,
+            Some(_) => ColorSupport::Monochrome,
+            None => ColorSupport::TrueColor,
+        };
+
+        Profile { colors, repeat: self.capability("rep").is_some(), ..Profile::default() }
+    }
+}
+
+/// Probes the controlling terminal (`/dev/tty`), waiting at most `timeout` for it to reply.
+///
+/// See [probe_tty].
+pub fn probe(timeout: Duration) -> io::Result<Probe> {
+    let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    probe_tty(&mut tty, timeout)
+}
+
+/// Probes a terminal, waiting at most `timeout` for it to reply.
+///
+/// The terminal is put into raw mode while probing. Anything typed while it's probed is lost,
+/// and replies that arrive after the timeout are read as input by whatever reads next.
+pub fn probe_tty(tty: &mut File, timeout: Duration) -> io::Result<Probe> {
+    let fd = tty.as_raw_fd();
+    let _raw_mode = match RawMode::enable(fd) {
+        Some(raw_mode) => raw_mode,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a terminal")),
+    };
+
+    tty.write_all(Probe::queries().as_bytes())?;
+    tty.flush()?;
+
+    let deadline = Instant::now() + timeout;
+    let mut replies = Vec::new();
+    let mut buffer = [0; 1024];
+    loop {
+        let probe = Probe::parse(&String::from_utf8_lossy(&replies));
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if !probe.attributes.is_empty() || !poll(&mut [poll_fd(Some(fd))], Some(remaining))? {
+            return Ok(probe);
+        }
+
+        match read_fd(fd, &mut buffer)? {
+            0 => return Ok(probe),
+            n => replies.extend_from_slice(&buffer[..n]),
+        }
+    }
+}
+
+/// Parses a capability from an XTGETTCAP reply (`hex-name=hex-value`).
+fn parse_capability(reply: &str) -> Option<(String, String)> {
+    let (name, value) = match reply.find('=') {
+        Some(i) => (&reply[..i], &reply[(i + 1)..]),
+        None => (reply, ""),
+    };
+
+    Some((decode_hex(name)?, decode_hex(value)?))
+}
+
+/// Decodes a hex-encoded string.
+fn decode_hex(hex: &str) -> Option<String> {
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..(i + 2)).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect::<Option<Vec<_>>>()?;
+
+    String::from_utf8(bytes).ok()
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::probe::*;
+    use crate::pty::open_pty;
+    use std::io::Read;
+
+    #[test]
+    fn parse_replies() {
+        let replies = "\x1BP1+r636F6C6F7273=323536\x1B\\\x1BP0+r\x1B\\\x1BP1+r524742\x1B\\\
+                       \x1B[?2004;2$y\x1B[?2026;0$yx\x1B[?64;1;22c";
+
+        let probe = Probe::parse(replies);
+        assert_eq!(probe.attributes, [64, 1, 22]);
+        assert_eq!(probe.capability("colors"), Some("256"));
+        assert_eq!(probe.capability("RGB"), Some(""));
+        assert_eq!(probe.capability("rep"), None);
+        assert_eq!(probe.modes, [(2004, false)]);
+        assert_eq!(probe.profile().colors, ColorSupport::TrueColor);
+    }
+
+    #[test]
+    fn create_profiles() {
+        let probe = Probe::parse("\x1BP1+r636F6C6F7273=38\x1B\\\x1BP1+r726570=1B\x1B\\");
+        let profile = probe.profile();
+        assert_eq!(profile.colors, ColorSupport::Ansi16);
+        assert!(profile.repeat);
+
+        assert_eq!(Probe::parse("\x1B[?1;2c").profile(), Profile::default());
+        assert!(Probe::queries().starts_with("\x1BP+q524742\x1B\\\x1BP+q5463\x1B\\"));
+        assert!(Probe::queries().ends_with("\x1B[?2026$p\x1B[c"));
+    }
+
+    #[test]
+    fn probe_terminal() {
+        let (mut master, mut slave) = open_pty(None).unwrap();
+        let terminal = std::thread::spawn(move || {
+            let mut queries = Vec::new();
+            let mut buffer = [0; 256];
+            while !queries.ends_with(b"\x1B[c") {
+                let n = master.read(&mut buffer).unwrap();
+                queries.extend_from_slice(&buffer[..n]);
+            }
+
+            master.write_all(b"\x1BP1+r636F6C6F7273=323536\x1B\\\x1B[?62;22c").unwrap();
+            (master, queries)
+        });
+
+        let probe = probe_tty(&mut slave, Duration::from_secs(5)).unwrap();
+        let (_master, queries) = terminal.join().unwrap();
+        assert_eq!(String::from_utf8(queries).unwrap(), Probe::queries());
+        assert_eq!(probe.attributes, [62, 22]);
+        assert_eq!(probe.profile().colors, ColorSupport::Ansi256);
+    }
+}
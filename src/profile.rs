@@ -0,0 +1,250 @@
+// -------------------------------------------------------------------------------------------------
+
+/// The colors that a terminal supports.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum ColorSupport {
+    /// No colors. Other styles, such as bold or underline, are still supported.
+    Monochrome,
+
+    /// The 8 standard colors and their bright variants.
+    Ansi16,
+
+    /// The 256-color palette.
+    Ansi256,
+
+    /// 24-bit RGB colors.
+    TrueColor,
+}
+
+impl Default for ColorSupport {
+    #[inline]
+    fn default() -> Self {
+        ColorSupport::TrueColor
+    }
+}
+
+/// The capabilities of the terminal that the output is intended for.
+///
+/// The default profile doesn't remove or convert anything, but also doesn't assume support for
+/// less common sequences that aren't already in the input.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Profile {
+    /// The colors that the terminal supports.
+    /// Colors that aren't supported are converted to the nearest supported color.
+    pub colors: ColorSupport,
+
+    /// Whether the terminal supports REP (`CSI n b`), which repeats the last printed character.
+    pub repeat: bool,
+
+    /// Whether the terminal shows bold text with the 8 standard colors using their bright variants
+    /// (e.g. `1;31` looks the same as `1;91`).
+    ///
+    /// This lets the optimizer skip color changes that wouldn't be visible. Terminals differ, so it
+    /// is never assumed.
+    pub bold_is_bright: bool,
+
+    /// What SGR 21 (`CSI 21 m`) does on the terminal.
+    pub sgr21: Sgr21,
+
+    /// How the terminal expects 256-color and RGB colors to be written.
+    /// Colors are read in either form, and written in this one.
+    pub color_encoding: ColorEncoding,
+
+    /// Whether the terminal supports hyperlinks (`OSC 8`). If not, they're written as the
+    /// [Options::hyperlinks](crate::Options::hyperlinks) option says.
+    pub hyperlinks: bool,
+
+    /// Whether the output isn't shown with any styles (e.g. because it's written to a file).
+    /// Every style change is removed.
+    pub plain: bool,
+}
+
+impl Profile {
+    /// The default profile, for use in constants.
+    pub(crate) const DEFAULT: Profile = Profile {
+        colors: ColorSupport::TrueColor,
+        repeat: false,
+        bold_is_bright: false,
+        sgr21: Sgr21::DoubleUnderline,
+        color_encoding: ColorEncoding::Semicolons,
+        hyperlinks: true,
+        plain: false,
+    };
+
+    /// A profile for xterm and terminals that closely emulate it.
+    pub fn xterm() -> Self {
+        Profile {
+            colors: ColorSupport::TrueColor,
+            repeat: true,
+            bold_is_bright: false,
+            sgr21: Sgr21::DoubleUnderline,
+            color_encoding: ColorEncoding::Semicolons,
+            hyperlinks: true,
+            plain: false,
+        }
+    }
+
+    /// A profile for output that isn't shown with any styles or hyperlinks.
+    pub fn plain() -> Self {
+        Profile {
+            colors: ColorSupport::Monochrome,
+            hyperlinks: false,
+            plain: true,
+            ..Profile::default()
+        }
+    }
+
+    /// Detects the profile of the terminal that stdout is written to.
+    ///
+    /// See [Profile::detect_with].
+    pub fn detect() -> Self {
+        use std::io::IsTerminal;
+        Profile::detect_with(|name| std::env::var(name).ok(), std::io::stdout().is_terminal())
+    }
+
+    /// Detects the profile of a terminal from environment variables, as command-line tools
+    /// usually do.
+    ///
+    /// - If the output isn't a terminal, or `TERM` is `dumb`, the [plain](Profile::plain) profile
+    ///   is used unless `CLICOLOR_FORCE` is set (to anything but `0`).
+    /// - The colors are guessed from `TERM` (e.g. `xterm-256color`), and from the terminfo
+    ///   database if the `terminfo` feature is enabled.
+    /// - If `COLORTERM` is `truecolor` or `24bit`, RGB colors are supported.
+    /// - If `NO_COLOR` is set (to anything but an empty string), colors are removed.
+    ///
+    /// ```
+    /// # use ansi_optimizer::{ColorSupport, Profile};
+    /// let env = |name: &str| match name {
+    ///     "TERM" => Some("xterm-256color".to_owned()),
+    ///     "COLORTERM" => Some("truecolor".to_owned()),
+    ///     _ => None,
+    /// };
+    ///
+    /// assert_eq!(Profile::detect_with(env, true).colors, ColorSupport::TrueColor);
+    /// assert_eq!(Profile::detect_with(env, false), Profile::plain());
+    /// ```
+    pub fn detect_with(var: impl Fn(&str) -> Option<String>, is_terminal: bool) -> Self {
+        let var = |name| var(name).filter(|value| !value.is_empty());
+        let forced = matches!(var("CLICOLOR_FORCE").as_deref(), Some(value) if value != "0");
+        let term = var("TERM").unwrap_or_default();
+        if !forced && (!is_terminal || term == "dumb") {
+            return Profile::plain();
+        }
+
+        let mut profile = Profile::from_term(&term);
+        if let Some("truecolor" | "24bit") = var("COLORTERM").as_deref() {
+            profile.colors = ColorSupport::TrueColor;
+        }
+
+        if var("NO_COLOR").is_some() {
+            profile.colors = ColorSupport::Monochrome;
+        }
+
+        profile
+    }
+
+    /// Guesses the profile of a terminal from its name.
+    fn from_term(term: &str) -> Self {
+        #[cfg(feature = "terminfo")]
+        if let Ok(info) = crate::terminfo::Terminfo::load(term) {
+            return info.profile();
+        }
+
+        let colors = match term {
+            term if term.ends_with("-direct") || term.ends_with("-truecolor") => {
+                ColorSupport::TrueColor
+            }
+            term if term.contains("256color") => ColorSupport::Ansi256,
+            _ => ColorSupport::Ansi16,
+        };
+
+        Profile { colors, ..Profile::default() }
+    }
+}
+
+impl Default for Profile {
+    #[inline]
+    fn default() -> Self {
+        Profile::DEFAULT
+    }
+}
+
+/// How 256-color and RGB colors are written. See [Profile::color_encoding].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ColorEncoding {
+    /// Separated by semicolons (e.g. `38;5;208` or `38;2;255;135;0`), which most terminals and
+    /// multiplexers accept.
+    Semicolons,
+
+    /// Separated by colons as in ITU T.416, with an empty color space ID for RGB colors (e.g.
+    /// `38:5:208` or `38:2::255:135:0`).
+    Colons,
+}
+
+impl Default for ColorEncoding {
+    #[inline]
+    fn default() -> Self {
+        ColorEncoding::Semicolons
+    }
+}
+
+/// What SGR 21 does on a terminal. See [Profile::sgr21].
+///
+/// ECMA-48 defines it as a double underline, which is what modern terminals do. Some older ones
+/// (e.g. the Linux console before 4.17) use it to turn bold off instead, like SGR 22.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Sgr21 {
+    /// It starts a double underline.
+    DoubleUnderline,
+
+    /// It turns bold and faint off.
+    NormalIntensity,
+
+    /// It isn't known. SGR sequences with it are written unchanged, and double underlines are
+    /// written as `4:2` instead.
+    Ambiguous,
+}
+
+impl Default for Sgr21 {
+    #[inline]
+    fn default() -> Self {
+        Sgr21::DoubleUnderline
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::profile::*;
+
+    fn detect(vars: &[(&str, &str)], is_terminal: bool) -> Profile {
+        let var = |name: &str| {
+            vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string())
+        };
+
+        Profile::detect_with(var, is_terminal)
+    }
+
+    #[test]
+    fn detect_profiles() {
+        assert_eq!(detect(&[("TERM", "xterm-256color")], false), Profile::plain());
+        assert_eq!(detect(&[("TERM", "dumb")], true), Profile::plain());
+        assert_eq!(detect(&[("TERM", "xterm-256color")], true).colors, ColorSupport::Ansi256);
+        assert_eq!(detect(&[("TERM", "unknown-term")], true).colors, ColorSupport::Ansi16);
+
+        let vars = [("TERM", "xterm-256color"), ("COLORTERM", "truecolor")];
+        assert_eq!(detect(&vars, true).colors, ColorSupport::TrueColor);
+
+        let vars = [("TERM", "xterm-256color"), ("CLICOLOR_FORCE", "1")];
+        assert_eq!(detect(&vars, false).colors, ColorSupport::Ansi256);
+
+        let vars = [("TERM", "xterm-256color"), ("CLICOLOR_FORCE", "0")];
+        assert_eq!(detect(&vars, false), Profile::plain());
+
+        let vars = [("TERM", "xterm-256color"), ("COLORTERM", "24bit"), ("NO_COLOR", "1")];
+        let profile = detect(&vars, true);
+        assert_eq!(profile.colors, ColorSupport::Monochrome);
+        assert!(!profile.plain);
+    }
+}
@@ -0,0 +1,364 @@
+//! Running a command in a pseudoterminal, and optimizing its output.
+//!
+//! This is behind the `pty` feature, and is only available on Unix. The command's output is fed
+//! through an [Optimizer] as it arrives, and is written whenever nothing more arrives within
+//! [Options::max_buffered_time]. Input is forwarded to the command unchanged, and the
+//! pseudoterminal is resized along with the terminal.
+//!
+//! ```no_run
+//! # use ansi_optimizer::{pty, Options};
+//! # use std::process::Command;
+//! let status = pty::run(&mut Command::new("htop"), Options::default())?;
+//! std::process::exit(status.code().unwrap_or(1));
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::bytes::ByteOptimizer;
+use crate::{Optimizer, Options};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant};
+
+// -------------------------------------------------------------------------------------------------
+
+/// How long output is held back if [Options::max_buffered_time] isn't set.
+/// Without a limit, the output of an interactive command wouldn't appear until it wrote text.
+const DEFAULT_MAX_BUFFERED_TIME: Duration = Duration::from_millis(5);
+
+/// The write end of the pipe that `SIGWINCH` is reported through, or -1.
+static RESIZED: AtomicI32 = AtomicI32::new(-1);
+
+// -------------------------------------------------------------------------------------------------
+
+/// Runs a command in a pseudoterminal, writing its optimized output to stdout.
+///
+/// If stdin is a terminal, it's put into raw mode until the command exits, and its size is used
+/// for the [Options::columns] and [Options::rows].
+///
+/// ## Returns
+///
+/// The exit status of the command.
+pub fn run(command: &mut Command, options: Options) -> io::Result<ExitStatus> {
+    let _raw_mode = RawMode::enable(libc::STDIN_FILENO);
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    proxy(command, options, Some(libc::STDIN_FILENO), &mut stdout)
+}
+
+/// Runs a command in a pseudoterminal, forwarding `input` to it and writing its optimized output
+/// to `output`.
+fn proxy(
+    command: &mut Command,
+    mut options: Options,
+    input: Option<RawFd>,
+    output: &mut dyn Write,
+) -> io::Result<ExitStatus> {
+    let size = input.and_then(window_size);
+    if let Some(size) = &size {
+        options.columns = Some(size.ws_col);
+        options.rows = Some(size.ws_row);
+    }
+
+    if options.max_buffered_time.is_none() {
+        options.max_buffered_time = Some(DEFAULT_MAX_BUFFERED_TIME);
+    }
+
+    let (mut master, slave) = open_pty(size)?;
+    let mut child = spawn(command, slave)?;
+    let resized = ResizeSignal::install()?;
+
+    let mut stream = ByteOptimizer::new(Optimizer::with_options(options));
+    let mut input = input;
+    let mut buffer = [0u8; 8192];
+    loop {
+        let timeout =
+            stream.optimizer.deadline().map(|d| d.saturating_duration_since(Instant::now()));
+        let mut fds =
+            [poll_fd(Some(master.as_raw_fd())), poll_fd(Some(resized.read)), poll_fd(input)];
+
+        if !poll(&mut fds, timeout)? {
+            stream.flush();
+            write_output(&mut stream, output)?;
+            continue;
+        }
+
+        if fds[1].revents != 0 {
+            resized.drain();
+            if let Some(size) = input.and_then(window_size) {
+                set_window_size(master.as_raw_fd(), &size)?;
+                stream.optimizer.resize(size.ws_col, size.ws_row);
+                stream.flush();
+                write_output(&mut stream, output)?;
+            }
+        }
+
+        if let (Some(fd), true) = (input, fds[2].revents != 0) {
+            match read_fd(fd, &mut buffer)? {
+                0 => input = None,
+                n => master.write_all(&buffer[..n])?,
+            }
+        }
+
+        if fds[0].revents != 0 {
+            match master.read(&mut buffer) {
+                // Linux reports EIO once every copy of the slave has been closed.
+                Ok(0) => break,
+                Err(error) if error.raw_os_error() == Some(libc::EIO) => break,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+                Ok(n) => {
+                    stream.feed(&buffer[..n]);
+                    write_output(&mut stream, output)?;
+                }
+            }
+        }
+    }
+
+    stream.finish();
+    write_output(&mut stream, output)?;
+    child.wait()
+}
+
+/// Writes the output of the optimizer.
+fn write_output(stream: &mut ByteOptimizer, output: &mut dyn Write) -> io::Result<()> {
+    if !stream.output.is_empty() {
+        output.write_all(&stream.output)?;
+        output.flush()?;
+        stream.output.clear();
+    }
+
+    Ok(())
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Opens a pseudoterminal.
+///
+/// ## Returns
+///
+/// The master and slave ends of it.
+pub(crate) fn open_pty(size: Option<libc::winsize>) -> io::Result<(File, File)> {
+    let (mut master, mut slave) = (-1, -1);
+    let mut size = size;
+    let size_ptr = size.as_mut().map_or(std::ptr::null_mut(), |size| size as *mut _);
+    let result = unsafe {
+        libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null_mut(), size_ptr)
+    };
+
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let (master, slave) = unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+    set_cloexec(master.as_raw_fd())?;
+    set_cloexec(slave.as_raw_fd())?;
+    Ok((master, slave))
+}
+
+/// Spawns a command with a pseudoterminal as its controlling terminal and standard streams.
+fn spawn(command: &mut Command, slave: File) -> io::Result<std::process::Child> {
+    command.stdin(Stdio::from(slave.try_clone()?));
+    command.stdout(Stdio::from(slave.try_clone()?));
+    command.stderr(Stdio::from(slave));
+
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    }
+
+    let child = command.spawn()?;
+
+    // The command keeps its copies of the slave until it's reconfigured. They need to be closed,
+    // so that reading from the master fails once the child exits.
+    command.stdin(Stdio::inherit()).stdout(Stdio::inherit()).stderr(Stdio::inherit());
+    Ok(child)
+}
+
+/// Gets the size of a terminal.
+fn window_size(fd: RawFd) -> Option<libc::winsize> {
+    let mut size = libc::winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+    match unsafe { libc::ioctl(fd, libc::TIOCGWINSZ as _, &mut size) } {
+        0 if size.ws_col > 0 && size.ws_row > 0 => Some(size),
+        _ => None,
+    }
+}
+
+/// Sets the size of a terminal.
+fn set_window_size(fd: RawFd, size: &libc::winsize) -> io::Result<()> {
+    match unsafe { libc::ioctl(fd, libc::TIOCSWINSZ as _, size) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+fn set_cloexec(fd: RawFd) -> io::Result<()> {
+    match unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+pub(crate) fn read_fd(fd: RawFd, buffer: &mut [u8]) -> io::Result<usize> {
+    loop {
+        match unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut _, buffer.len()) } {
+            n if n >= 0 => return Ok(n as usize),
+            _ => match io::Error::last_os_error() {
+                error if error.kind() == io::ErrorKind::Interrupted => {}
+                error => return Err(error),
+            },
+        }
+    }
+}
+
+pub(crate) fn poll_fd(fd: Option<RawFd>) -> libc::pollfd {
+    // Negative file descriptors are ignored by poll.
+    libc::pollfd { fd: fd.unwrap_or(-1), events: libc::POLLIN, revents: 0 }
+}
+
+/// Waits until one of the file descriptors is ready, or the timeout passes.
+///
+/// ## Returns
+///
+/// `false` if the timeout passed.
+pub(crate) fn poll(fds: &mut [libc::pollfd], timeout: Option<Duration>) -> io::Result<bool> {
+    // The timeout is rounded up, so it doesn't wake up just before the deadline.
+    let timeout = match timeout {
+        None => -1,
+        Some(timeout) => {
+            (timeout + Duration::from_micros(999)).as_millis().min(i32::MAX as u128) as i32
+        }
+    };
+
+    loop {
+        match unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) } {
+            0 => return Ok(false),
+            n if n > 0 => return Ok(true),
+            _ => match io::Error::last_os_error() {
+                error if error.kind() == io::ErrorKind::Interrupted => {}
+                error => return Err(error),
+            },
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Puts a terminal into raw mode, restoring its previous mode when dropped.
+pub(crate) struct RawMode {
+    fd: RawFd,
+    termios: libc::termios,
+}
+
+impl RawMode {
+    /// ## Returns
+    ///
+    /// `None` if the file descriptor isn't a terminal.
+    pub(crate) fn enable(fd: RawFd) -> Option<RawMode> {
+        let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(fd, &mut termios) } < 0 {
+            return None;
+        }
+
+        let mut raw = termios;
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            libc::tcsetattr(fd, libc::TCSANOW, &raw);
+        }
+
+        Some(RawMode { fd, termios })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.termios) };
+    }
+}
+
+/// A handler for `SIGWINCH` that makes a pipe readable when the terminal is resized, so it can be
+/// waited for along with the output. The previous handler is restored when dropped.
+struct ResizeSignal {
+    read: RawFd,
+    write: RawFd,
+    previous: libc::sigaction,
+}
+
+impl ResizeSignal {
+    fn install() -> io::Result<ResizeSignal> {
+        let mut fds = [-1; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let [read, write] = fds;
+        for &fd in &fds {
+            set_cloexec(fd)?;
+            unsafe { libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK) };
+        }
+
+        RESIZED.store(write, Ordering::SeqCst);
+
+        let mut previous = unsafe { std::mem::zeroed::<libc::sigaction>() };
+        let mut action = unsafe { std::mem::zeroed::<libc::sigaction>() };
+        action.sa_sigaction = on_resize as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        if unsafe { libc::sigaction(libc::SIGWINCH, &action, &mut previous) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(ResizeSignal { read, write, previous })
+    }
+
+    /// Reads everything written by the signal handler.
+    fn drain(&self) {
+        let mut buffer = [0u8; 64];
+        while unsafe { libc::read(self.read, buffer.as_mut_ptr() as *mut _, buffer.len()) } > 0 {}
+    }
+}
+
+impl Drop for ResizeSignal {
+    fn drop(&mut self) {
+        unsafe {
+            libc::sigaction(libc::SIGWINCH, &self.previous, std::ptr::null_mut());
+            RESIZED.store(-1, Ordering::SeqCst);
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+extern "C" fn on_resize(_: libc::c_int) {
+    let fd = RESIZED.load(Ordering::SeqCst);
+    if fd >= 0 {
+        unsafe { libc::write(fd, b"\0".as_ptr() as *const _, 1) };
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::pty::*;
+
+    #[test]
+    fn proxy_optimizes_output() {
+        let mut command = Command::new("printf");
+        command.arg("\\033[1m\\033[1mbold\\033[0m\\033[0m\\n");
+
+        let mut output = Vec::new();
+        let status = proxy(&mut command, Options::default(), None, &mut output).unwrap();
+        assert!(status.success());
+
+        // The pseudoterminal translates "\n" to "\r\n".
+        assert_eq!(String::from_utf8(output).unwrap(), "\x1B[1mbold\x1B[m\r\n");
+    }
+}
@@ -0,0 +1,137 @@
+//! Python bindings, using PyO3.
+//!
+//! This is behind the `python` feature, and builds an extension module named `ansi_optimizer`
+//! (e.g. with `maturin build --features python`).
+//!
+//! ```python
+//! import ansi_optimizer
+//!
+//! ansi_optimizer.optimize("\x1b[31m\x1b[1mred")  # "\x1b[1;31mred"
+//! ansi_optimizer.strip("\x1b[31mred")  # "red"
+//! ansi_optimizer.spans("a\x1b[1mb")  # [(0, 1, "text"), (1, 5, "sequence"), (5, 6, "text")]
+//!
+//! optimizer = ansi_optimizer.Optimizer()
+//! for line in sys.stdin:
+//!     sys.stdout.write(optimizer.feed(line))
+//! sys.stdout.write(optimizer.finish())
+//! ```
+
+use crate::error::Error;
+use crate::token::{Token, Tokens};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+// -------------------------------------------------------------------------------------------------
+
+/// An optimizer for a stream of text, which is split into arbitrary chunks.
+#[pyclass(name = "Optimizer")]
+pub struct PyOptimizer(crate::Optimizer);
+
+#[pymethods]
+impl PyOptimizer {
+    /// Creates an optimizer with the default options.
+    #[new]
+    fn new() -> Self {
+        PyOptimizer(crate::Optimizer::new())
+    }
+
+    /// Updates the optimizer with the next chunk of the stream, and returns the output that is
+    /// ready to be written.
+    fn feed(&mut self, chunk: &str) -> String {
+        self.0.feed(chunk);
+        self.0.take_output()
+    }
+
+    /// Finishes the stream, and returns the rest of the output.
+    /// The optimizer can be used for a new stream afterwards.
+    fn finish(&mut self) -> String {
+        self.0.finish();
+        self.0.take_output()
+    }
+}
+
+/// Optimizes a complete string.
+/// Raises `ValueError` if it contains an invalid or incomplete escape sequence.
+#[pyfunction]
+fn optimize(input: &str) -> PyResult<String> {
+    crate::optimize(input).map_err(|error| {
+        PyValueError::new_err(match error {
+            Error::InvalidSequence => "invalid escape sequence",
+            Error::Incomplete => "incomplete escape sequence",
+            Error::LimitExceeded => "escape sequence exceeds the limits",
+        })
+    })
+}
+
+/// Removes all escape sequences from a string.
+#[pyfunction]
+fn strip(input: &str) -> String {
+    crate::strip(input)
+}
+
+/// Splits a string into spans of text and escape sequences.
+///
+/// Each span is a `(start, end, kind)` tuple, where the kind is `"text"`, `"sequence"`,
+/// `"invalid"`, `"incomplete"`, or `"limit"`. The offsets are character indices, like Python's.
+#[pyfunction]
+fn spans(input: &str) -> Vec<(usize, usize, &'static str)> {
+    let mut offset = 0;
+    Tokens::new(input)
+        .spanned()
+        .map(|(span, token)| {
+            let start = offset;
+            offset += input[span.start..span.end].chars().count();
+            let kind = match token {
+                Ok(Token::Text(_)) => "text",
+                Ok(Token::Sequence(_)) => "sequence",
+                Err(Error::InvalidSequence) => "invalid",
+                Err(Error::Incomplete) => "incomplete",
+                Err(Error::LimitExceeded) => "limit",
+            };
+
+            (start, offset, kind)
+        })
+        .collect()
+}
+
+/// The `ansi_optimizer` module.
+#[pymodule]
+fn ansi_optimizer(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyOptimizer>()?;
+    module.add_function(wrap_pyfunction!(optimize, module)?)?;
+    module.add_function(wrap_pyfunction!(strip, module)?)?;
+    module.add_function(wrap_pyfunction!(spans, module)?)?;
+    Ok(())
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::python::*;
+
+    #[test]
+    fn optimizer() {
+        let mut optimizer = PyOptimizer::new();
+        assert_eq!(optimizer.feed("\x1B[31m\x1B[1"), "");
+        assert_eq!(optimizer.feed("mred\x1B[0m"), "\x1B[1;31mred");
+        assert_eq!(optimizer.finish(), "\x1B[m");
+        assert_eq!(optimizer.feed("\x1B[31m"), "");
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(optimize("\x1B[31m\x1B[1mred").unwrap(), "\x1B[1;31mred");
+        assert!(optimize("\x1B[31").is_err());
+        assert_eq!(strip("\x1B[31mred"), "red");
+    }
+
+    #[test]
+    fn spans_use_char_offsets() {
+        assert_eq!(spans("a\x1B[1mb"), vec![(0, 1, "text"), (1, 5, "sequence"), (5, 6, "text")]);
+        assert_eq!(
+            spans("\u{e9}\x1B[1m\x1B["),
+            vec![(0, 1, "text"), (1, 5, "sequence"), (5, 7, "incomplete")]
+        );
+    }
+}
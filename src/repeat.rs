@@ -0,0 +1,102 @@
+use crate::cost::Cost;
+use crate::cursor::digits;
+use crate::width::is_combining;
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Checks if a character can be repeated with REP.
+///
+/// This is limited to characters that are known to take up exactly one cell on their own:
+/// printable ASCII, and the box drawing and block element characters.
+#[inline]
+pub(crate) fn is_repeatable(c: char) -> bool {
+    matches!(c, ' '..='~' | '\u{2500}'..='\u{259F}')
+}
+
+/// Writes text, replacing runs of the same character with REP (repeat) sequences when cheaper.
+///
+/// ## Arguments
+///
+/// - `text`: The text.
+/// - `cost`: The cost model.
+/// - `out`: The output.
+pub(crate) fn write_compressed(text: &str, cost: &Cost, out: &mut String) {
+    let mut written = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if !is_repeatable(c) {
+            continue;
+        }
+
+        let mut count: usize = 1;
+        while chars.peek().map(|&(_, next)| next) == Some(c) {
+            chars.next();
+            count += 1;
+        }
+
+        let end = chars.peek().map_or(text.len(), |&(i, _)| i);
+        let repeats = match u16::try_from(count - 1) {
+            Ok(repeats) if repeats > 0 => repeats,
+            _ => continue,
+        };
+
+        let combines = matches!(chars.peek(), Some(&(_, next)) if is_combining(next));
+        if combines || cost.of(c.len_utf8() + len(repeats), 1) >= cost.of(end - start, 0) {
+            continue;
+        }
+
+        let first = start + c.len_utf8();
+        out.push_str(&text[written..first]);
+        write(repeats, out).expect("write to string");
+        written = end;
+    }
+
+    out.push_str(&text[written..]);
+}
+
+/// Gets the number of bytes needed to write a REP sequence.
+#[inline]
+fn len(repeats: u16) -> usize {
+    match repeats {
+        1 => 3,
+        n => 3 + digits(n),
+    }
+}
+
+/// Writes a REP sequence.
+fn write<W: Write>(repeats: u16, out: &mut W) -> std::fmt::Result {
+    match repeats {
+        1 => out.write_str("\x1B[b"),
+        n => write!(out, "\x1B[{}b", n),
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::repeat::*;
+
+    fn compressed(text: &str) -> String {
+        let mut out = String::new();
+        write_compressed(text, &Cost::BYTES, &mut out);
+        out
+    }
+
+    #[test]
+    fn compress_runs() {
+        assert_eq!(compressed("aaaa"), "aaaa");
+        assert_eq!(compressed("a-----b"), "a-----b");
+        assert_eq!(compressed("a------b"), "a-\x1B[5bb");
+        assert_eq!(compressed(&"=".repeat(80)), "=\x1B[79b");
+        assert_eq!(compressed(&"─".repeat(4)), "─\x1B[3b");
+
+        // Runs that are followed by a combining character are kept.
+        assert_eq!(compressed("-----\u{0301}"), "-----\u{0301}");
+
+        // Characters that might not be one cell wide are kept.
+        assert_eq!(compressed(&"あ".repeat(10)), "あ".repeat(10));
+    }
+}
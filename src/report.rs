@@ -0,0 +1,297 @@
+use crate::ansi::Sequence;
+use crate::token::{Token, Tokens};
+use crate::{Error, Optimizer, Options};
+use std::fmt::{Display, Formatter};
+
+// -------------------------------------------------------------------------------------------------
+
+/// What the optimizer did with each escape sequence of some input.
+///
+/// This is created with [report].
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Report {
+    /// The escape sequences of the input, in order.
+    pub sequences: Vec<SequenceReport>,
+
+    /// The size of the input, in bytes.
+    pub input_bytes: usize,
+
+    /// The size of the optimized output, in bytes.
+    pub output_bytes: usize,
+}
+
+/// What the optimizer did with an escape sequence.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct SequenceReport {
+    /// The byte offset of the sequence in the input.
+    pub offset: usize,
+
+    /// The sequence.
+    pub sequence: String,
+
+    /// What happened to the sequence.
+    pub disposition: Disposition,
+
+    /// The number of bytes the sequence added to (or removed from) the output, compared to the
+    /// input. The size of sequences that were merged into another is counted by that sequence.
+    pub delta: isize,
+}
+
+/// What happened to an escape sequence.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Disposition {
+    /// The sequence was written unchanged.
+    Kept,
+
+    /// The sequence was combined with later sequences, which were written as this.
+    Merged(String),
+
+    /// The sequence was written as this.
+    /// If it was combined with earlier sequences, this includes them.
+    Rewritten(String),
+
+    /// The sequence was removed, since it had no effect.
+    Dropped,
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for sequence in &self.sequences {
+            write!(f, "{:>8} {:>+5}  {:?} ", sequence.offset, sequence.delta, sequence.sequence)?;
+            match &sequence.disposition {
+                Disposition::Kept => writeln!(f, "kept")?,
+                Disposition::Merged(into) => writeln!(f, "merged into {:?}", into)?,
+                Disposition::Rewritten(into) => writeln!(f, "rewritten as {:?}", into)?,
+                Disposition::Dropped => writeln!(f, "dropped")?,
+            }
+        }
+
+        write!(f, "{} bytes -> {} bytes", self.input_bytes, self.output_bytes)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Tracks the output written for each escape sequence of the input.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Tracker {
+    sequences: Vec<Tracked>,
+
+    /// The SGR sequences that were merged into the style that hasn't been written yet.
+    style: Vec<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct Tracked {
+    offset: usize,
+    sequence: String,
+    output: String,
+    merged_into: Option<usize>,
+}
+
+impl Tracker {
+    /// Starts tracking a sequence.
+    ///
+    /// ## Returns
+    ///
+    /// The source of the output written for it.
+    fn start(&mut self, offset: usize, sequence: String) -> usize {
+        let output = String::new();
+        self.sequences.push(Tracked { offset, sequence, output, merged_into: None });
+        self.sequences.len() - 1
+    }
+
+    /// Records that output was written for a sequence.
+    pub fn write(&mut self, source: usize, text: &str) {
+        self.sequences[source].output.push_str(text);
+    }
+
+    /// Records that sequences were replaced by a later one.
+    pub fn merge(&mut self, sources: impl Iterator<Item = usize>, into: usize) {
+        for source in sources.filter(|&source| source != into) {
+            self.sequences[source].merged_into = Some(into);
+        }
+    }
+
+    /// Records that an SGR sequence was merged into the pending style.
+    pub fn merge_style(&mut self, source: usize) {
+        self.style.push(source);
+    }
+
+    /// Takes the SGR sequences merged into the pending style, since it's being written.
+    ///
+    /// ## Returns
+    ///
+    /// The source of the written style, which is the last of the SGR sequences.
+    pub fn take_style(&mut self) -> Option<usize> {
+        let last = self.style.pop()?;
+        let style = std::mem::take(&mut self.style);
+        self.merge(style.into_iter(), last);
+        Some(last)
+    }
+
+    /// Forgets the SGR sequences merged into the pending style, since it won't be written.
+    pub fn clear_style(&mut self) {
+        self.style.clear();
+    }
+
+    fn disposition(&self, tracked: &Tracked) -> Disposition {
+        if tracked.output == tracked.sequence {
+            return Disposition::Kept;
+        }
+
+        if !tracked.output.is_empty() {
+            return Disposition::Rewritten(tracked.output.clone());
+        }
+
+        let mut merged_into = tracked.merged_into;
+        while let Some(into) = merged_into.map(|i| &self.sequences[i]) {
+            if !into.output.is_empty() {
+                return Disposition::Merged(into.output.clone());
+            }
+
+            merged_into = into.merged_into;
+        }
+
+        Disposition::Dropped
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Optimizes some input, reporting what was done with each escape sequence.
+///
+/// This is meant for explaining the output (e.g. when tuning the [Options]), and is slower than
+/// optimizing normally.
+///
+/// ```
+/// # use ansi_optimizer::report::{report, Disposition};
+/// # use ansi_optimizer::Options;
+/// let report = report("\x1B[31m\x1B[1mred\x1B[?25h", &Options::default()).unwrap();
+/// assert_eq!(report.sequences[0].disposition, Disposition::Merged("\x1B[1;31m".to_owned()));
+/// assert_eq!(report.sequences[1].disposition, Disposition::Rewritten("\x1B[1;31m".to_owned()));
+/// assert_eq!(report.sequences[2].disposition, Disposition::Kept);
+/// ```
+///
+/// ## Returns
+///
+/// If the input contains an invalid escape sequence, this returns [Error::InvalidSequence].
+/// If the input ends partway through an escape sequence, this returns [Error::Incomplete].
+pub fn report(input: &str, options: &Options) -> Result<Report, Error> {
+    let mut optimizer = Optimizer::with_options(options.clone());
+    optimizer.tracker = Some(Box::default());
+
+    let mut offset = 0;
+    for token in Tokens::new(input) {
+        match token? {
+            Token::Text(text) => {
+                optimizer.update_text(text);
+                offset += text.len();
+            }
+            Token::Sequence(sequence) => {
+                offset += update_tracked(&mut optimizer, offset, &sequence)?;
+            }
+        }
+    }
+
+    optimizer.flush();
+
+    let tracker = optimizer.tracker.take().unwrap_or_default();
+    let sequences = tracker.sequences.iter().map(|tracked| SequenceReport {
+        offset: tracked.offset,
+        sequence: tracked.sequence.clone(),
+        disposition: tracker.disposition(tracked),
+        delta: tracked.output.len() as isize - tracked.sequence.len() as isize,
+    });
+
+    Ok(Report {
+        sequences: sequences.collect(),
+        input_bytes: input.len(),
+        output_bytes: optimizer.output.len(),
+    })
+}
+
+/// Updates the optimizer with a sequence, tracking the output written for it.
+///
+/// ## Returns
+///
+/// The length of the sequence, or [Error::LimitExceeded] if the [Options] say to return it.
+fn update_tracked(
+    optimizer: &mut Optimizer,
+    offset: usize,
+    sequence: &Sequence,
+) -> Result<usize, Error> {
+    let text = sequence.to_string();
+    let len = text.len();
+    let source = optimizer.tracker.as_mut().map(|tracker| tracker.start(offset, text));
+
+    optimizer.pending.set_source(source);
+    let result = optimizer.update_sequence(sequence);
+    optimizer.pending.set_source(None);
+    result.map(|_| len)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::report::*;
+    use crate::Profile;
+
+    fn dispositions(input: &str, options: &Options) -> Vec<(String, Disposition)> {
+        let report = report(input, options).unwrap();
+        let deltas = report.sequences.iter().map(|s| s.delta).sum::<isize>();
+        let text = report.sequences.iter().map(|s| s.sequence.len()).sum::<usize>();
+        assert_eq!(report.output_bytes as isize, report.input_bytes as isize + deltas);
+        assert!(report.input_bytes >= text);
+
+        report.sequences.into_iter().map(|s| (s.sequence, s.disposition)).collect()
+    }
+
+    fn owned(sequences: &[(&str, Disposition)]) -> Vec<(String, Disposition)> {
+        sequences.iter().map(|(s, d)| (s.to_string(), d.clone())).collect()
+    }
+
+    #[test]
+    fn report_dispositions() {
+        let options = Options::default();
+        assert_eq!(
+            dispositions("\x1B[31m\x1B[39ma\x1B[1mb\x1B]0;x\x07\x1B]0;x\x07c", &options),
+            owned(&[
+                ("\x1B[31m", Disposition::Dropped),
+                ("\x1B[39m", Disposition::Dropped),
+                ("\x1B[1m", Disposition::Kept),
+                ("\x1B]0;x\x07", Disposition::Kept),
+                ("\x1B]0;x\x07", Disposition::Dropped),
+            ])
+        );
+
+        assert_eq!(
+            dispositions("\x1B[5;5H\x1B[A\x1B[?25l\x1B[?25h!", &options),
+            owned(&[
+                ("\x1B[5;5H", Disposition::Merged("\x1B[4;5H".to_owned())),
+                ("\x1B[A", Disposition::Rewritten("\x1B[4;5H".to_owned())),
+                ("\x1B[?25l", Disposition::Dropped),
+                ("\x1B[?25h", Disposition::Dropped),
+            ])
+        );
+    }
+
+    #[test]
+    fn report_rewritten() {
+        let options =
+            Options { profile: Profile::xterm(), repeat_runs: true, ..Options::default() };
+        let result = report("\x1B[0;1ma\x1B[3b", &options).unwrap();
+        assert_eq!(result.sequences[0].disposition, Disposition::Rewritten("\x1B[1m".to_owned()));
+        assert_eq!(result.sequences[0].delta, -2);
+        assert_eq!(result.sequences[1].disposition, Disposition::Kept);
+
+        let options = Options { repeat_runs: true, ..Options::default() };
+        let result = report("a\x1B[3b", &options).unwrap();
+        assert_eq!(result.sequences[0].disposition, Disposition::Rewritten("aaa".to_owned()));
+        assert_eq!(
+            result.to_string(),
+            "       1    -1  \"\\u{1b}[3b\" rewritten as \"aaa\"\n5 bytes -> 4 bytes"
+        );
+    }
+}
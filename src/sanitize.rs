@@ -0,0 +1,174 @@
+use crate::ansi::{ControlSequence, Sequence};
+use crate::csi::{CsiCommand, WindowOp};
+use crate::options::ControlChars;
+use crate::optimizer::{is_device_query, is_kitty_keyboard, is_mouse_report};
+use std::borrow::Cow;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Checks if a sequence is unsafe to display from untrusted content.
+///
+/// This includes sequences that make the terminal send a reply (which is then read as input by
+/// whatever is running in it), and sequences that change how keyboard input is encoded.
+/// DEC private modes are checked separately by [is_input_mode], since a single sequence can change
+/// both safe and unsafe modes.
+pub(crate) fn is_unsafe(sequence: &Sequence) -> bool {
+    match sequence {
+        Sequence::CSI(csi) => is_unsafe_csi(csi),
+
+        // Color queries (e.g. `OSC 11 ; ? ST`), font changes and queries (`OSC 50`), and clipboard
+        // writes and queries (`OSC 52`).
+        Sequence::OSC(_, string) => {
+            let text = without_controls(string.text());
+            let command = text.split(';').next().unwrap_or_default();
+            matches!(command.parse(), Ok(50u16 | 52)) || text.split(';').any(|part| part == "?")
+        }
+
+        // DECRQSS (`DCS $ q`), XTGETTCAP (`DCS + q`), and user-defined keys (DECUDK, `DCS | `).
+        Sequence::DCS(_, string) => {
+            let text = without_controls(string.text());
+            let command = text.trim_start_matches(|c: char| c.is_ascii_digit() || c == ';');
+            text.starts_with("$q") || text.starts_with("+q") || command.starts_with('|')
+        }
+
+        // DECID, and application keypad mode (DECKPAM and DECKPNM).
+        Sequence::Regular(seq) => {
+            seq.intermediates().is_empty() && matches!(seq.finalizer(), "Z" | "=" | ">")
+        }
+
+        Sequence::APC(..) => false,
+    }
+}
+
+/// Removes the C0 controls from the text of a string, since terminals ignore them there.
+fn without_controls(text: &str) -> Cow<'_, str> {
+    if text.contains(|c: char| c < ' ') {
+        Cow::Owned(text.chars().filter(|c| *c >= ' ').collect())
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Checks if a DEC private mode changes how the terminal reports input.
+pub(crate) fn is_input_mode(mode: u16) -> bool {
+    // Application cursor keys, application keypad, focus events, and bracketed paste.
+    matches!(mode, 1 | 66 | 1004 | 2004)
+}
+
+fn is_unsafe_csi(csi: &ControlSequence) -> bool {
+    if is_device_query(csi) || is_kitty_keyboard(csi) || is_mouse_report(csi) {
+        return true;
+    }
+
+    match (csi.private_marker(), csi.intermediates(), csi.finalizer()) {
+        // Terminal parameters (DECREQTPARM).
+        (None, "", "x") => true,
+
+        // Mode reports (DECRQM) and the terminal version (XTVERSION).
+        (None, "$", "p") | (Some('?'), "$", "p") | (Some('>'), "", "q") => true,
+
+        // Window manipulation and reports, except for saving and restoring the title.
+        (None, "", "t") => !matches!(
+            CsiCommand::from(csi),
+            CsiCommand::Window(WindowOp::PushTitle(_) | WindowOp::PopTitle(_) | WindowOp::Refresh)
+        ),
+        _ => false,
+    }
+}
+
+/// Checks if a character is a stray control character.
+///
+/// Tabs, line feeds, and carriage returns are used for formatting, and escapes start sequences.
+/// 8-bit C1 controls (`U+0080` to `U+009F`) are only left in text when they don't start a valid
+/// sequence, so they're always stray.
+#[inline]
+fn is_stray_control(c: char) -> bool {
+    c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x1B')
+}
+
+/// Removes or escapes the stray control characters in text.
+pub(crate) fn replace_controls(text: &str, mode: ControlChars) -> Cow<'_, str> {
+    if mode == ControlChars::Keep || !text.chars().any(is_stray_control) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut replaced = String::with_capacity(text.len());
+    for c in text.chars() {
+        match (mode, is_stray_control(c)) {
+            (_, false) | (ControlChars::Keep, _) => replaced.push(c),
+            (ControlChars::Strip, true) => {}
+            (ControlChars::Escape, true) => {
+                let c = c as u32;
+                if c >= 0x80 {
+                    replaced.push_str("M-");
+                }
+
+                replaced.push('^');
+                replaced.push(((c & 0x7F) as u8 ^ 0x40) as char);
+            }
+        }
+    }
+
+    Cow::Owned(replaced)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::sanitize::*;
+    use std::convert::TryFrom;
+
+    fn unsafe_sequence(sequence: &str) -> bool {
+        is_unsafe(&Sequence::try_from(sequence).unwrap())
+    }
+
+    #[test]
+    fn unsafe_sequences() {
+        assert!(unsafe_sequence("\x1B[c"));
+        assert!(unsafe_sequence("\x1B[>0c"));
+        assert!(unsafe_sequence("\x1B[6n"));
+        assert!(unsafe_sequence("\x1B[21t"));
+        assert!(unsafe_sequence("\x1B[?2004$p"));
+        assert!(unsafe_sequence("\x1BZ"));
+        assert!(unsafe_sequence("\x1B]11;?\x07"));
+        assert!(unsafe_sequence("\x1BP$qm\x1B\\"));
+        assert!(unsafe_sequence("\x1BP1;1|17/6C\x1B\\"));
+        assert!(unsafe_sequence("\x1BP|17/6C\x1B\\"));
+        assert!(unsafe_sequence("\x1B]50;?\x07"));
+        assert!(unsafe_sequence("\x1B]50;fixed\x07"));
+
+        // 8-bit C1 introducers.
+        assert!(unsafe_sequence("\u{9B}6n"));
+        assert!(unsafe_sequence("\u{9B}>0c"));
+        assert!(unsafe_sequence("\u{90}$qm\u{9C}"));
+        assert!(unsafe_sequence("\u{9D}11;?\u{9C}"));
+
+        // C0 controls inside strings, which terminals ignore.
+        assert!(unsafe_sequence("\x1B]11;?\x01\x07"));
+        assert!(unsafe_sequence("\x1B]5\x010;?\x07"));
+        assert!(unsafe_sequence("\x1BP$\x0Bqm\x1B\\"));
+        assert!(unsafe_sequence("\x1BP1;\x011|17/6C\x1B\\"));
+        assert!(unsafe_sequence("\x1B]52;c;aGk=\x07"));
+        assert!(unsafe_sequence("\x1B]5\x012;c;aGk=\x07"));
+        assert!(unsafe_sequence("\x1B]52\x01;c;aGk=\x07"));
+
+        assert!(!unsafe_sequence("\x1B[1;31m"));
+        assert!(!unsafe_sequence("\x1B[2J"));
+        assert!(!unsafe_sequence("\x1B]0;title\x07"));
+        assert!(!unsafe_sequence("\x1B7"));
+        assert!(!unsafe_sequence("\x1BP0;1q#0;2;0;0;0#0~~\x1B\\"));
+    }
+
+    #[test]
+    fn replace_control_chars() {
+        let text = "a\0b\x08\x0B\x0C\x7F\tc\r\n";
+        assert_eq!(replace_controls(text, ControlChars::Keep), text);
+        assert_eq!(replace_controls(text, ControlChars::Strip), "ab\tc\r\n");
+        assert_eq!(replace_controls(text, ControlChars::Escape), "a^@b^H^K^L^?\tc\r\n");
+
+        let text = "a\u{85}b\u{9B}c\u{A0}";
+        assert_eq!(replace_controls(text, ControlChars::Strip), "abc\u{A0}");
+        assert_eq!(replace_controls(text, ControlChars::Escape), "aM-^EbM-^[c\u{A0}");
+    }
+}
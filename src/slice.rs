@@ -0,0 +1,134 @@
+//! Slicing styled text by the cells it takes up.
+//!
+//! ```
+//! # use ansi_optimizer::slice_visible;
+//! let line = "\x1B[1mbold \x1B[31mand red\x1B[m";
+//! assert_eq!(slice_visible(line, 2..7), "\x1B[1mld \x1B[31man\x1B[m");
+//! assert_eq!(slice_visible(line, 9..20), "\x1B[1;31mred\x1B[m");
+//! ```
+
+use crate::bidi::{bidi_control, Embeddings};
+use crate::token::{Token, Tokens};
+use crate::width::{clusters, text_width_with};
+use crate::{Bidi, Optimizer, Options};
+use std::ops::Range;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Gets the part of a line of text that is shown in a range of columns, starting from zero.
+///
+/// The style that is active at the start of the range is set before the first character, and
+/// the style is reset at the end, so slices can be written anywhere. Escape sequences that don't
+/// change the style are kept if they come before the end of the range, and invalid ones are
+/// removed. Wide characters that are only partly inside the range are replaced with spaces.
+pub fn slice_visible(input: &str, columns: Range<usize>) -> String {
+    slice_visible_with(input, columns, &Options::default())
+}
+
+/// Gets the part of a line of text that is shown in a range of columns, using [Options].
+///
+/// With [Bidi::Isolate], the embeddings that are open at the start of the range are opened again
+/// at the start of the slice, and the ones that are still open at the end are closed. See
+/// [slice_visible].
+pub fn slice_visible_with(input: &str, columns: Range<usize>, options: &Options) -> String {
+    let mut optimizer = Optimizer::with_options(options.clone());
+    let mut embeddings = Embeddings::default();
+    let mut opened = false;
+    let mut column = 0;
+
+    'tokens: for token in Tokens::new(input) {
+        let text = match token {
+            Ok(Token::Text(text)) => text,
+            Ok(Token::Sequence(sequence)) => {
+                optimizer.feed(&sequence.to_string());
+                continue;
+            }
+            Err(_) => continue,
+        };
+
+        for cluster in clusters(text) {
+            let cells = text_width_with(cluster, &options.width_policy);
+            let end = column + cells;
+            if let (Some(c), Bidi::Isolate | Bidi::Strip) = (bidi_control(cluster), options.bidi) {
+                if options.bidi == Bidi::Isolate && column < columns.end {
+                    embeddings.update(c);
+                    if opened {
+                        optimizer.feed(cluster);
+                    }
+                }
+
+                continue;
+            }
+
+            if cells > 0 && !opened && end > columns.start && column < columns.end {
+                optimizer.feed(&embeddings.reopen());
+                opened = true;
+            }
+
+            if cells == 0 {
+                // Combining marks and control characters belong to the character before them.
+                if column > columns.start && column <= columns.end {
+                    optimizer.feed(cluster);
+                }
+            } else if column >= columns.end {
+                break 'tokens;
+            } else if column >= columns.start && end <= columns.end {
+                optimizer.feed(cluster);
+            } else if end > columns.start {
+                let visible = end.min(columns.end) - column.max(columns.start);
+                optimizer.feed(&" ".repeat(visible));
+            }
+
+            column = end;
+        }
+    }
+
+    if opened {
+        optimizer.feed(&embeddings.close());
+    }
+
+    optimizer.feed("\x1B[m");
+    optimizer.finish();
+    optimizer.take_output()
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::slice::*;
+
+    #[test]
+    fn slice_columns() {
+        assert_eq!(slice_visible("abcdef", 1..3), "bc");
+        assert_eq!(slice_visible("abc", 2..10), "c");
+        assert_eq!(slice_visible("abc", 5..10), "");
+        assert_eq!(slice_visible("e\u{301}e\u{302}e", 1..2), "e\u{302}");
+        assert_eq!(slice_visible("a\x1B]0;title\x07\x1B[1m\x1B[3", 1..2), "\x1B]0;title\x07");
+    }
+
+    #[test]
+    fn slice_keeps_style() {
+        assert_eq!(slice_visible("\x1B[41ma\x1B[1mb\x1B[0mc", 1..3), "\x1B[1;41mb\x1B[mc");
+        assert_eq!(slice_visible("\x1B[41mab\x1B[1m", 0..2), "\x1B[41mab\x1B[m");
+    }
+
+    #[test]
+    fn slice_bidi() {
+        let input = "a\u{202B}bc\u{2066}d\u{2069}e\u{202C}f";
+        let isolate = Options { bidi: Bidi::Isolate, ..Options::default() };
+        let strip = Options { bidi: Bidi::Strip, ..Options::default() };
+        assert_eq!(slice_visible(input, 1..3), "bc\u{2066}");
+        assert_eq!(slice_visible_with(input, 1..3, &isolate), "\u{202B}bc\u{202C}");
+        assert_eq!(slice_visible_with(input, 2..4, &isolate), "\u{202B}c\u{2066}d\u{2069}\u{202C}");
+        assert_eq!(slice_visible_with(input, 2..4, &strip), "cd");
+        assert_eq!(slice_visible_with(input, 5..6, &isolate), "f");
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn slice_wide_characters() {
+        assert_eq!(slice_visible("\u{65e5}\u{672c}\u{8a9e}", 1..5), " \u{672c} ");
+        assert_eq!(slice_visible("\x1B[1m\u{65e5}", 1..2), "\x1B[1m \x1B[m");
+    }
+}
@@ -0,0 +1,48 @@
+use crate::token::{Token, Tokens};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Removes all ANSI escape sequences from a string.
+///
+/// Invalid sequences have their `ESC` (or C1 control) character removed, and an unfinished
+/// sequence at the end of the input is removed entirely.
+pub fn strip(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    strip_into(input, &mut output);
+    output
+}
+
+/// Removes all ANSI escape sequences from a string, appending the result to an existing buffer.
+///
+/// This behaves the same as [strip], but allows the output buffer to be reused between calls.
+pub fn strip_into(input: &str, output: &mut String) {
+    for token in Tokens::new(input) {
+        if let Ok(Token::Text(text)) = token {
+            output.push_str(text);
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::strip::*;
+
+    #[test]
+    fn strip_sequences() {
+        assert_eq!(strip("plain text"), "plain text");
+        assert_eq!(strip("\x1B[1;31mred\x1B[m \x1B]0;title\x07text"), "red text");
+        assert_eq!(strip("a\x1B\x01b"), "a\x01b");
+        assert_eq!(strip("cut\x1B[38;5"), "cut");
+        assert_eq!(strip("a\x1BPq#0;2;0;0;0#0~~\x1B\\b"), "ab");
+        assert_eq!(strip("a\x1B_Ga=T;AAAA\x1B\\b"), "ab");
+    }
+
+    #[test]
+    fn strip_into_appends() {
+        let mut buffer = String::from("> ");
+        strip_into("\x1B[1mbold\x1B[0m", &mut buffer);
+        assert_eq!(buffer, "> bold");
+    }
+}
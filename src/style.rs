@@ -0,0 +1,1052 @@
+use crate::ansi::{Param, Params};
+use crate::color::{Color, ColorBlindness, Palette};
+use crate::profile::{ColorEncoding, ColorSupport, Profile, Sgr21};
+use std::fmt::{Debug, Display, Formatter, Write};
+use std::sync::Arc;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A set of text attributes.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Attributes(u16);
+
+impl Attributes {
+    pub const NONE: Attributes = Attributes(0);
+    pub const BOLD: Attributes = Attributes(1 << 0);
+    pub const FAINT: Attributes = Attributes(1 << 1);
+    pub const ITALIC: Attributes = Attributes(1 << 2);
+    pub const BLINK: Attributes = Attributes(1 << 3);
+    pub const RAPID_BLINK: Attributes = Attributes(1 << 4);
+    pub const REVERSE: Attributes = Attributes(1 << 5);
+    pub const HIDDEN: Attributes = Attributes(1 << 6);
+    pub const STRIKETHROUGH: Attributes = Attributes(1 << 7);
+    pub const OVERLINE: Attributes = Attributes(1 << 8);
+    pub const ALL: Attributes = Attributes((1 << 9) - 1);
+
+    /// Returns `true` if every attribute in `other` is set.
+    #[inline]
+    pub const fn contains(self, other: Attributes) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns `true` if any attribute in `other` is set.
+    #[inline]
+    pub const fn intersects(self, other: Attributes) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Returns `true` if no attributes are set.
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Sets the attributes in `other`.
+    #[inline]
+    pub fn insert(&mut self, other: Attributes) {
+        self.0 |= other.0;
+    }
+
+    /// Clears the attributes in `other`.
+    #[inline]
+    pub fn remove(&mut self, other: Attributes) {
+        self.0 &= !other.0;
+    }
+
+    /// Returns the union of two sets of attributes.
+    #[inline]
+    pub const fn union(self, other: Attributes) -> Attributes {
+        Attributes(self.0 | other.0)
+    }
+
+    /// Returns the attributes in `self` that are not in `other`.
+    #[inline]
+    pub const fn difference(self, other: Attributes) -> Attributes {
+        Attributes(self.0 & !other.0)
+    }
+}
+
+/// An underline style.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Underline {
+    None,
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
+impl Default for Underline {
+    #[inline]
+    fn default() -> Self {
+        Underline::None
+    }
+}
+
+/// The style of text, as set by SGR (Select Graphic Rendition) sequences.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Style {
+    pub attributes: Attributes,
+    pub underline: Underline,
+    pub foreground: Color,
+    pub background: Color,
+    pub underline_color: Color,
+}
+
+/// The parts of a style that are removed from the output.
+///
+/// ```
+/// # use ansi_optimizer::style::{Attributes, StyleFilter};
+/// // Keep colors, but remove blinking and reversed text.
+/// let filter = StyleFilter {
+///     attributes: Attributes::BLINK.union(Attributes::RAPID_BLINK).union(Attributes::REVERSE),
+///     ..StyleFilter::default()
+/// };
+///
+/// // Only keep bold text, and remove every color.
+/// let filter = StyleFilter::allow(Attributes::BOLD);
+/// ```
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default)]
+pub struct StyleFilter {
+    /// The attributes to remove.
+    pub attributes: Attributes,
+
+    /// Whether to remove underlines.
+    pub underline: bool,
+
+    /// Whether to remove foreground colors.
+    pub foreground: bool,
+
+    /// Whether to remove background colors.
+    pub background: bool,
+
+    /// Whether to remove underline colors.
+    pub underline_color: bool,
+}
+
+/// A function that is applied to every style before it's written to the output.
+///
+/// This can be used to translate between color themes, or to force a style onto all text.
+/// Clones share the same function, and are only equal to each other.
+///
+/// ```
+/// # use ansi_optimizer::{Color, Optimizer, Options, Style};
+/// # use ansi_optimizer::style::StyleMapper;
+/// let mapper = StyleMapper::new(|mut style: Style| {
+///     if style.foreground == Color::Indexed(1) {
+///         style.foreground = Color::Rgb(0xFF, 0x5F, 0x56);
+///     }
+///
+///     style
+/// });
+///
+/// let mut optimizer = Optimizer::with_options(Options {
+///     style_mapper: Some(mapper),
+///     ..Options::default()
+/// });
+///
+/// optimizer.update("\x1B[31mred").unwrap();
+/// assert_eq!(optimizer.to_string(), "\x1B[38;2;255;95;86mred");
+/// ```
+#[derive(Clone)]
+pub struct StyleMapper(Arc<dyn Fn(Style) -> Style + Send + Sync>);
+
+/// The shortest SGR sequence that changes the terminal to a style.
+/// This is created by [Style::transition_from], and written with [Display].
+#[derive(Clone, Copy, Debug)]
+pub struct Transition<'a> {
+    style: &'a Style,
+    prev: Option<&'a Style>,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Toggled attributes, and their SGR set and reset codes.
+/// Bold and faint are excluded, since they share a reset code.
+const TOGGLES: &[(Attributes, u16, u16)] = &[
+    (Attributes::ITALIC, 3, 23),
+    (Attributes::BLINK, 5, 25),
+    (Attributes::RAPID_BLINK, 6, 25),
+    (Attributes::REVERSE, 7, 27),
+    (Attributes::HIDDEN, 8, 28),
+    (Attributes::STRIKETHROUGH, 9, 29),
+    (Attributes::OVERLINE, 53, 55),
+];
+
+impl StyleFilter {
+    /// Creates a filter that removes everything except for some attributes.
+    pub fn allow(attributes: Attributes) -> Self {
+        StyleFilter {
+            attributes: Attributes::ALL.difference(attributes),
+            underline: true,
+            foreground: true,
+            background: true,
+            underline_color: true,
+        }
+    }
+
+    /// Returns `true` if the filter doesn't remove anything.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        *self == StyleFilter::default()
+    }
+
+    /// Removes the filtered parts of a style.
+    pub fn apply(&self, style: &mut Style) {
+        style.attributes.remove(self.attributes);
+        if self.underline {
+            style.underline = Underline::None;
+        }
+
+        let filter = |enabled: bool, color: &mut Color| {
+            if enabled {
+                *color = Color::Default;
+            }
+        };
+
+        filter(self.foreground, &mut style.foreground);
+        filter(self.background, &mut style.background);
+        filter(self.underline_color, &mut style.underline_color);
+    }
+}
+
+impl StyleMapper {
+    /// Creates a mapper from a function.
+    pub fn new(map: impl Fn(Style) -> Style + Send + Sync + 'static) -> Self {
+        StyleMapper(Arc::new(map))
+    }
+
+    /// Creates a mapper that changes each color of a style (foreground, background, and underline).
+    pub fn colors(map: impl Fn(Color) -> Color + Send + Sync + 'static) -> Self {
+        StyleMapper::new(move |style| Style {
+            foreground: map(style.foreground),
+            background: map(style.background),
+            underline_color: map(style.underline_color),
+            ..style
+        })
+    }
+
+    /// Creates a mapper that replaces colors which are commonly confused with a type of color
+    /// blindness. See [ColorBlindness::substitute].
+    ///
+    /// ```
+    /// # use ansi_optimizer::{ColorBlindness, Optimizer, Options};
+    /// # use ansi_optimizer::style::StyleMapper;
+    /// let mut optimizer = Optimizer::with_options(Options {
+    ///     style_mapper: Some(StyleMapper::color_blindness(ColorBlindness::Deuteranopia)),
+    ///     ..Options::default()
+    /// });
+    ///
+    /// optimizer.update("\x1B[31m-removed\x1B[32m+added").unwrap();
+    /// assert_eq!(
+    ///     optimizer.to_string(),
+    ///     "\x1B[38;2;213;94;0m-removed\x1B[38;2;0;158;115m+added"
+    /// );
+    /// ```
+    pub fn color_blindness(kind: ColorBlindness) -> Self {
+        StyleMapper::colors(move |color| kind.substitute(color))
+    }
+
+    /// Creates a mapper that converts every color to the nearest gray with the same luminance.
+    /// See [Color::to_grayscale].
+    ///
+    /// To remove colors entirely, use a [StyleFilter] or [ColorSupport::Monochrome] instead.
+    pub fn grayscale() -> Self {
+        StyleMapper::colors(Color::to_grayscale)
+    }
+
+    /// Creates a mapper that replaces blinking and rapidly blinking text with other attributes,
+    /// for terminals that don't support blinking or people who find it distracting.
+    ///
+    /// [Attributes::BOLD] or [Attributes::REVERSE] keep the text standing out, and
+    /// [Attributes::NONE] only removes the blinking.
+    ///
+    /// ```
+    /// # use ansi_optimizer::style::{Attributes, StyleMapper};
+    /// # use ansi_optimizer::{Optimizer, Options};
+    /// let mut optimizer = Optimizer::with_options(Options {
+    ///     style_mapper: Some(StyleMapper::replace_blink(Attributes::REVERSE)),
+    ///     ..Options::default()
+    /// });
+    ///
+    /// optimizer.update("\x1B[5mALERT\x1B[25m ok").unwrap();
+    /// assert_eq!(optimizer.to_string(), "\x1B[7mALERT\x1B[m ok");
+    /// ```
+    pub fn replace_blink(substitute: Attributes) -> Self {
+        let blink = Attributes::BLINK.union(Attributes::RAPID_BLINK);
+        StyleMapper::new(move |mut style| {
+            if style.attributes.intersects(blink) {
+                style.attributes.remove(blink);
+                style.attributes.insert(substitute);
+            }
+
+            style
+        })
+    }
+
+    /// Maps a style.
+    #[inline]
+    pub fn map(&self, style: Style) -> Style {
+        (self.0)(style)
+    }
+}
+
+impl Debug for StyleMapper {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StyleMapper(..)")
+    }
+}
+
+impl PartialEq for StyleMapper {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Style {
+    /// The default style, for use in constants.
+    pub const DEFAULT: Style = Style {
+        attributes: Attributes::NONE,
+        underline: Underline::None,
+        foreground: Color::Default,
+        background: Color::Default,
+        underline_color: Color::Default,
+    };
+
+    /// Returns `true` if this is the default style.
+    #[inline]
+    pub fn is_default(&self) -> bool {
+        *self == Style::default()
+    }
+
+    /// Checks if text looks the same with either style on a terminal.
+    ///
+    /// Colors are compared after converting them for the terminal, and underline colors are
+    /// ignored for text that isn't underlined. If the terminal shows bold text in bright colors,
+    /// those are compared instead.
+    ///
+    /// ```
+    /// # use ansi_optimizer::{Color, ColorSupport, Profile, Style};
+    /// let profile = Profile { colors: ColorSupport::Ansi16, ..Profile::default() };
+    /// let a = Style { foreground: Color::Rgb(255, 0, 0), ..Style::default() };
+    /// let b = Style { foreground: Color::Indexed(9), ..Style::default() };
+    /// assert!(a.visually_eq(&b, &profile));
+    /// assert!(!a.visually_eq(&b, &Profile::default()));
+    /// ```
+    pub fn visually_eq(&self, other: &Style, profile: &Profile) -> bool {
+        self.rendered(profile) == other.rendered(profile)
+    }
+
+    /// Converts the colors of the style to ones that are supported by a terminal.
+    ///
+    /// Underline colors can't be represented with the 16 standard colors, so they are removed
+    /// if the terminal doesn't support at least the 256-color palette.
+    #[inline]
+    pub fn downgrade(&mut self, support: ColorSupport) {
+        self.downgrade_with(support, Palette::xterm())
+    }
+
+    /// Converts the colors of the style to ones that are supported by a terminal with a palette.
+    ///
+    /// This behaves the same as [Style::downgrade], but finds the nearest colors in the given
+    /// palette.
+    pub fn downgrade_with(&mut self, support: ColorSupport, palette: &Palette) {
+        self.foreground = self.foreground.downgrade_with(support, palette);
+        self.background = self.background.downgrade_with(support, palette);
+        self.underline_color = match support {
+            ColorSupport::Monochrome | ColorSupport::Ansi16 => Color::Default,
+            support => self.underline_color.downgrade_with(support, palette),
+        };
+    }
+
+    /// Removes the parts of the style that don't change how text looks on a terminal.
+    fn rendered(mut self, profile: &Profile) -> Style {
+        self.downgrade(profile.colors);
+        if self.underline == Underline::None {
+            self.underline_color = Color::Default;
+        }
+
+        if profile.bold_is_bright && self.attributes.contains(Attributes::BOLD) {
+            if let Color::Indexed(n @ 0..=7) = self.foreground {
+                self.foreground = Color::Indexed(n + 8);
+            }
+        }
+
+        self
+    }
+
+    /// Applies the parameters of an SGR sequence to the style.
+    ///
+    /// ## Returns
+    ///
+    /// `false` if any of the parameters were not understood.
+    /// Parameters that were understood are still applied.
+    #[inline]
+    pub fn apply_sgr(&mut self, params: Params) -> bool {
+        self.apply_sgr_for(params, &Profile::default())
+    }
+
+    /// Applies the parameters of an SGR sequence to the style, as a terminal with a [Profile]
+    /// would.
+    ///
+    /// This only differs from [Style::apply_sgr] for codes that terminals disagree on, like
+    /// [SGR 21](Profile::sgr21).
+    pub fn apply_sgr_for(&mut self, mut params: Params, profile: &Profile) -> bool {
+        let mut understood = true;
+
+        // An SGR sequence without parameters is a reset.
+        let mut next = params.next();
+        if next.is_none() {
+            *self = Style::default();
+            return true;
+        }
+
+        while let Some(param) = next {
+            understood &= match param {
+                Param::Sub(mut sub) => match sub.next().flatten() {
+                    Some(4) => match sub.next().flatten().unwrap_or(0) {
+                        0 => self.set_underline(Underline::None),
+                        1 => self.set_underline(Underline::Single),
+                        2 => self.set_underline(Underline::Double),
+                        3 => self.set_underline(Underline::Curly),
+                        4 => self.set_underline(Underline::Dotted),
+                        5 => self.set_underline(Underline::Dashed),
+                        _ => false,
+                    },
+                    Some(code @ 38) | Some(code @ 48) | Some(code @ 58) => {
+                        let args = [sub.next(), sub.next(), sub.next(), sub.next(), sub.next()];
+                        let color = match args {
+                            // 38:5:n
+                            [Some(Some(5)), Some(Some(n)), None, ..] => indexed(n),
+
+                            // 38:2:r:g:b
+                            [Some(Some(2)), Some(Some(r)), Some(Some(g)), Some(Some(b)), None] => {
+                                rgb(r, g, b)
+                            }
+
+                            // 38:2:id:r:g:b
+                            [Some(Some(2)), Some(_), Some(Some(r)), Some(Some(g)), Some(Some(b))]
+                                if sub.next().is_none() =>
+                            {
+                                rgb(r, g, b)
+                            }
+
+                            _ => None,
+                        };
+
+                        self.set_color(code, color)
+                    }
+                    _ => false,
+                },
+
+                Param::Empty => self.apply_code(0, profile),
+                Param::Int(code @ 38) | Param::Int(code @ 48) | Param::Int(code @ 58) => {
+                    let color = match params.next().map(|p| p.value_or(0)) {
+                        Some(5) => params.next().and_then(|p| indexed(p.value_or(0))),
+                        Some(2) => {
+                            let mut channel = || params.next().map(|p| p.value_or(0));
+                            match (channel(), channel(), channel()) {
+                                (Some(r), Some(g), Some(b)) => rgb(r, g, b),
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    };
+
+                    self.set_color(code, color)
+                }
+                Param::Int(code) => self.apply_code(code, profile),
+            };
+
+            next = params.next();
+        }
+
+        understood
+    }
+
+    /// Gets the shortest SGR sequence that changes the style from `prev` to `self`.
+    ///
+    /// If `prev` is `None`, the terminal's current style isn't known, so the sequence starts by
+    /// resetting it.
+    ///
+    /// ```
+    /// # use ansi_optimizer::{Color, Style};
+    /// let prev = Style { foreground: Color::Indexed(1), ..Style::default() };
+    /// let style = Style { background: Color::Indexed(4), ..prev };
+    /// assert_eq!(style.transition_from(Some(&prev)).to_string(), "\x1B[44m");
+    /// assert_eq!(style.transition_from(None).to_string(), "\x1B[0;31;44m");
+    /// ```
+    pub fn transition_from<'a>(&'a self, prev: Option<&'a Style>) -> Transition<'a> {
+        Transition { style: self, prev }
+    }
+
+    /// Writes the shortest SGR sequence that changes the style from `prev` to `self`.
+    ///
+    /// Nothing is written if the styles are the same.
+    #[inline]
+    pub fn write_transition<W: Write>(&self, prev: &Style, out: &mut W) -> std::fmt::Result {
+        self.write_transition_for(prev, &Profile::default(), out)
+    }
+
+    /// Writes the shortest SGR sequence that changes the style from `prev` to `self`, using only
+    /// codes that mean the same thing on a terminal with a [Profile].
+    ///
+    /// See [Style::write_transition].
+    pub fn write_transition_for<W: Write>(
+        &self,
+        prev: &Style,
+        profile: &Profile,
+        out: &mut W,
+    ) -> std::fmt::Result {
+        if self == prev {
+            return Ok(());
+        }
+
+        let mut diff: ParamBuffer = ParamBuffer::new();
+        self.write_diff_params(prev, profile, &mut diff);
+
+        let mut reset: ParamBuffer = ParamBuffer::new();
+        self.write_diff_params(&Style::default(), profile, &mut reset);
+
+        out.write_str("\x1B[")?;
+        if reset.is_empty() {
+            // Resetting to the default style is always the shortest.
+        } else if diff.len <= reset.len + 2 {
+            out.write_str(diff.as_str())?;
+        } else {
+            out.write_str("0;")?;
+            out.write_str(reset.as_str())?;
+        }
+
+        out.write_str("m")
+    }
+
+    /// Writes the SGR parameters that change the style from `prev` to `self`, without resetting.
+    ///
+    /// This is also used to create sequences at compile time (see [style!](crate::style!)), so it
+    /// can only use `const` operations.
+    pub(crate) const fn write_diff_params<const N: usize>(
+        &self,
+        prev: &Style,
+        profile: &Profile,
+        out: &mut ParamBuffer<N>,
+    ) {
+        let mut params = ParamWriter(out);
+        let added = self.attributes.difference(prev.attributes);
+        let removed = prev.attributes.difference(self.attributes);
+
+        // Bold and faint share a reset code.
+        let intensity = Attributes::BOLD.union(Attributes::FAINT);
+        if removed.intersects(intensity) {
+            params.code(22);
+        }
+
+        let intensities = [(Attributes::BOLD, 1), (Attributes::FAINT, 2)];
+        let mut i = 0;
+        while i < intensities.len() {
+            let (attribute, set) = intensities[i];
+            if added.contains(attribute)
+                || (removed.intersects(intensity) && self.attributes.contains(attribute))
+            {
+                params.code(set);
+            }
+
+            i += 1;
+        }
+
+        // Blink and rapid blink share a reset code.
+        let blink = Attributes::BLINK.union(Attributes::RAPID_BLINK);
+        let blink_reset = removed.intersects(blink);
+        let mut i = 0;
+        while i < TOGGLES.len() {
+            let (attribute, set, reset) = TOGGLES[i];
+            i += 1;
+            if blink.contains(attribute) && blink_reset {
+                if attribute.contains(Attributes::BLINK) {
+                    params.code(reset);
+                }
+
+                if self.attributes.contains(attribute) {
+                    params.code(set);
+                }
+            } else if added.contains(attribute) {
+                params.code(set);
+            } else if removed.contains(attribute) {
+                params.code(reset);
+            }
+        }
+
+        if self.underline as u8 != prev.underline as u8 {
+            params.raw(match self.underline {
+                Underline::None => "24",
+                Underline::Single => "4",
+                Underline::Double if matches!(profile.sgr21, Sgr21::DoubleUnderline) => "21",
+                Underline::Double => "4:2",
+                Underline::Curly => "4:3",
+                Underline::Dotted => "4:4",
+                Underline::Dashed => "4:5",
+            });
+        }
+
+        if !same_color(self.foreground, prev.foreground) {
+            params.color(30, self.foreground, profile.color_encoding);
+        }
+
+        if !same_color(self.background, prev.background) {
+            params.color(40, self.background, profile.color_encoding);
+        }
+
+        if !same_color(self.underline_color, prev.underline_color) {
+            params.color(50, self.underline_color, profile.color_encoding);
+        }
+    }
+
+    /// Applies a single SGR code.
+    fn apply_code(&mut self, code: u16, profile: &Profile) -> bool {
+        match code {
+            0 => *self = Style::default(),
+            1 => self.attributes.insert(Attributes::BOLD),
+            2 => self.attributes.insert(Attributes::FAINT),
+            3 => self.attributes.insert(Attributes::ITALIC),
+            4 => self.underline = Underline::Single,
+            5 => self.attributes.insert(Attributes::BLINK),
+            6 => self.attributes.insert(Attributes::RAPID_BLINK),
+            7 => self.attributes.insert(Attributes::REVERSE),
+            8 => self.attributes.insert(Attributes::HIDDEN),
+            9 => self.attributes.insert(Attributes::STRIKETHROUGH),
+            21 => match profile.sgr21 {
+                Sgr21::DoubleUnderline => self.underline = Underline::Double,
+                Sgr21::NormalIntensity => {
+                    self.attributes.remove(Attributes::BOLD.union(Attributes::FAINT))
+                }
+                Sgr21::Ambiguous => return false,
+            },
+            22 => self.attributes.remove(Attributes::BOLD.union(Attributes::FAINT)),
+            23 => self.attributes.remove(Attributes::ITALIC),
+            24 => self.underline = Underline::None,
+            25 => self.attributes.remove(Attributes::BLINK.union(Attributes::RAPID_BLINK)),
+            27 => self.attributes.remove(Attributes::REVERSE),
+            28 => self.attributes.remove(Attributes::HIDDEN),
+            29 => self.attributes.remove(Attributes::STRIKETHROUGH),
+            30..=37 => self.foreground = Color::Indexed((code - 30) as u8),
+            39 => self.foreground = Color::Default,
+            40..=47 => self.background = Color::Indexed((code - 40) as u8),
+            49 => self.background = Color::Default,
+            53 => self.attributes.insert(Attributes::OVERLINE),
+            55 => self.attributes.remove(Attributes::OVERLINE),
+            59 => self.underline_color = Color::Default,
+            90..=97 => self.foreground = Color::Indexed((code - 90 + 8) as u8),
+            100..=107 => self.background = Color::Indexed((code - 100 + 8) as u8),
+            _ => return false,
+        }
+
+        true
+    }
+
+    #[inline]
+    fn set_underline(&mut self, underline: Underline) -> bool {
+        self.underline = underline;
+        true
+    }
+
+    /// Sets a color from an extended color code (38, 48, or 58).
+    fn set_color(&mut self, code: u16, color: Option<Color>) -> bool {
+        let color = match color {
+            Some(color) => color,
+            None => return false,
+        };
+
+        match code {
+            38 => self.foreground = color,
+            48 => self.background = color,
+            _ => self.underline_color = color,
+        }
+
+        true
+    }
+}
+
+fn indexed(n: u16) -> Option<Color> {
+    if n <= 255 {
+        Some(Color::Indexed(n as u8))
+    } else {
+        None
+    }
+}
+
+fn rgb(r: u16, g: u16, b: u16) -> Option<Color> {
+    if r <= 255 && g <= 255 && b <= 255 {
+        Some(Color::Rgb(r as u8, g as u8, b as u8))
+    } else {
+        None
+    }
+}
+
+/// Writes the SGR parameters that set a color, using `base` as the code of the first standard color
+/// (30, 40, or 50).
+pub(crate) fn write_color_params(base: u16, color: Color, out: &mut String) {
+    let mut params: ParamBuffer = ParamBuffer::new();
+    ParamWriter(&mut params).color(base, color, ColorEncoding::default());
+    out.push_str(params.as_str());
+}
+
+/// Checks if two colors are the same, in a `const` function.
+const fn same_color(a: Color, b: Color) -> bool {
+    match (a, b) {
+        (Color::Default, Color::Default) => true,
+        (Color::Indexed(a), Color::Indexed(b)) => a == b,
+        (Color::Rgb(r1, g1, b1), Color::Rgb(r2, g2, b2)) => r1 == r2 && g1 == g2 && b1 == b2,
+        _ => false,
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Escape sequence bytes, written to a buffer on the stack.
+///
+/// Every style change writes its parameters twice to find the shortest sequence, so this avoids
+/// allocating for them. The parameters for a whole style (e.g. `22;1;2;25;5;6;...;58:2::r:g:b`)
+/// are never longer than about 100 bytes. Everything is `const`, so that sequences can be created
+/// at compile time with the same code.
+pub(crate) struct ParamBuffer<const N: usize = 128> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> ParamBuffer<N> {
+    #[inline]
+    pub const fn new() -> Self {
+        ParamBuffer { bytes: [0; N], len: 0 }
+    }
+
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub const fn as_bytes(&self) -> &[u8] {
+        self.bytes.split_at(self.len).0
+    }
+
+    #[inline]
+    pub const fn as_str(&self) -> &str {
+        match std::str::from_utf8(self.as_bytes()) {
+            Ok(s) => s,
+            Err(_) => panic!("escape sequences are ascii"),
+        }
+    }
+
+    /// Appends bytes, panicking if they don't fit.
+    pub const fn push(&mut self, bytes: &[u8]) {
+        assert!(self.len + bytes.len() <= N, "escape sequence is too long");
+        let mut i = 0;
+        while i < bytes.len() {
+            self.bytes[self.len + i] = bytes[i];
+            i += 1;
+        }
+
+        self.len += bytes.len();
+    }
+
+    /// Appends a number in decimal.
+    pub const fn push_number(&mut self, mut n: u16) {
+        let mut digits = [0; 5];
+        let mut start = digits.len();
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (n % 10) as u8;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+
+        self.push(digits.split_at(start).1);
+    }
+}
+
+/// A helper for writing `;`-separated SGR parameters.
+struct ParamWriter<'a, const N: usize>(&'a mut ParamBuffer<N>);
+
+impl<'a, const N: usize> ParamWriter<'a, N> {
+    const fn separator(&mut self) {
+        if !self.0.is_empty() {
+            self.0.push(b";");
+        }
+    }
+
+    const fn code(&mut self, code: u16) {
+        self.separator();
+        self.0.push_number(code);
+    }
+
+    const fn raw(&mut self, raw: &str) {
+        self.separator();
+        self.0.push(raw.as_bytes());
+    }
+
+    /// Writes sub-parameters after a separator (`;` or `:`).
+    const fn values(&mut self, separator: &[u8], values: &[u8]) {
+        let mut i = 0;
+        while i < values.len() {
+            self.0.push(separator);
+            self.0.push_number(values[i] as u16);
+            i += 1;
+        }
+    }
+
+    /// Writes a color, using `base` as the code of the first standard color (30, 40, or 50).
+    const fn color(&mut self, base: u16, color: Color, encoding: ColorEncoding) {
+        match (color, encoding) {
+            (Color::Default, _) => self.code(base + 9),
+            (Color::Indexed(n), _) if n < 8 && base != 50 => self.code(base + n as u16),
+            (Color::Indexed(n), _) if n < 16 && base != 50 => self.code(base + 60 + (n - 8) as u16),
+            (Color::Indexed(n), ColorEncoding::Semicolons) => {
+                self.code(base + 8);
+                self.values(b";", &[5, n]);
+            }
+            (Color::Indexed(n), ColorEncoding::Colons) => {
+                self.code(base + 8);
+                self.values(b":", &[5, n]);
+            }
+            (Color::Rgb(r, g, b), ColorEncoding::Semicolons) => {
+                self.code(base + 8);
+                self.values(b";", &[2, r, g, b]);
+            }
+            (Color::Rgb(r, g, b), ColorEncoding::Colons) => {
+                self.code(base + 8);
+                self.values(b":", &[2]);
+                self.0.push(b":");
+                self.values(b":", &[r, g, b]);
+            }
+        }
+    }
+}
+
+impl Display for Transition<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(prev) = self.prev {
+            return self.style.write_transition(prev, f);
+        }
+
+        let mut reset: ParamBuffer = ParamBuffer::new();
+        self.style.write_diff_params(&Style::default(), &Profile::default(), &mut reset);
+        match reset.as_str() {
+            "" => f.write_str("\x1B[m"),
+            params => write!(f, "\x1B[0;{}m", params),
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::ansi::Sequence;
+    use crate::style::*;
+    use std::convert::TryFrom;
+
+    fn sgr(style: &mut Style, s: &str) -> bool {
+        match Sequence::try_from(s) {
+            Ok(Sequence::CSI(csi)) => style.apply_sgr(csi.params()),
+            other => panic!("not a CSI sequence: {:?}", other),
+        }
+    }
+
+    fn transition(from: &str, to: &str) -> String {
+        let (mut prev, mut next) = (Style::default(), Style::default());
+        sgr(&mut prev, from);
+        sgr(&mut next, to);
+
+        let mut out = String::new();
+        next.write_transition(&prev, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn filter() {
+        let mut style = Style::default();
+        sgr(&mut style, "\x1B[1;5;7;4;31;44m");
+
+        let mut filtered = style;
+        let filter = StyleFilter {
+            attributes: Attributes::BLINK.union(Attributes::REVERSE),
+            background: true,
+            ..StyleFilter::default()
+        };
+
+        filter.apply(&mut filtered);
+        assert_eq!(filtered.attributes, Attributes::BOLD);
+        assert_eq!(filtered.underline, Underline::Single);
+        assert_eq!((filtered.foreground, filtered.background), (Color::Indexed(1), Color::Default));
+
+        StyleFilter::allow(Attributes::BOLD).apply(&mut style);
+        assert_eq!(style, Style { attributes: Attributes::BOLD, ..Style::default() });
+    }
+
+    #[test]
+    fn apply_sgr_codes() {
+        let mut style = Style::default();
+        assert!(sgr(&mut style, "\x1B[1;3;4;31;104m"));
+        assert_eq!(style.attributes, Attributes::BOLD.union(Attributes::ITALIC));
+        assert_eq!(style.underline, Underline::Single);
+        assert_eq!(style.foreground, Color::Indexed(1));
+        assert_eq!(style.background, Color::Indexed(12));
+
+        // Reset some of them.
+        assert!(sgr(&mut style, "\x1B[22;24;39m"));
+        assert_eq!(style.attributes, Attributes::ITALIC);
+        assert_eq!(style.underline, Underline::None);
+        assert_eq!(style.foreground, Color::Default);
+
+        // Reset all of them.
+        assert!(sgr(&mut style, "\x1B[m"));
+        assert!(style.is_default());
+    }
+
+    #[test]
+    fn apply_sgr_extended_colors() {
+        let mut style = Style::default();
+        assert!(sgr(&mut style, "\x1B[38;5;123;48;2;1;2;3m"));
+        assert_eq!(style.foreground, Color::Indexed(123));
+        assert_eq!(style.background, Color::Rgb(1, 2, 3));
+
+        assert!(sgr(&mut style, "\x1B[38:2::4:5:6;48:5:7;58:2:8:9:10m"));
+        assert_eq!(style.foreground, Color::Rgb(4, 5, 6));
+        assert_eq!(style.background, Color::Indexed(7));
+        assert_eq!(style.underline_color, Color::Rgb(8, 9, 10));
+
+        assert!(sgr(&mut style, "\x1B[4:3m"));
+        assert_eq!(style.underline, Underline::Curly);
+    }
+
+    #[test]
+    fn apply_sgr_unknown() {
+        let mut style = Style::default();
+        assert!(!sgr(&mut style, "\x1B[1;73m"));
+        assert_eq!(style.attributes, Attributes::BOLD);
+        assert!(!sgr(&mut style, "\x1B[38;5;300m"));
+    }
+
+    #[test]
+    fn apply_sgr_21() {
+        let apply = |sgr21| {
+            let mut style = Style { attributes: Attributes::BOLD, ..Style::default() };
+            let profile = Profile { sgr21, ..Profile::default() };
+            match Sequence::try_from("\x1B[21m") {
+                Ok(Sequence::CSI(csi)) => (style.apply_sgr_for(csi.params(), &profile), style),
+                other => panic!("not a CSI sequence: {:?}", other),
+            }
+        };
+
+        let (understood, style) = apply(Sgr21::DoubleUnderline);
+        assert!(understood);
+        assert_eq!((style.attributes, style.underline), (Attributes::BOLD, Underline::Double));
+
+        let (understood, style) = apply(Sgr21::NormalIntensity);
+        assert!(understood);
+        assert_eq!((style.attributes, style.underline), (Attributes::NONE, Underline::None));
+
+        assert!(!apply(Sgr21::Ambiguous).0);
+
+        let double = Style { underline: Underline::Double, ..Style::default() };
+        let profile = Profile { sgr21: Sgr21::Ambiguous, ..Profile::default() };
+        let mut out = String::new();
+        double.write_transition_for(&Style::default(), &profile, &mut out).unwrap();
+        assert_eq!(out, "\x1B[4:2m");
+    }
+
+    #[test]
+    fn replace_blink() {
+        let bold = StyleMapper::replace_blink(Attributes::BOLD);
+        let style = |attributes| Style { attributes, ..Style::default() };
+        let blink = Attributes::RAPID_BLINK.union(Attributes::ITALIC);
+        assert_eq!(bold.map(style(blink)), style(Attributes::BOLD.union(Attributes::ITALIC)));
+        assert_eq!(bold.map(style(Attributes::FAINT)), style(Attributes::FAINT));
+
+        let none = StyleMapper::replace_blink(Attributes::NONE);
+        assert_eq!(none.map(style(Attributes::BLINK)), Style::default());
+    }
+
+    #[test]
+    fn transitions_with_colons() {
+        let profile = Profile { color_encoding: ColorEncoding::Colons, ..Profile::default() };
+        let transition = |from, to| {
+            let (mut prev, mut next) = (Style::default(), Style::default());
+            sgr(&mut prev, from);
+            sgr(&mut next, to);
+
+            let mut out = String::new();
+            next.write_transition_for(&prev, &profile, &mut out).unwrap();
+            out
+        };
+
+        assert_eq!(transition("\x1B[m", "\x1B[38;5;208;48;2;1;2;3m"), "\x1B[38:5:208;48:2::1:2:3m");
+        assert_eq!(transition("\x1B[m", "\x1B[58:2::1:2:3;31m"), "\x1B[31;58:2::1:2:3m");
+        assert_eq!(transition("\x1B[38:5:1m", "\x1B[38;5;9m"), "\x1B[91m");
+    }
+
+    #[test]
+    fn transitions_fit_buffer() {
+        // Every attribute changes, and every color is the longest it can be. Resetting is shorter,
+        // but the changes are written too to find that out.
+        let white = Color::Rgb(255, 255, 255);
+        let prev = Style {
+            attributes: Attributes::BOLD.union(Attributes::BLINK),
+            ..Style::default()
+        };
+
+        let style = Style {
+            attributes: Attributes::ALL.difference(prev.attributes).union(Attributes::FAINT),
+            underline: Underline::Dashed,
+            foreground: white,
+            background: white,
+            underline_color: white,
+        };
+
+        let profile = Profile { color_encoding: ColorEncoding::Colons, ..Profile::default() };
+        let mut out = String::new();
+        style.write_transition_for(&prev, &profile, &mut out).unwrap();
+        assert_eq!(
+            out,
+            "\x1B[0;2;3;6;7;8;9;53;4:5;38:2::255:255:255;48:2::255:255:255;58:2::255:255:255m"
+        );
+    }
+
+    #[test]
+    fn transitions() {
+        assert_eq!(transition("\x1B[m", "\x1B[m"), "");
+        assert_eq!(transition("\x1B[m", "\x1B[1;31m"), "\x1B[1;31m");
+        assert_eq!(transition("\x1B[1;31m", "\x1B[m"), "\x1B[m");
+        assert_eq!(transition("\x1B[1;31m", "\x1B[1;32m"), "\x1B[32m");
+        assert_eq!(transition("\x1B[1;2;31m", "\x1B[2;31m"), "\x1B[22;2m");
+        assert_eq!(transition("\x1B[5;6;31m", "\x1B[6;31m"), "\x1B[25;6m");
+        assert_eq!(transition("\x1B[m", "\x1B[91;100m"), "\x1B[91;100m");
+        assert_eq!(transition("\x1B[m", "\x1B[38;5;200;58;5;1m"), "\x1B[38;5;200;58;5;1m");
+
+        // Resetting is shorter than turning off every attribute.
+        assert_eq!(transition("\x1B[1;3;7;9;31m", "\x1B[4m"), "\x1B[0;4m");
+    }
+
+    #[test]
+    fn transitions_from() {
+        let (mut prev, mut style) = (Style::default(), Style::default());
+        sgr(&mut prev, "\x1B[1;31m");
+        sgr(&mut style, "\x1B[1;38;5;208m");
+
+        assert_eq!(style.transition_from(Some(&prev)).to_string(), "\x1B[38;5;208m");
+        assert_eq!(prev.transition_from(Some(&prev)).to_string(), "");
+        assert_eq!(Style::default().transition_from(Some(&prev)).to_string(), "\x1B[m");
+
+        // The terminal's style isn't known, so it's always reset first.
+        assert_eq!(style.transition_from(None).to_string(), "\x1B[0;1;38;5;208m");
+        assert_eq!(Style::default().transition_from(None).to_string(), "\x1B[m");
+    }
+}
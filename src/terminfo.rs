@@ -0,0 +1,386 @@
+//! Creating a [Profile] from the terminfo database.
+//!
+//! The entry for the terminal named by `TERM` is read from the compiled terminfo database (the
+//! same files that ncurses uses), and the colors and sequences it supports are derived from its
+//! capabilities. This lets command-line tools convert colors correctly without asking the user.
+//!
+//! ```no_run
+//! # use ansi_optimizer::Options;
+//! let profile = ansi_optimizer::terminfo::profile()?;
+//! let options = Options { profile, ..Options::default() };
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::profile::{ColorSupport, Profile};
+use std::convert::TryFrom;
+use std::env;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+// -------------------------------------------------------------------------------------------------
+
+/// The magic number of entries with 16-bit numbers.
+const MAGIC_LEGACY: i16 = 0o432;
+
+/// The magic number of entries with 32-bit numbers.
+const MAGIC_EXTENDED_NUMBERS: i16 = 0o1036;
+
+/// The index of the `colors` number capability.
+const MAX_COLORS: usize = 13;
+
+/// The index of the `rep` string capability.
+const REPEAT_CHAR: usize = 121;
+
+/// The directories that are searched after the ones in the environment.
+const SYSTEM_DIRS: [&str; 4] =
+    ["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo", "/usr/lib/terminfo"];
+
+/// A compiled terminfo entry.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Terminfo {
+    names: Vec<String>,
+    booleans: Vec<bool>,
+    numbers: Vec<Option<i32>>,
+    strings: Vec<Option<Vec<u8>>>,
+    extended: Vec<(String, Extended)>,
+}
+
+/// The value of a user-defined (extended) capability.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Extended {
+    Boolean(bool),
+    Number(i32),
+    String(Vec<u8>),
+}
+
+impl Terminfo {
+    /// Reads the entry for the terminal named by the `TERM` environment variable.
+    pub fn from_env() -> io::Result<Self> {
+        match env::var("TERM") {
+            Ok(term) if !term.is_empty() => Terminfo::load(&term),
+            _ => Err(io::Error::new(ErrorKind::NotFound, "TERM isn't set")),
+        }
+    }
+
+    /// Reads the entry for a terminal from the terminfo database.
+    ///
+    /// The directories in `TERMINFO`, `~/.terminfo`, and `TERMINFO_DIRS` are searched before the
+    /// usual system directories.
+    pub fn load(term: &str) -> io::Result<Self> {
+        let first = match term.bytes().next() {
+            Some(first) if !term.contains('/') => first,
+            _ => return Err(io::Error::new(ErrorKind::InvalidInput, "invalid terminal name")),
+        };
+
+        for dir in search_dirs() {
+            let letter = dir.join(char::from(first).to_string()).join(term);
+            let hex = dir.join(format!("{:x}", first)).join(term);
+            for path in [letter, hex] {
+                match std::fs::read(&path) {
+                    Ok(bytes) => return Terminfo::parse(&bytes),
+                    Err(error) if error.kind() == ErrorKind::NotFound => continue,
+                    Err(error) => return Err(error),
+                }
+            }
+        }
+
+        Err(io::Error::new(ErrorKind::NotFound, format!("no terminfo entry for {}", term)))
+    }
+
+    /// Parses a compiled terminfo entry.
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader { bytes, offset: 0 };
+        let number_size = match reader.short()? {
+            MAGIC_LEGACY => 2,
+            MAGIC_EXTENDED_NUMBERS => 4,
+            _ => return Err(invalid("not a compiled terminfo entry")),
+        };
+
+        let [names_size, booleans, numbers, strings, table_size] = reader.counts()?;
+        let names = reader.take(names_size)?;
+        let names = String::from_utf8_lossy(names.split(|&b| b == 0).next().unwrap_or_default());
+
+        let mut info = Terminfo {
+            names: names.split('|').map(str::to_owned).collect(),
+            booleans: reader.take(booleans)?.iter().map(|&b| b == 1).collect(),
+            ..Terminfo::default()
+        };
+
+        reader.align();
+        info.numbers =
+            (0..numbers).map(|_| reader.number(number_size)).collect::<Result<_, _>>()?;
+        let offsets = (0..strings).map(|_| reader.short()).collect::<Result<Vec<_>, _>>()?;
+        let table = reader.take(table_size)?;
+        info.strings = offsets.iter().map(|&offset| string_at(table, offset)).collect();
+
+        // The extended capabilities are optional, and older versions of ncurses ignore them.
+        if reader.offset < bytes.len() {
+            reader.align();
+            info.extended = reader.extended(number_size)?;
+        }
+
+        Ok(info)
+    }
+
+    /// Gets the names of the terminal. The first is the one that `TERM` is set to.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Gets the number of colors that the terminal supports (`colors`).
+    pub fn max_colors(&self) -> Option<i32> {
+        self.numbers.get(MAX_COLORS).copied().flatten().filter(|&colors| colors > 0)
+    }
+
+    /// Checks if the terminal supports 24-bit RGB colors (the `RGB` or `Tc` extension).
+    pub fn has_rgb(&self) -> bool {
+        self.extended.iter().any(|(name, value)| match (name.as_str(), value) {
+            ("RGB", Extended::Boolean(set)) | ("Tc", Extended::Boolean(set)) => *set,
+            ("RGB", _) => true,
+            _ => false,
+        })
+    }
+
+    /// Checks if the terminal can repeat a character (`rep`).
+    pub fn has_repeat(&self) -> bool {
+        matches!(self.strings.get(REPEAT_CHAR), Some(Some(_)))
+    }
+
+    /// Creates a profile for the terminal.
+    ///
+    /// Capabilities that terminfo doesn't describe are left as they are in the default profile.
+    pub fn profile(&self) -> Profile {
+        let colors = match self.max_colors() {
+            _ if self.has_rgb() => ColorSupport::TrueColor,
+            Some(256..=i32::MAX) => ColorSupport::Ansi256,
+            Some(8..=255) => ColorSupport::Ansi16,
+            _ => ColorSupport::Monochrome,
+        };
+
+        Profile { colors, repeat: self.has_repeat(), ..Profile::default() }
+    }
+}
+
+/// Creates a profile for the terminal named by the `TERM` environment variable.
+///
+/// See [Terminfo::profile].
+pub fn profile() -> io::Result<Profile> {
+    Terminfo::from_env().map(|info| info.profile())
+}
+
+/// Gets the directories to search for terminfo entries, in order.
+fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = env::var_os("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(Path::new(&home).join(".terminfo"));
+    }
+
+    if let Ok(list) = env::var("TERMINFO_DIRS") {
+        for dir in list.split(':') {
+            match dir {
+                // An empty entry stands for the system directories.
+                "" => dirs.extend(SYSTEM_DIRS.iter().map(PathBuf::from)),
+                dir => dirs.push(PathBuf::from(dir)),
+            }
+        }
+    }
+
+    dirs.extend(SYSTEM_DIRS.iter().map(PathBuf::from));
+    dirs
+}
+
+/// Gets the string at an offset into a string table.
+fn string_at(table: &[u8], offset: i16) -> Option<Vec<u8>> {
+    let rest = table.get(usize::try_from(offset).ok()?..)?;
+    rest.split(|&b| b == 0).next().map(<[u8]>::to_vec)
+}
+
+/// Creates an error for an invalid entry.
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// A reader for the little-endian values of a compiled entry.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self.bytes.get(self.offset..(self.offset + len));
+        self.offset += len;
+        bytes.ok_or_else(|| invalid("truncated terminfo entry"))
+    }
+
+    fn short(&mut self) -> io::Result<i16> {
+        let bytes = self.take(2)?;
+        Ok(i16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Reads a number, where negative numbers mean that it's absent.
+    fn number(&mut self, size: usize) -> io::Result<Option<i32>> {
+        let number = match size {
+            2 => i32::from(self.short()?),
+            _ => {
+                let bytes = self.take(4)?;
+                i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+            }
+        };
+
+        Ok(Some(number).filter(|&n| n >= 0))
+    }
+
+    /// Reads a header of 5 counts.
+    fn counts(&mut self) -> io::Result<[usize; 5]> {
+        let mut counts = [0; 5];
+        for count in counts.iter_mut() {
+            *count = usize::try_from(self.short()?).map_err(|_| invalid("negative size"))?;
+        }
+
+        Ok(counts)
+    }
+
+    /// Skips a byte to get to an even offset.
+    fn align(&mut self) {
+        self.offset += self.offset % 2;
+    }
+
+    /// Reads the extended capabilities.
+    fn extended(&mut self, number_size: usize) -> io::Result<Vec<(String, Extended)>> {
+        let [booleans, numbers, strings, entries, table_size] = self.counts()?;
+        let booleans = self.take(booleans)?.to_vec();
+        self.align();
+        let numbers =
+            (0..numbers).map(|_| self.number(number_size)).collect::<Result<Vec<_>, _>>()?;
+        let offsets = (0..strings).map(|_| self.short()).collect::<Result<Vec<_>, _>>()?;
+        let count = booleans.len() + numbers.len() + offsets.len();
+        let name_offsets = (0..count).map(|_| self.short()).collect::<Result<Vec<_>, _>>()?;
+        let table = self.take(table_size)?;
+
+        // The names come after the values, which are all terminated.
+        let values = entries.checked_sub(count).ok_or_else(|| invalid("invalid string count"))?;
+        let names_start = match values {
+            0 => 0,
+            n => match table.iter().enumerate().filter(|(_, &b)| b == 0).nth(n - 1) {
+                Some((i, _)) => i + 1,
+                None => return Err(invalid("truncated string table")),
+            },
+        };
+
+        let names = &table[names_start..];
+        let name = |i: usize| {
+            let name = string_at(names, name_offsets[i]).unwrap_or_default();
+            String::from_utf8_lossy(&name).into_owned()
+        };
+
+        let mut extended = Vec::with_capacity(count);
+        for (i, &set) in booleans.iter().enumerate() {
+            extended.push((name(i), Extended::Boolean(set == 1)));
+        }
+
+        for (i, number) in numbers.iter().enumerate() {
+            if let Some(number) = number {
+                extended.push((name(booleans.len() + i), Extended::Number(*number)));
+            }
+        }
+
+        for (i, &offset) in offsets.iter().enumerate() {
+            if let Some(string) = string_at(table, offset) {
+                let i = booleans.len() + numbers.len() + i;
+                extended.push((name(i), Extended::String(string)));
+            }
+        }
+
+        Ok(extended)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::terminfo::*;
+
+    /// Compiles an entry with the `colors` number, the `rep` string, and extended booleans.
+    fn compile(colors: i16, repeat: bool, extended: &[&str]) -> Vec<u8> {
+        fn short(out: &mut Vec<u8>, n: usize) {
+            out.extend_from_slice(&(n as i16).to_le_bytes());
+        }
+
+        let names = b"test|a test terminal\0";
+        let rep = b"%p1%c\x1B[%p2%{1}%-%db\0";
+        let mut out = Vec::new();
+        for n in [MAGIC_LEGACY as usize, names.len(), 0, MAX_COLORS + 1, REPEAT_CHAR + 1, rep.len()]
+        {
+            short(&mut out, n);
+        }
+
+        out.extend_from_slice(names);
+        out.resize(out.len() + out.len() % 2, 0);
+        for i in 0..=MAX_COLORS {
+            out.extend_from_slice(&(if i == MAX_COLORS { colors } else { -1 }).to_le_bytes());
+        }
+
+        for i in 0..=REPEAT_CHAR {
+            out.extend_from_slice(
+                &(if i == REPEAT_CHAR && repeat { 0i16 } else { -1 }).to_le_bytes(),
+            );
+        }
+
+        out.extend_from_slice(rep);
+        out.resize(out.len() + out.len() % 2, 0);
+
+        let table = extended.iter().map(|name| format!("{}\0", name)).collect::<String>();
+        for n in [extended.len(), 0, 0, extended.len(), table.len()] {
+            short(&mut out, n);
+        }
+
+        out.extend(extended.iter().map(|_| 1));
+        out.resize(out.len() + out.len() % 2, 0);
+        let mut offset = 0;
+        for name in extended {
+            short(&mut out, offset);
+            offset += name.len() + 1;
+        }
+
+        out.extend_from_slice(table.as_bytes());
+        out
+    }
+
+    #[test]
+    fn parse_entries() {
+        let info = Terminfo::parse(&compile(256, true, &[])).unwrap();
+        assert_eq!(info.names(), ["test", "a test terminal"]);
+        assert_eq!(info.max_colors(), Some(256));
+        assert!(info.has_repeat());
+        assert!(!info.has_rgb());
+
+        let info = Terminfo::parse(&compile(8, false, &["AX", "Tc"])).unwrap();
+        assert_eq!(info.max_colors(), Some(8));
+        assert!(!info.has_repeat());
+        assert!(info.has_rgb());
+
+        assert!(Terminfo::parse(b"\x1A\x01\x02").is_err());
+        assert!(Terminfo::parse(b"not terminfo").is_err());
+    }
+
+    #[test]
+    fn create_profiles() {
+        let profile = |colors, repeat, extended| {
+            Terminfo::parse(&compile(colors, repeat, extended)).unwrap().profile()
+        };
+
+        assert_eq!(profile(256, true, &[]).colors, ColorSupport::Ansi256);
+        assert!(profile(256, true, &[]).repeat);
+        assert_eq!(profile(16, false, &[]).colors, ColorSupport::Ansi16);
+        assert_eq!(profile(-1, false, &[]).colors, ColorSupport::Monochrome);
+        assert_eq!(profile(256, false, &["RGB"]).colors, ColorSupport::TrueColor);
+    }
+}
@@ -0,0 +1,340 @@
+//! Generators for random escape sequences and text, for fuzzing and property-based tests.
+//!
+//! This is behind the `testing` feature, and uses the [arbitrary] crate. [Sequence] implements
+//! [Arbitrary], and [ValidStream] and [NearValidStream] generate whole strings of input.
+//! [assert_visually_equivalent] checks that optimized output looks the same as the input, using
+//! the terminal emulator from the [vt100] crate.
+//!
+//! ```
+//! # use ansi_optimizer::testing::ValidStream;
+//! use arbitrary::{Arbitrary, Unstructured};
+//!
+//! let mut u = Unstructured::new(&[7, 1, 200, 3, 42, 9, 18, 255, 0, 64]);
+//! let ValidStream(input) = ValidStream::arbitrary(&mut u).unwrap();
+//! assert!(ansi_optimizer::optimize(&input).is_ok());
+//! ```
+
+use crate::ansi::{AnsiSequence, AnsiString, ControlSequence, Sequence};
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+// -------------------------------------------------------------------------------------------------
+
+/// A random string of text and valid escape sequences.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct ValidStream(pub String);
+
+/// A random string of text and escape sequences, some of which are invalid or cut short.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct NearValidStream(pub String);
+
+// -------------------------------------------------------------------------------------------------
+
+/// Control sequence parameter bytes. Every slice of this is a valid parameter string.
+const PARAMETERS: &str = "0;1;22;38;5;196;48;2;10;20;30;4:3;58:2::1:2:3;?1049;>1;=0123456789<";
+
+/// Intermediate bytes.
+const INTERMEDIATES: &str = " !\"#$%&'()*+,-./";
+
+/// Control sequence finalizer bytes.
+const CSI_FINALIZERS: &str = "@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+/// Regular sequence finalizer bytes, except for the ones that start other kinds of sequences.
+const FINALIZERS: &str =
+    "0123456789:;<=>?@ABCDEFGHIJKLMNOQRSTUVWXYZ\\^`abcdefghijklmnopqrstuvwxyz{|}~";
+
+/// The strings of OSC sequences.
+const OSC_STRINGS: &[&str] = &[
+    "0;title",
+    "2;",
+    "4;1;rgb:ff/00/00",
+    "4;1;?",
+    "7;file:///tmp",
+    "8;;https://example.com",
+    "8;;",
+    "10;#ffffff",
+    "11;?",
+    "52;c;dGV4dA==",
+    "104",
+    "104;1",
+    "112",
+    "1337;File=inline=1:AAAA",
+];
+
+/// The strings of DCS sequences.
+const DCS_STRINGS: &[&str] =
+    &["q#0;2;0;0;0#0~~-", "$qm", "+q544e", "1$r0m", "tmux;\x1B\x1B[1m", "tmux;\x1B\x1B]0;a\x07"];
+
+/// The strings of APC sequences.
+const APC_STRINGS: &[&str] = &["Ga=T,f=100;AAAA", "Gi=1;OK", ""];
+
+/// Runs of text, including control characters and wide characters.
+const TEXT: &[&str] = &[
+    "text",
+    "a",
+    "aaaaaaaa",
+    " ",
+    "        ",
+    "é",
+    "e\u{301}",
+    "日本語",
+    "👍",
+    "\r",
+    "\n",
+    "\r\n",
+    "\t",
+    "\x08",
+    "\x07",
+];
+
+/// Escape sequences that are invalid.
+const INVALID: &[&str] = &["\x1B\x01", "\x1B\n", "\x1B\x7F", "\x1B\u{e9}", "\x1B]0;a\x1Bx"];
+
+// -------------------------------------------------------------------------------------------------
+
+impl<'a> Arbitrary<'a> for Sequence<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=4)? {
+            0 => Sequence::CSI(ControlSequence {
+                parameters: slice(u, PARAMETERS, 16)?,
+                intermediates: intermediates(u)?,
+                finalizer: char_of(u, CSI_FINALIZERS)?,
+            }),
+            1 => Sequence::OSC(introducer("]"), string(u, OSC_STRINGS, true)?),
+            2 => Sequence::DCS(introducer("P"), string(u, DCS_STRINGS, false)?),
+            3 => Sequence::APC(introducer("_"), string(u, APC_STRINGS, true)?),
+            _ => Sequence::Regular(AnsiSequence {
+                intermediates: intermediates(u)?,
+                finalizer: char_of(u, FINALIZERS)?,
+            }),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for ValidStream {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut stream = String::new();
+        for _ in 0..u.int_in_range(0..=64)? {
+            if u.ratio(1, 2)? {
+                stream.push_str(u.choose(TEXT)?);
+            } else {
+                stream.push_str(&Sequence::arbitrary(u)?.to_string());
+            }
+        }
+
+        Ok(ValidStream(stream))
+    }
+}
+
+impl<'a> Arbitrary<'a> for NearValidStream {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut stream = String::new();
+        for _ in 0..u.int_in_range(0..=64)? {
+            match u.int_in_range(0..=5)? {
+                0 | 1 => stream.push_str(u.choose(TEXT)?),
+                2 | 3 => stream.push_str(&Sequence::arbitrary(u)?.to_string()),
+                4 => stream.push_str(u.choose(INVALID)?),
+                _ => {
+                    // Every generated sequence is ASCII, so it can be cut anywhere.
+                    let sequence = Sequence::arbitrary(u)?.to_string();
+                    let len = u.int_in_range(1..=(sequence.len() - 1))?;
+                    stream.push_str(&sequence[..len]);
+                }
+            }
+        }
+
+        Ok(NearValidStream(stream))
+    }
+}
+
+/// Picks a slice of an ASCII string, of at most `max` bytes.
+fn slice(u: &mut Unstructured<'_>, pool: &'static str, max: usize) -> Result<&'static str> {
+    let start = u.int_in_range(0..=pool.len())?;
+    let len = u.int_in_range(0..=max.min(pool.len() - start))?;
+    Ok(&pool[start..(start + len)])
+}
+
+/// Picks a single character of an ASCII string.
+fn char_of(u: &mut Unstructured<'_>, pool: &'static str) -> Result<&'static str> {
+    let i = u.choose_index(pool.len())?;
+    Ok(&pool[i..=i])
+}
+
+/// Picks the intermediates of a sequence, which are usually empty.
+fn intermediates(u: &mut Unstructured<'_>) -> Result<&'static str> {
+    match u.ratio(1, 4)? {
+        true => slice(u, INTERMEDIATES, 2),
+        false => Ok(""),
+    }
+}
+
+/// Creates the sequence that starts a string.
+fn introducer(finalizer: &'static str) -> AnsiSequence<'static> {
+    AnsiSequence { intermediates: "", finalizer }
+}
+
+/// Picks the text of a string, and whether it's terminated by `BEL` or `ESC \`.
+fn string(
+    u: &mut Unstructured<'_>,
+    pool: &[&'static str],
+    bel: bool,
+) -> Result<AnsiString<'static>> {
+    Ok(AnsiString {
+        text: u.choose(pool)?,
+        finalizer: if bel && u.arbitrary()? { "\x07" } else { "\x1B\\" },
+    })
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Asserts that two strings look the same when written to a terminal with the given size.
+///
+/// Both are written to an emulated terminal, and the text, styles, and cursor are compared
+/// afterwards. Anything that can't be seen (e.g. the current style) isn't compared.
+///
+/// ```
+/// # use ansi_optimizer::testing::assert_visually_equivalent;
+/// let input = "\x1B[31m\x1B[1mred\x1B[m\x1B[2;1Hnext";
+/// assert_visually_equivalent(input, &ansi_optimizer::optimize(input).unwrap(), 80, 24);
+/// ```
+///
+/// ## Panics
+///
+/// If any cell or the cursor looks different. The message shows the first cell that differs.
+#[track_caller]
+pub fn assert_visually_equivalent(a: &str, b: &str, columns: u16, rows: u16) {
+    let render = |input: &str| {
+        let mut parser = vt100::Parser::new(rows, columns, 0);
+        parser.process(input.as_bytes());
+        parser
+    };
+
+    let (a_parser, b_parser) = (render(a), render(b));
+    let (a_screen, b_screen) = (a_parser.screen(), b_parser.screen());
+    for row in 0..rows {
+        for column in 0..columns {
+            let a_cell = a_screen.cell(row, column);
+            let b_cell = b_screen.cell(row, column);
+            assert!(
+                a_cell == b_cell,
+                "cell at row {}, column {} differs: {:?} != {:?}\n{:?}\n{:?}",
+                row,
+                column,
+                a_cell,
+                b_cell,
+                a,
+                b
+            );
+        }
+    }
+
+    let a_cursor = (a_screen.cursor_position(), a_screen.hide_cursor());
+    let b_cursor = (b_screen.cursor_position(), b_screen.hide_cursor());
+    assert!(
+        a_cursor == b_cursor,
+        "cursor differs: {:?} != {:?}\n{:?}\n{:?}",
+        a_cursor,
+        b_cursor,
+        a,
+        b
+    );
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::*;
+    use crate::token::{Token, Tokens};
+    use crate::Optimizer;
+    use std::convert::TryFrom;
+
+    /// Generates random bytes for creating values, using a fixed seed so failures can be
+    /// reproduced.
+    fn random_bytes(count: usize) -> impl Iterator<Item = Vec<u8>> {
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        (0..count).map(move |_| {
+            let mut bytes = vec![0u8; 1024];
+            for byte in &mut bytes {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                *byte = (state >> 24) as u8;
+            }
+
+            bytes
+        })
+    }
+
+    /// Runs a test with many random values.
+    fn for_each_random<T: for<'a> Arbitrary<'a>>(mut test: impl FnMut(T)) {
+        for bytes in random_bytes(500) {
+            test(T::arbitrary(&mut Unstructured::new(&bytes)).unwrap());
+        }
+    }
+
+    #[test]
+    fn sequences_roundtrip() {
+        for bytes in random_bytes(500) {
+            let mut u = Unstructured::new(&bytes);
+            while !u.is_empty() {
+                let sequence = Sequence::arbitrary(&mut u).unwrap();
+                let text = sequence.to_string();
+                assert_eq!(Sequence::try_from(text.as_str()), Ok(sequence), "{:?}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn valid_streams_tokenize() {
+        for_each_random(|ValidStream(input)| {
+            let mut joined = String::new();
+            for token in Tokens::new(&input) {
+                match token {
+                    Ok(Token::Text(text)) => joined.push_str(text),
+                    Ok(Token::Sequence(sequence)) => joined.push_str(&sequence.to_string()),
+                    Err(error) => panic!("{:?} in {:?}", error, input),
+                }
+            }
+
+            assert_eq!(joined, input);
+            assert!(crate::optimize(&input).is_ok());
+        });
+    }
+
+    #[test]
+    fn visually_equivalent() {
+        let input = "\x1B[1m\x1B[31mab\x1B[0m\x1B[2;1H\x1B[2;1Hcd\x1B[?25l\x1B[?25h";
+        assert_visually_equivalent(input, &crate::optimize(input).unwrap(), 10, 3);
+        assert_visually_equivalent("ab\x1B[1m", "ab", 10, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "cell at row 0, column 1 differs")]
+    fn visually_different_cells() {
+        assert_visually_equivalent("ab", "a\x1B[1mb", 10, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "cursor differs")]
+    fn visually_different_cursor() {
+        assert_visually_equivalent("ab", "ab\x08", 10, 3);
+    }
+
+    #[test]
+    fn near_valid_streams_split() {
+        for_each_random(|NearValidStream(input)| {
+            let optimize = |chunks: &[&str]| {
+                let mut optimizer = Optimizer::new();
+                chunks.iter().for_each(|chunk| optimizer.feed(chunk));
+                optimizer.finish();
+                optimizer.to_string()
+            };
+
+            // The output doesn't depend on where the stream is split into chunks.
+            let whole = optimize(&[&input]);
+            for (i, _) in input.char_indices().step_by(7) {
+                assert_eq!(optimize(&[&input[..i], &input[i..]]), whole, "{:?} at {}", input, i);
+            }
+        });
+    }
+}
@@ -0,0 +1,104 @@
+//! Helpers for tmux's passthrough sequence.
+//!
+//! tmux interprets the sequences written to a pane itself, and only forwards the ones it
+//! understands to the outer terminal. Other sequences (e.g. images or clipboard writes) need to be
+//! wrapped in a passthrough sequence, which has every `ESC` in it doubled:
+//!
+//! ```text
+//! DCS tmux; ESC ESC ] 52 ; c ; ... BEL ST
+//! ```
+//!
+//! The [Optimizer](crate::Optimizer) passes wrapped sequences through untouched. To optimize the
+//! sequences inside them (e.g. when replaying a recording outside of tmux), [unwrap] the input
+//! first.
+
+use crate::ansi::{AnsiString, Sequence};
+use crate::token::{Token, Tokens};
+
+// -------------------------------------------------------------------------------------------------
+
+/// The prefix of a passthrough sequence's string.
+const PREFIX: &str = "tmux;";
+
+/// Wraps text in a tmux passthrough sequence.
+pub fn wrap(input: &str) -> String {
+    let mut output = String::with_capacity(input.len() + 12);
+    wrap_into(input, &mut output);
+    output
+}
+
+/// Wraps text in a tmux passthrough sequence, appending the result to an existing buffer.
+///
+/// This behaves the same as [wrap], but allows the output buffer to be reused between calls.
+pub fn wrap_into(input: &str, output: &mut String) {
+    output.push_str("\x1BP");
+    output.push_str(PREFIX);
+    for (i, part) in input.split('\x1B').enumerate() {
+        if i > 0 {
+            output.push_str("\x1B\x1B");
+        }
+
+        output.push_str(part);
+    }
+
+    output.push_str("\x1B\\");
+}
+
+/// Replaces every tmux passthrough sequence with the sequences wrapped inside it.
+///
+/// Everything else, including invalid or unfinished sequences, is kept as-is.
+pub fn unwrap(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    unwrap_into(input, &mut output);
+    output
+}
+
+/// Replaces every tmux passthrough sequence with the sequences wrapped inside it, appending the
+/// result to an existing buffer.
+///
+/// This behaves the same as [unwrap], but allows the output buffer to be reused between calls.
+pub fn unwrap_into(input: &str, output: &mut String) {
+    for (span, token) in Tokens::new(input).spanned() {
+        match token {
+            Ok(Token::Sequence(Sequence::DCS(_, ref string))) if is_passthrough(string) => {
+                let payload = &string.text()[PREFIX.len()..];
+                for (i, part) in payload.split("\x1B\x1B").enumerate() {
+                    if i > 0 {
+                        output.push('\x1B');
+                    }
+
+                    output.push_str(part);
+                }
+            }
+
+            _ => output.push_str(&input[span.start..span.end]),
+        }
+    }
+}
+
+/// Checks if the string of a device control string is a tmux passthrough sequence.
+#[inline]
+fn is_passthrough(string: &AnsiString) -> bool {
+    string.text().starts_with(PREFIX)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::tmux::*;
+
+    #[test]
+    fn wrap_sequences() {
+        assert_eq!(wrap("\x1B]52;c;dGV4dA==\x07"), "\x1BPtmux;\x1B\x1B]52;c;dGV4dA==\x07\x1B\\");
+        assert_eq!(wrap("\x1B_Ga=T;AAAA\x1B\\"), "\x1BPtmux;\x1B\x1B_Ga=T;AAAA\x1B\x1B\\\x1B\\");
+    }
+
+    #[test]
+    fn unwrap_sequences() {
+        let inner = "\x1B_Ga=T;AAAA\x1B\\\x1B[1mx";
+        assert_eq!(unwrap(&format!("a{}b", wrap(inner))), format!("a{}b", inner));
+        assert_eq!(unwrap("\x1BP$qm\x1B\\\x1B[1m"), "\x1BP$qm\x1B\\\x1B[1m");
+        assert_eq!(unwrap("a\x1B\x01\x1BPtmux;\x1B\x1B"), "a\x1B\x01\x1BPtmux;\x1B\x1B");
+    }
+}
@@ -0,0 +1,422 @@
+use crate::ansi::{Parse, Sequence};
+use crate::error::{Error, Result};
+use crate::lex::Lexer;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A token of text containing ANSI escape sequences.
+#[derive(Eq, PartialEq, Debug)]
+pub enum Token<'a> {
+    /// A run of text without any escape sequences.
+    /// This may still contain other control characters (e.g. `\n` or `\x08`).
+    Text(&'a str),
+
+    /// A single escape sequence.
+    Sequence(Sequence<'a>),
+}
+
+/// A range of byte offsets within the original input.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Span {
+    /// The offset of the first byte.
+    pub start: usize,
+
+    /// The offset after the last byte.
+    pub end: usize,
+}
+
+/// An iterator that splits a string into [Token]s.
+///
+/// Sequences can also start with an 8-bit (C1) control character, like `U+009B` for CSI. These
+/// are yielded as the 7-bit sequences they're equivalent to, so the [Display](std::fmt::Display)
+/// of a sequence might not match the input exactly. Its [Span] always does.
+///
+/// If an escape sequence is invalid, [Error::InvalidSequence] is yielded and tokenizing resumes
+/// from the character after the `ESC` (or C1 control). If the input ends partway through an escape
+/// sequence, [Error::Incomplete] is yielded and [Tokens::remaining] will contain the unfinished
+/// sequence.
+#[derive(Clone, Debug)]
+pub struct Tokens<'a> {
+    source: &'a str,
+    remaining: &'a str,
+    stalled: bool,
+}
+
+impl<'a> Tokens<'a> {
+    /// Creates a tokenizer over a string.
+    pub fn new(input: &'a str) -> Self {
+        Tokens {
+            source: input,
+            remaining: input,
+            stalled: false,
+        }
+    }
+
+    /// Gets the remaining text that hasn't been tokenized.
+    #[inline]
+    pub fn remaining(&self) -> &'a str {
+        self.remaining
+    }
+
+    /// Gets the byte offset of the next token from the start of the input.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.source.len() - self.remaining.len()
+    }
+
+    /// Converts this into an iterator that also yields the [Span] of each token.
+    ///
+    /// The span of an [Error::InvalidSequence] covers the skipped `ESC` (or C1 control), and the
+    /// span of an [Error::Incomplete] covers the unfinished sequence.
+    #[inline]
+    pub fn spanned(self) -> SpannedTokens<'a> {
+        SpannedTokens { tokens: self }
+    }
+}
+
+/// An iterator that splits a string into [Token]s and their [Span]s.
+///
+/// This is created by [Tokens::spanned].
+#[derive(Clone, Debug)]
+pub struct SpannedTokens<'a> {
+    tokens: Tokens<'a>,
+}
+
+impl<'a> SpannedTokens<'a> {
+    /// Gets the remaining text that hasn't been tokenized.
+    #[inline]
+    pub fn remaining(&self) -> &'a str {
+        self.tokens.remaining()
+    }
+}
+
+impl<'a> Iterator for SpannedTokens<'a> {
+    type Item = (Span, Result<Token<'a>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.tokens.offset();
+        let token = self.tokens.next()?;
+        let end = match token {
+            Err(Error::Incomplete) => self.tokens.source.len(),
+            _ => self.tokens.offset(),
+        };
+
+        Some((Span { start, end }, token))
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Result<Token<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining.is_empty() || self.stalled {
+            return None;
+        }
+
+        // Plain text makes up most of the input, so skip straight to the next escape sequence.
+        let text_len = find_escape(self.remaining).unwrap_or(self.remaining.len());
+        if text_len > 0 {
+            let (text, rest) = self.remaining.split_at(text_len);
+            self.remaining = rest;
+            return Some(Ok(Token::Text(text)));
+        }
+
+        let mut lexer = Lexer::new(self.remaining);
+        match Sequence::parse(&mut lexer) {
+            Ok(sequence) => {
+                self.remaining = lexer.remaining();
+                Some(Ok(Token::Sequence(sequence)))
+            }
+            Err(Error::Incomplete) => {
+                self.stalled = true;
+                Some(Err(Error::Incomplete))
+            }
+            Err(error) => {
+                let opener = self.remaining.chars().next().map_or(1, char::len_utf8);
+                self.remaining = &self.remaining[opener..];
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// Splits a string into [Token]s.
+#[inline]
+pub fn tokenize(input: &str) -> Tokens<'_> {
+    Tokens::new(input)
+}
+
+impl Span {
+    /// Gets the length of the span in bytes.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Returns `true` if the span is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Gets the 1-based line and column (in characters) of the start of the span.
+    ///
+    /// ## Arguments
+    ///
+    /// - `source`: The input that the span was created from.
+    pub fn line_column(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.start];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line = before.bytes().filter(|&b| b == b'\n').count() + 1;
+        let column = before[line_start..].chars().count() + 1;
+        (line, column)
+    }
+
+    /// Gets the 1-based column (in terminal cells) of the start of the span.
+    ///
+    /// Unlike [Span::line_column], escape sequences take up no space and wide characters take up
+    /// two cells. See [crate::width].
+    ///
+    /// ## Arguments
+    ///
+    /// - `source`: The input that the span was created from.
+    pub fn screen_column(&self, source: &str) -> usize {
+        let before = &source[..self.start];
+        let line_start = before.rfind(['\n', '\r']).map_or(0, |i| i + 1);
+        crate::width::width(&before[line_start..]) + 1
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Converts the tokens of some input to JSON, with one object per line.
+///
+/// Each object has the byte `offset` of the token, its `type`, and its `text`. The type is one of
+/// `text`, `csi`, `osc`, `dcs`, `apc`, `escape`, or `error`, and the other fields depend on it:
+///
+/// ```
+/// # use ansi_optimizer::token::to_json_tokens;
+/// assert_eq!(
+///     to_json_tokens("a\x1B[?1;38:2::9m"),
+///     concat!(
+///         r#"{"offset":0,"text":"a","type":"text"}"#, "\n",
+///         r#"{"finalizer":"m","intermediates":"","offset":1,"params":[1,[38,2,null,9]],"#,
+///         r#""private_marker":"?","text":"\u001b[?1;38:2::9m","type":"csi"}"#, "\n",
+///     )
+/// );
+/// ```
+#[cfg(feature = "serde")]
+pub fn to_json_tokens(input: &str) -> String {
+    use crate::ansi::Param;
+    use serde_json::{json, Value};
+
+    let mut output = String::new();
+    for (span, token) in Tokens::new(input).spanned() {
+        let text = &input[span.start..span.end];
+        let mut value = match token {
+            Ok(Token::Text(_)) => json!({ "type": "text" }),
+            Ok(Token::Sequence(Sequence::CSI(csi))) => {
+                let params = csi.params().map(|param| match param {
+                    Param::Empty => Value::Null,
+                    Param::Int(n) => json!(n),
+                    Param::Sub(sub) => json!(sub.collect::<Vec<_>>()),
+                });
+
+                json!({
+                    "type": "csi",
+                    "private_marker": csi.private_marker(),
+                    "params": params.collect::<Vec<_>>(),
+                    "intermediates": csi.intermediates(),
+                    "finalizer": csi.finalizer(),
+                })
+            }
+            Ok(Token::Sequence(Sequence::OSC(_, string))) => json!({
+                "type": "osc",
+                "command": string.command().map(|(command, _)| command),
+                "data": string.text(),
+            }),
+            Ok(Token::Sequence(Sequence::DCS(_, string))) => {
+                json!({ "type": "dcs", "data": string.text() })
+            }
+            Ok(Token::Sequence(Sequence::APC(_, string))) => {
+                json!({ "type": "apc", "data": string.text() })
+            }
+            Ok(Token::Sequence(Sequence::Regular(sequence))) => json!({
+                "type": "escape",
+                "intermediates": sequence.intermediates(),
+                "finalizer": sequence.finalizer(),
+            }),
+            Err(Error::InvalidSequence) => json!({ "type": "error", "error": "invalid" }),
+            Err(Error::Incomplete) => json!({ "type": "error", "error": "incomplete" }),
+            Err(Error::LimitExceeded) => json!({ "type": "error", "error": "limit" }),
+        };
+
+        value["offset"] = json!(span.start);
+        value["text"] = json!(text);
+        output.push_str(&value.to_string());
+        output.push('\n');
+    }
+
+    output
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Finds the byte index of the next `ESC` or C1 control character.
+///
+/// C1 controls are encoded as `0xC2` followed by `0x80` to `0x9F`. The same lead byte is used by
+/// other Latin-1 characters (e.g. `U+00A0`), which are skipped.
+pub(crate) fn find_escape(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut start = 0;
+    while let Some(found) = find_escape_byte(&bytes[start..]) {
+        let i = start + found;
+        if bytes[i] == 0x1B || matches!(bytes.get(i + 1), Some(0x80..=0x9F)) {
+            return Some(i);
+        }
+
+        start = i + 1;
+    }
+
+    None
+}
+
+/// Finds the byte index of the next `ESC` byte or `0xC2` lead byte.
+#[cfg(feature = "memchr")]
+#[inline]
+fn find_escape_byte(bytes: &[u8]) -> Option<usize> {
+    memchr::memchr2(0x1B, 0xC2, bytes)
+}
+
+/// Finds the byte index of the next `ESC` byte or `0xC2` lead byte.
+#[cfg(not(feature = "memchr"))]
+#[inline]
+fn find_escape_byte(bytes: &[u8]) -> Option<usize> {
+    bytes.iter().position(|&b| b == 0x1B || b == 0xC2)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::token::*;
+    use std::convert::TryFrom;
+
+    fn seq(s: &str) -> Token<'_> {
+        Token::Sequence(Sequence::try_from(s).unwrap())
+    }
+
+    #[test]
+    fn tokenize_text_and_sequences() {
+        let tokens = tokenize("Hello\x1B[1mworld\x1B[m\x1B7\n").collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Ok(Token::Text("Hello")),
+                Ok(seq("\x1B[1m")),
+                Ok(Token::Text("world")),
+                Ok(seq("\x1B[m")),
+                Ok(seq("\x1B7")),
+                Ok(Token::Text("\n")),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_invalid() {
+        let tokens = tokenize("a\x1B\x01b").collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Ok(Token::Text("a")),
+                Err(Error::InvalidSequence),
+                Ok(Token::Text("\x01b")),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_incomplete() {
+        let mut tokens = tokenize("text\x1B[38;5");
+        assert_eq!(tokens.next(), Some(Ok(Token::Text("text"))));
+        assert_eq!(tokens.next(), Some(Err(Error::Incomplete)));
+        assert_eq!(tokens.remaining(), "\x1B[38;5");
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn tokenize_c1_controls() {
+        // Latin-1 characters that aren't controls share their lead byte with C1 controls.
+        let tokens = tokenize("\u{A0}a\u{9B}1m\u{9D}0;title\u{9C}\u{90}1$r\u{9C}\u{85}\u{9B}\x01");
+        assert_eq!(
+            tokens.collect::<Vec<_>>(),
+            vec![
+                Ok(Token::Text("\u{A0}a")),
+                Ok(seq("\x1B[1m")),
+                Ok(seq("\x1B]0;title\x1B\\")),
+                Ok(seq("\x1BP1$r\x1B\\")),
+                Ok(seq("\x1BE")),
+                Err(Error::InvalidSequence),
+                Ok(Token::Text("\x01")),
+            ]
+        );
+
+        // Spans cover the 8-bit input, and sequences are written as 7-bit ones.
+        let input = "\u{9B}6n";
+        let (span, token) = tokenize(input).spanned().next().unwrap();
+        assert_eq!(span, Span { start: 0, end: input.len() });
+        assert_eq!(token.unwrap(), seq("\x1B[6n"));
+        assert_eq!(Sequence::try_from("\u{9B}?25h").unwrap().to_string(), "\x1B[?25h");
+        assert_eq!(find_escape("\u{A9}\u{B0} \u{9B}"), Some(5));
+        assert_eq!(find_escape("\u{A9}\u{B0}"), None);
+
+        let mut tokens = tokenize("\u{9D}8;;http://a");
+        assert_eq!(tokens.next(), Some(Err(Error::Incomplete)));
+    }
+
+    #[test]
+    fn tokenize_spans() {
+        let input = "ab\n\x1B[1mc\x1B\x01\x1B[3";
+        let spans = tokenize(input).spanned().map(|(span, _)| span).collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                Span { start: 0, end: 3 },
+                Span { start: 3, end: 7 },
+                Span { start: 7, end: 8 },
+                Span { start: 8, end: 9 },
+                Span { start: 9, end: 10 },
+                Span { start: 10, end: 13 },
+            ]
+        );
+
+        // Line and column of the sequence after the newline.
+        assert_eq!(spans[1].line_column(input), (2, 1));
+        assert_eq!(spans[3].line_column(input), (2, 6));
+        assert_eq!(spans[3].screen_column(input), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_tokens() {
+        let json = to_json_tokens("\x1B]0;title\x07\x1BP1$r\x1B\\\x1B(B\x1B\x01");
+        let lines = json.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                r#"{"command":0,"data":"0;title","offset":0,"text":"\u001b]0;title\u0007","type":"osc"}"#,
+                r#"{"data":"1$r","offset":10,"text":"\u001bP1$r\u001b\\","type":"dcs"}"#,
+                r#"{"finalizer":"B","intermediates":"(","offset":17,"text":"\u001b(B","type":"escape"}"#,
+                r#"{"error":"invalid","offset":20,"text":"\u001b","type":"error"}"#,
+                r#"{"offset":21,"text":"\u0001","type":"text"}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn display_roundtrip() {
+        for s in &["\x1B[38;2;1;2;3m", "\x1B]0;Title\x1B\\", "\x1B(B", "\x1B[?25h"] {
+            assert_eq!(Sequence::try_from(*s).unwrap().to_string(), *s);
+        }
+    }
+}
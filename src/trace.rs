@@ -0,0 +1,120 @@
+//! Logging for the decisions made by the optimizer.
+//!
+//! With the `log` feature, every decision about a sequence is logged at the `trace` level with the
+//! `ansi_optimizer` target. This explains why a sequence was removed or changed:
+//!
+//! ```text
+//! parsed "\u{1b}[31m"
+//! merged "\u{1b}[31m": into the pending style
+//! rewritten "style changes": as "\u{1b}[1;31m"
+//! dropped "\u{1b}[?25h": mode is already set
+//! ```
+//!
+//! Without the feature, nothing is logged and the arguments are never evaluated.
+
+/// Logs a decision about a sequence.
+///
+/// ## Arguments
+///
+/// - `$action`: What was done (e.g. `"dropped"`).
+/// - `$sequence`: The sequence, or anything else that implements [Display](std::fmt::Display).
+/// - The rest is an optional reason, formatted like [format!].
+#[cfg(feature = "log")]
+macro_rules! decision {
+    ($action:literal, $sequence:expr) => {
+        log::trace!(target: "ansi_optimizer", "{} {:?}", $action, $sequence.to_string())
+    };
+    ($action:literal, $sequence:expr, $($reason:tt)+) => {
+        log::trace!(
+            target: "ansi_optimizer",
+            "{} {:?}: {}",
+            $action,
+            $sequence.to_string(),
+            format_args!($($reason)+)
+        )
+    };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! decision {
+    ($action:literal, $sequence:expr) => {
+        if false {
+            $crate::trace::ignore(format_args!("{}", $sequence));
+        }
+    };
+    ($action:literal, $sequence:expr, $($reason:tt)+) => {
+        if false {
+            $crate::trace::ignore(format_args!("{}", $sequence));
+            $crate::trace::ignore(format_args!($($reason)+));
+        }
+    };
+}
+
+pub(crate) use decision;
+
+/// Type checks the arguments of a decision that isn't logged.
+#[cfg(not(feature = "log"))]
+#[inline(always)]
+pub(crate) fn ignore(_: std::fmt::Arguments) {}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::trace::*;
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn logs_decisions() {
+        use crate::Optimizer;
+        use log::{Log, Metadata, Record};
+        use std::cell::RefCell;
+
+        thread_local! {
+            static LOGGED: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+        }
+
+        // Other tests can log at the same time, so only this thread's messages are kept.
+        struct Logger;
+        impl Log for Logger {
+            fn enabled(&self, metadata: &Metadata) -> bool {
+                metadata.target() == "ansi_optimizer"
+            }
+
+            fn log(&self, record: &Record) {
+                if self.enabled(record.metadata()) {
+                    LOGGED.with(|logged| logged.borrow_mut().push(record.args().to_string()));
+                }
+            }
+
+            fn flush(&self) {}
+        }
+
+        let _ = log::set_logger(&Logger);
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let mut optimizer = Optimizer::new();
+        optimizer.update("\x1B[31m\x1B[?25lx\x1B[?25l").unwrap();
+        let logged = LOGGED.with(|logged| logged.take());
+        assert!(logged.contains(&r#"parsed "\u{1b}[31m""#.to_owned()));
+        assert!(logged.contains(&r#"merged "\u{1b}[31m": into the pending style"#.to_owned()));
+        assert!(logged.iter().any(|line| line.starts_with(r#"dropped "\u{1b}[?25l": "#)));
+    }
+
+    #[cfg(not(feature = "log"))]
+    #[test]
+    fn ignores_decisions() {
+        let mut evaluated = false;
+        decision!("dropped", {
+            evaluated = true;
+            "sequence"
+        });
+
+        decision!("dropped", "sequence", "{}", {
+            evaluated = true;
+            "reason"
+        });
+
+        assert!(!evaluated);
+    }
+}
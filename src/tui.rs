@@ -0,0 +1,251 @@
+//! A [ratatui] backend that optimizes what it draws.
+//!
+//! Ratatui only draws the cells that changed since the last frame, but it moves the cursor to
+//! each run of them with an absolute position, and sets every part of the style when it changes.
+//! This backend writes the same updates through an [Optimizer], which replaces the moves and
+//! style changes with shorter ones.
+//!
+//! ```
+//! # use ansi_optimizer::tui::OptimizedBackend;
+//! use ratatui::layout::Size;
+//! use ratatui::widgets::Paragraph;
+//! use ratatui::Terminal;
+//!
+//! let backend = OptimizedBackend::new(Vec::new(), Size::new(10, 2));
+//! let mut terminal = Terminal::new(backend)?;
+//! terminal.draw(|frame| frame.render_widget(Paragraph::new("hi"), frame.area()))?;
+//! assert_eq!(terminal.backend().get_ref(), b"\x1B[Hhi\x1B[?25l");
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::style::{Attributes, Underline};
+use crate::width::text_width_with;
+use crate::{Color, Optimizer, Options, Style};
+use ratatui::backend::{Backend, ClearType, WindowSize};
+use ratatui::buffer::Cell;
+use ratatui::layout::{Position, Size};
+use ratatui::style::{Color as TuiColor, Modifier};
+use std::io::{self, Write};
+
+// -------------------------------------------------------------------------------------------------
+
+/// The ratatui modifiers that are attributes, and the attributes for them.
+const MODIFIERS: [(Modifier, Attributes); 8] = [
+    (Modifier::BOLD, Attributes::BOLD),
+    (Modifier::DIM, Attributes::FAINT),
+    (Modifier::ITALIC, Attributes::ITALIC),
+    (Modifier::SLOW_BLINK, Attributes::BLINK),
+    (Modifier::RAPID_BLINK, Attributes::RAPID_BLINK),
+    (Modifier::REVERSED, Attributes::REVERSE),
+    (Modifier::HIDDEN, Attributes::HIDDEN),
+    (Modifier::CROSSED_OUT, Attributes::STRIKETHROUGH),
+];
+
+/// A ratatui backend that writes optimized escape sequences to a writer.
+///
+/// The size of the terminal isn't detected, and has to be given to [OptimizedBackend::new] and
+/// [OptimizedBackend::resize]. Nothing is written until ratatui flushes the backend, which it
+/// does after drawing each frame.
+pub struct OptimizedBackend<W: Write> {
+    output: W,
+    optimizer: Optimizer,
+    size: Size,
+    cursor: Position,
+}
+
+impl<W: Write> OptimizedBackend<W> {
+    /// Creates a backend for a terminal with the given size, in cells.
+    pub fn new(output: W, size: Size) -> Self {
+        Self::with_options(output, size, Options::default())
+    }
+
+    /// Creates a backend for a terminal with the given size, in cells, using [Options].
+    pub fn with_options(output: W, size: Size, options: Options) -> Self {
+        let mut optimizer = Optimizer::with_options(options);
+        optimizer.resize(size.width, size.height);
+        OptimizedBackend { output, optimizer, size, cursor: Position::ORIGIN }
+    }
+
+    /// Changes the size of the terminal.
+    ///
+    /// Ratatui redraws everything on the next frame after its size changes.
+    pub fn resize(&mut self, size: Size) {
+        self.size = size;
+        self.optimizer.resize(size.width, size.height);
+    }
+
+    /// Gets a reference to the writer.
+    pub fn get_ref(&self) -> &W {
+        &self.output
+    }
+
+    /// Gets a mutable reference to the writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    /// Moves the cursor to a position, starting from zero.
+    fn move_to(&mut self, position: Position) {
+        self.optimizer.feed(&format!("\x1B[{};{}H", position.y + 1, position.x + 1));
+        self.cursor = position;
+    }
+}
+
+impl<W: Write> Backend for OptimizedBackend<W> {
+    fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        // Moves that aren't needed are removed by the optimizer, as long as it knows where the
+        // cursor is. It doesn't know before the first one, though.
+        let mut next = None;
+        for (x, y, cell) in content {
+            if cell.skip {
+                continue;
+            }
+
+            let position = Position { x, y };
+            if next != Some(position) {
+                self.move_to(position);
+            }
+
+            let symbol = cell.symbol();
+            self.optimizer.write_styled(symbol, cell_style(cell));
+            let cells = text_width_with(symbol, &self.optimizer.options.width_policy);
+            self.cursor.x = x.saturating_add(cells.max(1) as u16);
+            next = Some(self.cursor);
+        }
+
+        Ok(())
+    }
+
+    fn append_lines(&mut self, n: u16) -> io::Result<()> {
+        self.optimizer.feed(&"\n".repeat(n as usize));
+        self.cursor.y = self.cursor.y.saturating_add(n).min(self.size.height.saturating_sub(1));
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        self.optimizer.feed("\x1B[?25l");
+        Ok(())
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        self.optimizer.feed("\x1B[?25h");
+        Ok(())
+    }
+
+    fn get_cursor_position(&mut self) -> io::Result<Position> {
+        Ok(self.cursor)
+    }
+
+    fn set_cursor_position<P: Into<Position>>(&mut self, position: P) -> io::Result<()> {
+        self.move_to(position.into());
+        Ok(())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.clear_region(ClearType::All)
+    }
+
+    fn clear_region(&mut self, clear_type: ClearType) -> io::Result<()> {
+        self.optimizer.feed(match clear_type {
+            ClearType::All => "\x1B[2J",
+            ClearType::AfterCursor => "\x1B[J",
+            ClearType::BeforeCursor => "\x1B[1J",
+            ClearType::CurrentLine => "\x1B[2K",
+            ClearType::UntilNewLine => "\x1B[K",
+        });
+
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<Size> {
+        Ok(self.size)
+    }
+
+    fn window_size(&mut self) -> io::Result<WindowSize> {
+        Ok(WindowSize { columns_rows: self.size, pixels: Size::default() })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.optimizer.flush();
+        self.output.write_all(self.optimizer.take_output().as_bytes())?;
+        self.output.flush()
+    }
+}
+
+/// Gets the style of a ratatui cell.
+fn cell_style(cell: &Cell) -> Style {
+    let mut style =
+        Style { foreground: color(cell.fg), background: color(cell.bg), ..Style::default() };
+
+    if cell.modifier.contains(Modifier::UNDERLINED) {
+        style.underline = Underline::Single;
+    }
+
+    for (modifier, attribute) in MODIFIERS.iter() {
+        if cell.modifier.contains(*modifier) {
+            style.attributes.insert(*attribute);
+        }
+    }
+
+    style
+}
+
+/// Converts a ratatui color.
+fn color(color: TuiColor) -> Color {
+    match color {
+        TuiColor::Reset => Color::Default,
+        TuiColor::Black => Color::Indexed(0),
+        TuiColor::Red => Color::Indexed(1),
+        TuiColor::Green => Color::Indexed(2),
+        TuiColor::Yellow => Color::Indexed(3),
+        TuiColor::Blue => Color::Indexed(4),
+        TuiColor::Magenta => Color::Indexed(5),
+        TuiColor::Cyan => Color::Indexed(6),
+        TuiColor::Gray => Color::Indexed(7),
+        TuiColor::DarkGray => Color::Indexed(8),
+        TuiColor::LightRed => Color::Indexed(9),
+        TuiColor::LightGreen => Color::Indexed(10),
+        TuiColor::LightYellow => Color::Indexed(11),
+        TuiColor::LightBlue => Color::Indexed(12),
+        TuiColor::LightMagenta => Color::Indexed(13),
+        TuiColor::LightCyan => Color::Indexed(14),
+        TuiColor::White => Color::Indexed(15),
+        TuiColor::Rgb(r, g, b) => Color::Rgb(r, g, b),
+        TuiColor::Indexed(index) => Color::Indexed(index),
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::tui::*;
+    use ratatui::style::Stylize;
+    use ratatui::text::Line;
+    use ratatui::widgets::Paragraph;
+    use ratatui::Terminal;
+
+    #[test]
+    fn draw_frames() {
+        let backend = OptimizedBackend::new(Vec::new(), Size::new(6, 2));
+        let mut terminal = Terminal::new(backend).unwrap();
+        terminal
+            .draw(|frame| {
+                let line = Line::from(vec!["ab".red(), "cd".red().bold()]);
+                frame.render_widget(Paragraph::new(vec![line, "ef".into()]), frame.area());
+            })
+            .unwrap();
+
+        terminal
+            .draw(|frame| {
+                frame.render_widget(Paragraph::new(vec!["".into(), "eg".into()]), frame.area())
+            })
+            .unwrap();
+
+        let output = String::from_utf8(terminal.backend().get_ref().clone()).unwrap();
+        assert_eq!(output, "\x1B[H\x1B[31mab\x1B[1mcd\r\n\x1B[mef\x1B[?25l\x1B[H    \x1B[2;2Hg");
+    }
+}
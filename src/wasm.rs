@@ -0,0 +1,86 @@
+//! JavaScript bindings for WebAssembly, using `wasm-bindgen`.
+//!
+//! This is behind the `wasm` feature. The bindings only take and return strings, so invalid or
+//! incomplete escape sequences are passed through unchanged rather than being reported as errors.
+//!
+//! ```js
+//! import { optimize, Optimizer } from "ansi-optimizer";
+//!
+//! const output = optimize("\x1b[31m\x1b[1mred");
+//!
+//! const optimizer = new Optimizer();
+//! socket.onmessage = (event) => terminal.write(optimizer.feed(event.data));
+//! socket.onclose = () => terminal.write(optimizer.finish());
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+// -------------------------------------------------------------------------------------------------
+
+/// An optimizer for a stream of text, which is split into arbitrary chunks.
+#[wasm_bindgen(js_name = Optimizer)]
+pub struct StreamOptimizer(crate::Optimizer);
+
+#[wasm_bindgen(js_class = Optimizer)]
+impl StreamOptimizer {
+    /// Creates an optimizer with the default options.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        StreamOptimizer(crate::Optimizer::new())
+    }
+
+    /// Updates the optimizer with the next chunk of the stream.
+    ///
+    /// ## Returns
+    ///
+    /// The output that is ready to be written.
+    pub fn feed(&mut self, chunk: &str) -> String {
+        self.0.feed(chunk);
+        self.0.take_output()
+    }
+
+    /// Finishes the stream. The optimizer can be used for a new stream afterwards.
+    ///
+    /// ## Returns
+    ///
+    /// The rest of the output, including everything that was held back.
+    pub fn finish(&mut self) -> String {
+        self.0.finish();
+        self.0.take_output()
+    }
+}
+
+impl Default for StreamOptimizer {
+    fn default() -> Self {
+        StreamOptimizer::new()
+    }
+}
+
+/// Optimizes a complete string.
+#[wasm_bindgen]
+pub fn optimize(input: &str) -> String {
+    let mut optimizer = StreamOptimizer::new();
+    let mut output = optimizer.feed(input);
+    output.push_str(&optimizer.finish());
+    output
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::wasm::*;
+
+    #[test]
+    fn optimize_string() {
+        assert_eq!(optimize("\x1B[31m\x1B[1mred\x1B["), "\x1B[1;31mred\x1B[");
+    }
+
+    #[test]
+    fn stream() {
+        let mut optimizer = StreamOptimizer::new();
+        assert_eq!(optimizer.feed("a\x1B[1"), "a");
+        assert_eq!(optimizer.feed("mb\x1B[m"), "\x1B[1mb");
+        assert_eq!(optimizer.finish(), "\x1B[m");
+    }
+}
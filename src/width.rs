@@ -0,0 +1,252 @@
+//! Measuring how many terminal cells text takes up.
+//!
+//! With the `unicode` feature, text is split into grapheme clusters and measured with the
+//! [unicode-width](https://docs.rs/unicode-width) tables, so wide characters (e.g. CJK and most
+//! emoji) take up two cells and combining marks take up none. Without it, every character that
+//! isn't a control character or a common combining mark takes up one cell.
+//!
+//! ```
+//! # use ansi_optimizer::width::width;
+//! assert_eq!(width("\x1B[1mbold\x1B[m"), 4);
+//! assert_eq!(width("e\u{301}"), 1);
+//! ```
+//!
+//! Terminals disagree on the width of some text, like emoji joined with `ZWJ`. A [WidthPolicy]
+//! says how the terminal that the output is written to shows it.
+
+use crate::bidi::bidi_control;
+use crate::token::{Token, Tokens};
+
+// -------------------------------------------------------------------------------------------------
+
+/// How a terminal shows text that terminals disagree on the width of.
+///
+/// The default policy doesn't assume anything, and the cursor position is unknown after writing
+/// such text. The [width] functions guess its width with the Unicode tables instead.
+///
+/// ```
+/// # use ansi_optimizer::width::{width_with, WidthPolicy};
+/// let policy = WidthPolicy { hidden_soft_hyphens: true, ..WidthPolicy::default() };
+/// assert_eq!(width_with("co\u{AD}op", &policy), 4);
+/// ```
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default)]
+pub struct WidthPolicy {
+    /// How characters joined with a zero width joiner (`ZWJ`) are shown.
+    pub joined: Joined,
+
+    /// Whether combining marks (e.g. accents) take up a cell of their own, instead of being drawn
+    /// over the character before them.
+    pub separate_combining_marks: bool,
+
+    /// Whether soft hyphens (`U+00AD`) are hidden, instead of being shown as a hyphen like most
+    /// terminals do.
+    pub hidden_soft_hyphens: bool,
+}
+
+/// How characters joined with a zero width joiner are shown. See [WidthPolicy::joined].
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Joined {
+    /// The terminal isn't known, so neither is the width.
+    Unknown,
+
+    /// They're shown as a single character (e.g. one emoji), as wide as the first one.
+    Combined,
+
+    /// Each of them is shown on its own.
+    Separate,
+}
+
+impl Default for Joined {
+    #[inline]
+    fn default() -> Self {
+        Joined::Unknown
+    }
+}
+
+/// Gets the number of cells that the text of a string takes up.
+///
+/// Escape sequences and control characters take up no space, so this only makes sense for text
+/// on a single line.
+pub fn width(input: &str) -> usize {
+    width_with(input, &WidthPolicy::default())
+}
+
+/// Gets the number of cells that the text of a string takes up, using a [WidthPolicy].
+///
+/// See [width].
+pub fn width_with(input: &str, policy: &WidthPolicy) -> usize {
+    Tokens::new(input)
+        .filter_map(|token| match token {
+            Ok(Token::Text(text)) => Some(text_width_with(text, policy)),
+            _ => None,
+        })
+        .sum()
+}
+
+/// Gets the number of cells that text without escape sequences takes up, using a [WidthPolicy].
+pub(crate) fn text_width_with(text: &str, policy: &WidthPolicy) -> usize {
+    clusters(text)
+        .filter(|cluster| !cluster.starts_with(char::is_control))
+        .map(|cluster| match cluster_width(cluster, policy) {
+            Some(width) => usize::from(width),
+            None => fallback_width(cluster, policy),
+        })
+        .sum()
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Splits text into the units that the cursor moves over.
+#[cfg(feature = "unicode")]
+pub(crate) fn clusters(text: &str) -> impl Iterator<Item = &str> {
+    unicode_segmentation::UnicodeSegmentation::graphemes(text, true)
+}
+
+/// Splits text into the units that the cursor moves over.
+#[cfg(not(feature = "unicode"))]
+pub(crate) fn clusters(text: &str) -> impl Iterator<Item = &str> {
+    text.char_indices().map(move |(i, c)| &text[i..(i + c.len_utf8())])
+}
+
+/// Gets the number of cells that a printable grapheme cluster takes up.
+///
+/// ## Returns
+///
+/// `None` if terminals are known to disagree on it, and the [WidthPolicy] doesn't say which of
+/// them is right. This includes emoji joined by `ZWJ` or changed by a variation selector, and
+/// flags.
+#[cfg(feature = "unicode")]
+pub(crate) fn cluster_width(cluster: &str, policy: &WidthPolicy) -> Option<u16> {
+    use unicode_width::UnicodeWidthChar;
+
+    if cluster == "\u{AD}" {
+        return Some(if policy.hidden_soft_hyphens { 0 } else { 1 });
+    }
+
+    if bidi_control(cluster).is_some() {
+        return Some(0);
+    }
+
+    if cluster.contains('\u{200D}') && policy.joined != Joined::Unknown {
+        let parts = cluster.split('\u{200D}').filter(|part| !part.is_empty());
+        let mut widths = parts.map(|part| cluster_width(part, policy));
+        return match policy.joined {
+            Joined::Combined => widths.next().flatten(),
+            _ => widths.sum(),
+        };
+    }
+
+    let disputed =
+        |c: char| matches!(c, '\u{200D}' | '\u{FE0E}' | '\u{FE0F}' | '\u{1F1E6}'..='\u{1F1FF}');
+
+    let mut chars = cluster.chars();
+    let first = chars.next().filter(|&c| !disputed(c))?;
+    let mut width = first.width()? as u16;
+    for c in chars {
+        if c.width() != Some(0) || disputed(c) {
+            return None;
+        }
+
+        if policy.separate_combining_marks && is_combining(c) {
+            width += 1;
+        }
+    }
+
+    Some(width)
+}
+
+/// Gets the number of cells that a printable character takes up.
+///
+/// ## Returns
+///
+/// `None` if it isn't printable ASCII or known from the [WidthPolicy], since other characters
+/// might be wide.
+#[cfg(not(feature = "unicode"))]
+pub(crate) fn cluster_width(cluster: &str, policy: &WidthPolicy) -> Option<u16> {
+    match cluster.as_bytes() {
+        [b' '..=b'~'] => Some(1),
+        [0xC2, 0xAD] => Some(if policy.hidden_soft_hyphens { 0 } else { 1 }),
+        _ if bidi_control(cluster).is_some() => Some(0),
+        _ if policy.separate_combining_marks && cluster.starts_with(is_combining) => Some(1),
+        _ => None,
+    }
+}
+
+/// Checks if a character combines with the character before it.
+///
+/// This only covers the common combining marks, variation selectors, and the zero-width joiner.
+/// Terminals differ in whether REP repeats a character alone or together with its combining marks,
+/// so a character that is followed by one of these isn't repeated.
+#[inline]
+pub(crate) fn is_combining(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{200D}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FE20}'..='\u{FE2F}'
+    )
+}
+
+/// Guesses the width of a grapheme cluster that terminals disagree on.
+#[cfg(feature = "unicode")]
+fn fallback_width(cluster: &str, _policy: &WidthPolicy) -> usize {
+    unicode_width::UnicodeWidthStr::width(cluster)
+}
+
+/// Guesses the width of a character.
+#[cfg(not(feature = "unicode"))]
+fn fallback_width(cluster: &str, policy: &WidthPolicy) -> usize {
+    let separate = policy.separate_combining_marks;
+    cluster.chars().filter(|&c| separate || !is_combining(c)).count()
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::width::*;
+
+    #[test]
+    fn width_ignores_sequences() {
+        assert_eq!(width(""), 0);
+        assert_eq!(width("\x1B[31mred\x1B[m text\x1B]0;title\x07"), 8);
+        assert_eq!(width("a\tb\r\n"), 2);
+        assert_eq!(width("e\u{301}\u{301}"), 1);
+        assert_eq!(width("a\u{202E}b\u{2069}\u{200F}"), 2);
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn width_of_wide_characters() {
+        assert_eq!(width("日本語"), 6);
+        assert_eq!(width("a👍b"), 4);
+        let policy = WidthPolicy::default();
+        assert_eq!(cluster_width("👍", &policy), Some(2));
+        assert_eq!(cluster_width("e\u{301}", &policy), Some(1));
+        assert_eq!(cluster_width("\u{2764}\u{FE0F}", &policy), None);
+        assert_eq!(cluster_width("👩\u{200D}💻", &policy), None);
+        assert_eq!(cluster_width("🇨🇦", &policy), None);
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn width_with_policy() {
+        let policy = WidthPolicy {
+            joined: Joined::Combined,
+            separate_combining_marks: true,
+            hidden_soft_hyphens: true,
+        };
+
+        assert_eq!(cluster_width("👩\u{200D}💻", &policy), Some(2));
+        assert_eq!(cluster_width("e\u{301}", &policy), Some(2));
+        assert_eq!(width_with("soft\u{AD}hyphen", &policy), 10);
+        assert_eq!(width("soft\u{AD}hyphen"), 11);
+
+        let policy = WidthPolicy { joined: Joined::Separate, ..WidthPolicy::default() };
+        assert_eq!(cluster_width("👩\u{200D}💻", &policy), Some(4));
+        assert_eq!(cluster_width("👩\u{200D}\u{2764}\u{FE0F}\u{200D}👨", &policy), None);
+    }
+}
@@ -0,0 +1,132 @@
+//! Wrapping styled text to a width.
+//!
+//! ```
+//! # use ansi_optimizer::{wrap, Options};
+//! let wrapped = wrap("\x1B[41mabcdef\x1B[m", 4, &Options::default());
+//! assert_eq!(wrapped, "\x1B[41mabcd\x1B[m\n\x1B[41mef\x1B[m");
+//! ```
+
+use crate::bidi::{bidi_control, Embeddings};
+use crate::token::{Token, Tokens};
+use crate::width::{clusters, text_width_with};
+use crate::{Bidi, Optimizer, Options, Style};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Wraps lines that are wider than a number of cells, and optimizes the escape sequences.
+///
+/// Lines are broken between any two characters, like a terminal would. The style is reset before
+/// each added line break, so backgrounds don't extend to the edge of the terminal, and is set
+/// again at the start of the next line. Escape sequences are assumed not to move the cursor, so
+/// this is only meant for text that is written from top to bottom.
+///
+/// Bidirectional formatting characters are handled according to [Options::bidi].
+pub fn wrap(input: &str, width: usize, options: &Options) -> String {
+    let width = width.max(1);
+    let mut optimizer = Optimizer::with_options(options.clone());
+    let mut embeddings = Embeddings::default();
+    let mut column: usize = 0;
+
+    for (span, token) in Tokens::new(input).spanned() {
+        let text = match token {
+            Ok(Token::Text(text)) => text,
+            _ => {
+                optimizer.feed(&input[span.start..span.end]);
+                continue;
+            }
+        };
+
+        let mut start = 0;
+        let mut offset = 0;
+        for cluster in clusters(text) {
+            if let Some(c) = bidi_control(cluster) {
+                match options.bidi {
+                    Bidi::Pass => {}
+                    Bidi::Isolate => embeddings.update(c),
+                    Bidi::Strip => {
+                        optimizer.feed(&text[start..offset]);
+                        start = offset + cluster.len();
+                    }
+                }
+            }
+
+            match cluster.chars().next() {
+                Some('\n') => {
+                    embeddings.update('\n');
+                    column = 0;
+                }
+                Some('\r') => column = 0,
+                Some('\t') => column = (column / 8 + 1) * 8,
+                Some('\x08') => column = column.saturating_sub(1),
+                _ => {
+                    let cells = text_width_with(cluster, &options.width_policy);
+                    if column > 0 && column + cells > width {
+                        optimizer.feed(&text[start..offset]);
+                        optimizer.feed(&embeddings.close());
+                        optimizer.write_styled("\n", Style::default());
+                        optimizer.feed(&embeddings.reopen());
+                        start = offset;
+                        column = 0;
+                    }
+
+                    column += cells;
+                }
+            }
+
+            offset += cluster.len();
+        }
+
+        optimizer.feed(&text[start..]);
+    }
+
+    optimizer.feed(&embeddings.close());
+    optimizer.finish();
+    optimizer.take_output()
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::wrap::*;
+
+    #[test]
+    fn wrap_lines() {
+        let options = Options::default();
+        assert_eq!(wrap("", 4, &options), "");
+        assert_eq!(wrap("abcd\nefghi\r\nj", 4, &options), "abcd\nefgh\ni\r\nj");
+        assert_eq!(wrap("ab\tc\x08de", 4, &options), "ab\t\nc\x08de");
+        assert_eq!(wrap("e\u{301}e\u{301}e\u{301}", 2, &options), "e\u{301}e\u{301}\ne\u{301}");
+        assert_eq!(wrap("abc", 0, &options), "a\nb\nc");
+    }
+
+    #[test]
+    fn wrap_keeps_style() {
+        let options = Options::default();
+        assert_eq!(
+            wrap("\x1B[1m\x1B[31mabc\x1B[4mde\x1B[m", 2, &options),
+            "\x1B[1;31mab\x1B[m\n\x1B[1;31mc\x1B[4md\x1B[m\n\x1B[1;4;31me\x1B[m"
+        );
+        assert_eq!(wrap("ab\x1B[1mcd", 2, &options), "ab\n\x1B[1mcd");
+        assert_eq!(wrap("abc\x1B[", 2, &options), "ab\nc\x1B[");
+    }
+
+    #[test]
+    fn wrap_bidi() {
+        let input = "a\u{202E}bcd\u{202C}e";
+        let isolate = Options { bidi: Bidi::Isolate, ..Options::default() };
+        let strip = Options { bidi: Bidi::Strip, ..Options::default() };
+        assert_eq!(wrap(input, 2, &Options::default()), "a\u{202E}b\ncd\u{202C}\ne");
+        assert_eq!(wrap(input, 2, &isolate), "a\u{202E}b\u{202C}\n\u{202E}cd\u{202C}\ne");
+        assert_eq!(wrap(input, 2, &strip), "ab\ncd\ne");
+        assert_eq!(wrap("\u{2067}abc", 2, &isolate), "\u{2067}ab\u{2069}\n\u{2067}c\u{2069}");
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn wrap_wide_characters() {
+        let options = Options::default();
+        assert_eq!(wrap("a\u{65e5}\u{672c}", 2, &options), "a\n\u{65e5}\n\u{672c}");
+        assert_eq!(wrap("\u{65e5}", 1, &options), "\u{65e5}");
+    }
+}
@@ -0,0 +1,384 @@
+//! An [io::Write] adapter that optimizes everything written through it.
+//!
+//! ```
+//! # use ansi_optimizer::writer::Writer;
+//! # use std::io::Write;
+//! let mut writer = Writer::new(Vec::new());
+//! write!(writer, "\x1B[31m\x1B[1m")?;
+//! write!(writer, "red\x1B[m")?;
+//! assert_eq!(writer.into_inner()?, b"\x1B[1;31mred\x1B[m");
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::bytes::ByteOptimizer;
+use crate::token::{Token, Tokens};
+use crate::{Optimizer, Options};
+use std::io::{self, Write};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Optimizes the bytes written to it, and writes the output to another writer.
+///
+/// Sequences are held back until [Writer::flush] is called or the stream is finished, like an
+/// [Optimizer] given the stream with [Optimizer::feed]. Bytes that aren't valid UTF-8 are passed
+/// through unchanged. The stream is finished when the writer is dropped, but errors are ignored
+/// then; use [Writer::into_inner] to handle them.
+///
+/// If the underlying writer fails after a write's bytes were given to the optimizer, the write
+/// still succeeds, and the error is returned by the next write or flush instead. The output that
+/// wasn't written is kept, and written by the next write or flush after that.
+pub struct Writer<W: Write> {
+    /// This is only `None` after [Writer::into_inner] takes it.
+    output: Option<W>,
+    stream: ByteOptimizer,
+    max_write_size: Option<usize>,
+    emit: Emit,
+
+    /// The error from giving the output of a successful write to the underlying writer.
+    error: Option<io::Error>,
+}
+
+/// When a [Writer] gives its output to the underlying writer.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Emit {
+    /// After every write.
+    Always,
+
+    /// After a write that completes a line, or when the writer is flushed.
+    /// Only the complete lines are written, and the rest is kept until later.
+    Lines,
+
+    /// Only when the writer is flushed.
+    Flush,
+}
+
+impl Default for Emit {
+    #[inline]
+    fn default() -> Self {
+        Emit::Always
+    }
+}
+
+impl<W: Write> Writer<W> {
+    /// Creates a writer that optimizes with the default options.
+    pub fn new(output: W) -> Self {
+        Self::with_options(output, Options::default())
+    }
+
+    /// Creates a writer that optimizes with the provided options.
+    pub fn with_options(output: W, options: Options) -> Self {
+        Writer {
+            output: Some(output),
+            stream: ByteOptimizer::new(Optimizer::with_options(options)),
+            max_write_size: None,
+            emit: Emit::default(),
+            error: None,
+        }
+    }
+
+    /// Limits how many bytes are given to the underlying writer in a single write.
+    ///
+    /// The output is split into chunks that are no larger than `max` bytes, without splitting an
+    /// escape sequence or character between them. This is meant for serial devices and terminal
+    /// bridges with a maximum frame size. Escape sequences that are larger than `max` bytes on
+    /// their own (e.g. inline images) have to be split anyway.
+    pub fn max_write_size(mut self, max: usize) -> Self {
+        self.max_write_size = Some(max.max(1));
+        self
+    }
+
+    /// Sets when output is given to the underlying writer.
+    ///
+    /// Output that is batched together is written with fewer writes, which is cheaper for
+    /// producers that write a little at a time (e.g. one sequence per write). The output is the
+    /// same either way, but programs that use [Emit::Lines] or [Emit::Flush] need to flush the
+    /// writer before waiting for input, just like with a [BufWriter](std::io::BufWriter).
+    pub fn emit(mut self, emit: Emit) -> Self {
+        self.emit = emit;
+        self
+    }
+
+    /// Gets the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.output.as_ref().expect("writer is present")
+    }
+
+    /// Gets the underlying writer mutably.
+    ///
+    /// Writing to it directly bypasses the sequences being held back.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.output.as_mut().expect("writer is present")
+    }
+
+    /// Finishes the stream, writing everything that was held back.
+    ///
+    /// The writer can be used for a new stream afterwards.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.take_error()?;
+        self.stream.finish();
+        self.write_output(self.stream.output.len())?;
+        self.get_mut().flush()
+    }
+
+    /// Finishes the stream, and gets the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.finish()?;
+        Ok(self.output.take().expect("writer is present"))
+    }
+
+    /// Returns the error from an earlier write, if there was one.
+    fn take_error(&mut self) -> io::Result<()> {
+        self.error.take().map_or(Ok(()), Err)
+    }
+
+    /// Writes the first `end` bytes of the optimizer's output to the underlying writer, in chunks
+    /// no larger than the maximum write size.
+    fn write_output(&mut self, end: usize) -> io::Result<()> {
+        let output = self.output.as_mut().expect("writer is present");
+        let mut written = 0;
+        let result = loop {
+            let rest = &self.stream.output[written..end];
+            if rest.is_empty() {
+                break Ok(());
+            }
+
+            let len = self.max_write_size.map_or(rest.len(), |max| chunk_len(rest, max));
+            if let Err(error) = output.write_all(&rest[..len]) {
+                break Err(error);
+            }
+
+            written += len;
+        };
+
+        self.stream.output.drain(..written);
+        result
+    }
+}
+
+impl<W: Write> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.take_error()?;
+        self.stream.feed(buf);
+        let end = match self.emit {
+            Emit::Always => self.stream.output.len(),
+            Emit::Lines => {
+                self.stream.output.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1)
+            }
+            Emit::Flush => 0,
+        };
+
+        // The input can't be given back to the caller, so it's written and the error is saved for
+        // later. Otherwise, retrying the write (e.g. with `write_all`) would repeat the input.
+        if let Err(error) = self.write_output(end) {
+            self.error = Some(error);
+        }
+
+        Ok(buf.len())
+    }
+
+    /// Writes everything that is being held back, except for incomplete sequences and characters,
+    /// and flushes the underlying writer.
+    fn flush(&mut self) -> io::Result<()> {
+        self.take_error()?;
+        self.stream.flush();
+        self.write_output(self.stream.output.len())?;
+        self.get_mut().flush()
+    }
+}
+
+impl<W: Write> Drop for Writer<W> {
+    fn drop(&mut self) {
+        if self.output.is_some() {
+            let _ = self.finish();
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Gets the length of the first chunk of output, which is as long as possible without being longer
+/// than `max` bytes or ending partway through an escape sequence or character.
+fn chunk_len(output: &[u8], max: usize) -> usize {
+    if output.len() <= max {
+        return output.len();
+    }
+
+    // Only the start of the output is checked, with room for a character that crosses `max`.
+    // Bytes that aren't valid UTF-8 are written on their own.
+    let output = &output[..(max + 4).min(output.len())];
+    let text = match std::str::from_utf8(output) {
+        Ok(text) => text,
+        Err(error) if error.valid_up_to() == 0 => return 1,
+        Err(error) => std::str::from_utf8(&output[..error.valid_up_to()]).expect("valid utf-8"),
+    };
+
+    let mut end = 0;
+    for (span, token) in Tokens::new(text).spanned() {
+        if span.end <= max {
+            end = span.end;
+            continue;
+        }
+
+        // Text can be split between any two characters.
+        if let Ok(Token::Text(_)) = token {
+            end = floor_char_boundary(text, max);
+        }
+
+        break;
+    }
+
+    match end {
+        0 => floor_char_boundary(text, max).max(text.chars().next().map_or(1, char::len_utf8)),
+        end => end,
+    }
+}
+
+/// Gets the largest character boundary that isn't after `index`.
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+
+    index
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::writer::*;
+
+    /// A writer that records each write separately.
+    #[derive(Default)]
+    struct Chunks(Vec<Vec<u8>>);
+
+    impl Write for Chunks {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A writer that fails on its first write.
+    #[derive(Default)]
+    struct FailOnce {
+        failed: bool,
+        written: Vec<u8>,
+    }
+
+    impl Write for FailOnce {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if !std::mem::replace(&mut self.failed, true) {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_chunks() {
+        let mut writer = Writer::new(Chunks::default()).max_write_size(8);
+        writer
+            .write_all(b"abcdef\x1B[1;31m\xFFghi\xC3\xA9\xC3\xA9\xC3\xA9j\x1B]0;long title\x07")
+            .unwrap();
+        writer.write_all(b"\x1B[?25l").unwrap();
+        writer.flush().unwrap();
+
+        let chunks = writer.into_inner().unwrap().0;
+        assert_eq!(
+            chunks,
+            vec![
+                b"abcdef".to_vec(),
+                b"\x1B[1;31m".to_vec(),
+                b"\xFF".to_vec(),
+                b"ghi\xC3\xA9\xC3\xA9".to_vec(),
+                b"\xC3\xA9j".to_vec(),
+                b"\x1B]0;long".to_vec(),
+                b" title\x07".to_vec(),
+                b"\x1B[?25l".to_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn chunk_lengths() {
+        assert_eq!(chunk_len(b"abc", 8), 3);
+        assert_eq!(chunk_len(b"ab\xF0\x9F\x98\x80c", 3), 2);
+        assert_eq!(chunk_len(b"ab\xF0\x9F\x98\x80c", 6), 6);
+        assert_eq!(chunk_len(b"\xF0\x9F\x98\x80c", 1), 4);
+        assert_eq!(chunk_len(b"ab\x1B[1;31mc", 4), 2);
+
+        // Only the start of the output is checked.
+        let mut output = vec![b'a'; 1 << 20];
+        output.extend_from_slice(b"\xFF\x1B[1m");
+        assert_eq!(chunk_len(&output, 16), 16);
+    }
+
+    #[test]
+    fn write_invalid_bytes() {
+        // The stream continues after the invalid bytes, so the hyperlink is still open.
+        let mut writer = Writer::new(Vec::new());
+        writer.write_all(b"\x1B]8;;https://a\x07a\xFFb\x1B]8;;\x07\x1B[3\xFEc").unwrap();
+        assert_eq!(
+            writer.into_inner().unwrap(),
+            b"\x1B]8;;https://a\x07a\xFFb\x1B]8;;\x07\x1B[3\xFEc"
+        );
+    }
+
+    #[test]
+    fn write_error() {
+        // The input isn't repeated when the write is retried.
+        let mut writer = Writer::new(FailOnce::default());
+        assert_eq!(writer.write(b"a\x1B[1mb").unwrap(), 6);
+        assert_eq!(writer.write(b"c").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+        writer.write_all(b"c").unwrap();
+        assert_eq!(writer.into_inner().unwrap().written, b"a\x1B[1mbc");
+
+        let mut writer = Writer::new(FailOnce::default());
+        writer.write_all(b"a").unwrap();
+        assert!(writer.flush().is_err());
+        writer.flush().unwrap();
+        assert_eq!(writer.get_ref().written, b"a");
+    }
+
+    #[test]
+    fn write_batched() {
+        let mut writer = Writer::new(Chunks::default()).emit(Emit::Lines);
+        writer.write_all(b"\x1B[1m").unwrap();
+        writer.write_all(b"a").unwrap();
+        writer.write_all(b"\x1B[m\nb").unwrap();
+        writer.write_all(b"c").unwrap();
+        assert_eq!(writer.get_ref().0, vec![b"\x1B[1ma\x1B[m\n".to_vec()]);
+        writer.flush().unwrap();
+        assert_eq!(writer.get_ref().0.concat(), b"\x1B[1ma\x1B[m\nbc");
+
+        let mut writer = Writer::new(Chunks::default()).emit(Emit::Flush);
+        writer.write_all(b"a\n").unwrap();
+        writer.write_all(b"b\n").unwrap();
+        assert!(writer.get_ref().0.is_empty());
+        assert_eq!(writer.into_inner().unwrap().0, vec![b"a\nb\n".to_vec()]);
+    }
+
+    #[test]
+    fn write_held_back() {
+        let mut writer = Writer::new(Vec::new());
+        writer.write_all(b"a\x1B[?25l\x1B[?2").unwrap();
+        assert_eq!(writer.get_ref(), b"a");
+        writer.write_all(b"5h").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.get_ref(), b"a");
+        writer.write_all(b"\x1B[1mb\xC3").unwrap();
+        assert_eq!(writer.into_inner().unwrap(), b"a\x1B[1mb\xC3");
+    }
+}
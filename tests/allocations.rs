@@ -0,0 +1,84 @@
+//! Checks that optimizing SGR-dense input doesn't allocate once the optimizer's buffers have grown.
+//!
+//! This needs its own test binary, since it replaces the global allocator to count allocations.
+
+#![cfg(feature = "optimize")]
+
+use ansi_optimizer::{Color, Optimizer, Style};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+// -------------------------------------------------------------------------------------------------
+
+/// The system allocator, counting the allocations made by each thread.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Returns the number of allocations made by the current thread while running `f`.
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[test]
+fn optimize_sgr() {
+    let inputs = [
+        (0..4096)
+            .map(|i| format!("\x1B[{};{}m\x1B[1m{}\x1B[22m", 31 + i % 7, 41 + i % 5, i % 10))
+            .collect::<String>(),
+        "\x1B[38;5;1;48;2;1;2;3mx\x1B[4:3;58:2::1:2:3my\x1B[m".repeat(1024),
+    ];
+
+    let mut optimizer = Optimizer::new();
+    for input in &inputs {
+        optimizer.update(input).unwrap();
+        optimizer.reset();
+        assert_eq!(allocations(|| optimizer.update(input).unwrap()), 0, "{:?}", &input[..32]);
+    }
+}
+
+#[test]
+fn write_transition() {
+    let styles = (0..256u32)
+        .map(|i| Style {
+            foreground: Color::Indexed(1 + (i % 7) as u8),
+            background: Color::Rgb(i as u8, 0, 0),
+            ..Style::default()
+        })
+        .collect::<Vec<_>>();
+
+    let mut output = String::with_capacity(64 * styles.len());
+    let count = allocations(|| {
+        for pair in styles.windows(2) {
+            pair[1].write_transition(&pair[0], &mut output).unwrap();
+        }
+    });
+
+    assert_eq!(count, 0);
+    assert!(!output.is_empty());
+}
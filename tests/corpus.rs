@@ -0,0 +1,260 @@
+//! Golden tests for the optimizer.
+//!
+//! Each `tests/corpus/NAME.in` file is optimized and compared to `NAME.out`. The input and output
+//! are also run through a simple terminal emulator, to check that they leave the screen in the
+//! same state.
+//!
+//! Run with `BLESS=1` to write the current output of every input as its expected output.
+
+#![cfg(feature = "optimize")]
+
+use ansi_optimizer::ansi::{ControlSequence, Param, Sequence};
+use ansi_optimizer::token::{Token, Tokens};
+use ansi_optimizer::{Profile, Style};
+use std::fs;
+use std::path::Path;
+
+// -------------------------------------------------------------------------------------------------
+
+const WIDTH: usize = 80;
+const HEIGHT: usize = 24;
+
+/// The state of an emulated terminal.
+///
+/// Lines that scroll off the top of the screen are kept, so the whole output can be compared.
+#[derive(Clone, Debug, Default)]
+struct Screen {
+    lines: Vec<Vec<(char, Style)>>,
+    top: usize,
+    row: usize,
+    col: usize,
+    style: Style,
+    saved: (usize, usize, Style),
+    last: Option<char>,
+}
+
+impl Screen {
+    fn run(input: &str) -> Screen {
+        let mut screen = Screen::default();
+        for token in Tokens::new(input) {
+            match token {
+                Ok(Token::Text(text)) => text.chars().for_each(|c| screen.print(c)),
+                Ok(Token::Sequence(Sequence::CSI(csi))) => screen.csi(&csi),
+                Ok(Token::Sequence(Sequence::Regular(seq))) => match seq.finalizer() {
+                    "7" => screen.saved = (screen.row - screen.top, screen.col, screen.style),
+                    "8" => screen.restore(),
+                    "D" => screen.line_feed(),
+                    "E" => {
+                        screen.line_feed();
+                        screen.col = 0;
+                    }
+                    "M" => screen.move_to(screen.row.saturating_sub(1), screen.col),
+                    _ => {}
+                },
+                Ok(Token::Sequence(_)) => {}
+                Err(error) => panic!("{:?} in {:?}", error, input),
+            }
+        }
+
+        screen
+    }
+
+    fn print(&mut self, c: char) {
+        match c {
+            '\r' => self.col = 0,
+            '\n' => self.line_feed(),
+            '\x08' => self.col = self.col.min(WIDTH - 1).saturating_sub(1),
+            '\t' => self.col = ((self.col / 8 + 1) * 8).min(WIDTH - 1),
+            c if c.is_control() => {}
+            c => {
+                if self.col >= WIDTH {
+                    self.line_feed();
+                    self.col = 0;
+                }
+
+                self.set(self.col, c, self.style);
+                self.col += 1;
+                self.last = Some(c);
+            }
+        }
+    }
+
+    fn csi(&mut self, csi: &ControlSequence) {
+        if csi.private_marker().is_some() || !csi.intermediates().is_empty() {
+            return;
+        }
+
+        let params = csi.params().collect::<Vec<_>>();
+        let n = |i: usize| match params.get(i) {
+            Some(Param::Int(n)) if *n > 0 => usize::from(*n),
+            _ => 1,
+        };
+
+        let (row, col) = (self.row, self.col.min(WIDTH - 1));
+        match csi.finalizer() {
+            "A" => self.move_to(row.saturating_sub(n(0)), col),
+            "B" => self.move_to(row + n(0), col),
+            "C" => self.move_to(row, col + n(0)),
+            "D" => self.move_to(row, col.saturating_sub(n(0))),
+            "E" => self.move_to(row + n(0), 0),
+            "F" => self.move_to(row.saturating_sub(n(0)), 0),
+            "G" | "`" => self.move_to(row, n(0) - 1),
+            "d" => self.move_to(self.top + n(0) - 1, col),
+            "H" | "f" => self.move_to(self.top + n(0) - 1, n(1) - 1),
+            "J" => self.erase_display(params.first().and_then(Param::value).unwrap_or(0)),
+            "K" => self.erase_line(params.first().and_then(Param::value).unwrap_or(0)),
+            "X" => self.erase(row, col, (col + n(0)).min(WIDTH)),
+            "b" => {
+                if let Some(c) = self.last {
+                    (0..n(0)).for_each(|_| self.print(c));
+                }
+            }
+            "m" => {
+                self.style.apply_sgr(csi.params());
+            }
+            "s" => self.saved = (row - self.top, col, self.saved.2),
+            "u" => {
+                let style = self.style;
+                self.restore();
+                self.style = style;
+            }
+            _ => {}
+        }
+    }
+
+    /// Moves the cursor within the screen.
+    fn move_to(&mut self, row: usize, col: usize) {
+        self.row = row.max(self.top).min(self.top + HEIGHT - 1);
+        self.col = col.min(WIDTH - 1);
+    }
+
+    /// Moves the cursor down a line, scrolling the screen if it's at the bottom.
+    fn line_feed(&mut self) {
+        self.row += 1;
+        if self.row >= self.top + HEIGHT {
+            self.top = self.row + 1 - HEIGHT;
+        }
+    }
+
+    fn restore(&mut self) {
+        let (row, col, style) = self.saved;
+        self.move_to(self.top + row, col);
+        self.style = style;
+    }
+
+    fn set(&mut self, col: usize, c: char, style: Style) {
+        if self.lines.len() <= self.row {
+            self.lines.resize(self.row + 1, Vec::new());
+        }
+
+        let line = &mut self.lines[self.row];
+        if line.len() <= col {
+            line.resize(col + 1, (' ', Style::default()));
+        }
+
+        line[col] = (c, style);
+    }
+
+    /// Erases part of a line, filling it with the current background color.
+    fn erase(&mut self, row: usize, start: usize, end: usize) {
+        let blank = Style { background: self.style.background, ..Style::default() };
+        let (saved_row, saved_col) = (self.row, self.col);
+        self.row = row;
+        (start..end).for_each(|col| self.set(col, ' ', blank));
+        self.row = saved_row;
+        self.col = saved_col;
+    }
+
+    fn erase_line(&mut self, mode: u16) {
+        let col = self.col.min(WIDTH - 1);
+        match mode {
+            0 => self.erase(self.row, col, WIDTH),
+            1 => self.erase(self.row, 0, col + 1),
+            _ => self.erase(self.row, 0, WIDTH),
+        }
+    }
+
+    fn erase_display(&mut self, mode: u16) {
+        let rows = match mode {
+            0 => (self.row + 1)..(self.top + HEIGHT),
+            1 => self.top..self.row,
+            _ => self.top..(self.top + HEIGHT),
+        };
+
+        rows.for_each(|row| self.erase(row, 0, WIDTH));
+        if mode < 2 {
+            self.erase_line(mode);
+        }
+    }
+
+    /// Checks if two screens look the same.
+    fn visually_eq(&self, other: &Screen) -> bool {
+        let profile = Profile::default();
+        let blank = (' ', Style::default());
+        let cell = |screen: &Screen, row: usize, col: usize| {
+            screen.lines.get(row).and_then(|line| line.get(col)).copied().unwrap_or(blank)
+        };
+
+        let rows = self.lines.len().max(other.lines.len());
+        let cells_eq = (0..rows).all(|row| {
+            (0..WIDTH).all(|col| {
+                let (a, b) = (cell(self, row, col), cell(other, row, col));
+                a.0 == b.0 && a.1.visually_eq(&b.1, &profile)
+            })
+        });
+
+        cells_eq && (self.row, self.col.min(WIDTH - 1)) == (other.row, other.col.min(WIDTH - 1))
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[test]
+fn corpus() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let bless = std::env::var_os("BLESS").is_some();
+
+    let mut inputs = fs::read_dir(&dir)
+        .expect("read corpus directory")
+        .map(|entry| entry.expect("read corpus entry").path())
+        .filter(|path| path.extension() == Some("in".as_ref()))
+        .collect::<Vec<_>>();
+
+    inputs.sort();
+    assert!(!inputs.is_empty(), "no inputs in {}", dir.display());
+
+    let mut failures = Vec::new();
+    for path in &inputs {
+        let name = path.file_stem().expect("file name").to_string_lossy();
+        let input = fs::read_to_string(path).expect("read input");
+        let output = ansi_optimizer::optimize(&input).expect("optimize input");
+
+        if !Screen::run(&input).visually_eq(&Screen::run(&output)) {
+            failures.push(format!("{}: output looks different than the input", name));
+        }
+
+        let expected_path = path.with_extension("out");
+        if bless {
+            fs::write(&expected_path, &output).expect("write expected output");
+            continue;
+        }
+
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == output => {}
+            Ok(expected) => failures.push(format!(
+                "{}: output changed\n  expected: {:?}\n    actual: {:?}",
+                name, expected, output
+            )),
+            Err(_) => failures.push(format!("{}: missing {}.out (run with BLESS=1)", name, name)),
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}\n", failures.join("\n"));
+}
+
+#[test]
+fn screen_detects_differences() {
+    assert!(Screen::run("ab\x1B[2Dc").visually_eq(&Screen::run("cb\x1B[D")));
+    assert!(!Screen::run("\x1B[31ma").visually_eq(&Screen::run("a")));
+    assert!(!Screen::run("a\nb").visually_eq(&Screen::run("a\r\nb")));
+}